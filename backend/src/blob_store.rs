@@ -0,0 +1,96 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use axum::{body::Bytes, extract::{DefaultBodyLimit, Path}, http::{header, HeaderMap, StatusCode}, response::{IntoResponse, Response}, routing::{get, post}, Router};
+use common::entities::BlobId;
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+const BLOB_DIR: &str = "blobs";
+pub const MAX_BLOB_SIZE: usize = 10 * 1024 * 1024;
+
+/// Recognises supported image formats by their magic bytes
+pub fn detect_mime(data: &[u8]) -> Option<&'static str> {
+  match data {
+    [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+    [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+    [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+    [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+    _ => None,
+  }
+}
+
+fn blob_path(hash: &str) -> Option<PathBuf> {
+  let valid = hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+  valid.then(|| PathBuf::from(BLOB_DIR).join(hash))
+}
+
+/// Stores the request body under its SHA-256 and responds with the hash
+async fn upload(headers: HeaderMap, body: Bytes) -> Response {
+  let Some(mime) = detect_mime(&body) else {
+    return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported image format").into_response();
+  };
+  let declared = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+  if declared != Some(mime) {
+    return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Content type does not match image data").into_response();
+  }
+  match write_blob(&body).await {
+    Ok(hash) => hash.into_response(),
+    Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Could not store blob").into_response(),
+  }
+}
+
+/// Stores `data` under its SHA-256 unless it is stored already, and returns the hash
+pub async fn write_blob(data: &[u8]) -> std::io::Result<BlobId> {
+  let hash = format!("{:x}", Sha256::digest(data));
+  let path = PathBuf::from(BLOB_DIR).join(&hash);
+  if !tokio::fs::try_exists(&path).await.unwrap_or(false) {
+    // Write under a temporary name of its own first so a partially written blob is never served,
+    // even when the same image is uploaded twice at once
+    let temp = path.with_extension(format!("{:08x}.part", rand::random::<u32>()));
+    tokio::fs::create_dir_all(BLOB_DIR).await?;
+    tokio::fs::write(&temp, data).await?;
+    // Hard linking fails if the target exists, unlike renaming, and a blob already stored under
+    // the hash has the same contents
+    let linked = tokio::fs::hard_link(&temp, &path).await;
+    tokio::fs::remove_file(&temp).await?;
+    match linked {
+      Ok(()) => info!("Blob stored: {hash} ({}, {} bytes)", detect_mime(data).unwrap_or("unknown type"), data.len()),
+      Err(error) if error.kind() == ErrorKind::AlreadyExists => (),
+      Err(error) => return Err(error),
+    }
+  }
+  Ok(hash)
+}
+
+/// Stored image data with its media type
+pub struct Blob {
+  pub mime: &'static str,
+  pub data: Vec<u8>,
+}
+
+/// Reads a stored blob, `None` if `hash` is not a valid id or nothing is stored under it
+pub async fn read_blob(hash: &str) -> Option<Blob> {
+  let data = tokio::fs::read(blob_path(hash)?).await.ok()?;
+  let mime = detect_mime(&data).unwrap_or("application/octet-stream");
+  Some(Blob { mime, data })
+}
+
+async fn download(Path(hash): Path<String>) -> Response {
+  if blob_path(&hash).is_none() {
+    return (StatusCode::BAD_REQUEST, "Invalid blob id").into_response();
+  }
+  let Some(Blob { mime, data }) = read_blob(&hash).await else {
+    return (StatusCode::NOT_FOUND, "Blob does not exist").into_response();
+  };
+  (
+    [(header::CONTENT_TYPE, mime), (header::CACHE_CONTROL, "public, max-age=31536000, immutable")],
+    data,
+  ).into_response()
+}
+
+pub fn blob_server() -> Router {
+  Router::new()
+    .route("/", post(upload))
+    .route("/:hash", get(download))
+    .layer(DefaultBodyLimit::max(MAX_BLOB_SIZE))
+}
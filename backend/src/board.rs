@@ -1,10 +1,9 @@
 use std::{collections::HashMap, pin::Pin};
 
-use common::{entities::Position, websocket::{ToClient, ToServer}};
+use common::{api::Version, board_file::FileElement, entities::{Element, ElementId, Layer, LayerId, Placement, Position, ZOrder}, spatial::SpatialIndex, text_ot::{self, TextOp}, websocket::{ToClient, ToServer}};
 use futures_util::Future;
-use tracing::info;
 
-use crate::socket_endpoint::{Client, SocketHandler};
+use crate::{history::{self, Change, Revision}, socket_endpoint::{Client, SocketHandler}};
 
 type AsyncFnOnce = Box<dyn (FnOnce() -> Pin<Box<dyn Future<Output = ()> + Send>>) + Send>;
 
@@ -12,6 +11,29 @@ pub struct Board {
   clients: HashMap<u64, Client>,
 
   positions: HashMap<u64, Position>,
+  elements: HashMap<ElementId, Element>,
+  next_element_id: ElementId,
+  placements: HashMap<ElementId, Placement>,
+  /// Bounds of the elements
+  index: SpatialIndex<ElementId>,
+  /// Layers from bottom to top, elements without a layer are drawn below all of them
+  layers: Vec<(LayerId, Layer)>,
+  next_layer_id: LayerId,
+  /// Edits applied to each sticky note, the edit at index `i` moved it from revision `i` to `i + 1`
+  sticky_history: HashMap<ElementId, Vec<Vec<TextOp>>>,
+  /// Milliseconds since the Unix epoch when the board was created
+  created_at: u64,
+  /// Picked at random when the board is loaded, tells clients whether the board they knew is
+  /// still the one loaded
+  instance: u64,
+  /// Tagged changes answered so far, as the number of the next change of each author. Authors
+  /// send their changes in order, so those numbered below it were answered.
+  answered: HashMap<u64, u64>,
+  /// Changes of the contents in the order they were applied, version `n` is the board after the
+  /// first `n` of them. Only kept while the board is loaded, like the contents.
+  history: Vec<Revision>,
+  /// Ticks the board stays loaded while nobody is connected, before it is deleted
+  idle_ticks: u32,
   delete: Option<AsyncFnOnce>,
 }
 
@@ -21,15 +43,386 @@ impl Board {
     Self {
       clients: HashMap::new(),
       positions: HashMap::new(),
+      elements: HashMap::new(),
+      next_element_id: 0,
+      placements: HashMap::new(),
+      index: SpatialIndex::default(),
+      layers: Vec::new(),
+      next_layer_id: 0,
+      sticky_history: HashMap::new(),
+      created_at: history::now(),
+      instance: rand::random(),
+      answered: HashMap::new(),
+      history: Vec::new(),
+      idle_ticks: 0,
       delete: Some(Box::new(move || Box::pin(delete())))
     }
   }
 
+  /// Keeps the board loaded for `ticks` ticks without clients, so that a board made for someone
+  /// else to open is still there when they do
+  pub fn stay_loaded(mut self, ticks: u32) -> Self {
+    self.idle_ticks = ticks;
+    self
+  }
+
   async fn broadcast(&mut self, message: ToClient) {
     for client in self.clients.values_mut() {
       client.send(message.clone()).await;
     }
   }
+
+  async fn broadcast_except(&mut self, except: u64, message: ToClient) {
+    for (id, client) in self.clients.iter_mut() {
+      if *id != except {
+        client.send(message.clone()).await;
+      }
+    }
+  }
+
+  async fn send(&mut self, client_id: u64, message: ToClient) {
+    if let Some(client) = self.clients.get_mut(&client_id) {
+      client.send(message).await;
+    }
+  }
+
+  fn layer_locked(&self, layer: Option<LayerId>) -> bool {
+    let Some(layer) = layer else { return false; };
+    self.layers.iter().any(|(id, l)| *id == layer && l.locked)
+  }
+
+  /// Whether `id` exists and can be changed
+  fn editable(&self, id: ElementId) -> bool {
+    self.placements.get(&id).is_some_and(|placement| !self.layer_locked(placement.layer))
+  }
+
+  fn top_z(&self, layer: Option<LayerId>) -> i64 {
+    self.placements.values()
+      .filter(|placement| placement.layer == layer)
+      .map(|placement| placement.z + 1)
+      .max()
+      .unwrap_or(0)
+  }
+
+  fn bottom_z(&self, layer: Option<LayerId>) -> i64 {
+    self.placements.values()
+      .filter(|placement| placement.layer == layer)
+      .map(|placement| placement.z - 1)
+      .min()
+      .unwrap_or(0)
+  }
+
+  /// Editable elements among `ids` without duplicates, from bottom to top
+  fn stacked(&self, mut ids: Vec<ElementId>) -> Vec<ElementId> {
+    ids.sort();
+    ids.dedup();
+    ids.retain(|id| self.editable(*id));
+    ids.sort_by_key(|id| self.placements[id].stacking_key(&self.layers));
+    ids
+  }
+
+  /// Updates the bounds of `id` in the spatial index after it was added, changed or removed
+  fn reindex(&mut self, id: ElementId) {
+    match self.elements.get(&id).and_then(Element::bounds) {
+      Some(bounds) => self.index.insert(id, bounds),
+      None => { self.index.remove(&id); }
+    }
+  }
+
+  /// Elements that are shown to clients, from bottom to top
+  pub fn visible_elements(&self) -> Vec<Element> {
+    let hidden = |layer: Option<LayerId>| self.layers.iter().any(|(id, l)| Some(*id) == layer && l.hidden);
+    let mut ids = self.placements.iter()
+      .filter(|(_, placement)| !hidden(placement.layer))
+      .map(|(id, _)| *id)
+      .collect::<Vec<_>>();
+    ids.sort_by_key(|id| (self.placements[id].stacking_key(&self.layers), *id));
+    ids.iter().map(|id| self.elements[id].clone()).collect()
+  }
+
+  /// Layers from bottom to top and elements by id, as board files hold them
+  pub fn contents(&self) -> (Vec<(LayerId, Layer)>, Vec<FileElement>) {
+    let mut elements = self.placements.iter()
+      .map(|(id, placement)| FileElement { id: *id, placement: *placement, element: self.elements[id].clone() })
+      .collect::<Vec<_>>();
+    elements.sort_by_key(|element| element.id);
+    (self.layers.clone(), elements)
+  }
+
+  /// Replaces all layers and elements with those of a board file
+  pub async fn open(&mut self, layers: Vec<(LayerId, Layer)>, elements: Vec<FileElement>) {
+    self.set_contents(layers.clone(), elements.clone()).await;
+    self.history.push(Revision { time: history::now(), change: Change::Open { layers, elements } });
+  }
+
+  /// Fills a new board with the contents of the board or snapshot `from`
+  pub async fn fork(&mut self, from: String, layers: Vec<(LayerId, Layer)>, elements: Vec<FileElement>) {
+    self.set_contents(layers.clone(), elements.clone()).await;
+    self.history.push(Revision { time: history::now(), change: Change::Fork { from, layers, elements } });
+  }
+
+  /// Replaces all layers and elements with those of an earlier version. The history is kept, the
+  /// restore is a change like any other.
+  pub async fn restore(&mut self, version: u64, layers: Vec<(LayerId, Layer)>, elements: Vec<FileElement>) {
+    self.set_contents(layers.clone(), elements.clone()).await;
+    self.history.push(Revision { time: history::now(), change: Change::Restore { version, layers, elements } });
+  }
+
+  /// Replaces all layers and elements and sends the new contents to every client. Ids are never
+  /// handed out again, so edits in flight for removed elements cannot land on new ones.
+  async fn set_contents(&mut self, layers: Vec<(LayerId, Layer)>, elements: Vec<FileElement>) {
+    self.next_layer_id = layers.iter().map(|(id, _)| id.saturating_add(1)).max().unwrap_or(0).max(self.next_layer_id);
+    self.layers = layers;
+    self.elements.clear();
+    self.placements.clear();
+    self.sticky_history.clear();
+    self.index = SpatialIndex::default();
+    for FileElement { id, placement, mut element } in elements {
+      // The edits that led to the text are not in the file, clients start over from it
+      if let Element::Sticky(sticky) = &mut element {
+        sticky.revision = 0;
+        self.sticky_history.insert(id, Vec::new());
+      }
+      self.next_element_id = self.next_element_id.max(id.saturating_add(1));
+      self.elements.insert(id, element);
+      self.placements.insert(id, placement);
+      self.reindex(id);
+    }
+    self.broadcast_layers().await;
+    let elements = self.element_list();
+    self.broadcast(elements).await;
+  }
+
+  /// Every version of the board, from the empty board it was created as to the current one
+  pub fn versions(&self) -> Vec<Version> {
+    let created = Version { number: 0, time: self.created_at, client: None, summary: "Created the board".to_owned() };
+    let changes = self.history.iter().enumerate().map(|(i, revision)| Version {
+      number: i as u64 + 1,
+      time: revision.time,
+      client: revision.change.client(),
+      summary: revision.change.summary(),
+    });
+    std::iter::once(created).chain(changes).collect()
+  }
+
+  /// Changes that lead to `version`, or none if the board has no such version
+  pub fn history_until(&self, version: u64) -> Option<Vec<Revision>> {
+    self.history.get(..usize::try_from(version).ok()?).map(<[Revision]>::to_vec)
+  }
+
+  /// Builds the board that applying `revisions` to an empty board gives, which is the board at
+  /// the version they lead to. Boards apply changes deterministically, so it is the same as the
+  /// board clients saw then.
+  pub async fn replay(revisions: &[Revision]) -> Board {
+    let mut board = Board::new(|| async {});
+    for revision in revisions {
+      match &revision.change {
+        Change::Message { client_id, message } => { board.apply(*client_id, message.clone()).await; }
+        Change::Open { layers, elements } | Change::Fork { layers, elements, .. } | Change::Restore { layers, elements, .. } =>
+          board.set_contents(layers.clone(), elements.clone()).await,
+      }
+    }
+    board
+  }
+
+  fn element_list(&self) -> ToClient {
+    ToClient::ElementList {
+      board: self.instance,
+      elements: self.elements.iter()
+        .map(|(id, element)| (id.to_owned(), element.to_owned(), self.placements[id]))
+        .collect()
+    }
+  }
+
+  /// Applies a change of the contents of the board sent by `client_id` and tells clients about
+  /// it. Returns whether anything changed.
+  async fn apply(&mut self, client_id: u64, message: ToServer) -> bool {
+    match message {
+      ToServer::CreateElement { mut element, layer } => {
+        let layer = layer.filter(|layer| self.layers.iter().any(|(id, _)| id == layer));
+        if self.layer_locked(layer) {
+          return false;
+        }
+        let id = self.next_element_id;
+        self.next_element_id += 1;
+        if let Element::Sticky(sticky) = &mut element {
+          sticky.revision = 0;
+          self.sticky_history.insert(id, vec![]);
+        }
+        let placement = Placement { layer, z: self.top_z(layer) };
+        self.elements.insert(id, element.clone());
+        self.placements.insert(id, placement);
+        self.reindex(id);
+        self.broadcast(ToClient::NewElement { id, element, placement }).await;
+        true
+      }
+      ToServer::UpdateElement { id, mut element } => {
+        if !self.editable(id) {
+          return false;
+        }
+        let Some(old) = self.elements.get_mut(&id) else { return false; };
+        // Sticky note text only changes through edits, so a stale copy cannot overwrite it
+        if let (Element::Sticky(old), Element::Sticky(new)) = (&*old, &mut element) {
+          new.text = old.text.clone();
+          new.revision = old.revision;
+        }
+        *old = element.clone();
+        self.reindex(id);
+        self.broadcast(ToClient::ElementUpdated { id, element }).await;
+        true
+      }
+      ToServer::DeleteElement { id } => {
+        if !self.editable(id) {
+          return false;
+        }
+        if self.elements.remove(&id).is_none() {
+          return false;
+        }
+        self.placements.remove(&id);
+        self.sticky_history.remove(&id);
+        self.reindex(id);
+        self.broadcast(ToClient::ElementDeleted { id }).await;
+        true
+      }
+      ToServer::EditSticky { id, revision, ops } => {
+        self.edit_sticky(client_id, id, revision, ops).await
+      }
+      ToServer::Transform { ids, transform } => {
+        let ids = self.stacked(ids);
+        let elements = ids.into_iter()
+          .filter_map(|id| {
+            let element = self.elements.get_mut(&id)?;
+            element.transform(&transform);
+            Some((id, element.clone()))
+          })
+          .collect::<Vec<_>>();
+        for (id, _) in &elements {
+          self.reindex(*id);
+        }
+        if elements.is_empty() {
+          return false;
+        }
+        self.broadcast(ToClient::ElementsUpdated { elements }).await;
+        true
+      }
+      ToServer::Reorder { ids, to } => {
+        let mut ids = self.stacked(ids);
+        // Keep the relative order of the moved elements
+        if to == ZOrder::Back {
+          ids.reverse();
+        }
+        for id in &ids {
+          let layer = self.placements[id].layer;
+          let z = match to {
+            ZOrder::Front => self.top_z(layer),
+            ZOrder::Back => self.bottom_z(layer),
+          };
+          self.placements.insert(*id, Placement { layer, z });
+        }
+        let changed = !ids.is_empty();
+        self.broadcast_placements(ids).await;
+        changed
+      }
+      ToServer::SetLayer { ids, layer } => {
+        let known = layer.is_none_or(|layer| self.layers.iter().any(|(id, _)| *id == layer));
+        if !known || self.layer_locked(layer) {
+          return false;
+        }
+        let ids = self.stacked(ids);
+        for id in &ids {
+          let z = self.top_z(layer);
+          self.placements.insert(*id, Placement { layer, z });
+        }
+        let changed = !ids.is_empty();
+        self.broadcast_placements(ids).await;
+        changed
+      }
+      ToServer::CreateLayer { name } => {
+        let id = self.next_layer_id;
+        self.next_layer_id += 1;
+        self.layers.push((id, Layer { name, hidden: false, locked: false }));
+        self.broadcast_layers().await;
+        true
+      }
+      ToServer::UpdateLayer { id, layer } => {
+        let Some((_, old)) = self.layers.iter_mut().find(|(l, _)| *l == id) else { return false; };
+        *old = layer;
+        self.broadcast_layers().await;
+        true
+      }
+      ToServer::DeleteLayer { id } => {
+        let Some(index) = self.layers.iter().position(|(l, _)| *l == id) else { return false; };
+        self.layers.remove(index);
+        let mut moved = self.placements.iter()
+          .filter(|(_, placement)| placement.layer == Some(id))
+          .map(|(element, placement)| (placement.z, *element))
+          .collect::<Vec<_>>();
+        moved.sort();
+        for (_, element) in &moved {
+          let z = self.top_z(None);
+          self.placements.insert(*element, Placement { layer: None, z });
+        }
+        self.broadcast_layers().await;
+        self.broadcast_placements(moved.into_iter().map(|(_, element)| element)).await;
+        true
+      }
+      ToServer::MoveLayer { id, index } => {
+        let Some(old) = self.layers.iter().position(|(l, _)| *l == id) else { return false; };
+        let layer = self.layers.remove(old);
+        self.layers.insert(index.min(self.layers.len()), layer);
+        self.broadcast_layers().await;
+        true
+      }
+      // The pointer and carets are not part of the contents, tags are taken off in `on_message`
+      ToServer::Move { .. } | ToServer::StickyCaret { .. } | ToServer::Tagged { .. } => false,
+    }
+  }
+  
+
+  async fn broadcast_layers(&mut self) {
+    let layers = self.layers.clone();
+    self.broadcast(ToClient::LayerList { layers }).await;
+  }
+
+  async fn broadcast_placements(&mut self, ids: impl IntoIterator<Item = ElementId>) {
+    let placements = ids.into_iter()
+      .filter_map(|id| Some((id, *self.placements.get(&id)?)))
+      .collect::<Vec<_>>();
+    if !placements.is_empty() {
+      self.broadcast(ToClient::PlacementsUpdated { placements }).await;
+    }
+  }
+
+  /// Applies a message and adds it to the history if it changed the contents, returns whether it did
+  async fn record(&mut self, client_id: u64, message: ToServer) -> bool {
+    let applied = self.apply(client_id, message.clone()).await;
+    if applied {
+      self.history.push(Revision { time: history::now(), change: Change::Message { client_id, message } });
+    }
+    applied
+  }
+
+  /// Rebases an edit made against `revision` onto the current text and applies it, returns
+  /// whether it could
+  async fn edit_sticky(&mut self, client_id: u64, id: ElementId, revision: u64, ops: Vec<TextOp>) -> bool {
+    if !self.editable(id) {
+      return false;
+    }
+    let Some(Element::Sticky(sticky)) = self.elements.get_mut(&id) else { return false; };
+    let history = self.sticky_history.entry(id).or_default();
+    let Some(concurrent) = history.get(revision as usize..) else { return false; };
+    let concurrent = concurrent.concat();
+    let (ops, _) = text_ot::transform(&ops, &concurrent, false);
+    if text_ot::apply(&ops, &mut sticky.text).is_err() {
+      return false;
+    }
+    history.push(ops.clone());
+    sticky.revision = history.len() as u64;
+    let revision = sticky.revision;
+    self.send(client_id, ToClient::StickyAck { id, revision }).await;
+    self.broadcast_except(client_id, ToClient::StickyEdited { id, revision, ops }).await;
+    true
+  }
 }
 
 impl SocketHandler for Board {
@@ -40,6 +433,8 @@ impl SocketHandler for Board {
         .map(|(id, pos)| (id.to_owned(), pos.to_owned()))
         .collect()
     }).await;
+    client.send(ToClient::LayerList { layers: self.layers.clone() }).await;
+    client.send(self.element_list()).await;
     self.clients.insert(id, client);
     self.broadcast(ToClient::NewClient { id }).await;
   }
@@ -50,6 +445,21 @@ impl SocketHandler for Board {
         self.positions.insert(client_id, Position { x, y } );
         self.broadcast(ToClient::ClientMoved { id: client_id, x, y } ).await;
       }
+      ToServer::StickyCaret { id, position } => {
+        self.broadcast_except(client_id, ToClient::StickyCaret { client: client_id, id, position }).await;
+      }
+      ToServer::Tagged { author, seq, message } => {
+        let next = self.answered.entry(author).or_default();
+        // Sent again on a new connection, its effect is in the contents the client got on it
+        if seq < *next {
+          self.send(client_id, ToClient::Ack { seq }).await;
+          return;
+        }
+        *next = seq + 1;
+        let answer = if self.record(client_id, *message).await { ToClient::Ack { seq } } else { ToClient::Rejected { seq } };
+        self.send(client_id, answer).await;
+      }
+      message => { self.record(client_id, message).await; }
     };
   }
   
@@ -60,10 +470,151 @@ impl SocketHandler for Board {
   }
   
   async fn tick(&mut self) {
-    if self.clients.len() == 0 {
+    if self.clients.is_empty() {
+      if self.idle_ticks > 0 {
+        self.idle_ticks -= 1;
+        return;
+      }
       if let Some(f) = self.delete.take() {
         f().await;
       }
     }
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+  use common::entities::{Color, Sticky};
+
+  use super::*;
+
+  fn sticky(text: &str, revision: u64) -> Element {
+    Element::Sticky(Sticky {
+      position: Position { x: 0.0, y: 0.0 },
+      width: 100.0,
+      height: 80.0,
+      color: Color::STICKY_YELLOW,
+      rotation: 0.0,
+      text: text.to_owned(),
+      revision,
+    })
+  }
+
+  #[tokio::test]
+  async fn opened_contents_read_back() {
+    let mut board = Board::new(|| async {});
+    board.next_element_id = 9;
+    let layers = vec![(3, Layer { name: "Notes".to_owned(), hidden: false, locked: false })];
+    let elements = vec![
+      FileElement { id: 2, placement: Placement { layer: None, z: 0 }, element: sticky("a", 0) },
+      FileElement { id: 5, placement: Placement { layer: Some(3), z: 1 }, element: sticky("b", 0) },
+    ];
+    board.open(layers.clone(), elements.clone()).await;
+    assert_eq!(board.contents(), (layers, elements));
+    assert_eq!(board.visible_elements().len(), 2);
+    // Ids of removed elements are not handed out again
+    assert_eq!(board.next_element_id, 9);
+    assert_eq!(board.next_layer_id, 4);
+  }
+
+  #[tokio::test]
+  async fn opened_sticky_notes_start_over() {
+    let mut board = Board::new(|| async {});
+    let elements = vec![FileElement { id: 0, placement: Placement { layer: None, z: 0 }, element: sticky("a", 7) }];
+    board.open(vec![], elements).await;
+    assert_eq!(board.contents().1[0].element, sticky("a", 0));
+    assert_eq!(board.sticky_history[&0], Vec::<Vec<TextOp>>::new());
+    assert_eq!(board.next_element_id, 1);
+  }
+
+  #[tokio::test]
+  async fn replaying_the_history_rebuilds_every_version() {
+    let mut board = Board::new(|| async {});
+    board.on_message(1, ToServer::CreateLayer { name: "Notes".to_owned() }).await;
+    board.on_message(1, ToServer::CreateElement { element: sticky("", 0), layer: Some(0) }).await;
+    let ops = vec![TextOp::Insert { position: 0, text: "hi".to_owned() }];
+    board.on_message(2, ToServer::EditSticky { id: 0, revision: 0, ops }).await;
+    // Neither changes the contents, so neither is a version
+    board.on_message(2, ToServer::Move { x: 1.0, y: 2.0 }).await;
+    board.on_message(1, ToServer::DeleteElement { id: 5 }).await;
+    board.on_message(1, ToServer::CreateElement { element: sticky("b", 0), layer: None }).await;
+
+    let versions = board.versions();
+    let summaries = versions.iter().map(|v| (v.number, v.client, v.summary.as_str())).collect::<Vec<_>>();
+    assert_eq!(summaries, [
+      (0, None, "Created the board"),
+      (1, Some(1), "Added a layer"),
+      (2, Some(1), "Added a sticky note"),
+      (3, Some(2), "Edited a sticky note"),
+      (4, Some(1), "Added a sticky note"),
+    ]);
+    let latest = Board::replay(&board.history_until(4).unwrap()).await;
+    assert_eq!(latest.contents(), board.contents());
+    let (_, elements) = Board::replay(&board.history_until(2).unwrap()).await.contents();
+    assert_eq!(elements.iter().map(|e| e.element.clone()).collect::<Vec<_>>(), [sticky("", 0)]);
+    assert!(board.history_until(5).is_none());
+  }
+
+  #[tokio::test]
+  async fn restoring_adds_a_version() {
+    let mut board = Board::new(|| async {});
+    board.on_message(1, ToServer::CreateElement { element: sticky("a", 0), layer: None }).await;
+    board.on_message(1, ToServer::DeleteElement { id: 0 }).await;
+    let (layers, elements) = Board::replay(&board.history_until(1).unwrap()).await.contents();
+    board.restore(1, layers, elements).await;
+
+    assert_eq!(board.versions().last().unwrap().summary, "Restored version 1");
+    assert_eq!(board.contents().1[0].element, sticky("a", 0));
+    // The deleted id is not reused by the restore or later elements
+    board.on_message(1, ToServer::CreateElement { element: sticky("b", 0), layer: None }).await;
+    assert_eq!(board.contents().1.iter().map(|e| e.id).collect::<Vec<_>>(), [0, 1]);
+    let replayed = Board::replay(&board.history_until(4).unwrap()).await;
+    assert_eq!(replayed.contents(), board.contents());
+  }
+
+  #[tokio::test]
+  async fn tagged_changes_are_recorded_untagged() {
+    let mut board = Board::new(|| async {});
+    board.on_message(1, ToServer::CreateLayer { name: "Locked".to_owned() }).await;
+    board.on_message(1, ToServer::UpdateLayer { id: 0, layer: Layer { name: "Locked".to_owned(), hidden: false, locked: true } }).await;
+    let tagged = |seq, layer| ToServer::Tagged { author: 7, seq, message: Box::new(ToServer::CreateElement { element: sticky("", 0), layer }) };
+    board.on_message(1, tagged(0, None)).await;
+    // Refused, so it is not a version
+    board.on_message(1, tagged(1, Some(0))).await;
+    assert_eq!(board.versions().len(), 4);
+    assert!(matches!(board.history[2].change, Change::Message { message: ToServer::CreateElement { .. }, .. }));
+    assert_eq!(board.versions()[3].summary, "Added a sticky note");
+  }
+
+  #[tokio::test]
+  async fn changes_sent_again_are_applied_once() {
+    let mut board = Board::new(|| async {});
+    let tagged = |author, seq| ToServer::Tagged { author, seq, message: Box::new(ToServer::CreateElement { element: sticky("", 0), layer: None }) };
+    board.on_message(1, tagged(7, 0)).await;
+    board.on_message(1, tagged(7, 1)).await;
+    // The connection closed before the answers came, and the author sends both again
+    board.on_message(2, tagged(7, 0)).await;
+    board.on_message(2, tagged(7, 1)).await;
+    board.on_message(2, tagged(7, 2)).await;
+    board.on_message(3, tagged(8, 0)).await;
+    assert_eq!(board.elements.len(), 4);
+  }
+
+  #[tokio::test]
+  async fn forks_wait_for_their_first_client() {
+    let deleted = Arc::new(AtomicBool::new(false));
+    let mut board = Board::new({
+      let deleted = deleted.clone();
+      move || async move { deleted.store(true, Ordering::SeqCst) }
+    }).stay_loaded(2);
+    board.fork("general".to_owned(), vec![], vec![]).await;
+    board.tick().await;
+    board.tick().await;
+    assert!(!deleted.load(Ordering::SeqCst));
+    board.tick().await;
+    assert!(deleted.load(Ordering::SeqCst));
+    assert_eq!(board.versions()[1].summary, "Forked from general");
+  }
+}
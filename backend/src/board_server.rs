@@ -1,13 +1,27 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::{HashMap, HashSet}, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
 
-use axum::{extract::{Path, Query, State, WebSocketUpgrade}, http::StatusCode, response::{IntoResponse, Response}, routing::{get, post}, Router};
+use axum::{extract::{DefaultBodyLimit, Path, Query, State, WebSocketUpgrade}, http::{header, StatusCode}, response::{IntoResponse, Response}, routing::{get, post}, Json, Router};
 use serde::Deserialize;
 use tokio::sync::Mutex;
 use tracing::info;
 
-use crate::{board::Board, socket_endpoint::SocketEndpoint};
+use common::{api::Preview, board_file::{self, Asset, BoardFile, Metadata}, entities::{Color, Element, Position}, spatial::Rect};
 
-const MAIN_SERVER_URL: &'static str = "http://localhost:8080/internal";
+use crate::{blob_store::{self, detect_mime, MAX_BLOB_SIZE}, board::Board, history::Revision, snapshot_store, export::{pdf::{self, Layout, Paper}, png, svg, Scene}, socket_endpoint::SocketEndpoint};
+
+const MAIN_SERVER_URL: &str = "http://localhost:8080/internal";
+/// Zoom strokes are tessellated for in SVG exports, so that they stay smooth when enlarged
+const SVG_ZOOM: f32 = 4.0;
+/// Largest number of pixels per board unit of PNG exports
+const MAX_PNG_SCALE: f32 = 16.0;
+/// Largest print size of tiled PDF exports relative to the size on screen
+const MAX_PDF_SCALE: f32 = 16.0;
+/// Zoom strokes are tessellated for in PDF exports, printers resolve finer than screens
+const PDF_ZOOM: f32 = 4.0;
+/// Margin of PDF pages printed on paper, in millimetres
+const DEFAULT_PDF_MARGIN: f32 = 10.0;
+/// Ticks a forked board stays loaded before anyone opens it
+const FORK_IDLE_TICKS: u32 = 12;
 
 async fn ws(ws: WebSocketUpgrade, Path(socket_id): Path<String>, State(state): State<Arc<Mutex<ServerState>>>) -> Response{
   let state = state.lock().await;
@@ -17,6 +31,331 @@ async fn ws(ws: WebSocketUpgrade, Path(socket_id): Path<String>, State(state): S
   }
 } 
 
+/// Content disposition of a download named after a board, keeping only characters that are safe
+/// in a quoted header value and in file names
+fn attachment(name: &str, extension: &str) -> String {
+  let name = name.chars()
+    .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ' '))
+    .collect::<String>();
+  let name = if name.trim().is_empty() { "board" } else { name.trim() };
+  format!("attachment; filename=\"{name}.{extension}\"")
+}
+
+/// Reads the state of a loaded board
+async fn inspect_board<T: Send + 'static>(state: &Mutex<ServerState>, socket_id: &str, f: impl FnOnce(&Board) -> T + Send + 'static) -> Result<T, Response> {
+  let result = match state.lock().await.endpoints.get(socket_id) {
+    Some(endpoint) => endpoint.inspect(f),
+    None => return Err((StatusCode::NOT_FOUND, "Board is not loaded").into_response()),
+  };
+  result.await.ok_or_else(|| (StatusCode::NOT_FOUND, "Board is not loaded").into_response())
+}
+
+/// Visible elements of a loaded board from bottom to top
+async fn visible_elements(state: &Mutex<ServerState>, socket_id: &str) -> Result<Vec<Element>, Response> {
+  inspect_board(state, socket_id, Board::visible_elements).await
+}
+
+/// Downloads a loaded board as a board file, with the data of its images
+async fn download_file(Path(socket_id): Path<String>, State(state): State<Arc<Mutex<ServerState>>>) -> Response {
+  let (layers, elements) = match inspect_board(&state, &socket_id, Board::contents).await {
+    Ok(contents) => contents,
+    Err(response) => return response,
+  };
+  let blobs = elements.iter()
+    .filter_map(|e| match &e.element {
+      Element::Image(image) => Some(image.blob.clone()),
+      _ => None,
+    })
+    .collect::<HashSet<_>>();
+  let mut assets = Vec::new();
+  for id in blobs {
+    // Images whose blob is gone are kept, they show as placeholders as they do on the board
+    if let Some(blob) = blob_store::read_blob(&id).await {
+      assets.push(Asset { id, mime: blob.mime.to_owned(), data: blob.data });
+    }
+  }
+  assets.sort_by(|a, b| a.id.cmp(&b.id));
+  let saved_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+  let file = BoardFile { metadata: Metadata { name: socket_id.clone(), saved_at }, layers, elements, assets };
+  (
+    [(header::CONTENT_TYPE, "application/json".to_owned()), (header::CONTENT_DISPOSITION, attachment(&socket_id, board_file::EXTENSION))],
+    file.to_json(),
+  ).into_response()
+}
+
+/// Replaces the contents of a loaded board with those of a board file. The images of the file are
+/// stored as blobs, clients connected to the board receive the new contents.
+async fn upload_file(Path(socket_id): Path<String>, State(state): State<Arc<Mutex<ServerState>>>, body: String) -> Response {
+  let file = match BoardFile::from_json(&body) {
+    Ok(file) => file,
+    Err(error) => return (StatusCode::BAD_REQUEST, error.to_string()).into_response(),
+  };
+  // Assets are stored under the hash of their data, which the ids in the file need not match
+  let mut blobs = HashMap::new();
+  for asset in file.assets {
+    if detect_mime(&asset.data).is_none() || asset.data.len() > MAX_BLOB_SIZE {
+      return (StatusCode::BAD_REQUEST, format!("Asset {} is not a supported image", asset.id)).into_response();
+    }
+    match blob_store::write_blob(&asset.data).await {
+      Ok(hash) => { blobs.insert(asset.id, hash); }
+      Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Could not store images").into_response(),
+    }
+  }
+  let mut elements = file.elements;
+  for element in &mut elements {
+    if let Element::Image(image) = &mut element.element {
+      if let Some(hash) = blobs.get(&image.blob) {
+        image.blob = hash.clone();
+      }
+    }
+  }
+  let layers = file.layers;
+  let replaced = match state.lock().await.endpoints.get(&socket_id) {
+    Some(endpoint) => endpoint.update(move |board| Box::pin(board.open(layers, elements))),
+    None => return (StatusCode::NOT_FOUND, "Board is not loaded").into_response(),
+  };
+  match replaced.await {
+    Some(()) => StatusCode::NO_CONTENT.into_response(),
+    None => (StatusCode::NOT_FOUND, "Board is not loaded").into_response(),
+  }
+}
+
+/// Downloads the visible elements of a loaded board as an SVG document
+async fn export_svg(Path(socket_id): Path<String>, State(state): State<Arc<Mutex<ServerState>>>) -> Response {
+  let elements = match visible_elements(&state, &socket_id).await {
+    Ok(elements) => elements,
+    Err(response) => return response,
+  };
+  let scene = Scene::new(&elements, SVG_ZOOM, None);
+  let images = scene.load_images().await;
+  (
+    [(header::CONTENT_TYPE, "image/svg+xml".to_owned()), (header::CONTENT_DISPOSITION, attachment(&socket_id, "svg"))],
+    svg::render(&scene, &images),
+  ).into_response()
+}
+
+#[derive(Deserialize)]
+struct ExportPngPars {
+  /// Pixels per board unit
+  scale: Option<f32>,
+  /// `#rrggbb` or `transparent`, white by default
+  background: Option<String>,
+  /// Region of the board to export in board units, all elements by default
+  x: Option<f32>,
+  y: Option<f32>,
+  width: Option<f32>,
+  height: Option<f32>,
+}
+
+/// Downloads a PNG image of a loaded board or of a region of it
+async fn export_png(Path(socket_id): Path<String>, Query(pars): Query<ExportPngPars>, State(state): State<Arc<Mutex<ServerState>>>) -> Response {
+  let scale = pars.scale.unwrap_or(1.0);
+  if !(scale > 0.0 && scale <= MAX_PNG_SCALE) {
+    return (StatusCode::BAD_REQUEST, format!("Scale must be above 0 and at most {MAX_PNG_SCALE}")).into_response();
+  }
+  let background = match pars.background.as_deref() {
+    None => Some(Color { r: 255, g: 255, b: 255, a: 255 }),
+    Some("transparent") => None,
+    Some(hex) => match Color::from_hex(&format!("#{}", hex.trim_start_matches('#'))) {
+      Some(color) => Some(color),
+      None => return (StatusCode::BAD_REQUEST, "Background must be #rrggbb or transparent").into_response(),
+    },
+  };
+  let region = match (pars.x, pars.y, pars.width, pars.height) {
+    (None, None, None, None) => None,
+    (Some(x), Some(y), Some(width), Some(height))
+      if [x, y, width, height].iter().all(|v| v.is_finite()) && width > 0.0 && height > 0.0 => {
+      Some(Rect { min: Position { x, y }, max: Position { x: x + width, y: y + height } })
+    }
+    _ => return (StatusCode::BAD_REQUEST, "A region needs x, y and a positive width and height").into_response(),
+  };
+  let elements = match visible_elements(&state, &socket_id).await {
+    Ok(elements) => elements,
+    Err(response) => return response,
+  };
+  let scene = Scene::new(&elements, scale, region);
+  let (width, height) = png::size(&scene.bounds, scale);
+  if width as u64 * height as u64 > png::MAX_PIXELS {
+    return (StatusCode::BAD_REQUEST, format!("Image of {width}x{height} pixels is too large, lower the scale or export a region")).into_response();
+  }
+  let images = scene.load_images().await;
+  let Ok(image) = tokio::task::spawn_blocking(move || png::render(&scene, scale, background, &images)).await else {
+    return (StatusCode::INTERNAL_SERVER_ERROR, "Could not render image").into_response();
+  };
+  (
+    [(header::CONTENT_TYPE, "image/png".to_owned()), (header::CONTENT_DISPOSITION, attachment(&socket_id, "png"))],
+    image,
+  ).into_response()
+}
+
+#[derive(Deserialize)]
+struct ExportPdfPars {
+  /// `a4` or `letter`, by default the page has the size of the board
+  paper: Option<String>,
+  #[serde(default)]
+  landscape: bool,
+  /// Empty space at the edges of the paper, in millimetres
+  margin: Option<f32>,
+  /// Print size relative to the size on screen. With a scale the board is tiled over as many
+  /// sheets as it needs, without one it is fit on a single sheet.
+  scale: Option<f32>,
+}
+
+/// Downloads a loaded board as a vector PDF document, optionally printed on sheets of paper
+async fn export_pdf(Path(socket_id): Path<String>, Query(pars): Query<ExportPdfPars>, State(state): State<Arc<Mutex<ServerState>>>) -> Response {
+  let paper = match pars.paper.as_deref() {
+    None => None,
+    Some("a4") => Some(Paper::A4),
+    Some("letter") => Some(Paper::LETTER),
+    Some(_) => return (StatusCode::BAD_REQUEST, "Paper must be a4 or letter").into_response(),
+  };
+  let layout = match (paper, pars.scale) {
+    (None, None) => Layout::Board,
+    (None, Some(_)) => return (StatusCode::BAD_REQUEST, "A scale needs a paper size to tile on").into_response(),
+    (Some(paper), scale) => {
+      let paper = if pars.landscape { paper.landscape() } else { paper };
+      let margin = pars.margin.unwrap_or(DEFAULT_PDF_MARGIN) * pdf::POINTS_PER_MM;
+      if !(margin >= 0.0 && 2.0 * margin < paper.width.min(paper.height)) {
+        return (StatusCode::BAD_REQUEST, "Margin must leave room on the paper").into_response();
+      }
+      match scale {
+        None => Layout::Fit { paper, margin },
+        Some(scale) if scale > 0.0 && scale <= MAX_PDF_SCALE => Layout::Tile { paper, margin, scale: scale * pdf::POINTS_PER_UNIT },
+        Some(_) => return (StatusCode::BAD_REQUEST, format!("Scale must be above 0 and at most {MAX_PDF_SCALE}")).into_response(),
+      }
+    }
+  };
+  let elements = match visible_elements(&state, &socket_id).await {
+    Ok(elements) => elements,
+    Err(response) => return response,
+  };
+  let scene = Scene::new(&elements, PDF_ZOOM, None);
+  let pages = pdf::page_count(layout, &scene.bounds);
+  if pages > pdf::MAX_PAGES {
+    return (StatusCode::BAD_REQUEST, format!("Board takes {pages} pages, lower the scale")).into_response();
+  }
+  let images = scene.load_images().await;
+  let Ok(document) = tokio::task::spawn_blocking(move || pdf::render(&scene, layout, &images)).await else {
+    return (StatusCode::INTERNAL_SERVER_ERROR, "Could not render document").into_response();
+  };
+  (
+    [(header::CONTENT_TYPE, "application/pdf".to_owned()), (header::CONTENT_DISPOSITION, attachment(&socket_id, "pdf"))],
+    document,
+  ).into_response()
+}
+
+/// Lists the versions of a loaded board, oldest first. The history is kept in memory for as long
+/// as the board stays loaded, so it only covers the current session and goes with its contents
+/// once the board unloads.
+async fn history(Path(socket_id): Path<String>, State(state): State<Arc<Mutex<ServerState>>>) -> Response {
+  match inspect_board(&state, &socket_id, Board::versions).await {
+    Ok(versions) => Json(versions).into_response(),
+    Err(response) => response,
+  }
+}
+
+/// Changes of a loaded board that lead to `version`
+async fn history_until(state: &Mutex<ServerState>, socket_id: &str, version: u64) -> Result<Vec<Revision>, Response> {
+  inspect_board(state, socket_id, move |board| board.history_until(version)).await?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Board has no version {version}")).into_response())
+}
+
+/// Contents of a loaded board at an earlier version. The board is rebuilt outside of its socket,
+/// clients keep drawing meanwhile.
+async fn preview_version(Path((socket_id, version)): Path<(String, u64)>, State(state): State<Arc<Mutex<ServerState>>>) -> Response {
+  let revisions = match history_until(&state, &socket_id, version).await {
+    Ok(revisions) => revisions,
+    Err(response) => return response,
+  };
+  let (layers, elements) = Board::replay(&revisions).await.contents();
+  Json(Preview { version, layers, elements }).into_response()
+}
+
+/// Brings a loaded board back to an earlier version, as a new change on top of its history
+async fn restore_version(Path((socket_id, version)): Path<(String, u64)>, State(state): State<Arc<Mutex<ServerState>>>) -> Response {
+  let revisions = match history_until(&state, &socket_id, version).await {
+    Ok(revisions) => revisions,
+    Err(response) => return response,
+  };
+  let (layers, elements) = Board::replay(&revisions).await.contents();
+  let restored = match state.lock().await.endpoints.get(&socket_id) {
+    Some(endpoint) => endpoint.update(move |board| Box::pin(board.restore(version, layers, elements))),
+    None => return (StatusCode::NOT_FOUND, "Board is not loaded").into_response(),
+  };
+  match restored.await {
+    Some(()) => StatusCode::NO_CONTENT.into_response(),
+    None => (StatusCode::NOT_FOUND, "Board is not loaded").into_response(),
+  }
+}
+
+#[derive(Deserialize)]
+struct SnapshotPars {
+  name: String,
+}
+
+/// Saves the current contents of a loaded board as a named snapshot. The contents are read
+/// between two messages of the board, so the snapshot is a state that clients saw.
+async fn take_snapshot(Path(socket_id): Path<String>, Query(SnapshotPars { name }): Query<SnapshotPars>, State(state): State<Arc<Mutex<ServerState>>>) -> Response {
+  if !snapshot_store::valid_name(&name) {
+    return (StatusCode::BAD_REQUEST, "Snapshot names may only hold letters, digits, spaces, dots, dashes and underscores").into_response();
+  }
+  let (layers, elements) = match inspect_board(&state, &socket_id, Board::contents).await {
+    Ok(contents) => contents,
+    Err(response) => return response,
+  };
+  // Images stay in the blob store of this server, which forks of the snapshot share
+  let saved_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+  let file = BoardFile { metadata: Metadata { name: socket_id.clone(), saved_at }, layers, elements, assets: vec![] };
+  match snapshot_store::write_snapshot(&socket_id, &name, &file).await {
+    Ok(true) => StatusCode::CREATED.into_response(),
+    Ok(false) => (StatusCode::CONFLICT, "Board already has a snapshot of that name").into_response(),
+    Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Could not store snapshot").into_response(),
+  }
+}
+
+/// Lists the snapshots of a board, which need not be loaded
+async fn snapshots(Path(socket_id): Path<String>) -> Response {
+  Json(snapshot_store::list_snapshots(&socket_id).await).into_response()
+}
+
+#[derive(Deserialize)]
+struct ForkPars {
+  /// Board to copy
+  from: String,
+  /// Snapshot of `from` to copy, its current contents by default
+  snapshot: Option<String>,
+  /// Name of the new board
+  name: String,
+}
+
+/// Loads a new board with a copy of the contents of another board or of one of its snapshots
+async fn fork(Query(ForkPars { from, snapshot, name }): Query<ForkPars>, State(state_arc): State<Arc<Mutex<ServerState>>>) -> Response {
+  let (layers, elements, origin) = match snapshot {
+    Some(snapshot) => match snapshot_store::read_snapshot(&from, &snapshot).await {
+      Some(file) => (file.layers, file.elements, format!("{from} ({snapshot})")),
+      None => return (StatusCode::NOT_FOUND, "Snapshot does not exist").into_response(),
+    },
+    // Boards only have contents while they are loaded
+    None => match inspect_board(&state_arc, &from, Board::contents).await {
+      Ok((layers, elements)) => (layers, elements, from),
+      Err(response) => return response,
+    },
+  };
+  let mut board = Board::new({
+    let state_arc = state_arc.clone();
+    let name = name.clone();
+    move || delete_board(state_arc, name)
+  }).stay_loaded(FORK_IDLE_TICKS);
+  board.fork(origin, layers, elements).await;
+  let mut state = state_arc.lock().await;
+  if state.endpoints.contains_key(&name) {
+    return (StatusCode::CONFLICT, "Board already exists").into_response();
+  }
+  state.endpoints.insert(name.clone(), SocketEndpoint::new(board));
+  info!("Board forked: {name}");
+  format!("/boards/{name}").into_response()
+}
+
 async fn delete_board(state: Arc<Mutex<ServerState>>, name: String) {
   let client = reqwest::Client::new();
   client.delete(format!("{MAIN_SERVER_URL}/delete_board"))
@@ -32,17 +371,20 @@ struct CreateBoardPars {
   name: String
 }
 
+/// Loads an empty board, or keeps the board already loaded under the name, such as a fork
 async fn create_board(Query(CreateBoardPars { name }): Query<CreateBoardPars>, State(state_arc): State<Arc<Mutex<ServerState>>>) -> Response {
   let mut state = state_arc.lock().await; 
-  let state_arc = state_arc.clone();
-  let name_clone = name.clone();
-  state.endpoints.insert(name.clone(), SocketEndpoint::new(Board::new(move || delete_board(state_arc, name_clone))));
-  info!("Board loaded: {name}");
+  if !state.endpoints.contains_key(&name) {
+    let state_arc = state_arc.clone();
+    let name_clone = name.clone();
+    state.endpoints.insert(name.clone(), SocketEndpoint::new(Board::new(move || delete_board(state_arc, name_clone))));
+    info!("Board loaded: {name}");
+  }
   format!("/boards/{name}").into_response()
 }
 
 struct ServerState {
-  endpoints: HashMap<String, SocketEndpoint>,
+  endpoints: HashMap<String, SocketEndpoint<Board>>,
 }
 
 pub fn board_server() -> Router {
@@ -50,6 +392,15 @@ pub fn board_server() -> Router {
     endpoints: HashMap::new(),
   };
   Router::new().route("/boards/:socket_id", get(ws))
+    .route("/boards/:socket_id/export.svg", get(export_svg))
+    .route("/boards/:socket_id/export.png", get(export_png))
+    .route("/boards/:socket_id/export.pdf", get(export_pdf))
+    .route("/boards/:socket_id/file", get(download_file).put(upload_file).layer(DefaultBodyLimit::max(board_file::MAX_FILE_SIZE)))
+    .route("/boards/:socket_id/history", get(history))
+    .route("/boards/:socket_id/history/:version", get(preview_version))
+    .route("/boards/:socket_id/history/:version/restore", post(restore_version))
+    .route("/boards/:socket_id/snapshots", get(snapshots).post(take_snapshot))
+    .route("/fork", post(fork))
     .route("/create_board", post(create_board))
     .with_state(Arc::new(Mutex::new(state)))
 }
\ No newline at end of file
@@ -0,0 +1,245 @@
+//! The font of exports: glyph outlines for exports that draw text themselves, and subsets of the
+//! font for documents that embed it
+
+use std::collections::BTreeSet;
+
+use ttf_parser::{Face, OutlineBuilder, RawFace, Tag};
+
+use super::raster::Point;
+
+/// DejaVu Sans, built in so that exports have text wherever the server runs. The license is next
+/// to the font file.
+const FONT: &[u8] = include_bytes!("../../fonts/DejaVuSans.ttf");
+/// Tables a viewer needs to draw the glyphs of an embedded TrueType font. Documents pick glyphs by
+/// id, so the character map and the names are left out.
+const SUBSET_TABLES: [&[u8; 4]; 9] = [b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"prep"];
+/// Line segments each curve of a glyph is flattened into
+const CURVE_SEGMENTS: usize = 8;
+
+/// Collects the contours of glyphs as polygons, in pixels with y down
+struct Outline {
+  polygons: Vec<Vec<Point>>,
+  /// Pixels per font unit
+  scale: f32,
+  /// Position of the glyph origin
+  origin: Point,
+}
+
+impl Outline {
+  fn point(&self, x: f32, y: f32) -> Point {
+    (self.origin.0 + x * self.scale, self.origin.1 - y * self.scale)
+  }
+
+  fn last(&self) -> Point {
+    self.polygons.last().and_then(|p| p.last()).copied().unwrap_or(self.origin)
+  }
+
+  fn push(&mut self, point: Point) {
+    if let Some(polygon) = self.polygons.last_mut() {
+      polygon.push(point);
+    }
+  }
+}
+
+impl OutlineBuilder for Outline {
+  fn move_to(&mut self, x: f32, y: f32) {
+    let point = self.point(x, y);
+    self.polygons.push(vec![point]);
+  }
+
+  fn line_to(&mut self, x: f32, y: f32) {
+    let point = self.point(x, y);
+    self.push(point);
+  }
+
+  fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+    let (start, control, end) = (self.last(), self.point(x1, y1), self.point(x, y));
+    for i in 1..=CURVE_SEGMENTS {
+      let t = i as f32 / CURVE_SEGMENTS as f32;
+      let s = 1.0 - t;
+      let at = |a: f32, b: f32, c: f32| s * s * a + 2.0 * s * t * b + t * t * c;
+      self.push((at(start.0, control.0, end.0), at(start.1, control.1, end.1)));
+    }
+  }
+
+  fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+    let (start, first, second, end) = (self.last(), self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+    for i in 1..=CURVE_SEGMENTS {
+      let t = i as f32 / CURVE_SEGMENTS as f32;
+      let s = 1.0 - t;
+      let at = |a: f32, b: f32, c: f32, d: f32| s * s * s * a + 3.0 * s * s * t * b + 3.0 * s * t * t * c + t * t * t * d;
+      self.push((at(start.0, first.0, second.0, end.0), at(start.1, first.1, second.1, end.1)));
+    }
+  }
+
+  fn close(&mut self) {}
+}
+
+pub fn face() -> Face<'static> {
+  Face::parse(FONT, 0).expect("The built in font is valid")
+}
+
+/// Outline of `text` set in one line at `size` pixels, starting at the origin on the baseline.
+/// Characters the font lacks are skipped.
+pub fn text_outline(text: &str, size: f32) -> Vec<Vec<Point>> {
+  let face = face();
+  let mut outline = Outline { polygons: vec![], scale: size / face.units_per_em() as f32, origin: (0.0, 0.0) };
+  for c in text.chars() {
+    let Some(glyph) = face.glyph_index(c) else { continue; };
+    face.outline_glyph(glyph, &mut outline);
+    let advance = face.glyph_hor_advance(glyph).unwrap_or(0) as f32 * outline.scale;
+    outline.origin.0 += advance;
+  }
+  outline.polygons
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+  Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+  Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Sum of the big endian words of a table, as the table directory records it
+fn checksum(data: &[u8]) -> u32 {
+  data.chunks(4).fold(0, |sum: u32, chunk| {
+    let mut word = [0; 4];
+    word[..chunk.len()].copy_from_slice(chunk);
+    sum.wrapping_add(u32::from_be_bytes(word))
+  })
+}
+
+/// Glyphs a composite glyph is made of, none for a simple glyph
+fn components(glyph: &[u8]) -> Vec<u16> {
+  const ARGS_ARE_WORDS: u16 = 0x0001;
+  const HAS_SCALE: u16 = 0x0008;
+  const MORE_COMPONENTS: u16 = 0x0020;
+  const HAS_XY_SCALE: u16 = 0x0040;
+  const HAS_2X2: u16 = 0x0080;
+  if u16_at(glyph, 0).is_none_or(|contours| (contours as i16) >= 0) {
+    return vec![];
+  }
+  let mut components = vec![];
+  // Components follow the contour count and the bounding box
+  let mut at = 10;
+  while let (Some(flags), Some(component)) = (u16_at(glyph, at), u16_at(glyph, at + 2)) {
+    components.push(component);
+    at += 4 + if flags & ARGS_ARE_WORDS != 0 { 4 } else { 2 };
+    at += match () {
+      _ if flags & HAS_SCALE != 0 => 2,
+      _ if flags & HAS_XY_SCALE != 0 => 4,
+      _ if flags & HAS_2X2 != 0 => 8,
+      _ => 0,
+    };
+    if flags & MORE_COMPONENTS == 0 {
+      break;
+    }
+  }
+  components
+}
+
+/// TrueType font file of the built in font with only `glyphs`, the glyphs they are composed of and
+/// the `.notdef` glyph. The other glyphs are left empty rather than removed, so glyph ids stay the
+/// same as in the whole font.
+pub fn subset(glyphs: &BTreeSet<u16>) -> Vec<u8> {
+  let raw = RawFace::parse(FONT, 0).expect("The built in font is valid");
+  let table = |tag: &[u8; 4]| raw.table(Tag::from_bytes(tag));
+  let (head, maxp, loca, glyf) = match (table(b"head"), table(b"maxp"), table(b"loca"), table(b"glyf")) {
+    (Some(head), Some(maxp), Some(loca), Some(glyf)) => (head, maxp, loca, glyf),
+    _ => panic!("The built in font has TrueType outlines"),
+  };
+  let long_offsets = u16_at(head, 50) == Some(1);
+  let count = u16_at(maxp, 4).unwrap_or(0);
+  let offset = |glyph: usize| match long_offsets {
+    true => u32_at(loca, glyph * 4).unwrap_or(0) as usize,
+    false => u16_at(loca, glyph * 2).unwrap_or(0) as usize * 2,
+  };
+  let data = |glyph: u16| glyf.get(offset(glyph as usize)..offset(glyph as usize + 1)).unwrap_or(&[]);
+
+  let mut kept = BTreeSet::new();
+  let mut queue = glyphs.iter().copied().chain([0]).collect::<Vec<_>>();
+  while let Some(glyph) = queue.pop() {
+    if glyph < count && kept.insert(glyph) {
+      queue.extend(components(data(glyph)));
+    }
+  }
+  let (mut new_glyf, mut new_loca) = (vec![], vec![]);
+  for glyph in 0..count {
+    new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+    if kept.contains(&glyph) {
+      new_glyf.extend(data(glyph));
+      new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+    }
+  }
+  new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+  let mut new_head = head.to_vec();
+  // The adjustment is worked out once the file is complete, and the new locations are all long
+  new_head[8..12].fill(0);
+  new_head[50..52].copy_from_slice(&1_u16.to_be_bytes());
+
+  let tables = SUBSET_TABLES.iter().filter_map(|tag| {
+    let data = match *tag {
+      b"glyf" => new_glyf.as_slice(),
+      b"head" => new_head.as_slice(),
+      b"loca" => new_loca.as_slice(),
+      _ => table(tag)?,
+    };
+    Some((*tag, data))
+  }).collect::<Vec<_>>();
+  let count = tables.len() as u16;
+  let selector = 15 - count.leading_zeros() as u16;
+  let range = 16 << selector;
+  let mut file = vec![];
+  for value in [0x0001, 0x0000, count, range, selector, count * 16 - range] {
+    file.extend(value.to_be_bytes());
+  }
+  let mut offset = 12 + 16 * tables.len();
+  let mut head_offset = 0;
+  for (tag, data) in &tables {
+    file.extend(*tag);
+    file.extend(checksum(data).to_be_bytes());
+    file.extend((offset as u32).to_be_bytes());
+    file.extend((data.len() as u32).to_be_bytes());
+    if *tag == b"head" {
+      head_offset = offset;
+    }
+    offset += data.len().next_multiple_of(4);
+  }
+  for (_, data) in &tables {
+    file.extend(*data);
+    file.resize(file.len().next_multiple_of(4), 0);
+  }
+  let adjustment = 0xB1B0_AFBA_u32.wrapping_sub(checksum(&file));
+  file[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+  file
+}
+
+#[cfg(test)]
+mod tests {
+  use ttf_parser::GlyphId;
+
+  use super::*;
+
+  fn polygons(face: &Face, glyph: u16) -> Vec<Vec<Point>> {
+    let mut outline = Outline { polygons: vec![], scale: 1.0, origin: (0.0, 0.0) };
+    face.outline_glyph(GlyphId(glyph), &mut outline);
+    outline.polygons
+  }
+
+  #[test]
+  fn subsets_keep_the_glyphs_asked_for_and_their_components() {
+    let face = face();
+    let glyph = |c| face.glyph_index(c).unwrap().0;
+    // Dotted z is made of the glyphs of z and of the dot in the built in font
+    let (dotted, other) = (glyph('ż'), glyph('Q'));
+    let font = subset(&BTreeSet::from([dotted]));
+    assert!(font.len() < FONT.len() / 10);
+    assert_eq!(checksum(&font), 0xB1B0_AFBA);
+    let subset = Face::parse(&font, 0).unwrap();
+    assert_eq!(subset.number_of_glyphs(), face.number_of_glyphs());
+    assert!(polygons(&face, dotted).len() >= 2);
+    assert_eq!(polygons(&subset, dotted), polygons(&face, dotted));
+    assert!(polygons(&subset, other).is_empty());
+  }
+}
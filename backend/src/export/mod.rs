@@ -0,0 +1,248 @@
+//! Conversion of board elements into flat shapes that file formats can draw
+
+mod font;
+pub mod pdf;
+pub mod png;
+mod raster;
+pub mod svg;
+
+use std::collections::HashMap;
+
+use common::{entities::{BlobId, Color, Element, Position, Text}, line_drawing::{display_strip, strip_triangles}, spatial::Rect};
+
+use crate::blob_store::{read_blob, Blob};
+
+/// Empty space around the elements of an exported board, in board units
+const MARGIN: f32 = 20.0;
+/// Inner spacing of sticky notes, matches `.sticky-text` in board.css
+const STICKY_PADDING: f32 = 12.0;
+const STICKY_FONT_SIZE: f32 = 18.0;
+const STICKY_LINE_HEIGHT: f32 = 1.3;
+/// Height of capital letters above the baseline relative to the font size, used to place the
+/// first baseline of a text box
+const ASCENT: f32 = 0.8;
+/// Average glyph width relative to the font size, used to wrap the text of sticky notes
+const CHAR_WIDTH: f32 = 0.55;
+
+/// Coordinate with at most two decimals, which is well below a pixel at any sensible zoom
+fn number(value: f32) -> String {
+  let text = format!("{value:.2}");
+  let text = text.trim_end_matches('0').trim_end_matches('.');
+  if text == "-0" { "0".to_owned() } else { text.to_owned() }
+}
+
+pub enum Shape {
+  /// Filled triangles of a stroke, all wound the same way so that overlapping ones can be filled
+  /// as one path with the nonzero rule without covering anything twice
+  Triangles { triangles: Vec<[Position; 3]>, color: Color },
+  /// Filled rectangle rotated clockwise by `rotation` radians around its top left corner
+  Rect { position: Position, width: f32, height: f32, rotation: f32, color: Color },
+  /// Lines of text in a box whose top left corner is `position`, rotated like `Rect`
+  Text { position: Position, rotation: f32, lines: Vec<String>, font_size: f32, line_height: f32, color: Color },
+  /// Stored image stretched over a rectangle rotated like `Rect`
+  Image { position: Position, width: f32, height: f32, rotation: f32, blob: BlobId },
+}
+
+impl Shape {
+  /// Offsets of the baselines of the lines of a `Text` below the top of its box, empty for other
+  /// shapes
+  pub fn baselines(&self) -> Vec<f32> {
+    let Shape::Text { lines, font_size, line_height, .. } = self else { return vec![]; };
+    // Line boxes are centred on the glyphs, as in CSS
+    let first = (line_height - 1.0) / 2.0 * font_size + ASCENT * font_size;
+    (0..lines.len()).map(|i| first + i as f32 * line_height * font_size).collect()
+  }
+}
+
+/// Elements of a board as shapes from bottom to top, with the area they cover
+pub struct Scene {
+  pub bounds: Rect,
+  pub shapes: Vec<Shape>,
+}
+
+impl Scene {
+  /// Shapes of `elements`, given from bottom to top. Strokes are tessellated as they would be
+  /// displayed at `zoom`, exports that get enlarged need a higher zoom to keep round parts
+  /// smooth. `region` limits the scene to the elements it touches, by default the scene covers
+  /// all elements with a margin.
+  pub fn new<'a>(elements: impl IntoIterator<Item = &'a Element>, zoom: f32, region: Option<Rect>) -> Scene {
+    let elements = elements.into_iter()
+      .filter(|element| match (region, element.bounds()) {
+        (Some(region), Some(bounds)) => region.intersects(&bounds),
+        (None, _) => true,
+        (Some(_), None) => false,
+      })
+      .collect::<Vec<_>>();
+    let bounds = region.unwrap_or_else(|| {
+      elements.iter()
+        .filter_map(|element| element.bounds())
+        .reduce(|a, b| a.union(&b))
+        .unwrap_or(Rect::point(Position { x: 0.0, y: 0.0 }))
+        .expand(MARGIN)
+    });
+    let mut shapes = vec![];
+    for element in elements {
+      shapes.extend(element_shapes(element, zoom));
+    }
+    Scene { bounds, shapes }
+  }
+
+  /// Data of the images in the scene that are still stored
+  pub async fn load_images(&self) -> HashMap<BlobId, Blob> {
+    let mut images = HashMap::new();
+    for shape in &self.shapes {
+      let Shape::Image { blob, .. } = shape else { continue; };
+      if images.contains_key(blob) {
+        continue;
+      }
+      if let Some(data) = read_blob(blob).await {
+        images.insert(blob.clone(), data);
+      }
+    }
+    images
+  }
+}
+
+fn element_shapes(element: &Element, zoom: f32) -> Vec<Shape> {
+  match element {
+    Element::Stroke(stroke) => {
+      let strip = display_strip(stroke, zoom);
+      let triangles = strip_triangles(&strip)
+        .map(|t| t.map(|p| Position { x: p.x as f32, y: p.y as f32 }))
+        .collect::<Vec<_>>();
+      if triangles.is_empty() {
+        return vec![];
+      }
+      vec![Shape::Triangles { triangles, color: stroke.color }]
+    }
+    Element::Text(Text { position, content, font_size, color, rotation }) => vec![Shape::Text {
+      position: *position,
+      rotation: *rotation,
+      lines: content.split('\n').map(str::to_owned).collect(),
+      font_size: *font_size,
+      line_height: Text::LINE_HEIGHT,
+      color: *color,
+    }],
+    Element::Sticky(sticky) => {
+      let (sin, cos) = sticky.rotation.sin_cos();
+      let text_position = Position {
+        x: sticky.position.x + (cos - sin) * STICKY_PADDING,
+        y: sticky.position.y + (sin + cos) * STICKY_PADDING,
+      };
+      let columns = ((sticky.width - 2.0 * STICKY_PADDING) / (STICKY_FONT_SIZE * CHAR_WIDTH)).floor().max(1.0) as usize;
+      vec![
+        Shape::Rect {
+          position: sticky.position,
+          width: sticky.width,
+          height: sticky.height,
+          rotation: sticky.rotation,
+          color: sticky.color,
+        },
+        Shape::Text {
+          position: text_position,
+          rotation: sticky.rotation,
+          lines: wrap(&sticky.text, columns),
+          font_size: STICKY_FONT_SIZE,
+          line_height: STICKY_LINE_HEIGHT,
+          color: Color::BLACK,
+        },
+      ]
+    }
+    Element::Image(image) => vec![Shape::Image {
+      position: image.position,
+      width: image.width * image.scale,
+      height: image.height * image.scale,
+      rotation: image.rotation,
+      blob: image.blob.clone(),
+    }],
+  }
+}
+
+/// Breaks `text` into lines of at most `columns` characters, between words where possible, like
+/// `white-space: pre-wrap` with `overflow-wrap: break-word`
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+  let mut lines = vec![];
+  let mut finish = |line: &mut String| lines.push(std::mem::take(line).trim_end().to_owned());
+  for paragraph in text.split('\n') {
+    let mut line = String::new();
+    let mut length = 0;
+    for word in paragraph.split_inclusive(' ') {
+      let word_length = word.trim_end().chars().count();
+      if length > 0 && length + word_length > columns {
+        finish(&mut line);
+        length = 0;
+      }
+      let mut chars = word.chars().peekable();
+      while chars.peek().is_some() {
+        if length == columns && chars.peek() != Some(&' ') {
+          finish(&mut line);
+          length = 0;
+        }
+        line.push(chars.next().unwrap());
+        length += 1;
+      }
+    }
+    finish(&mut line);
+  }
+  lines
+}
+
+#[cfg(test)]
+mod tests {
+  use common::entities::{Stroke, StrokeStyle};
+
+  use super::*;
+
+  fn stroke(points: &[(f32, f32)]) -> Element {
+    Element::Stroke(Stroke {
+      points: points.iter().map(|(x, y)| Position { x: *x, y: *y }).collect(),
+      width: 4.0,
+      color: Color::BLACK,
+      pressure: vec![],
+      style: StrokeStyle::default(),
+    })
+  }
+
+  #[test]
+  fn wraps_between_words() {
+    assert_eq!(wrap("one two three", 8), vec!["one two", "three"]);
+    assert_eq!(wrap("a\n\nb", 8), vec!["a", "", "b"]);
+  }
+
+  #[test]
+  fn breaks_long_words() {
+    assert_eq!(wrap("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+    assert_eq!(wrap("ab abcdefgh", 4), vec!["ab", "abcd", "efgh"]);
+  }
+
+  #[test]
+  fn region_keeps_touching_elements() {
+    let elements = [stroke(&[(0.0, 0.0), (10.0, 0.0)]), stroke(&[(100.0, 100.0), (110.0, 100.0)])];
+    let region = Rect { min: Position { x: -5.0, y: -5.0 }, max: Position { x: 5.0, y: 5.0 } };
+    let scene = Scene::new(&elements, 1.0, Some(region));
+    assert_eq!(scene.shapes.len(), 1);
+    assert_eq!(scene.bounds, region);
+  }
+
+  #[test]
+  fn bounds_cover_elements_with_margin() {
+    let scene = Scene::new(&[stroke(&[(0.0, 0.0), (10.0, 0.0)])], 1.0, None);
+    let reach = 2.0 * std::f32::consts::SQRT_2 + MARGIN;
+    let expected = [-reach, -reach, 10.0 + reach, reach];
+    let actual = [scene.bounds.min.x, scene.bounds.min.y, scene.bounds.max.x, scene.bounds.max.y];
+    for (a, b) in actual.iter().zip(expected) {
+      assert!((a - b).abs() < 1e-4);
+    }
+  }
+
+  #[test]
+  fn triangles_share_a_winding() {
+    let scene = Scene::new(&[stroke(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 5.0)])], 1.0, None);
+    let Shape::Triangles { triangles, .. } = &scene.shapes[0] else { panic!("stroke is not triangles") };
+    assert!(!triangles.is_empty());
+    for [a, b, c] in triangles {
+      // Slivers can come out flat or flipped once rounded to single precision
+      assert!((b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x) > -1e-4);
+    }
+  }
+}
@@ -0,0 +1,533 @@
+//! Vector PDF documents of scenes, on a single page or tiled over sheets of paper
+//!
+//! The scene is drawn once into a form XObject in board coordinates. Every page places that form
+//! with its own transform and clips it to the printable area, so tiling does not repeat the
+//! drawing on each page.
+
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, fmt::Write as _, io::Write};
+
+use common::{entities::{BlobId, Color, Position}, spatial::Rect};
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::blob_store::Blob;
+
+use super::{font, number, Scene, Shape};
+
+/// Points per board unit of pages sized to the board, board units are CSS pixels
+pub const POINTS_PER_UNIT: f32 = 0.75;
+pub const POINTS_PER_MM: f32 = 72.0 / 25.4;
+/// Most pages of a tiled document
+pub const MAX_PAGES: usize = 200;
+
+/// Object ids of the parts every document has
+const CATALOG: usize = 1;
+const PAGES: usize = 2;
+const FONT: usize = 3;
+const BOARD: usize = 4;
+
+/// Sheet size in points, portrait
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Paper {
+  pub width: f32,
+  pub height: f32,
+}
+
+impl Paper {
+  pub const A4: Paper = Paper { width: 595.28, height: 841.89 };
+  pub const LETTER: Paper = Paper { width: 612.0, height: 792.0 };
+
+  pub fn landscape(self) -> Paper {
+    Paper { width: self.height, height: self.width }
+  }
+}
+
+/// How the scene is laid out on pages
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+  /// One page of the size of the scene, at `POINTS_PER_UNIT`
+  Board,
+  /// The whole scene scaled to fit one sheet, inside a margin in points
+  Fit { paper: Paper, margin: f32 },
+  /// The scene at `scale` points per board unit, split over as many sheets as it takes, row by
+  /// row from the top left
+  Tile { paper: Paper, margin: f32, scale: f32 },
+}
+
+/// Part of the scene shown on a page
+#[derive(Debug, PartialEq)]
+struct Page {
+  width: f32,
+  height: f32,
+  /// Points per board unit
+  scale: f32,
+  /// Board position shown at the top left corner of `area`
+  origin: Position,
+  /// Part of the page the scene is drawn in, as left, bottom, width and height in points
+  area: [f32; 4],
+}
+
+/// Number of pages `layout` takes for a scene covering `bounds`
+pub fn page_count(layout: Layout, bounds: &Rect) -> usize {
+  match layout {
+    Layout::Tile { paper, margin, scale } => {
+      let (columns, rows) = tiles(paper, margin, scale, bounds);
+      columns.saturating_mul(rows)
+    }
+    _ => 1,
+  }
+}
+
+/// Columns and rows of sheets needed to tile `bounds`
+fn tiles(paper: Paper, margin: f32, scale: f32, bounds: &Rect) -> (usize, usize) {
+  let (width, height) = (paper.width - 2.0 * margin, paper.height - 2.0 * margin);
+  let count = |length: f32, available: f32| ((length * scale / available).ceil().max(1.0)).min(u32::MAX as f32) as usize;
+  (count(bounds.width(), width), count(bounds.height(), height))
+}
+
+fn pages(layout: Layout, bounds: &Rect) -> Vec<Page> {
+  let (board_width, board_height) = (bounds.width().max(1.0), bounds.height().max(1.0));
+  match layout {
+    Layout::Board => {
+      let (width, height) = (board_width * POINTS_PER_UNIT, board_height * POINTS_PER_UNIT);
+      vec![Page { width, height, scale: POINTS_PER_UNIT, origin: bounds.min, area: [0.0, 0.0, width, height] }]
+    }
+    Layout::Fit { paper, margin } => {
+      let (width, height) = (paper.width - 2.0 * margin, paper.height - 2.0 * margin);
+      let scale = (width / board_width).min(height / board_height);
+      let (used_width, used_height) = (board_width * scale, board_height * scale);
+      // Centred on the sheet
+      let area = [(paper.width - used_width) / 2.0, (paper.height - used_height) / 2.0, used_width, used_height];
+      vec![Page { width: paper.width, height: paper.height, scale, origin: bounds.min, area }]
+    }
+    Layout::Tile { paper, margin, scale } => {
+      let (width, height) = (paper.width - 2.0 * margin, paper.height - 2.0 * margin);
+      let (columns, rows) = tiles(paper, margin, scale, bounds);
+      let mut pages = vec![];
+      for row in 0..rows {
+        for column in 0..columns {
+          let origin = Position {
+            x: bounds.min.x + column as f32 * width / scale,
+            y: bounds.min.y + row as f32 * height / scale,
+          };
+          pages.push(Page { width: paper.width, height: paper.height, scale, origin, area: [margin, margin, width, height] });
+        }
+      }
+      pages
+    }
+  }
+}
+
+/// Factor of a colour or transform with four decimals, coordinates get by with fewer
+fn ratio(value: f32) -> String {
+  let text = format!("{value:.4}");
+  let text = text.trim_end_matches('0').trim_end_matches('.');
+  if text == "-0" { "0".to_owned() } else { text.to_owned() }
+}
+
+/// Glyphs of the built in font a document shows, with the character each stands for
+type Glyphs = BTreeMap<u16, char>;
+
+/// PDF hex string of the glyphs of `text` in the built in font, two bytes per glyph as Identity-H
+/// encodes them. Characters the font lacks are skipped, the others are added to `glyphs`.
+fn string(text: &str, face: &ttf_parser::Face, glyphs: &mut Glyphs) -> Vec<u8> {
+  let mut string = "<".to_owned();
+  for c in text.chars() {
+    let Some(glyph) = face.glyph_index(c) else { continue; };
+    glyphs.entry(glyph.0).or_insert(c);
+    let _ = write!(string, "{:04X}", glyph.0);
+  }
+  string.push('>');
+  string.into_bytes()
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+  let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+  encoder.write_all(data).and_then(|_| encoder.finish()).expect("Compressing in memory cannot fail")
+}
+
+/// Operators setting the fill colour, with the name of the graphics state for its alpha
+fn fill(color: Color, alphas: &mut BTreeSet<u8>) -> String {
+  let channel = |c: u8| ratio(c as f32 / 255.0);
+  let mut operators = format!("{} {} {} rg", channel(color.r), channel(color.g), channel(color.b));
+  if color.a < 255 {
+    alphas.insert(color.a);
+    let _ = write!(operators, " /A{} gs", color.a);
+  }
+  operators
+}
+
+/// Operator moving the origin to `position` and rotating clockwise by `rotation` radians, in the
+/// y down coordinates of the board
+fn placement(position: Position, rotation: f32) -> String {
+  let (sin, cos) = rotation.sin_cos();
+  format!("{} {} {} {} {} {} cm", ratio(cos), ratio(sin), ratio(-sin), ratio(cos), number(position.x), number(position.y))
+}
+
+/// Decoded image as PDF image XObjects: the colour, and the alpha as a soft mask when there is
+/// any transparency
+struct PdfImage {
+  width: u32,
+  height: u32,
+  rgb: Vec<u8>,
+  alpha: Option<Vec<u8>>,
+}
+
+fn decode(blob: &Blob) -> Option<PdfImage> {
+  let image = image::load_from_memory(&blob.data).ok()?.to_rgba8();
+  let rgb = image.pixels().flat_map(|p| [p.0[0], p.0[1], p.0[2]]).collect();
+  let alpha = image.pixels().map(|p| p.0[3]).collect::<Vec<_>>();
+  let opaque = alpha.iter().all(|a| *a == 255);
+  Some(PdfImage { width: image.width(), height: image.height(), rgb, alpha: (!opaque).then_some(alpha) })
+}
+
+/// Content stream of the form drawing the scene, in board coordinates
+fn board_content(scene: &Scene, image_names: &HashMap<&BlobId, usize>, alphas: &mut BTreeSet<u8>, glyphs: &mut Glyphs) -> Vec<u8> {
+  let face = font::face();
+  let mut content = vec![];
+  for shape in &scene.shapes {
+    match shape {
+      Shape::Triangles { triangles, color } => {
+        let _ = writeln!(content, "q {}", fill(*color, alphas));
+        for [a, b, c] in triangles {
+          let _ = writeln!(
+            content, "{} {} m {} {} l {} {} l h",
+            number(a.x), number(a.y), number(b.x), number(b.y), number(c.x), number(c.y),
+          );
+        }
+        // A single fill with the nonzero rule, so overlapping triangles are covered once
+        content.extend(b"f Q\n");
+      }
+      Shape::Rect { position, width, height, rotation, color } => {
+        let _ = writeln!(
+          content, "q {} {} 0 0 {} {} re f Q",
+          placement(*position, *rotation), fill(*color, alphas), number(*width), number(*height),
+        );
+      }
+      Shape::Text { position, rotation, lines, font_size, color, .. } => {
+        let _ = writeln!(content, "q {} {} BT /F1 {} Tf", placement(*position, *rotation), fill(*color, alphas), number(*font_size));
+        for (line, baseline) in lines.iter().zip(shape.baselines()) {
+          // Text space is y up, flip it back to upright glyphs in the y down board space
+          let _ = write!(content, "1 0 0 -1 0 {} Tm ", number(baseline));
+          content.extend(string(line, &face, glyphs));
+          content.extend(b" Tj\n");
+        }
+        content.extend(b"ET Q\n");
+      }
+      Shape::Image { position, width, height, rotation, blob } => {
+        let Some(index) = image_names.get(blob) else { continue; };
+        // Image space is y up, its first row goes to the top of the box
+        let _ = writeln!(
+          content, "q {} {} 0 0 {} 0 {} cm /Im{index} Do Q",
+          placement(*position, *rotation), number(*width), number(-height), number(*height),
+        );
+      }
+    }
+  }
+  content
+}
+
+/// CMap that maps the glyphs of a document back to the characters they show, so that its text can
+/// be searched and copied
+fn to_unicode(glyphs: &Glyphs) -> String {
+  let mut cmap = "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+    /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+    /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+    1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n".to_owned();
+  let glyphs = glyphs.iter().collect::<Vec<_>>();
+  // A section maps at most 100 codes
+  for chunk in glyphs.chunks(100) {
+    let _ = writeln!(cmap, "{} beginbfchar", chunk.len());
+    for (glyph, c) in chunk {
+      let utf16 = c.encode_utf16(&mut [0; 2]).iter().map(|unit| format!("{unit:04X}")).collect::<String>();
+      let _ = writeln!(cmap, "<{glyph:04X}> <{utf16}>");
+    }
+    cmap.push_str("endbfchar\n");
+  }
+  cmap.push_str("endcmap\nCMapName currentdict /defineresource pop\nend\nend\n");
+  cmap
+}
+
+/// Writes the built in font with only `glyphs` as the composite font `FONT`, and returns the next
+/// free object id
+fn write_font(writer: &mut Writer, glyphs: &Glyphs, next_id: usize) -> usize {
+  let (cid_font, descriptor, file, cmap) = (next_id, next_id + 1, next_id + 2, next_id + 3);
+  let face = font::face();
+  let per_unit = 1000.0 / face.units_per_em() as f32;
+  let scaled = |value: i16| (value as f32 * per_unit).round();
+  // Subsets are named with a tag of six capitals that differs between subsets
+  let hash = glyphs.keys().fold(0x811C_9DC5_u32, |hash, glyph| (hash ^ *glyph as u32).wrapping_mul(0x0100_0193));
+  let tag = (0..6).map(|i| (b'A' + (hash >> (5 * i)) as u8 % 26) as char).collect::<String>();
+  let name = format!("{tag}+DejaVuSans");
+  let widths = glyphs.keys()
+    .map(|glyph| format!("{glyph} [{}]", (face.glyph_hor_advance(ttf_parser::GlyphId(*glyph)).unwrap_or(0) as f32 * per_unit).round()))
+    .collect::<Vec<_>>()
+    .join(" ");
+  let bounds = face.global_bounding_box();
+
+  writer.object(FONT, &format!(
+    "<< /Type /Font /Subtype /Type0 /BaseFont /{name} /Encoding /Identity-H /DescendantFonts [{cid_font} 0 R] /ToUnicode {cmap} 0 R >>",
+  ));
+  writer.object(cid_font, &format!(
+    "<< /Type /Font /Subtype /CIDFontType2 /BaseFont /{name} /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> /FontDescriptor {descriptor} 0 R /W [{widths}] /CIDToGIDMap /Identity >>",
+  ));
+  writer.object(descriptor, &format!(
+    "<< /Type /FontDescriptor /FontName /{name} /Flags 32 /FontBBox [{} {} {} {}] /ItalicAngle 0 /Ascent {} /Descent {} /CapHeight {} /StemV 80 /FontFile2 {file} 0 R >>",
+    scaled(bounds.x_min), scaled(bounds.y_min), scaled(bounds.x_max), scaled(bounds.y_max),
+    scaled(face.ascender()), scaled(face.descender()), scaled(face.capital_height().unwrap_or(face.ascender())),
+  ));
+  let subset = font::subset(&glyphs.keys().copied().collect());
+  writer.stream(file, &format!("/Length1 {}", subset.len()), &subset);
+  writer.stream(cmap, "", to_unicode(glyphs).as_bytes());
+  next_id + 4
+}
+
+/// Serialises numbered objects and the cross-reference table that locates them
+struct Writer {
+  output: Vec<u8>,
+  offsets: Vec<usize>,
+}
+
+impl Writer {
+  fn new() -> Writer {
+    // The binary comment marks the file as binary for transfer programs
+    Writer { output: b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec(), offsets: vec![] }
+  }
+
+  fn object(&mut self, id: usize, dictionary: &str) {
+    self.begin(id);
+    self.output.extend(dictionary.as_bytes());
+    self.output.extend(b"\nendobj\n");
+  }
+
+  fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) {
+    let data = compress(data);
+    self.begin(id);
+    let _ = write!(self.output, "<< {dictionary} /Filter /FlateDecode /Length {} >>\nstream\n", data.len());
+    self.output.extend(data);
+    self.output.extend(b"\nendstream\nendobj\n");
+  }
+
+  fn begin(&mut self, id: usize) {
+    if self.offsets.len() < id {
+      self.offsets.resize(id, 0);
+    }
+    self.offsets[id - 1] = self.output.len();
+    let _ = writeln!(self.output, "{id} 0 obj");
+  }
+
+  fn finish(mut self) -> Vec<u8> {
+    let xref = self.output.len();
+    let _ = writeln!(self.output, "xref\n0 {}\n0000000000 65535 f ", self.offsets.len() + 1);
+    for offset in &self.offsets {
+      let _ = writeln!(self.output, "{offset:010} 00000 n ");
+    }
+    let _ = write!(
+      self.output, "trailer\n<< /Size {} /Root {CATALOG} 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+      self.offsets.len() + 1,
+    );
+    self.output
+  }
+}
+
+/// PDF document of `scene` laid out by `layout`. Images are embedded from `images`, the ones
+/// missing there are left out. Text is set in the built in font, embedded with only the glyphs
+/// the scene uses.
+pub fn render(scene: &Scene, layout: Layout, images: &HashMap<BlobId, Blob>) -> Vec<u8> {
+  let mut writer = Writer::new();
+  let mut next_id = BOARD + 1;
+
+  let mut image_names = HashMap::new();
+  let mut image_objects = vec![];
+  for shape in &scene.shapes {
+    let Shape::Image { blob, .. } = shape else { continue; };
+    if image_names.contains_key(blob) {
+      continue;
+    }
+    let Some(image) = images.get(blob).and_then(decode) else { continue; };
+    let index = image_objects.len();
+    image_names.insert(blob, index);
+    let mask = image.alpha.as_ref().map(|alpha| {
+      let id = next_id;
+      let dictionary = format!("/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray /BitsPerComponent 8", image.width, image.height);
+      writer.stream(id, &dictionary, alpha);
+      next_id += 1;
+      id
+    });
+    let mut dictionary = format!("/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8", image.width, image.height);
+    if let Some(mask) = mask {
+      let _ = write!(dictionary, " /SMask {mask} 0 R");
+    }
+    writer.stream(next_id, &dictionary, &image.rgb);
+    image_objects.push(next_id);
+    next_id += 1;
+  }
+
+  let mut alphas = BTreeSet::new();
+  let mut glyphs = Glyphs::new();
+  let content = board_content(scene, &image_names, &mut alphas, &mut glyphs);
+  let states = alphas.iter()
+    .map(|a| format!("/A{a} << /ca {} >>", ratio(*a as f32 / 255.0)))
+    .collect::<Vec<_>>()
+    .join(" ");
+  let xobjects = image_objects.iter().enumerate()
+    .map(|(index, id)| format!("/Im{index} {id} 0 R"))
+    .collect::<Vec<_>>()
+    .join(" ");
+  let bounds = scene.bounds;
+  writer.stream(BOARD, &format!(
+    "/Type /XObject /Subtype /Form /BBox [{} {} {} {}] /Resources << /Font << /F1 {FONT} 0 R >> /ExtGState << {states} >> /XObject << {xobjects} >> >>",
+    number(bounds.min.x), number(bounds.min.y), number(bounds.max.x), number(bounds.max.y),
+  ), &content);
+  next_id = write_font(&mut writer, &glyphs, next_id);
+
+  let mut page_ids = vec![];
+  for page in pages(layout, &scene.bounds) {
+    let [left, bottom, width, height] = page.area;
+    let top = bottom + height;
+    // Flips the board's y down space and puts `origin` at the top left corner of the area
+    let content = format!(
+      "q {} {} {} {} re W n {} 0 0 {} {} {} cm /Board Do Q\n",
+      number(left), number(bottom), number(width), number(height),
+      ratio(page.scale), ratio(-page.scale),
+      number(left - page.origin.x * page.scale), number(top + page.origin.y * page.scale),
+    );
+    let (page_id, content_id) = (next_id, next_id + 1);
+    next_id += 2;
+    writer.object(page_id, &format!(
+      "<< /Type /Page /Parent {PAGES} 0 R /MediaBox [0 0 {} {}] /Resources << /XObject << /Board {BOARD} 0 R >> >> /Contents {content_id} 0 R >>",
+      number(page.width), number(page.height),
+    ));
+    writer.stream(content_id, "", content.as_bytes());
+    page_ids.push(page_id);
+  }
+  let kids = page_ids.iter().map(|id| format!("{id} 0 R")).collect::<Vec<_>>().join(" ");
+  writer.object(PAGES, &format!("<< /Type /Pages /Kids [{kids}] /Count {} >>", page_ids.len()));
+  writer.object(CATALOG, &format!("<< /Type /Catalog /Pages {PAGES} 0 R >>"));
+  writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Read;
+
+  use common::entities::{Element, Stroke, StrokeStyle, Text};
+  use flate2::read::ZlibDecoder;
+
+  use super::*;
+
+  fn p(x: f32, y: f32) -> Position {
+    Position { x, y }
+  }
+
+  fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+    Rect { min: p(x, y), max: p(x + width, y + height) }
+  }
+
+  fn stroke(color: Color) -> Element {
+    Element::Stroke(Stroke {
+      points: vec![p(0.0, 0.0), p(100.0, 50.0)],
+      width: 6.0,
+      color,
+      pressure: vec![],
+      style: StrokeStyle::default(),
+    })
+  }
+
+  fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+  }
+
+  /// Decompressed contents of all streams of a document
+  fn streams(pdf: &[u8]) -> Vec<String> {
+    let mut streams = vec![];
+    let mut rest = pdf;
+    while let Some(start) = find(rest, b"stream\n") {
+      let data = &rest[start + 7..];
+      let end = find(data, b"\nendstream").unwrap();
+      let mut decoded = vec![];
+      ZlibDecoder::new(&data[..end]).read_to_end(&mut decoded).unwrap();
+      streams.push(String::from_utf8_lossy(&decoded).into_owned());
+      rest = &data[end + b"\nendstream".len()..];
+    }
+    streams
+  }
+
+  #[test]
+  fn cross_references_point_at_objects() {
+    let scene = Scene::new(&[stroke(Color::BLACK)], 1.0, None);
+    let pdf = render(&scene, Layout::Board, &HashMap::new());
+    let xref = pdf.windows(6).rposition(|w| w == b"\nxref\n").unwrap() + 1;
+    let table = std::str::from_utf8(&pdf[xref..]).unwrap();
+    assert!(table.ends_with(&format!("startxref\n{xref}\n%%EOF\n")));
+    let entries = table.lines().skip(3).take_while(|l| l.ends_with(" n ")).collect::<Vec<_>>();
+    assert!(entries.len() > BOARD);
+    for (i, entry) in entries.into_iter().enumerate() {
+      let offset = entry[..10].parse::<usize>().unwrap();
+      assert!(pdf[offset..].starts_with(format!("{} 0 obj\n", i + 1).as_bytes()));
+    }
+  }
+
+  #[test]
+  fn board_page_matches_the_scene() {
+    let scene = Scene::new(&[stroke(Color::BLACK)], 1.0, Some(rect(0.0, 0.0, 200.0, 100.0)));
+    let pdf = String::from_utf8_lossy(&render(&scene, Layout::Board, &HashMap::new())).into_owned();
+    assert!(pdf.contains("/MediaBox [0 0 150 75]"));
+    assert!(pdf.contains("/Count 1"));
+  }
+
+  #[test]
+  fn translucent_strokes_are_filled_once() {
+    let scene = Scene::new(&[stroke(Color { r: 255, g: 0, b: 0, a: 51 })], 1.0, None);
+    let pdf = render(&scene, Layout::Board, &HashMap::new());
+    let board = streams(&pdf).into_iter().find(|s| s.contains(" m ")).unwrap();
+    assert!(board.starts_with("q 1 0 0 rg /A51 gs\n"));
+    assert_eq!(board.matches("\nf Q\n").count(), 1);
+    assert!(String::from_utf8_lossy(&pdf).contains("/A51 << /ca 0.2 >>"));
+  }
+
+  #[test]
+  fn fit_centres_the_scene() {
+    let bounds = rect(0.0, 0.0, 1000.0, 500.0);
+    let margin = 10.0 * POINTS_PER_MM;
+    let [page] = &pages(Layout::Fit { paper: Paper::A4, margin }, &bounds)[..] else { panic!("not one page") };
+    let width = Paper::A4.width - 2.0 * margin;
+    assert!((page.scale - width / 1000.0).abs() < 1e-6);
+    assert!((page.area[0] - margin).abs() < 1e-3);
+    assert!((page.area[1] + page.area[3] / 2.0 - Paper::A4.height / 2.0).abs() < 1e-3);
+  }
+
+  #[test]
+  fn tiles_cover_the_scene_row_by_row() {
+    let paper = Paper { width: 120.0, height: 220.0 };
+    let bounds = rect(-50.0, 0.0, 250.0, 250.0);
+    let layout = Layout::Tile { paper, margin: 10.0, scale: 1.0 };
+    let pages = pages(layout, &bounds);
+    // 100 by 200 points are printable, so 3 columns and 2 rows
+    assert_eq!(page_count(layout, &bounds), 6);
+    let origins = pages.iter().map(|page| page.origin).collect::<Vec<_>>();
+    assert_eq!(origins, [p(-50.0, 0.0), p(50.0, 0.0), p(150.0, 0.0), p(-50.0, 200.0), p(50.0, 200.0), p(150.0, 200.0)]);
+    assert!(pages.iter().all(|page| page.area == [10.0, 10.0, 100.0, 200.0]));
+    let pdf = String::from_utf8_lossy(&render(&Scene::new(&[], 1.0, Some(bounds)), layout, &HashMap::new())).into_owned();
+    assert!(pdf.contains("/Count 6"));
+  }
+
+  #[test]
+  fn text_is_set_in_the_embedded_font() {
+    let face = font::face();
+    let glyph = |c| face.glyph_index(c).unwrap().0;
+    let mut glyphs = Glyphs::new();
+    assert_eq!(string("ż\u{e000}€", &face, &mut glyphs), format!("<{:04X}{:04X}>", glyph('ż'), glyph('€')).into_bytes());
+    assert_eq!(glyphs, Glyphs::from([(glyph('ż'), 'ż'), (glyph('€'), '€')]));
+
+    let text = Element::Text(Text { position: p(0.0, 0.0), content: "Zażółć gęślą jaźń".to_owned(), font_size: 12.0, color: Color::BLACK, rotation: 0.0 });
+    let pdf = render(&Scene::new(&[text], 1.0, None), Layout::Board, &HashMap::new());
+    let streams = streams(&pdf);
+    let shown = format!("<{}> Tj", "Zażółć gęślą jaźń".chars().map(|c| format!("{:04X}", glyph(c))).collect::<String>());
+    assert!(streams.iter().any(|s| s.contains(&shown)));
+    assert!(streams.iter().any(|s| s.contains(&format!("<{:04X}> <0142>", glyph('ł')))));
+    let pdf = String::from_utf8_lossy(&pdf).into_owned();
+    assert!(pdf.contains("/Subtype /CIDFontType2"));
+    assert!(pdf.contains("/Encoding /Identity-H"));
+    assert!(!pdf.contains("Helvetica"));
+  }
+}
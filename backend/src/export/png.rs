@@ -0,0 +1,177 @@
+//! PNG images of scenes, drawn with the software rasterizer
+
+use std::{collections::HashMap, io::Cursor};
+
+use common::{entities::{BlobId, Color, Position}, spatial::Rect};
+use image::{ImageFormat, RgbaImage};
+
+use crate::blob_store::Blob;
+
+use super::{font::text_outline, raster::{Canvas, Point}, Scene, Shape};
+
+/// Most pixels of an exported image, bounds the memory an export takes
+pub const MAX_PIXELS: u64 = 4096 * 4096;
+
+/// Size in pixels of an image of `bounds` at `scale` pixels per board unit
+pub fn size(bounds: &Rect, scale: f32) -> (u32, u32) {
+  let pixels = |length: f32| (length * scale).ceil().clamp(1.0, u32::MAX as f32) as u32;
+  (pixels(bounds.width()), pixels(bounds.height()))
+}
+
+/// Corners of a `width` by `height` box rotated clockwise by `rotation` radians around its top
+/// left corner `origin`
+fn rotated_box(origin: Point, width: f32, height: f32, rotation: f32) -> Vec<Point> {
+  let (sin, cos) = rotation.sin_cos();
+  [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+    .into_iter()
+    .map(|(x, y)| (origin.0 + cos * x - sin * y, origin.1 + sin * x + cos * y))
+    .collect()
+}
+
+/// PNG image of `scene` at `scale` pixels per board unit, over `background` or transparent. The
+/// scene should be made for a zoom of `scale`, so strokes get the detail they have on screen.
+/// Images are taken from `images`, the ones missing there are left out.
+pub fn render(scene: &Scene, scale: f32, background: Option<Color>, images: &HashMap<BlobId, Blob>) -> Vec<u8> {
+  let (width, height) = size(&scene.bounds, scale);
+  let mut canvas = Canvas::new(width, height, background);
+  let origin = scene.bounds.min;
+  let pixel = |p: Position| ((p.x - origin.x) * scale, (p.y - origin.y) * scale);
+  let mut decoded: HashMap<&BlobId, Option<RgbaImage>> = HashMap::new();
+  for shape in &scene.shapes {
+    match shape {
+      Shape::Triangles { triangles, color } => {
+        let polygons = triangles.iter().map(|t| t.iter().map(|p| pixel(*p)).collect()).collect::<Vec<_>>();
+        canvas.fill(&polygons, *color);
+      }
+      Shape::Rect { position, width, height, rotation, color } => {
+        canvas.fill(&[rotated_box(pixel(*position), width * scale, height * scale, *rotation)], *color);
+      }
+      Shape::Text { position, rotation, lines, font_size, color, .. } => {
+        let (sin, cos) = rotation.sin_cos();
+        let start = pixel(*position);
+        let mut polygons = vec![];
+        for (line, baseline) in lines.iter().zip(shape.baselines()) {
+          polygons.extend(text_outline(line, font_size * scale).into_iter().map(|polygon| {
+            polygon.into_iter()
+              .map(|(x, y)| {
+                let y = y + baseline * scale;
+                (start.0 + cos * x - sin * y, start.1 + sin * x + cos * y)
+              })
+              .collect()
+          }));
+        }
+        canvas.fill(&polygons, *color);
+      }
+      Shape::Image { position, width, height, rotation, blob } => {
+        let image = decoded.entry(blob).or_insert_with(|| {
+          let data = &images.get(blob)?.data;
+          Some(image::load_from_memory(data).ok()?.to_rgba8())
+        });
+        if let Some(image) = image {
+          canvas.draw_image(image, pixel(*position), width * scale, height * scale, *rotation);
+        }
+      }
+    }
+  }
+  let mut png = vec![];
+  canvas.image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).expect("Encoding to memory cannot fail");
+  png
+}
+
+#[cfg(test)]
+mod tests {
+  use common::entities::{Cap, Element, Image, Join, Sticky, Stroke, StrokeStyle, Text};
+  use image::Rgba;
+
+  use super::*;
+
+  const WHITE: Color = Color { r: 255, g: 255, b: 255, a: 255 };
+
+  fn p(x: f32, y: f32) -> Position {
+    Position { x, y }
+  }
+
+  fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+    Rect { min: p(x, y), max: p(x + width, y + height) }
+  }
+
+  fn butt_line(from: Position, to: Position, color: Color) -> Element {
+    Element::Stroke(Stroke {
+      points: vec![from, to],
+      width: 4.0,
+      color,
+      pressure: vec![],
+      style: StrokeStyle { join: Join::Round, cap: Cap::Butt, dash: vec![], dash_offset: 0.0 },
+    })
+  }
+
+  fn decode(png: &[u8]) -> RgbaImage {
+    image::load_from_memory_with_format(png, ImageFormat::Png).unwrap().to_rgba8()
+  }
+
+  fn export(elements: &[Element], region: Rect, scale: f32, background: Option<Color>) -> RgbaImage {
+    let scene = Scene::new(elements, scale, Some(region));
+    decode(&render(&scene, scale, background, &HashMap::new()))
+  }
+
+  #[test]
+  fn scales_the_region() {
+    let line = butt_line(p(0.0, 0.0), p(10.0, 0.0), Color::BLACK);
+    let image = export(&[line], rect(0.0, -5.0, 10.0, 10.0), 2.0, Some(WHITE));
+    assert_eq!(image.dimensions(), (20, 20));
+    // The line is 4 units thick around y = 0, which is 8 pixels around the middle row
+    for y in 0..20 {
+      let expected = if (6..14).contains(&y) { [0, 0, 0, 255] } else { [255; 4] };
+      assert_eq!(image.get_pixel(10, y).0, expected, "row {y}");
+    }
+  }
+
+  #[test]
+  fn transparent_background() {
+    let line = butt_line(p(0.0, 0.0), p(10.0, 0.0), Color { a: 128, ..Color::BLACK });
+    let image = export(&[line], rect(0.0, -5.0, 10.0, 10.0), 1.0, None);
+    assert_eq!(image.get_pixel(5, 0).0, [0; 4]);
+    assert_eq!(image.get_pixel(5, 5).0, [0, 0, 0, 128]);
+  }
+
+  #[test]
+  fn later_shapes_cover_earlier_ones() {
+    let sticky = Element::Sticky(Sticky {
+      position: p(0.0, 0.0), width: 10.0, height: 10.0, color: Color::STICKY_YELLOW,
+      rotation: 0.0, text: String::new(), revision: 0,
+    });
+    let line = butt_line(p(0.0, 5.0), p(10.0, 5.0), Color { r: 0, g: 0, b: 255, a: 255 });
+    let region = rect(0.0, 0.0, 10.0, 10.0);
+    let yellow = Color::STICKY_YELLOW;
+    assert_eq!(export(&[line.clone(), sticky.clone()], region, 1.0, None).get_pixel(5, 5).0, [yellow.r, yellow.g, yellow.b, 255]);
+    assert_eq!(export(&[sticky, line], region, 1.0, None).get_pixel(5, 5).0, [0, 0, 255, 255]);
+  }
+
+  #[test]
+  fn draws_images() {
+    let mut png = vec![];
+    RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255])).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+    let image = Image { position: p(2.0, 2.0), width: 2.0, height: 2.0, scale: 2.0, rotation: 0.0, blob: "ab".repeat(32) };
+    let scene = Scene::new(&[Element::Image(image.clone())], 1.0, Some(rect(0.0, 0.0, 8.0, 8.0)));
+    let blobs = HashMap::from([(image.blob, Blob { mime: "image/png", data: png })]);
+    let exported = decode(&render(&scene, 1.0, None, &blobs));
+    assert_eq!(exported.get_pixel(1, 1).0, [0; 4]);
+    assert_eq!(exported.get_pixel(2, 2).0, [255, 0, 0, 255]);
+    assert_eq!(exported.get_pixel(5, 5).0, [255, 0, 0, 255]);
+    assert_eq!(exported.get_pixel(6, 6).0, [0; 4]);
+  }
+
+  #[test]
+  fn draws_text() {
+    let text = Element::Text(Text { position: p(0.0, 0.0), content: "Hello".to_owned(), font_size: 20.0, color: Color::BLACK, rotation: 0.0 });
+    let image = export(&[text], rect(0.0, 0.0, 60.0, 24.0), 1.0, None);
+    let inked = image.pixels().filter(|p| p.0[3] == 255).count();
+    assert!(inked > 50, "{inked} pixels of text");
+  }
+
+  #[test]
+  fn size_is_at_least_a_pixel() {
+    assert_eq!(size(&rect(0.0, 0.0, 0.0, 0.0), 1.0), (1, 1));
+    assert_eq!(size(&rect(0.0, 0.0, 10.5, 3.0), 2.0), (21, 6));
+  }
+}
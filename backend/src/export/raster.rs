@@ -0,0 +1,245 @@
+//! Software rasterizer, so that exports work on machines without a GPU
+
+use common::entities::Color;
+use image::{Rgba, RgbaImage};
+
+/// Sub-scanlines sampled per row of pixels, coverage along each of them is exact
+const SUBSCANLINES: usize = 4;
+
+pub type Point = (f32, f32);
+
+/// Polygon edge, `top` is above `bottom`
+struct Edge {
+  top: Point,
+  bottom: Point,
+  /// +1 for edges going down, -1 for edges going up
+  winding: i32,
+}
+
+impl Edge {
+  fn x_at(&self, y: f32) -> f32 {
+    let t = (y - self.top.1) / (self.bottom.1 - self.top.1);
+    self.top.0 + t * (self.bottom.0 - self.top.0)
+  }
+}
+
+/// Adds `weight` times the part of each pixel of `row` covered by the span `start..end`
+fn add_span(row: &mut [f32], start: f32, end: f32, weight: f32) {
+  let (start, end) = (start.max(0.0), end.min(row.len() as f32));
+  if start >= end {
+    return;
+  }
+  let (first, last) = (start as usize, end as usize);
+  if first == last {
+    row[first] += (end - start) * weight;
+    return;
+  }
+  row[first] += (first as f32 + 1.0 - start) * weight;
+  for coverage in &mut row[first + 1..last] {
+    *coverage += weight;
+  }
+  if let Some(coverage) = row.get_mut(last) {
+    *coverage += (end - last as f32) * weight;
+  }
+}
+
+/// Draws `color` with `alpha` over `pixel`, both with straight alpha
+fn blend(pixel: &mut Rgba<u8>, color: [u8; 3], alpha: f32) {
+  let [r, g, b, a] = pixel.0;
+  let below = a as f32 / 255.0 * (1.0 - alpha);
+  let out = alpha + below;
+  if out <= 0.0 {
+    return;
+  }
+  let mix = |src: u8, dst: u8| ((src as f32 * alpha + dst as f32 * below) / out).round() as u8;
+  pixel.0 = [mix(color[0], r), mix(color[1], g), mix(color[2], b), (out * 255.0).round() as u8];
+}
+
+/// Image that shapes are drawn on, in pixel coordinates with y down
+pub struct Canvas {
+  pub image: RgbaImage,
+}
+
+impl Canvas {
+  pub fn new(width: u32, height: u32, background: Option<Color>) -> Canvas {
+    let background = background.map_or([0; 4], |c| [c.r, c.g, c.b, c.a]);
+    Canvas { image: RgbaImage::from_pixel(width, height, Rgba(background)) }
+  }
+
+  /// Fills the area inside `polygons` with the nonzero rule, so that overlapping polygons of the
+  /// same winding are covered once. Edges are anti-aliased.
+  pub fn fill(&mut self, polygons: &[Vec<Point>], color: Color) {
+    let mut edges = polygons.iter()
+      .flat_map(|polygon| polygon.iter().zip(polygon.iter().cycle().skip(1)))
+      .filter(|(a, b)| a.1 != b.1 && [a.0, a.1, b.0, b.1].iter().all(|c| c.is_finite()))
+      .map(|(a, b)| match a.1 < b.1 {
+        true => Edge { top: *a, bottom: *b, winding: 1 },
+        false => Edge { top: *b, bottom: *a, winding: -1 },
+      })
+      .collect::<Vec<_>>();
+    if edges.is_empty() || color.a == 0 {
+      return;
+    }
+    edges.sort_by(|a, b| a.top.1.total_cmp(&b.top.1));
+    let (width, height) = (self.image.width() as usize, self.image.height() as usize);
+    let min_x = edges.iter().map(|e| e.top.0.min(e.bottom.0)).fold(f32::INFINITY, f32::min);
+    let max_x = edges.iter().map(|e| e.top.0.max(e.bottom.0)).fold(f32::NEG_INFINITY, f32::max);
+    let max_y = edges.iter().map(|e| e.bottom.1).fold(f32::NEG_INFINITY, f32::max);
+    let left = (min_x.floor().max(0.0) as usize).min(width);
+    let right = (max_x.ceil().max(0.0) as usize).min(width);
+    let top = (edges[0].top.1.floor().max(0.0) as usize).min(height);
+    let bottom = (max_y.ceil().max(0.0) as usize).min(height);
+    if left >= right {
+      return;
+    }
+
+    let mut row = vec![0.0; right - left];
+    let mut active: Vec<&Edge> = vec![];
+    let mut next = 0;
+    let mut crossings = vec![];
+    for y in top..bottom {
+      row.fill(0.0);
+      for s in 0..SUBSCANLINES {
+        let scan = y as f32 + (s as f32 + 0.5) / SUBSCANLINES as f32;
+        while next < edges.len() && edges[next].top.1 <= scan {
+          active.push(&edges[next]);
+          next += 1;
+        }
+        active.retain(|e| e.bottom.1 > scan);
+        crossings.clear();
+        crossings.extend(active.iter().map(|e| (e.x_at(scan), e.winding)));
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut winding = 0;
+        let mut start = 0.0;
+        for (x, w) in &crossings {
+          if winding == 0 {
+            start = *x;
+          }
+          winding += w;
+          if winding == 0 {
+            add_span(&mut row, start - left as f32, x - left as f32, 1.0 / SUBSCANLINES as f32);
+          }
+        }
+      }
+      let rgb = [color.r, color.g, color.b];
+      let alpha = color.a as f32 / 255.0;
+      for (i, coverage) in row.iter().enumerate() {
+        if *coverage > 0.0 {
+          blend(self.image.get_pixel_mut((left + i) as u32, y as u32), rgb, alpha * coverage.min(1.0));
+        }
+      }
+    }
+  }
+
+  /// Draws `image` stretched over a `width` by `height` rectangle whose top left corner is
+  /// `position`, rotated clockwise by `rotation` radians around that corner. Pixels are sampled
+  /// bilinearly at their centres.
+  pub fn draw_image(&mut self, image: &RgbaImage, position: Point, width: f32, height: f32, rotation: f32) {
+    if image.width() == 0 || image.height() == 0 || width <= 0.0 || height <= 0.0 {
+      return;
+    }
+    let (sin, cos) = rotation.sin_cos();
+    let corners = [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+      .map(|(x, y)| (position.0 + cos * x - sin * y, position.1 + sin * x + cos * y));
+    let range = |values: [f32; 4], limit: u32| {
+      let min = values.iter().copied().fold(f32::INFINITY, f32::min).floor().max(0.0) as u32;
+      let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max).ceil().max(0.0) as u32;
+      min.min(limit)..max.min(limit)
+    };
+    let (scale_x, scale_y) = (image.width() as f32 / width, image.height() as f32 / height);
+    for y in range(corners.map(|c| c.1), self.image.height()) {
+      for x in range(corners.map(|c| c.0), self.image.width()) {
+        let (dx, dy) = (x as f32 + 0.5 - position.0, y as f32 + 0.5 - position.1);
+        let (u, v) = (cos * dx + sin * dy, cos * dy - sin * dx);
+        if (0.0..width).contains(&u) && (0.0..height).contains(&v) {
+          let [r, g, b, a] = sample(image, u * scale_x - 0.5, v * scale_y - 0.5);
+          blend(self.image.get_pixel_mut(x, y), [r, g, b], a as f32 / 255.0);
+        }
+      }
+    }
+  }
+}
+
+/// Bilinear sample of `image` at a position in pixels, where pixel centres are at whole numbers
+fn sample(image: &RgbaImage, x: f32, y: f32) -> [u8; 4] {
+  let clamp = |v: f32, size: u32| v.clamp(0.0, (size - 1) as f32);
+  let (x, y) = (clamp(x, image.width()), clamp(y, image.height()));
+  let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+  let (x1, y1) = ((x0 + 1).min(image.width() - 1), (y0 + 1).min(image.height() - 1));
+  let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+  let pixel = |x, y| image.get_pixel(x, y).0.map(|c| c as f32);
+  let (p00, p10, p01, p11) = (pixel(x0, y0), pixel(x1, y0), pixel(x0, y1), pixel(x1, y1));
+  std::array::from_fn(|i| {
+    let top = p00[i] + (p10[i] - p00[i]) * fx;
+    let bottom = p01[i] + (p11[i] - p01[i]) * fx;
+    (top + (bottom - top) * fy).round() as u8
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const RED: Color = Color { r: 255, g: 0, b: 0, a: 255 };
+
+  fn square(x: f32, y: f32, size: f32) -> Vec<Point> {
+    vec![(x, y), (x + size, y), (x + size, y + size), (x, y + size)]
+  }
+
+  #[test]
+  fn covers_pixels_inside() {
+    let mut canvas = Canvas::new(4, 4, None);
+    canvas.fill(&[square(1.0, 1.0, 2.0)], RED);
+    for (x, y, pixel) in canvas.image.enumerate_pixels() {
+      let inside = (1..3).contains(&x) && (1..3).contains(&y);
+      assert_eq!(pixel.0, if inside { [255, 0, 0, 255] } else { [0; 4] });
+    }
+  }
+
+  #[test]
+  fn antialiases_partial_pixels() {
+    let mut canvas = Canvas::new(3, 1, None);
+    canvas.fill(&[square(0.5, 0.0, 1.0)], RED);
+    assert_eq!(canvas.image.get_pixel(0, 0).0, [255, 0, 0, 128]);
+    assert_eq!(canvas.image.get_pixel(1, 0).0, [255, 0, 0, 128]);
+    assert_eq!(canvas.image.get_pixel(2, 0).0, [0; 4]);
+  }
+
+  #[test]
+  fn overlaps_are_covered_once() {
+    let mut canvas = Canvas::new(4, 4, Some(Color { r: 255, g: 255, b: 255, a: 255 }));
+    let translucent = Color { a: 128, ..Color::BLACK };
+    canvas.fill(&[square(0.0, 0.0, 3.0), square(1.0, 1.0, 3.0)], translucent);
+    assert_eq!(canvas.image.get_pixel(0, 0), canvas.image.get_pixel(1, 1));
+    assert_eq!(canvas.image.get_pixel(1, 1).0, [127, 127, 127, 255]);
+  }
+
+  #[test]
+  fn opposite_windings_cut_holes() {
+    let mut canvas = Canvas::new(5, 5, None);
+    let mut hole = square(1.0, 1.0, 3.0);
+    hole.reverse();
+    canvas.fill(&[square(0.0, 0.0, 5.0), hole], RED);
+    assert_eq!(canvas.image.get_pixel(0, 0).0[3], 255);
+    assert_eq!(canvas.image.get_pixel(2, 2).0[3], 0);
+  }
+
+  #[test]
+  fn clips_to_the_canvas() {
+    let mut canvas = Canvas::new(2, 2, None);
+    canvas.fill(&[square(-10.0, -10.0, 11.0), square(1.5, 1.5, 10.0)], RED);
+    assert_eq!(canvas.image.get_pixel(0, 0).0[3], 255);
+    assert_eq!(canvas.image.get_pixel(1, 0).0[3], 0);
+    assert_eq!(canvas.image.get_pixel(1, 1).0[3], 64);
+  }
+
+  #[test]
+  fn draws_rotated_images() {
+    let mut canvas = Canvas::new(4, 4, None);
+    let image = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 255, 255]));
+    // A quarter turn clockwise around the top left corner swings the image down and to the left
+    canvas.draw_image(&image, (2.0, 0.0), 2.0, 1.0, std::f32::consts::FRAC_PI_2);
+    let covered = canvas.image.enumerate_pixels().filter(|(_, _, p)| p.0[3] > 0).map(|(x, y, _)| (x, y)).collect::<Vec<_>>();
+    assert_eq!(covered, vec![(1, 0), (1, 1)]);
+  }
+}
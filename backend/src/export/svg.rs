@@ -0,0 +1,189 @@
+//! Standalone SVG documents of scenes
+
+use std::{collections::HashMap, fmt::Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::entities::{BlobId, Color, Position};
+
+use crate::blob_store::Blob;
+
+use super::{number, Scene, Shape};
+
+/// Font of text elements, with a fallback for viewers that do not have it
+const FONT_FAMILY: &str = "Raleway, sans-serif";
+
+fn escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&apos;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+/// Fill attributes of `color`, the opacity is left out for opaque colours
+fn fill(color: Color) -> String {
+  match color.a {
+    255 => format!(r#"fill="{}""#, color.to_hex()),
+    a => format!(r#"fill="{}" fill-opacity="{}""#, color.to_hex(), number(a as f32 / 255.0)),
+  }
+}
+
+/// Transform placing a box whose top left corner is `position`, rotated clockwise by `rotation`
+/// radians around that corner
+fn placement(position: Position, rotation: f32) -> String {
+  let translate = format!("translate({} {})", number(position.x), number(position.y));
+  if rotation == 0.0 {
+    return format!(r#"transform="{translate}""#);
+  }
+  format!(r#"transform="{translate} rotate({})""#, number(rotation.to_degrees()))
+}
+
+/// Path data of the triangles, each as its own closed subpath
+fn triangles_path(triangles: &[[Position; 3]]) -> String {
+  let mut path = String::new();
+  for [a, b, c] in triangles {
+    let _ = write!(
+      path, "M{} {}L{} {}L{} {}Z",
+      number(a.x), number(a.y), number(b.x), number(b.y), number(c.x), number(c.y),
+    );
+  }
+  path
+}
+
+/// SVG document of `scene`, whose viewport is the bounds of the scene in board units. Images are
+/// embedded from `images`, the ones missing there are left out.
+pub fn render(scene: &Scene, images: &HashMap<BlobId, Blob>) -> String {
+  let bounds = scene.bounds;
+  let mut svg = String::new();
+  let _ = writeln!(
+    svg,
+    r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="{x} {y} {w} {h}">"#,
+    x = number(bounds.min.x), y = number(bounds.min.y),
+    w = number(bounds.width()), h = number(bounds.height()),
+  );
+  for shape in &scene.shapes {
+    match shape {
+      Shape::Triangles { triangles, color } => {
+        let _ = writeln!(svg, r#"<path d="{}" {}/>"#, triangles_path(triangles), fill(*color));
+      }
+      Shape::Rect { position, width, height, rotation, color } => {
+        let _ = writeln!(
+          svg, r#"<rect width="{}" height="{}" {} {}/>"#,
+          number(*width), number(*height), fill(*color), placement(*position, *rotation),
+        );
+      }
+      Shape::Text { position, rotation, lines, font_size, color, .. } => {
+        let _ = write!(
+          svg, r#"<text font-family="{FONT_FAMILY}" font-size="{}" {} {} xml:space="preserve">"#,
+          number(*font_size), fill(*color), placement(*position, *rotation),
+        );
+        for (line, baseline) in lines.iter().zip(shape.baselines()) {
+          let _ = write!(svg, r#"<tspan x="0" y="{}">{}</tspan>"#, number(baseline), escape(line));
+        }
+        svg.push_str("</text>\n");
+      }
+      Shape::Image { position, width, height, rotation, blob } => {
+        let Some(image) = images.get(blob) else { continue; };
+        let _ = writeln!(
+          svg, r#"<image width="{}" height="{}" preserveAspectRatio="none" href="data:{};base64,{}" {}/>"#,
+          number(*width), number(*height), image.mime, STANDARD.encode(&image.data), placement(*position, *rotation),
+        );
+      }
+    }
+  }
+  svg.push_str("</svg>\n");
+  svg
+}
+
+#[cfg(test)]
+mod tests {
+  use common::{entities::{Cap, Element, Image, Join, Sticky, Stroke, StrokeStyle, Text}, spatial::Rect};
+
+  use super::*;
+
+  fn p(x: f32, y: f32) -> Position {
+    Position { x, y }
+  }
+
+  fn line(points: Vec<Position>, color: Color, cap: Cap) -> Element {
+    Element::Stroke(Stroke {
+      points,
+      width: 4.0,
+      color,
+      pressure: vec![],
+      style: StrokeStyle { join: Join::Miter { limit: 4.0 }, cap, dash: vec![], dash_offset: 0.0 },
+    })
+  }
+
+  fn render_elements(elements: &[Element]) -> String {
+    render(&Scene::new(elements, 1.0, None), &HashMap::new())
+  }
+
+  #[test]
+  fn butt_line() {
+    let svg = render_elements(&[line(vec![p(0.0, 0.0), p(30.0, 0.0)], Color::BLACK, Cap::Butt)]);
+    insta::assert_snapshot!(svg);
+  }
+
+  #[test]
+  fn translucent_square_line() {
+    let color = Color { r: 200, g: 30, b: 40, a: 128 };
+    let svg = render_elements(&[line(vec![p(0.0, 0.0), p(0.0, 20.0)], color, Cap::Square)]);
+    insta::assert_snapshot!(svg);
+  }
+
+  #[test]
+  fn text_lines() {
+    let svg = render_elements(&[Element::Text(Text {
+      position: p(10.0, 20.0),
+      content: "Plan <v2>\n  & next".to_owned(),
+      font_size: 20.0,
+      color: Color { r: 0, g: 0, b: 255, a: 255 },
+      rotation: std::f32::consts::FRAC_PI_2,
+    })]);
+    insta::assert_snapshot!(svg);
+  }
+
+  #[test]
+  fn sticky_note() {
+    let svg = render_elements(&[Element::Sticky(Sticky {
+      position: p(0.0, 0.0),
+      width: 120.0,
+      height: 100.0,
+      color: Color::STICKY_YELLOW,
+      rotation: 0.0,
+      text: "Ship the export before Friday".to_owned(),
+      revision: 3,
+    })]);
+    insta::assert_snapshot!(svg);
+  }
+
+  #[test]
+  fn embedded_image() {
+    let image = Image { position: p(5.0, 5.0), width: 40.0, height: 20.0, scale: 0.5, rotation: 0.0, blob: "ab".repeat(32) };
+    let blob = Blob { mime: "image/png", data: b"\x89PNG".to_vec() };
+    let scene = Scene::new(&[Element::Image(image.clone())], 1.0, None);
+    insta::assert_snapshot!(render(&scene, &HashMap::from([(image.blob, blob)])));
+    // Images whose data is missing are left out rather than pointing nowhere
+    assert!(!render(&scene, &HashMap::new()).contains("<image"));
+  }
+
+  #[test]
+  fn stacking_order_is_kept() {
+    let region = Rect { min: p(0.0, 0.0), max: p(10.0, 10.0) };
+    let elements = [
+      line(vec![p(0.0, 5.0), p(10.0, 5.0)], Color::BLACK, Cap::Butt),
+      Element::Sticky(Sticky { position: p(0.0, 0.0), width: 10.0, height: 10.0, color: Color::STICKY_YELLOW, rotation: 0.0, text: String::new(), revision: 0 }),
+    ];
+    let svg = render(&Scene::new(&elements, 1.0, Some(region)), &HashMap::new());
+    assert!(svg.find("<path").unwrap() < svg.find("<rect").unwrap());
+    assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10" viewBox="0 0 10 10">"#));
+  }
+}
@@ -0,0 +1,72 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use common::{board_file::FileElement, entities::{Element, Layer, LayerId}, websocket::ToServer};
+
+/// Change to the contents of a board, kept as it was made so that it can be applied again
+#[derive(Clone, Debug)]
+pub enum Change {
+  /// Message of a client that the board applied
+  Message { client_id: u64, message: ToServer },
+  /// Contents of a board file that replaced the board
+  Open { layers: Vec<(LayerId, Layer)>, elements: Vec<FileElement> },
+  /// Contents of another board or of one of its snapshots that a new board started with
+  Fork { from: String, layers: Vec<(LayerId, Layer)>, elements: Vec<FileElement> },
+  /// Contents of an earlier version that replaced the board
+  Restore { version: u64, layers: Vec<(LayerId, Layer)>, elements: Vec<FileElement> },
+}
+
+impl Change {
+  pub fn client(&self) -> Option<u64> {
+    match self {
+      Change::Message { client_id, .. } => Some(*client_id),
+      Change::Open { .. } | Change::Fork { .. } | Change::Restore { .. } => None,
+    }
+  }
+
+  /// What the change did, as shown in the history of the board
+  pub fn summary(&self) -> String {
+    let message = match self {
+      Change::Message { message, .. } => message,
+      Change::Open { .. } => return "Opened a board file".to_owned(),
+      Change::Fork { from, .. } => return format!("Forked from {from}"),
+      Change::Restore { version, .. } => return format!("Restored version {version}"),
+    };
+    describe(message).to_owned()
+  }
+}
+
+fn describe(message: &ToServer) -> &'static str {
+  match message {
+    ToServer::CreateElement { element, .. } => match element {
+      Element::Stroke(_) => "Drew a stroke",
+      Element::Text(_) => "Added text",
+      Element::Sticky(_) => "Added a sticky note",
+      Element::Image(_) => "Added an image",
+    },
+    ToServer::UpdateElement { .. } => "Changed an element",
+    ToServer::DeleteElement { .. } => "Deleted an element",
+    ToServer::EditSticky { .. } => "Edited a sticky note",
+    ToServer::Transform { ids, .. } if ids.len() == 1 => "Transformed an element",
+    ToServer::Transform { .. } => "Transformed elements",
+    ToServer::Reorder { .. } => "Reordered elements",
+    ToServer::SetLayer { .. } => "Moved elements to a layer",
+    ToServer::CreateLayer { .. } => "Added a layer",
+    ToServer::UpdateLayer { .. } => "Changed a layer",
+    ToServer::DeleteLayer { .. } => "Deleted a layer",
+    ToServer::MoveLayer { .. } => "Moved a layer",
+    ToServer::Move { .. } | ToServer::StickyCaret { .. } => "Moved the pointer",
+    ToServer::Tagged { message, .. } => describe(message),
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Revision {
+  /// Milliseconds since the Unix epoch
+  pub time: u64,
+  pub change: Change,
+}
+
+/// Milliseconds since the Unix epoch
+pub fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis() as u64)
+}
@@ -1,16 +1,21 @@
 mod socket_endpoint;
 mod board_server;
 mod board;
+mod blob_store;
+mod export;
+mod history;
+mod snapshot_store;
 
 use std::{collections::HashMap, sync::Arc};
 
-use axum::{extract::{Query, State}, response::{IntoResponse, Response}, routing::{get, delete}, Router};
+use axum::{extract::{Query, State}, http::{header, StatusCode}, response::{IntoResponse, Response}, routing::{get, delete, post}, Router};
+use blob_store::blob_server;
 use board_server::board_server;
 use serde::Deserialize;
 use tokio::{net::TcpListener, sync::Mutex};
 
-const INNER_BOARD_SERVER_URL: &'static str = "http://localhost:8080/board_server";
-const OUTER_BOARD_SERVER_URL: &'static str = "/api/board_server";
+const INNER_BOARD_SERVER_URL: &str = "http://localhost:8080/board_server";
+const OUTER_BOARD_SERVER_URL: &str = "/api/board_server";
 
 #[derive(Deserialize)]
 struct BoardUrlPars {
@@ -20,19 +25,114 @@ struct BoardUrlPars {
 async fn board_url(Query(BoardUrlPars {name}): Query<BoardUrlPars>, State(state): State<Arc<Mutex<AppState>>>) -> Response {
     let mut state = state.lock().await;
     match state.board_urls.get(&name) {
-        Some(url) => url.to_owned().into_response(),
+        Some(Some(url)) => url.to_owned().into_response(),
+        Some(None) => (StatusCode::SERVICE_UNAVAILABLE, "Board is being created").into_response(),
         None => {
             let client = reqwest::Client::new();
             let path = client.post(format!("{INNER_BOARD_SERVER_URL}/create_board"))
                 .query(&[("name", &name)])
                 .send().await.unwrap().text().await.unwrap();
             let path = format!("{OUTER_BOARD_SERVER_URL}{path}");
-            state.board_urls.insert(name, path.clone());
+            state.board_urls.insert(name, Some(path.clone()));
             path.into_response()
         }
     }
 }
 
+/// Passes on the answer of the board server
+async fn forward(res: reqwest::Result<reqwest::Response>) -> Response {
+    let Ok(res) = res else {
+        return (StatusCode::BAD_GATEWAY, "Board server did not answer").into_response();
+    };
+    let status = res.status();
+    let content_type = res.headers().get(header::CONTENT_TYPE).cloned();
+    let mut response = (status, res.bytes().await.unwrap_or_default()).into_response();
+    if let Some(content_type) = content_type {
+        response.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    response
+}
+
+/// Url of the board server route made of `segments`, which are percent-encoded
+fn inner_url(segments: &[&str]) -> reqwest::Url {
+    let mut url = reqwest::Url::parse(INNER_BOARD_SERVER_URL).unwrap();
+    url.path_segments_mut().unwrap().extend(segments);
+    url
+}
+
+#[derive(Deserialize)]
+struct SnapshotPars {
+    name: String,
+    snapshot: String,
+}
+
+/// Saves the current contents of a board as a named snapshot
+async fn snapshot(Query(SnapshotPars {name, snapshot}): Query<SnapshotPars>) -> Response {
+    let client = reqwest::Client::new();
+    let res = client.post(inner_url(&["boards", &name, "snapshots"]))
+        .query(&[("name", &snapshot)])
+        .send().await;
+    forward(res).await
+}
+
+#[derive(Deserialize)]
+struct SnapshotsPars {
+    name: String,
+}
+
+/// Lists the snapshots of a board
+async fn snapshots(Query(SnapshotsPars {name}): Query<SnapshotsPars>) -> Response {
+    let res = reqwest::get(inner_url(&["boards", &name, "snapshots"])).await;
+    forward(res).await
+}
+
+#[derive(Deserialize)]
+struct ForkPars {
+    /// Board to copy
+    name: String,
+    /// Snapshot of the board to copy, its current contents by default
+    snapshot: Option<String>,
+    new_name: String,
+}
+
+/// Creates a board with a copy of another one and responds with its url, like `board_url`
+async fn fork(Query(ForkPars {name, snapshot, new_name}): Query<ForkPars>, State(state): State<Arc<Mutex<AppState>>>) -> Response {
+    // The name is reserved rather than the state locked while the board server copies the board
+    {
+        let mut state = state.lock().await;
+        if state.board_urls.contains_key(&new_name) {
+            return (StatusCode::CONFLICT, "Board already exists").into_response();
+        }
+        state.board_urls.insert(new_name.clone(), None);
+    }
+    let client = reqwest::Client::new();
+    let mut query = vec![("from", &name), ("name", &new_name)];
+    if let Some(snapshot) = &snapshot {
+        query.push(("snapshot", snapshot));
+    }
+    let res = client.post(inner_url(&["fork"]))
+        .query(&query)
+        .send().await;
+    let path = match res {
+        Ok(res) if res.status() == StatusCode::OK => res.text().await.map_err(|_| {
+            (StatusCode::BAD_GATEWAY, "Board server did not answer").into_response()
+        }),
+        res => Err(forward(res).await),
+    };
+    let mut state = state.lock().await;
+    match path {
+        Ok(path) => {
+            let path = format!("{OUTER_BOARD_SERVER_URL}{path}");
+            state.board_urls.insert(new_name, Some(path.clone()));
+            path.into_response()
+        }
+        Err(response) => {
+            state.board_urls.remove(&new_name);
+            response
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct DeleteBoardPars {
     name: String
@@ -48,7 +148,8 @@ type BoardId = String;
 type BoardUrl = String;
 
 struct AppState {
-    board_urls: HashMap<BoardId, BoardUrl>,
+    /// Urls of the loaded boards, `None` while a board is being forked under the name
+    board_urls: HashMap<BoardId, Option<BoardUrl>>,
 }
 
 #[tokio::main]
@@ -59,9 +160,13 @@ async fn main() {
     };
     let app = Router::new()
         .route("/board_url", get(board_url))
+        .route("/snapshot", post(snapshot))
+        .route("/snapshots", get(snapshots))
+        .route("/fork", post(fork))
         .route("/internal/delete_board", delete(delete_board))
         .with_state(Arc::new(Mutex::new(state)))
-        .nest("/board_server", board_server());
+        .nest("/board_server", board_server())
+        .nest("/blobs", blob_server());
     let listener = TcpListener::bind("0.0.0.0:8080").await.unwrap();
     tracing::info!("starting server");
     axum::serve(listener, app).await.unwrap();
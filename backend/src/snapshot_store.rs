@@ -0,0 +1,87 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use common::{api::Snapshot, board_file::{self, BoardFile}};
+use tracing::info;
+
+const SNAPSHOT_DIR: &str = "snapshots";
+const MAX_SNAPSHOT_NAME: usize = 64;
+
+/// Whether `name` can name a snapshot, it becomes part of a file name
+pub fn valid_name(name: &str) -> bool {
+  !name.trim().is_empty()
+    && name.len() <= MAX_SNAPSHOT_NAME
+    && !name.starts_with('.')
+    && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ' '))
+}
+
+/// Directory of the snapshots of a board. Board names can hold any character, so they are
+/// written in hex.
+fn board_dir(board: &str) -> PathBuf {
+  let hex = board.bytes().map(|b| format!("{b:02x}")).collect::<String>();
+  PathBuf::from(SNAPSHOT_DIR).join(hex)
+}
+
+fn snapshot_path(board: &str, name: &str) -> Option<PathBuf> {
+  valid_name(name).then(|| board_dir(board).join(format!("{name}.{}", board_file::EXTENSION)))
+}
+
+/// Stores `file` as snapshot `name` of `board`. Returns `false` if the board already has a
+/// snapshot of that name, snapshots are never overwritten.
+pub async fn write_snapshot(board: &str, name: &str, file: &BoardFile) -> std::io::Result<bool> {
+  let path = snapshot_path(board, name).ok_or(ErrorKind::InvalidInput)?;
+  tokio::fs::create_dir_all(board_dir(board)).await?;
+  // Write under a temporary name first so a partially written snapshot is never read
+  let temp = path.with_extension(format!("{:08x}.part", rand::random::<u32>()));
+  tokio::fs::write(&temp, file.to_json()).await?;
+  // Hard linking fails if the target exists, unlike renaming, so two snapshots taken at once
+  // cannot replace each other
+  let linked = tokio::fs::hard_link(&temp, &path).await;
+  tokio::fs::remove_file(&temp).await?;
+  match linked {
+    Ok(()) => {
+      info!("Snapshot taken: {board}/{name}");
+      Ok(true)
+    }
+    Err(error) if error.kind() == ErrorKind::AlreadyExists => Ok(false),
+    Err(error) => Err(error),
+  }
+}
+
+/// Reads a snapshot, `None` if `board` has no snapshot called `name` or it cannot be read
+pub async fn read_snapshot(board: &str, name: &str) -> Option<BoardFile> {
+  let text = tokio::fs::read_to_string(snapshot_path(board, name)?).await.ok()?;
+  BoardFile::from_json(&text).ok()
+}
+
+/// Snapshots of `board`, oldest first
+pub async fn list_snapshots(board: &str) -> Vec<Snapshot> {
+  let mut snapshots = Vec::new();
+  let Ok(mut entries) = tokio::fs::read_dir(board_dir(board)).await else { return snapshots; };
+  while let Ok(Some(entry)) = entries.next_entry().await {
+    let file_name = entry.file_name();
+    let Some(name) = file_name.to_str().and_then(|n| n.strip_suffix(&format!(".{}", board_file::EXTENSION))) else { continue; };
+    if let Some(file) = read_snapshot(board, name).await {
+      snapshots.push(Snapshot { name: name.to_owned(), saved_at: file.metadata.saved_at });
+    }
+  }
+  snapshots.sort_by(|a, b| (a.saved_at, &a.name).cmp(&(b.saved_at, &b.name)));
+  snapshots
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn names_cannot_leave_the_directory() {
+    assert!(valid_name("before review"));
+    assert!(valid_name("v1.2_final-2"));
+    assert!(!valid_name(""));
+    assert!(!valid_name("  "));
+    assert!(!valid_name(".."));
+    assert!(!valid_name("../boards"));
+    assert!(!valid_name("a/b"));
+    assert!(!valid_name(&"a".repeat(MAX_SNAPSHOT_NAME + 1)));
+    assert_eq!(board_dir("a/b"), PathBuf::from(SNAPSHOT_DIR).join("612f62"));
+  }
+}
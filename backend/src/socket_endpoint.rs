@@ -1,9 +1,10 @@
-use std::time::Duration;
+use std::{pin::Pin, time::Duration};
 
 use axum::{extract::{ws::{Message, WebSocket}, WebSocketUpgrade}, response::Response};
 use common::websocket::{ToClient, ToServer};
 use futures_util::{stream::{SplitSink, SplitStream}, SinkExt, StreamExt};
-use tokio::{select, sync::{broadcast, mpsc::{self, unbounded_channel, UnboundedReceiver}}, time::{self, Instant}};
+use futures_util::Future;
+use tokio::{select, sync::{broadcast, mpsc::{self, unbounded_channel, UnboundedReceiver}, oneshot}, time::{self, Instant}};
 
 pub struct Client {
   id: u64,
@@ -27,20 +28,19 @@ pub trait SocketHandler {
   fn tick(&mut self) -> impl std::future::Future<Output = ()> + std::marker::Send;
 }
 
-pub struct SocketEndpoint {
-  message_sender: mpsc::UnboundedSender<ServerMessage>,
+pub struct SocketEndpoint<H> {
+  message_sender: mpsc::UnboundedSender<ServerMessage<H>>,
   kill_sender: broadcast::Sender<()>
 }
 
-impl SocketEndpoint {
-  pub fn new(socket_handler: impl SocketHandler + Send + 'static) -> Self  {
+impl<H: SocketHandler + Send + 'static> SocketEndpoint<H> {
+  pub fn new(socket_handler: H) -> Self  {
     let (message_sender, message_receiver) = unbounded_channel();
     let (kill_sender, _) = broadcast::channel(1);
     tokio::spawn(pass_messages(message_receiver, socket_handler, kill_sender.subscribe()));
-    let state = SocketEndpoint {
+    SocketEndpoint {
       message_sender, kill_sender,
-    };
-    state
+    }
   }
 
   pub fn handler(&self, ws: WebSocketUpgrade) -> Response {
@@ -48,29 +48,65 @@ impl SocketEndpoint {
     let kill_receiver = self.kill_sender.subscribe();
     ws.on_upgrade(move |socket| on_upgrade(socket, message_sender, kill_receiver))
   }
+
+  /// Runs `f` on the handler between two messages and resolves to its result, or to `None` if
+  /// the handler has stopped. The returned future does not borrow the endpoint.
+  pub fn inspect<T: Send + 'static>(&self, f: impl FnOnce(&H) -> T + Send + 'static) -> impl Future<Output = Option<T>> {
+    let (result_sender, result_receiver) = oneshot::channel();
+    let sent = self.message_sender.send(ServerMessage::Inspect(Box::new(move |handler| {
+      let _ = result_sender.send(f(handler));
+    })));
+    async move {
+      sent.ok()?;
+      result_receiver.await.ok()
+    }
+  }
+
+  /// Runs the future made by `f` on the handler between two messages and resolves to its
+  /// result, or to `None` if the handler has stopped. The returned future does not borrow the
+  /// endpoint.
+  pub fn update<T: Send + 'static>(
+    &self,
+    f: impl for<'a> FnOnce(&'a mut H) -> Pin<Box<dyn Future<Output = T> + Send + 'a>> + Send + 'static,
+  ) -> impl Future<Output = Option<T>> {
+    let (result_sender, result_receiver) = oneshot::channel();
+    let sent = self.message_sender.send(ServerMessage::Update(Box::new(move |handler| Box::pin(async move {
+      let _ = result_sender.send(f(handler).await);
+    }))));
+    async move {
+      sent.ok()?;
+      result_receiver.await.ok()
+    }
+  }
 }
 
-impl Drop for SocketEndpoint {
+impl<H> Drop for SocketEndpoint<H> {
   fn drop(&mut self) {
     self.kill_sender.send(()).unwrap();
   }
 }
 
-enum ServerMessage {
+type BoxFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+enum ServerMessage<H> {
   NewClient(Client),
   Message {client_id: u64, message: ToServer },
   Disconnect {client_id: u64},
+  /// Reads the state of the handler outside of a socket
+  Inspect(Box<dyn FnOnce(&H) + Send>),
+  /// Changes the state of the handler outside of a socket
+  Update(Box<dyn for<'a> FnOnce(&'a mut H) -> BoxFuture<'a> + Send>),
 }
 
-async fn on_upgrade(socket: WebSocket, message_sender: mpsc::UnboundedSender<ServerMessage>, kill_receiver: broadcast::Receiver<()>) {
+async fn on_upgrade<H>(socket: WebSocket, message_sender: mpsc::UnboundedSender<ServerMessage<H>>, kill_receiver: broadcast::Receiver<()>) {
   let id = rand::random::<u64>();
   let (to_client, from_client) = socket.split();
   let client = Client { id, socket: to_client };
   socket_loop(message_sender, from_client, kill_receiver, client).await;
 }
 
-async fn socket_loop(
-  message_sender: mpsc::UnboundedSender<ServerMessage>, 
+async fn socket_loop<H>(
+  message_sender: mpsc::UnboundedSender<ServerMessage<H>>, 
   mut from_client: SplitStream<WebSocket>,
   mut kill_receiver: broadcast::Receiver<()>,
   client: Client
@@ -102,9 +138,9 @@ async fn socket_loop(
 
 }
 
-async fn pass_messages(
-  mut channel: UnboundedReceiver<ServerMessage>, 
-  mut socket_handler: impl SocketHandler,
+async fn pass_messages<H: SocketHandler>(
+  mut channel: UnboundedReceiver<ServerMessage<H>>, 
+  mut socket_handler: H,
   mut kill_receiver: broadcast::Receiver<()>,
 ) {
   let mut interval = time::interval_at(
@@ -119,6 +155,8 @@ async fn pass_messages(
           ServerMessage::Message { client_id, message } => 
             socket_handler.on_message(client_id, message).await,
           ServerMessage::Disconnect { client_id } => socket_handler.on_disconnect(client_id).await,
+          ServerMessage::Inspect(f) => f(&socket_handler),
+          ServerMessage::Update(f) => f(&mut socket_handler).await,
         };
       },
       _ = interval.tick() => {
@@ -0,0 +1,71 @@
+//! Spatial index on a board of 100k strokes: building it, culling to a screen sized viewport and
+//! hit-testing a point, against a scan over all bounding boxes
+
+use common::{
+    entities::{Color, Element, Position, Stroke},
+    spatial::{Rect, SpatialIndex},
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const STROKES: usize = 100_000;
+/// Side of the square board the strokes are scattered over
+const BOARD_SIZE: f32 = 100_000.0;
+
+/// Deterministic pseudo-random strokes of 20 points each
+fn board() -> Vec<Element> {
+    let mut state = 42u64;
+    let mut next = move || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) as f32 / (1u64 << 31) as f32
+    };
+    (0..STROKES)
+        .map(|_| {
+            let (x, y) = (next() * BOARD_SIZE, next() * BOARD_SIZE);
+            let points = (0..20)
+                .map(|i| Position { x: x + i as f32 * 5.0, y: y + next() * 50.0 })
+                .collect();
+            Element::Stroke(Stroke { points, width: 4.0, color: Color::BLACK, pressure: vec![], style: Default::default() })
+        })
+        .collect()
+}
+
+fn build(bounds: &[Rect]) -> SpatialIndex<usize> {
+    let mut index = SpatialIndex::default();
+    for (i, rect) in bounds.iter().enumerate() {
+        index.insert(i, *rect);
+    }
+    index
+}
+
+fn spatial(c: &mut Criterion) {
+    let elements = board();
+    let bounds = elements.iter().map(|e| e.bounds().unwrap()).collect::<Vec<_>>();
+    let index = build(&bounds);
+    let center = BOARD_SIZE / 2.0;
+    let viewport = Rect { min: Position { x: center, y: center }, max: Position { x: center + 1920.0, y: center + 1080.0 } };
+    let point = Position { x: center + 500.0, y: center + 500.0 };
+
+    c.bench_function("bounds of 100k strokes", |b| {
+        b.iter(|| elements.iter().filter_map(Element::bounds).count())
+    });
+    c.bench_function("build index of 100k strokes", |b| b.iter(|| build(black_box(&bounds))));
+    c.bench_function("cull viewport with index", |b| b.iter(|| index.query(black_box(&viewport)).len()));
+    c.bench_function("cull viewport with scan", |b| {
+        b.iter(|| bounds.iter().filter(|r| r.intersects(black_box(&viewport))).count())
+    });
+    c.bench_function("hit point with index", |b| b.iter(|| index.query_point(black_box(point)).len()));
+    c.bench_function("hit point with scan", |b| {
+        b.iter(|| bounds.iter().filter(|r| r.intersects(&Rect::point(black_box(point)))).count())
+    });
+    c.bench_function("move one stroke", |b| {
+        let mut index = build(&bounds);
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 7919) % STROKES;
+            index.insert(i, bounds[(i + 1) % STROKES]);
+        })
+    });
+}
+
+criterion_group!(benches, spatial);
+criterion_main!(benches);
@@ -0,0 +1,376 @@
+//! Native file format of a whole board, for backups and for moving boards between servers.
+//!
+//! A board file is a JSON object:
+//!
+//! ```json
+//! {
+//!   "format": "coboard",
+//!   "version": 1,
+//!   "metadata": { "name": "general", "saved_at": 1718000000 },
+//!   "layers": [[0, { "name": "Sketch", "hidden": false, "locked": false }]],
+//!   "elements": [{ "id": 4, "placement": { "layer": 0, "z": 1 }, "element": { "Stroke": { ... } } }],
+//!   "assets": [{ "id": "<sha-256>", "mime": "image/png", "data": "<base64>" }]
+//! }
+//! ```
+//!
+//! Elements and layers are written as [`crate::entities`] serialises them. Assets hold the data of
+//! the images on the board, under the blob ids the image elements refer to.
+//!
+//! Every change to the format increases [`CURRENT_VERSION`] and adds a step to `MIGRATIONS`,
+//! which upgrades files of the previous version. Files are upgraded one version at a time when
+//! they are read, so files of any earlier version can still be opened.
+//!
+//! Versions:
+//! 1. Metadata, layers, element ids and placements, and the image data as assets.
+
+use std::fmt;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+
+use crate::entities::{BlobId, Element, ElementId, Layer, LayerId, Placement};
+
+/// Value of the `format` field, which tells board files apart from other JSON
+pub const FORMAT: &str = "coboard";
+/// Version of the files written by this code
+pub const CURRENT_VERSION: u64 = 1;
+/// Usual extension of board files
+pub const EXTENSION: &str = "coboard";
+/// Largest board file servers accept, images make up most of it
+pub const MAX_FILE_SIZE: usize = 100 * 1024 * 1024;
+
+/// Step of the migration chain, upgrades the fields of a file to the next version
+type Migration = fn(Map<String, Value>) -> Result<Map<String, Value>, FileError>;
+
+/// Migrations from every earlier version, the one at index `i` upgrades version `i + 1`
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize - 1] = [];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Metadata {
+    pub name: String,
+    /// Seconds since the Unix epoch when the file was written, 0 if unknown
+    pub saved_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FileElement {
+    pub id: ElementId,
+    pub placement: Placement,
+    pub element: Element,
+}
+
+/// Data of a blob that an image element refers to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Asset {
+    pub id: BlobId,
+    pub mime: String,
+    #[serde(serialize_with = "encode_data", deserialize_with = "decode_data")]
+    pub data: Vec<u8>,
+}
+
+/// Contents of a board file of the current version
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BoardFile {
+    pub metadata: Metadata,
+    /// Layers from bottom to top
+    pub layers: Vec<(LayerId, Layer)>,
+    pub elements: Vec<FileElement>,
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileError {
+    /// The file is not valid JSON
+    Syntax(String),
+    /// The file is JSON but not a board file
+    NotABoard,
+    /// The file was written by a newer version of the format, which this code cannot read
+    TooNew(u64),
+    /// The file is a board file but its contents do not fit its version
+    Invalid(String),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Syntax(message) => write!(f, "The file is malformed: {message}"),
+            FileError::NotABoard => write!(f, "The file is not a board file"),
+            FileError::TooNew(version) => write!(
+                f,
+                "The file has version {version} of the format, this server reads up to version {CURRENT_VERSION}"
+            ),
+            FileError::Invalid(message) => write!(f, "The board file is invalid: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl BoardFile {
+    pub fn to_json(&self) -> String {
+        let mut value = serde_json::to_value(self).expect("Board files have string keys only");
+        let object = value.as_object_mut().expect("Board files are objects");
+        object.insert("format".to_owned(), json!(FORMAT));
+        object.insert("version".to_owned(), json!(CURRENT_VERSION));
+        value.to_string()
+    }
+
+    /// Reads a board file of any version up to the current one
+    pub fn from_json(text: &str) -> Result<BoardFile, FileError> {
+        Self::upgrade(text, &MIGRATIONS)
+    }
+
+    /// Reads a board file through the chain `migrations`, whose last step upgrades to version
+    /// `migrations.len() + 1`
+    fn upgrade(text: &str, migrations: &[Migration]) -> Result<BoardFile, FileError> {
+        let value = serde_json::from_str::<Value>(text)
+            .map_err(|error| FileError::Syntax(error.to_string()))?;
+        let Value::Object(mut object) = value else {
+            return Err(FileError::NotABoard);
+        };
+        if object.remove("format") != Some(json!(FORMAT)) {
+            return Err(FileError::NotABoard);
+        }
+        let version = match object.remove("version").as_ref().and_then(Value::as_u64) {
+            Some(version) if version >= 1 => version,
+            _ => return Err(FileError::Invalid("The version is missing".to_owned())),
+        };
+        if version > migrations.len() as u64 + 1 {
+            return Err(FileError::TooNew(version));
+        }
+        for migrate in &migrations[version as usize - 1..] {
+            object = migrate(object)?;
+        }
+        let file = serde_json::from_value::<BoardFile>(Value::Object(object))
+            .map_err(|error| FileError::Invalid(error.to_string()))?;
+        file.validate()?;
+        Ok(file)
+    }
+
+    fn validate(&self) -> Result<(), FileError> {
+        let mut ids = self.elements.iter().map(|e| e.id).collect::<Vec<_>>();
+        ids.sort();
+        if ids.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(FileError::Invalid(
+                "Two elements have the same id".to_owned(),
+            ));
+        }
+        let mut layers = self.layers.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        layers.sort();
+        if layers.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(FileError::Invalid("Two layers have the same id".to_owned()));
+        }
+        let unknown_layer = self.elements.iter().find_map(|e| {
+            e.placement
+                .layer
+                .filter(|layer| layers.binary_search(layer).is_err())
+        });
+        if let Some(layer) = unknown_layer {
+            return Err(FileError::Invalid(format!(
+                "Elements are placed on layer {layer}, which does not exist"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Writes asset data as standard base64 with padding, which keeps it readable as a JSON string
+fn encode_data<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(data))
+}
+
+fn decode_data<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    STANDARD.decode(text).map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::{Color, Image, Position, Sticky, Stroke, StrokeStyle, Text};
+
+    use super::*;
+
+    fn p(x: f32, y: f32) -> Position {
+        Position { x, y }
+    }
+
+    fn sample() -> BoardFile {
+        let stroke = Element::Stroke(Stroke {
+            points: vec![p(0.0, 0.0), p(10.5, -3.25)],
+            width: 4.0,
+            color: Color {
+                r: 1,
+                g: 2,
+                b: 3,
+                a: 200,
+            },
+            pressure: vec![0.5, 1.0],
+            style: StrokeStyle {
+                dash: StrokeStyle::DASHED.to_vec(),
+                ..StrokeStyle::default()
+            },
+        });
+        let sticky = Element::Sticky(Sticky {
+            position: p(5.0, 5.0),
+            width: 120.0,
+            height: 100.0,
+            color: Color::STICKY_YELLOW,
+            rotation: 0.25,
+            text: "Ünïcode ✓".to_owned(),
+            revision: 7,
+        });
+        let text = Element::Text(Text {
+            position: p(-1.0, 2.0),
+            content: "a\nb".to_owned(),
+            font_size: 18.0,
+            color: Color::BLACK,
+            rotation: 0.0,
+        });
+        let image = Element::Image(Image {
+            position: p(0.0, 0.0),
+            width: 2.0,
+            height: 1.0,
+            scale: 3.0,
+            rotation: 0.0,
+            blob: "ab".repeat(32),
+        });
+        let placed = |id, layer, z, element| FileElement {
+            id,
+            placement: Placement { layer, z },
+            element,
+        };
+        BoardFile {
+            metadata: Metadata {
+                name: "general".to_owned(),
+                saved_at: 1_718_000_000,
+            },
+            layers: vec![
+                (
+                    3,
+                    Layer {
+                        name: "Sketch".to_owned(),
+                        hidden: false,
+                        locked: true,
+                    },
+                ),
+                (
+                    1,
+                    Layer {
+                        name: "Notes".to_owned(),
+                        hidden: true,
+                        locked: false,
+                    },
+                ),
+            ],
+            elements: vec![
+                placed(0, None, -2, stroke),
+                placed(5, Some(3), 0, sticky),
+                placed(9, Some(1), 4, text),
+                placed(2, None, 1, image),
+            ],
+            assets: vec![Asset {
+                id: "ab".repeat(32),
+                mime: "image/png".to_owned(),
+                data: vec![0x89, b'P', b'N', b'G', 0, 255],
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let file = sample();
+        let json = file.to_json();
+        assert_eq!(BoardFile::from_json(&json), Ok(file));
+        let value = serde_json::from_str::<Value>(&json).unwrap();
+        assert_eq!(value["format"], json!(FORMAT));
+        assert_eq!(value["version"], json!(CURRENT_VERSION));
+        assert_eq!(value["assets"][0]["data"], json!("iVBORwD/"));
+    }
+
+    #[test]
+    fn rejects_files_it_cannot_read() {
+        assert!(matches!(
+            BoardFile::from_json("{"),
+            Err(FileError::Syntax(_))
+        ));
+        assert_eq!(BoardFile::from_json("[]"), Err(FileError::NotABoard));
+        assert_eq!(
+            BoardFile::from_json(r#"{ "type": "excalidraw", "elements": [] }"#),
+            Err(FileError::NotABoard)
+        );
+        assert_eq!(
+            BoardFile::from_json(r#"{ "format": "coboard", "version": 99 }"#),
+            Err(FileError::TooNew(99))
+        );
+        assert!(matches!(
+            BoardFile::from_json(r#"{ "format": "coboard" }"#),
+            Err(FileError::Invalid(_))
+        ));
+        assert!(matches!(
+            BoardFile::from_json(r#"{ "format": "coboard", "version": 1 }"#),
+            Err(FileError::Invalid(_))
+        ));
+        let mut duplicated = sample();
+        duplicated.elements[1].id = duplicated.elements[0].id;
+        assert!(matches!(
+            BoardFile::from_json(&duplicated.to_json()),
+            Err(FileError::Invalid(_))
+        ));
+        let mut orphaned = sample();
+        orphaned.layers.clear();
+        assert!(matches!(
+            BoardFile::from_json(&orphaned.to_json()),
+            Err(FileError::Invalid(_))
+        ));
+    }
+    /// Stub step that appends `mark` to the name of the board
+    fn rename(mut object: Map<String, Value>, mark: &str) -> Result<Map<String, Value>, FileError> {
+        let name = &mut object["metadata"]["name"];
+        *name = json!(format!("{}{mark}", name.as_str().unwrap()));
+        Ok(object)
+    }
+
+    /// The current file of `sample` as a file of `version`
+    fn at_version(version: u64) -> String {
+        let mut value = serde_json::from_str::<Value>(&sample().to_json()).unwrap();
+        value["version"] = json!(version);
+        value.to_string()
+    }
+
+    #[test]
+    fn upgrades_files_through_the_rest_of_the_chain() {
+        let migrations: [Migration; 2] = [|o| rename(o, "1"), |o| rename(o, "2")];
+        let named = |name: &str| {
+            let mut file = sample();
+            file.metadata.name = name.to_owned();
+            Ok(file)
+        };
+        assert_eq!(
+            BoardFile::upgrade(&at_version(1), &migrations),
+            named("general12")
+        );
+        assert_eq!(
+            BoardFile::upgrade(&at_version(2), &migrations),
+            named("general2")
+        );
+        assert_eq!(
+            BoardFile::upgrade(&at_version(3), &migrations),
+            named("general")
+        );
+        assert_eq!(
+            BoardFile::upgrade(&at_version(4), &migrations),
+            Err(FileError::TooNew(4))
+        );
+        assert!(matches!(
+            BoardFile::upgrade(&at_version(0), &migrations),
+            Err(FileError::Invalid(_))
+        ));
+        let failing: [Migration; 1] = [|_| Err(FileError::Invalid("Stub".to_owned()))];
+        assert_eq!(
+            BoardFile::upgrade(&at_version(1), &failing),
+            Err(FileError::Invalid("Stub".to_owned()))
+        );
+        assert_eq!(BoardFile::upgrade(&at_version(2), &failing), Ok(sample()));
+    }
+}
@@ -0,0 +1,581 @@
+//! Excalidraw scenes, as saved in `.excalidraw` files or copied to the clipboard. The hand drawn
+//! look is not imported, shapes get clean outlines.
+
+use std::f32::consts::PI;
+
+use serde::Deserialize;
+use serde_json::{error::Category, Value};
+
+use crate::entities::{Cap, Color, Element, Join, Position, Stroke, StrokeStyle, Text};
+
+use super::{distance, parse_color, with_opacity, Import, ImportError, Outline, FILLS};
+
+/// Length of the sides of arrowheads
+const ARROWHEAD_LENGTH: f32 = 20.0;
+/// Angle between the shaft of an arrow and each side of its head
+const ARROWHEAD_ANGLE: f32 = PI / 7.0;
+/// Thickness of freehand lines relative to their stroke width, as Excalidraw draws them
+const FREEDRAW_WIDTH: f32 = 4.25;
+/// Radius of rounded rectangles relative to their shorter side, up to `MAX_CORNER_RADIUS`
+const CORNER_RADIUS: f32 = 0.25;
+const MAX_CORNER_RADIUS: f32 = 32.0;
+/// Roundness type whose radius stops growing at `MAX_CORNER_RADIUS`, older types keep growing
+const ADAPTIVE_RADIUS: u32 = 3;
+
+#[derive(Deserialize)]
+struct Scene {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    elements: Option<Vec<Value>>,
+}
+
+#[derive(Deserialize)]
+struct Roundness {
+    #[serde(rename = "type")]
+    kind: u32,
+    value: Option<f32>,
+}
+
+/// The fields of Excalidraw elements that can be imported. Elements are bounded by the box at
+/// `x`, `y` and rotated clockwise by `angle` radians around its centre.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Shape {
+    #[serde(rename = "type")]
+    kind: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    angle: f32,
+    stroke_color: String,
+    background_color: String,
+    stroke_width: f32,
+    stroke_style: String,
+    /// In percent
+    opacity: f32,
+    /// Points of lines, arrows and freehand lines relative to `x` and `y`
+    points: Vec<[f32; 2]>,
+    pressures: Vec<f32>,
+    simulate_pressure: bool,
+    text: String,
+    font_size: f32,
+    roundness: Option<Roundness>,
+    start_arrowhead: Option<String>,
+    end_arrowhead: Option<String>,
+    is_deleted: bool,
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Shape {
+            kind: String::new(),
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            angle: 0.0,
+            stroke_color: "#1e1e1e".to_owned(),
+            background_color: "transparent".to_owned(),
+            stroke_width: 2.0,
+            stroke_style: "solid".to_owned(),
+            opacity: 100.0,
+            points: vec![],
+            pressures: vec![],
+            simulate_pressure: true,
+            text: String::new(),
+            font_size: 20.0,
+            roundness: None,
+            start_arrowhead: None,
+            end_arrowhead: None,
+            is_deleted: false,
+        }
+    }
+}
+
+impl Shape {
+    fn points(&self) -> Vec<Position> {
+        self.points
+            .iter()
+            .map(|[x, y]| Position {
+                x: self.x + x,
+                y: self.y + y,
+            })
+            .collect()
+    }
+
+    /// Centre of the box the element is rotated around. The box of a line is the box around its
+    /// points, which need not start at `x` and `y`.
+    fn center(&self) -> Position {
+        let points = self.points();
+        let (min, max) = match points.first() {
+            Some(first) => points.iter().fold((*first, *first), |(min, max), p| {
+                (
+                    Position {
+                        x: min.x.min(p.x),
+                        y: min.y.min(p.y),
+                    },
+                    Position {
+                        x: max.x.max(p.x),
+                        y: max.y.max(p.y),
+                    },
+                )
+            }),
+            None => (
+                Position {
+                    x: self.x,
+                    y: self.y,
+                },
+                Position {
+                    x: self.x + self.width,
+                    y: self.y + self.height,
+                },
+            ),
+        };
+        Position {
+            x: (min.x + max.x) / 2.0,
+            y: (min.y + max.y) / 2.0,
+        }
+    }
+
+    /// Moves a point of the unrotated element to where it is drawn
+    fn rotate(&self, point: Position) -> Position {
+        let center = self.center();
+        let (sin, cos) = self.angle.sin_cos();
+        let (dx, dy) = (point.x - center.x, point.y - center.y);
+        Position {
+            x: center.x + cos * dx - sin * dy,
+            y: center.y + sin * dx + cos * dy,
+        }
+    }
+
+    fn corner_radius(&self) -> f32 {
+        let Some(roundness) = &self.roundness else {
+            return 0.0;
+        };
+        let side = self.width.min(self.height);
+        match roundness.kind {
+            ADAPTIVE_RADIUS => {
+                let max = roundness.value.unwrap_or(MAX_CORNER_RADIUS);
+                (side * CORNER_RADIUS).min(max)
+            }
+            _ => side * CORNER_RADIUS,
+        }
+    }
+}
+
+/// Outline of an arrowhead of `kind` whose tip is at `tip`, on a shaft coming from `from`.
+/// `None` for kinds that cannot be drawn as a line.
+fn arrowhead(kind: &str, tip: Position, from: Position) -> Option<Outline> {
+    let length = distance(from, tip);
+    if length == 0.0 {
+        return Some(Outline::default());
+    }
+    let back = ((from.x - tip.x) / length, (from.y - tip.y) / length);
+    let side = |angle: f32| {
+        let (sin, cos) = angle.sin_cos();
+        Position {
+            x: tip.x + (cos * back.0 - sin * back.1) * ARROWHEAD_LENGTH,
+            y: tip.y + (sin * back.0 + cos * back.1) * ARROWHEAD_LENGTH,
+        }
+    };
+    let mut outline = Outline::default();
+    match kind {
+        "arrow" => {
+            outline.corner(side(ARROWHEAD_ANGLE));
+            outline.corner(tip);
+            outline.corner(side(-ARROWHEAD_ANGLE));
+        }
+        "triangle" | "triangle_outline" => {
+            outline.corner(side(ARROWHEAD_ANGLE));
+            outline.corner(tip);
+            outline.corner(side(-ARROWHEAD_ANGLE));
+            outline.close();
+        }
+        "bar" => {
+            outline.corner(side(PI / 2.0));
+            outline.corner(side(-PI / 2.0));
+        }
+        _ => return None,
+    }
+    Some(outline)
+}
+
+fn convert(import: &mut Import, shape: &Shape) {
+    let color = parse_color(&shape.stroke_color).unwrap_or_else(|| {
+        import.unsupported(format!("The colour {}", shape.stroke_color));
+        Color::BLACK
+    });
+    let color = with_opacity(color, shape.opacity / 100.0);
+    let dash = match shape.stroke_style.as_str() {
+        "dashed" => StrokeStyle::DASHED.to_vec(),
+        "dotted" => StrokeStyle::DOTTED.to_vec(),
+        _ => vec![],
+    };
+    let style = StrokeStyle {
+        join: Join::Round,
+        cap: Cap::Round,
+        dash,
+        dash_offset: 0.0,
+    };
+    let filled = parse_color(&shape.background_color).is_some_and(|color| color.a > 0);
+    let origin = Position {
+        x: shape.x,
+        y: shape.y,
+    };
+    let mut outlines = vec![];
+    match shape.kind.as_str() {
+        "rectangle" => {
+            let radius = shape.corner_radius();
+            outlines.push(Outline::rect(
+                origin,
+                shape.width,
+                shape.height,
+                (radius, radius),
+            ));
+        }
+        "diamond" => {
+            let (w, h) = (shape.width, shape.height);
+            let mut outline = Outline::default();
+            for (x, y) in [(w / 2.0, 0.0), (w, h / 2.0), (w / 2.0, h), (0.0, h / 2.0)] {
+                outline.corner(Position {
+                    x: origin.x + x,
+                    y: origin.y + y,
+                });
+            }
+            outline.close();
+            outlines.push(outline);
+        }
+        "ellipse" => {
+            let center = Position {
+                x: origin.x + shape.width / 2.0,
+                y: origin.y + shape.height / 2.0,
+            };
+            outlines.push(Outline::ellipse(
+                center,
+                (shape.width / 2.0, shape.height / 2.0),
+                0.0,
+            ));
+        }
+        "line" | "arrow" => {
+            let points = shape.points();
+            let mut outline = Outline::default();
+            for (i, point) in points.iter().enumerate() {
+                // Rounded lines are curves through their points
+                match shape.roundness.is_some() && i > 0 && i + 1 < points.len() {
+                    true => outline.smooth(*point),
+                    false => outline.corner(*point),
+                }
+            }
+            outlines.push(outline);
+            let mut ends = vec![];
+            if let [first, second, ..] = points[..] {
+                ends.push((&shape.start_arrowhead, first, second));
+            }
+            if let [.., second_last, last] = points[..] {
+                ends.push((&shape.end_arrowhead, last, second_last));
+            }
+            for (kind, tip, from) in ends {
+                let Some(kind) = kind else { continue };
+                match arrowhead(kind, tip, from) {
+                    Some(head) => outlines.push(head),
+                    None => import.unsupported(format!("Arrowheads of type {kind}")),
+                }
+                if kind == "triangle" {
+                    import.unsupported(FILLS);
+                }
+            }
+        }
+        "freedraw" => {
+            let points = shape
+                .points()
+                .into_iter()
+                .map(|p| shape.rotate(p))
+                .collect::<Vec<_>>();
+            let pressure = match !shape.simulate_pressure && shape.pressures.len() == points.len() {
+                true => shape.pressures.clone(),
+                false => vec![],
+            };
+            if points.is_empty() {
+                return;
+            }
+            import.elements.push(Element::Stroke(Stroke {
+                points,
+                width: shape.stroke_width * FREEDRAW_WIDTH,
+                color,
+                pressure,
+                style: StrokeStyle::default(),
+            }));
+            return;
+        }
+        "text" => {
+            if shape.text.trim().is_empty() {
+                return;
+            }
+            import.elements.push(Element::Text(Text {
+                // The text turns around the centre of its box, the board turns it around its top
+                // left corner, which is where that corner ends up
+                position: shape.rotate(origin),
+                content: shape.text.clone(),
+                font_size: shape.font_size,
+                color,
+                rotation: shape.angle,
+            }));
+            return;
+        }
+        "image" => {
+            import.unsupported("Images");
+            return;
+        }
+        "frame" | "magicframe" => {
+            import.unsupported("Frames, their contents are imported without them");
+            return;
+        }
+        "embeddable" | "iframe" => {
+            import.unsupported("Embedded web pages");
+            return;
+        }
+        kind => {
+            import.unsupported(format!("Elements of type {kind}"));
+            return;
+        }
+    }
+    if filled {
+        import.unsupported(FILLS);
+    }
+    for (i, outline) in outlines.into_iter().enumerate() {
+        // Arrowheads are solid even on dashed arrows
+        let style = match i {
+            0 => style.clone(),
+            _ => StrokeStyle {
+                dash: vec![],
+                ..style.clone()
+            },
+        };
+        let outline = outline.map(|p| shape.rotate(p));
+        import
+            .elements
+            .extend(outline.stroke(shape.stroke_width, color, style));
+    }
+}
+
+/// Converts an Excalidraw scene
+pub fn excalidraw(text: &str) -> Result<Import, ImportError> {
+    let not_excalidraw = || ImportError::Format("The file is not an Excalidraw drawing".to_owned());
+    let scene = serde_json::from_str::<Scene>(text).map_err(|error| match error.classify() {
+        Category::Data => not_excalidraw(),
+        _ => ImportError::Syntax(error.to_string()),
+    })?;
+    if scene
+        .kind
+        .is_some_and(|kind| !kind.starts_with("excalidraw"))
+    {
+        return Err(not_excalidraw());
+    }
+    let elements = scene.elements.ok_or_else(not_excalidraw)?;
+    let mut import = Import::default();
+    for element in elements {
+        match serde_json::from_value::<Shape>(element) {
+            Ok(shape) if shape.is_deleted => (),
+            Ok(shape) => convert(&mut import, &shape),
+            Err(_) => import.unsupported("Malformed elements, left out"),
+        }
+    }
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn p(x: f32, y: f32) -> Position {
+        Position { x, y }
+    }
+
+    fn close(a: Position, b: Position) -> bool {
+        (a.x - b.x).abs() < 1e-3 && (a.y - b.y).abs() < 1e-3
+    }
+
+    fn import(elements: Value) -> Import {
+        excalidraw(&json!({ "type": "excalidraw", "version": 2, "elements": elements }).to_string())
+            .unwrap()
+    }
+
+    fn corners(element: &Element) -> Vec<Position> {
+        let Element::Stroke(stroke) = element else {
+            panic!("{element:?} is not a stroke");
+        };
+        let mut points = stroke.points.clone();
+        points.dedup();
+        points
+    }
+
+    #[test]
+    fn converts_shapes() {
+        let imported = import(json!([
+            { "type": "rectangle", "x": 10, "y": 20, "width": 100, "height": 50, "strokeColor": "#e03131", "strokeWidth": 4, "opacity": 50 },
+            { "type": "diamond", "x": 0, "y": 0, "width": 20, "height": 10, "strokeStyle": "dashed" },
+            { "type": "ellipse", "x": 0, "y": 0, "width": 20, "height": 10 },
+        ]));
+        assert!(imported.unsupported.is_empty());
+        assert_eq!(
+            corners(&imported.elements[0]),
+            vec![
+                p(10.0, 20.0),
+                p(110.0, 20.0),
+                p(110.0, 70.0),
+                p(10.0, 70.0),
+                p(10.0, 20.0)
+            ]
+        );
+        let Element::Stroke(rectangle) = &imported.elements[0] else {
+            unreachable!()
+        };
+        assert_eq!(
+            rectangle.color,
+            Color {
+                r: 0xe0,
+                g: 0x31,
+                b: 0x31,
+                a: 128
+            }
+        );
+        assert_eq!(rectangle.width, 4.0);
+        assert_eq!(
+            corners(&imported.elements[1]),
+            vec![
+                p(10.0, 0.0),
+                p(20.0, 5.0),
+                p(10.0, 10.0),
+                p(0.0, 5.0),
+                p(10.0, 0.0)
+            ]
+        );
+        let Element::Stroke(diamond) = &imported.elements[1] else {
+            unreachable!()
+        };
+        assert_eq!(diamond.style.dash, StrokeStyle::DASHED);
+        let bounds = imported.elements[2].bounds().unwrap();
+        assert!((bounds.width() - 20.0 - 2.0 * std::f32::consts::SQRT_2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rotates_around_the_centre() {
+        let imported = import(json!([
+            { "type": "rectangle", "x": 0, "y": 0, "width": 20, "height": 10, "angle": PI / 2.0 },
+            { "type": "text", "x": 0, "y": 0, "width": 20, "height": 10, "angle": PI / 2.0, "text": "Hi", "fontSize": 8 },
+        ]));
+        let points = corners(&imported.elements[0]);
+        // Turned a quarter clockwise, the first corner goes from the top left to the top right
+        assert!(close(points[0], p(15.0, -5.0)), "{points:?}");
+        assert!(close(points[2], p(5.0, 15.0)), "{points:?}");
+        let Element::Text(text) = &imported.elements[1] else {
+            unreachable!()
+        };
+        assert!(close(text.position, p(15.0, -5.0)));
+        assert_eq!(text.rotation, PI / 2.0);
+        assert_eq!(text.font_size, 8.0);
+        assert_eq!(text.content, "Hi");
+    }
+
+    #[test]
+    fn rounds_rectangle_corners() {
+        let imported = import(json!([
+            { "type": "rectangle", "x": 0, "y": 0, "width": 400, "height": 200, "roundness": { "type": 3 } },
+            { "type": "rectangle", "x": 0, "y": 0, "width": 40, "height": 20, "roundness": { "type": 2 } },
+        ]));
+        let Element::Stroke(large) = &imported.elements[0] else {
+            unreachable!()
+        };
+        assert!(large.points.contains(&p(MAX_CORNER_RADIUS, 0.0)));
+        let Element::Stroke(small) = &imported.elements[1] else {
+            unreachable!()
+        };
+        assert!(small.points.contains(&p(5.0, 0.0)));
+    }
+
+    #[test]
+    fn converts_arrows_and_freehand_lines() {
+        let imported = import(json!([
+            { "type": "arrow", "x": 10, "y": 10, "points": [[0, 0], [100, 0]], "endArrowhead": "arrow", "strokeStyle": "dotted" },
+            { "type": "line", "x": 0, "y": 0, "points": [[0, 0], [50, 50], [100, 0]], "roundness": { "type": 2 } },
+            { "type": "freedraw", "x": 5, "y": 5, "points": [[0, 0], [1, 1], [2, 1]], "pressures": [0.1, 0.5, 0.9], "simulatePressure": false, "strokeWidth": 1 },
+        ]));
+        assert_eq!(imported.elements.len(), 4);
+        let head = corners(&imported.elements[1]);
+        assert_eq!(head.len(), 3);
+        assert_eq!(head[1], p(110.0, 10.0));
+        assert!(head[0].x < 110.0 && head[0].y < 10.0 && head[2].y > 10.0);
+        let (Element::Stroke(shaft), Element::Stroke(head)) =
+            (&imported.elements[0], &imported.elements[1])
+        else {
+            unreachable!()
+        };
+        assert_eq!(shaft.style.dash, StrokeStyle::DOTTED);
+        assert!(head.style.dash.is_empty());
+        // The middle point of a rounded line is passed smoothly, not as a corner
+        let Element::Stroke(curve) = &imported.elements[2] else {
+            unreachable!()
+        };
+        assert_eq!(
+            curve.points.iter().filter(|q| **q == p(50.0, 50.0)).count(),
+            1
+        );
+        let Element::Stroke(freehand) = &imported.elements[3] else {
+            unreachable!()
+        };
+        assert_eq!(freehand.points, vec![p(5.0, 5.0), p(6.0, 6.0), p(7.0, 6.0)]);
+        assert_eq!(freehand.pressure, vec![0.1, 0.5, 0.9]);
+        assert_eq!(freehand.width, FREEDRAW_WIDTH);
+    }
+
+    #[test]
+    fn reports_what_cannot_be_imported() {
+        let imported = import(json!([
+            { "type": "rectangle", "width": 10, "height": 10, "backgroundColor": "#a5d8ff" },
+            { "type": "image", "fileId": "abc", "width": 10, "height": 10 },
+            { "type": "frame", "width": 10, "height": 10, "name": "Frame 1" },
+            { "type": "arrow", "points": [[0, 0], [10, 0]], "startArrowhead": "circle" },
+            { "type": "rectangle", "width": "wide" },
+            { "type": "rectangle", "width": 10, "height": 10, "isDeleted": true },
+            { "type": "sticker" },
+        ]));
+        assert_eq!(
+            imported.unsupported,
+            vec![
+                FILLS,
+                "Images",
+                "Frames, their contents are imported without them",
+                "Arrowheads of type circle",
+                "Malformed elements, left out",
+                "Elements of type sticker",
+            ]
+        );
+        assert_eq!(imported.elements.len(), 2);
+    }
+
+    #[test]
+    fn reads_clipboard_scenes() {
+        let clipboard = json!({ "type": "excalidraw/clipboard", "elements": [{ "type": "line", "points": [[0, 0], [1, 0]] }] });
+        assert_eq!(
+            excalidraw(&clipboard.to_string()).unwrap().elements.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(excalidraw("{"), Err(ImportError::Syntax(_))));
+        assert!(matches!(excalidraw("[1, 2]"), Err(ImportError::Format(_))));
+        assert!(matches!(
+            excalidraw(r#"{ "type": "tldraw", "elements": [] }"#),
+            Err(ImportError::Format(_))
+        ));
+        assert!(matches!(
+            excalidraw(r#"{ "shapes": [] }"#),
+            Err(ImportError::Format(_))
+        ));
+    }
+}
@@ -0,0 +1,461 @@
+//! Conversion of drawings made with other tools into board elements.
+//!
+//! Importers convert what the board can show and name everything else in
+//! [`Import::unsupported`], so that nothing is left out without the user knowing. The board has
+//! no filled shapes, so filled areas are imported as their outlines.
+
+mod excalidraw;
+mod svg;
+mod xml;
+
+use std::{f32::consts::TAU, fmt};
+
+pub use excalidraw::excalidraw;
+pub use svg::svg;
+
+use crate::entities::{Color, Element, Position, Stroke, StrokeStyle, Transform};
+
+/// Distance between the points curves are flattened into, in board units. Strokes are smoothed
+/// through their points for display, so the points can be far apart.
+const CURVE_STEP: f32 = 8.0;
+/// Most points a single curve is flattened into
+const MAX_CURVE_POINTS: usize = 64;
+/// Reported for shapes drawn with a fill
+const FILLS: &str = "Fills, imported as outlines";
+
+/// Elements of an imported drawing from bottom to top, with what could not be converted
+#[derive(Debug, Default)]
+pub struct Import {
+    pub elements: Vec<Element>,
+    /// Constructs of the drawing that were left out or imported differently, each named once
+    pub unsupported: Vec<String>,
+}
+
+impl Import {
+    fn unsupported(&mut self, what: impl Into<String>) {
+        let what = what.into();
+        if !self.unsupported.contains(&what) {
+            self.unsupported.push(what);
+        }
+    }
+
+    /// Moves the elements so that the top left corner of their bounds is at `position`
+    pub fn place_at(&mut self, position: Position) {
+        let Some(bounds) = self
+            .elements
+            .iter()
+            .filter_map(Element::bounds)
+            .reduce(|a, b| a.union(&b))
+        else {
+            return;
+        };
+        let transform = Transform {
+            translation: Position {
+                x: position.x - bounds.min.x,
+                y: position.y - bounds.min.y,
+            },
+            ..Transform::IDENTITY
+        };
+        for element in &mut self.elements {
+            element.transform(&transform);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportError {
+    /// The file cannot be read, with a description of where it went wrong
+    Syntax(String),
+    /// The file can be read but is not a drawing in the expected format
+    Format(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Syntax(message) => write!(f, "The file is malformed: {message}"),
+            ImportError::Format(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Colours that CSS names, besides the extended keywords that drawings rarely use
+const NAMED_COLORS: [(&str, [u8; 3]); 18] = [
+    ("black", [0, 0, 0]),
+    ("silver", [192, 192, 192]),
+    ("gray", [128, 128, 128]),
+    ("grey", [128, 128, 128]),
+    ("white", [255, 255, 255]),
+    ("maroon", [128, 0, 0]),
+    ("red", [255, 0, 0]),
+    ("purple", [128, 0, 128]),
+    ("fuchsia", [255, 0, 255]),
+    ("green", [0, 128, 0]),
+    ("lime", [0, 255, 0]),
+    ("olive", [128, 128, 0]),
+    ("yellow", [255, 255, 0]),
+    ("navy", [0, 0, 128]),
+    ("blue", [0, 0, 255]),
+    ("teal", [0, 128, 128]),
+    ("aqua", [0, 255, 255]),
+    ("orange", [255, 165, 0]),
+];
+
+/// Parses a CSS colour: `#rgb`, `#rrggbb`, `#rrggbbaa`, `rgb()`, `rgba()`, a basic colour name or
+/// `transparent`
+fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim().to_ascii_lowercase();
+    if value == "transparent" {
+        return Some(Color {
+            a: 0,
+            ..Color::BLACK
+        });
+    }
+    if let Some(hex) = value.strip_prefix('#') {
+        let digits = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<_>>>()?;
+        return match digits[..] {
+            [r, g, b] => Some(Color {
+                r: r * 17,
+                g: g * 17,
+                b: b * 17,
+                a: 255,
+            }),
+            [r1, r2, g1, g2, b1, b2] => Some(Color {
+                r: r1 * 16 + r2,
+                g: g1 * 16 + g2,
+                b: b1 * 16 + b2,
+                a: 255,
+            }),
+            [r1, r2, g1, g2, b1, b2, a1, a2] => Some(Color {
+                r: r1 * 16 + r2,
+                g: g1 * 16 + g2,
+                b: b1 * 16 + b2,
+                a: a1 * 16 + a2,
+            }),
+            _ => None,
+        };
+    }
+    if let Some(arguments) = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))
+        .and_then(|v| v.strip_suffix(')'))
+    {
+        let parts = arguments
+            .split([',', ' ', '/'])
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>();
+        let channel = |part: &str| match part.strip_suffix('%') {
+            Some(percent) => percent.parse::<f32>().ok().map(|p| p / 100.0 * 255.0),
+            None => part.parse::<f32>().ok(),
+        };
+        let alpha = |part: &str| match part.strip_suffix('%') {
+            Some(percent) => percent.parse::<f32>().ok().map(|p| p / 100.0),
+            None => part.parse::<f32>().ok(),
+        };
+        let byte = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+        return match parts[..] {
+            [r, g, b] => Some(Color {
+                r: byte(channel(r)?),
+                g: byte(channel(g)?),
+                b: byte(channel(b)?),
+                a: 255,
+            }),
+            [r, g, b, a] => Some(Color {
+                r: byte(channel(r)?),
+                g: byte(channel(g)?),
+                b: byte(channel(b)?),
+                a: byte(alpha(a)? * 255.0),
+            }),
+            _ => None,
+        };
+    }
+    NAMED_COLORS
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, [r, g, b])| Color {
+            r: *r,
+            g: *g,
+            b: *b,
+            a: 255,
+        })
+}
+
+/// `color` with its alpha multiplied by `opacity`
+fn with_opacity(color: Color, opacity: f32) -> Color {
+    Color {
+        a: (color.a as f32 * opacity.clamp(0.0, 1.0)).round() as u8,
+        ..color
+    }
+}
+
+/// Points of a polyline being imported. Strokes are smoothed through their points for display,
+/// which would round off corners, so each corner is repeated: a smooth curve through a repeated
+/// point arrives and leaves along the straight segments next to it.
+#[derive(Debug, Default, Clone)]
+struct Outline {
+    points: Vec<Position>,
+}
+
+impl Outline {
+    fn corner(&mut self, point: Position) {
+        self.points.extend([point, point]);
+    }
+
+    /// Point on a curve, which the smoothing passes through without a corner
+    fn smooth(&mut self, point: Position) {
+        self.points.push(point);
+    }
+
+    fn last(&self) -> Option<Position> {
+        self.points.last().copied()
+    }
+
+    /// Goes back to the first point
+    fn close(&mut self) {
+        if let Some(first) = self.points.first().copied() {
+            self.corner(first);
+        }
+    }
+
+    /// Number of points a curve of roughly `length` is flattened into
+    fn curve_points(length: f32) -> usize {
+        ((length / CURVE_STEP).ceil() as usize).clamp(1, MAX_CURVE_POINTS)
+    }
+
+    fn cubic_to(&mut self, control1: Position, control2: Position, to: Position) {
+        let from = self.last().unwrap_or(to);
+        let length =
+            distance(from, control1) + distance(control1, control2) + distance(control2, to);
+        let count = Self::curve_points(length);
+        for i in 1..count {
+            let t = i as f32 / count as f32;
+            let s = 1.0 - t;
+            let at = |a: f32, b: f32, c: f32, d: f32| {
+                s * s * s * a + 3.0 * s * s * t * b + 3.0 * s * t * t * c + t * t * t * d
+            };
+            self.smooth(Position {
+                x: at(from.x, control1.x, control2.x, to.x),
+                y: at(from.y, control1.y, control2.y, to.y),
+            });
+        }
+        self.corner(to);
+    }
+
+    fn quadratic_to(&mut self, control: Position, to: Position) {
+        let from = self.last().unwrap_or(to);
+        let count = Self::curve_points(distance(from, control) + distance(control, to));
+        for i in 1..count {
+            let t = i as f32 / count as f32;
+            let s = 1.0 - t;
+            let at = |a: f32, b: f32, c: f32| s * s * a + 2.0 * s * t * b + t * t * c;
+            self.smooth(Position {
+                x: at(from.x, control.x, to.x),
+                y: at(from.y, control.y, to.y),
+            });
+        }
+        self.corner(to);
+    }
+
+    /// Adds the points of an elliptical arc with `radii` around `center`, whose axes are rotated
+    /// clockwise by `rotation`, from angle `start` over `sweep` radians. Only the points between
+    /// the ends are added, the end is added by the caller so that it is exact.
+    fn arc(&mut self, center: Position, radii: (f32, f32), rotation: f32, start: f32, sweep: f32) {
+        let count = Self::curve_points(sweep.abs() * radii.0.max(radii.1)).max(4);
+        let (sin, cos) = rotation.sin_cos();
+        let point = |angle: f32| {
+            let (x, y) = (radii.0 * angle.cos(), radii.1 * angle.sin());
+            Position {
+                x: center.x + cos * x - sin * y,
+                y: center.y + sin * x + cos * y,
+            }
+        };
+        for i in 1..count {
+            self.smooth(point(start + sweep * i as f32 / count as f32));
+        }
+    }
+
+    /// Closed outline of an ellipse, starting on its first axis
+    fn ellipse(center: Position, radii: (f32, f32), rotation: f32) -> Outline {
+        let mut outline = Outline::default();
+        let (sin, cos) = rotation.sin_cos();
+        outline.corner(Position {
+            x: center.x + cos * radii.0,
+            y: center.y + sin * radii.0,
+        });
+        outline.arc(center, radii, rotation, 0.0, TAU);
+        outline.close();
+        outline
+    }
+
+    /// Closed outline of an axis aligned rectangle whose corners are rounded with `radii`
+    fn rect(origin: Position, width: f32, height: f32, radii: (f32, f32)) -> Outline {
+        let (rx, ry) = (
+            radii.0.clamp(0.0, width / 2.0),
+            radii.1.clamp(0.0, height / 2.0),
+        );
+        let p = |x: f32, y: f32| Position {
+            x: origin.x + x,
+            y: origin.y + y,
+        };
+        let mut outline = Outline::default();
+        outline.corner(p(rx, 0.0));
+        if rx > 0.0 && ry > 0.0 {
+            let quarter = TAU / 4.0;
+            outline.corner(p(width - rx, 0.0));
+            outline.arc(p(width - rx, ry), (rx, ry), 0.0, -quarter, quarter);
+            outline.corner(p(width, ry));
+            outline.corner(p(width, height - ry));
+            outline.arc(p(width - rx, height - ry), (rx, ry), 0.0, 0.0, quarter);
+            outline.corner(p(width - rx, height));
+            outline.corner(p(rx, height));
+            outline.arc(p(rx, height - ry), (rx, ry), 0.0, quarter, quarter);
+            outline.corner(p(0.0, height - ry));
+            outline.corner(p(0.0, ry));
+            outline.arc(p(rx, ry), (rx, ry), 0.0, 2.0 * quarter, quarter);
+            outline.close();
+        } else {
+            outline.corner(p(width, 0.0));
+            outline.corner(p(width, height));
+            outline.corner(p(0.0, height));
+            outline.close();
+        }
+        outline
+    }
+
+    /// Applies `f` to every point
+    fn map(mut self, f: impl Fn(Position) -> Position) -> Outline {
+        for point in &mut self.points {
+            *point = f(*point);
+        }
+        self
+    }
+
+    /// Stroke along the outline, `None` if it does not go anywhere
+    fn stroke(self, width: f32, color: Color, style: StrokeStyle) -> Option<Element> {
+        let first = *self.points.first()?;
+        if self.points.iter().all(|p| *p == first)
+            || self
+                .points
+                .iter()
+                .any(|p| !p.x.is_finite() || !p.y.is_finite())
+        {
+            return None;
+        }
+        Some(Element::Stroke(Stroke {
+            points: self.points,
+            width,
+            color,
+            pressure: vec![],
+            style,
+        }))
+    }
+}
+
+fn distance(a: Position, b: Position) -> f32 {
+    (b.x - a.x).hypot(b.y - a.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{line_drawing::display_strip, spatial::Rect};
+
+    use super::*;
+
+    fn p(x: f32, y: f32) -> Position {
+        Position { x, y }
+    }
+
+    #[test]
+    fn parses_css_colors() {
+        let rgb = |r, g, b| Some(Color { r, g, b, a: 255 });
+        assert_eq!(parse_color("#fa0"), rgb(255, 170, 0));
+        assert_eq!(parse_color("#1E1E1E"), rgb(30, 30, 30));
+        assert_eq!(
+            parse_color("#ff000080"),
+            Some(Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 128
+            })
+        );
+        assert_eq!(parse_color(" rgb(10, 20, 30) "), rgb(10, 20, 30));
+        assert_eq!(
+            parse_color("rgba(10,20,30,0.5)"),
+            Some(Color {
+                r: 10,
+                g: 20,
+                b: 30,
+                a: 128
+            })
+        );
+        assert_eq!(parse_color("rgb(100% 0% 0%)"), rgb(255, 0, 0));
+        assert_eq!(parse_color("Navy"), rgb(0, 0, 128));
+        assert_eq!(parse_color("transparent").map(|c| c.a), Some(0));
+        assert_eq!(parse_color("#12345"), None);
+        assert_eq!(parse_color("url(#gradient)"), None);
+        assert_eq!(parse_color("papayawhip"), None);
+    }
+
+    #[test]
+    fn corners_stay_sharp_when_displayed() {
+        let outline = Outline::rect(p(0.0, 0.0), 100.0, 50.0, (0.0, 0.0));
+        let Some(Element::Stroke(stroke)) =
+            outline.stroke(2.0, Color::BLACK, StrokeStyle::default())
+        else {
+            panic!("The rectangle has an outline");
+        };
+        // Without the repeated corners the smoothing would bulge the sides outwards
+        let bounds = Rect::around(
+            display_strip(&stroke, 1.0)
+                .iter()
+                .map(|q| p(q.x as f32, q.y as f32)),
+        )
+        .unwrap();
+        assert!(bounds.min.x > -1.1 && bounds.min.y > -1.1, "{bounds:?}");
+        assert!(bounds.max.x < 101.1 && bounds.max.y < 51.1, "{bounds:?}");
+    }
+
+    #[test]
+    fn ellipses_are_closed_and_round() {
+        let outline = Outline::ellipse(p(10.0, 10.0), (20.0, 10.0), 0.0);
+        assert_eq!(outline.points.first(), outline.points.last());
+        for point in &outline.points {
+            let (x, y) = ((point.x - 10.0) / 20.0, (point.y - 10.0) / 10.0);
+            assert!((x.hypot(y) - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn rounded_rects_stay_in_their_box() {
+        let outline = Outline::rect(p(0.0, 0.0), 40.0, 20.0, (5.0, 5.0));
+        let bounds = Rect::around(outline.points.iter().copied()).unwrap();
+        assert_eq!(
+            bounds,
+            Rect {
+                min: p(0.0, 0.0),
+                max: p(40.0, 20.0)
+            }
+        );
+        assert_eq!(outline.points.first(), outline.points.last());
+    }
+
+    #[test]
+    fn placing_moves_the_top_left_corner() {
+        let mut import = Import {
+            elements: Outline::rect(p(10.0, 20.0), 5.0, 5.0, (0.0, 0.0))
+                .stroke(2.0, Color::BLACK, StrokeStyle::default())
+                .into_iter()
+                .collect(),
+            unsupported: vec![],
+        };
+        import.place_at(p(100.0, 100.0));
+        let bounds = import.elements[0].bounds().unwrap();
+        assert!((bounds.min.x - 100.0).abs() < 1e-4 && (bounds.min.y - 100.0).abs() < 1e-4);
+    }
+}
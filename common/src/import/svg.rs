@@ -0,0 +1,1288 @@
+//! SVG drawings: paths, basic shapes and text, with their transforms and presentation
+//! attributes. Style sheets, references and effects are reported.
+
+use std::f32::consts::TAU;
+
+use crate::entities::{Cap, Color, Element, Join, Position, StrokeStyle, Text};
+
+use super::{
+    parse_color, with_opacity,
+    xml::{self, Child, Node},
+    Import, ImportError, Outline, FILLS,
+};
+
+/// Height of capital letters above the baseline relative to the font size, the same as in board
+/// exports so that exported text comes back in place
+const ASCENT: f32 = 0.8;
+const DEFAULT_FONT_SIZE: f32 = 16.0;
+/// Width of the outline drawn for shapes that are only filled, in the units of the drawing
+const FILL_OUTLINE_WIDTH: f32 = 1.0;
+/// Elements that describe the drawing without being part of it. The contents of `defs` are
+/// only drawn through references, which are reported where they are used.
+const SKIPPED: [&str; 4] = ["title", "desc", "metadata", "defs"];
+
+/// Affine transform `[a, b, c, d, e, f]` as written in an SVG `matrix()`
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Matrix([f32; 6]);
+
+impl Matrix {
+    const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn translate(x: f32, y: f32) -> Matrix {
+        Matrix([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    fn apply(&self, p: Position) -> Position {
+        let [a, b, c, d, e, f] = self.0;
+        Position {
+            x: a * p.x + c * p.y + e,
+            y: b * p.x + d * p.y + f,
+        }
+    }
+
+    /// Transform applying `other` first and then `self`
+    fn compose(&self, other: &Matrix) -> Matrix {
+        let [a, b, c, d, e, f] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = other.0;
+        Matrix([
+            a * a2 + c * b2,
+            b * a2 + d * b2,
+            a * c2 + c * d2,
+            b * c2 + d * d2,
+            a * e2 + c * f2 + e,
+            b * e2 + d * f2 + f,
+        ])
+    }
+
+    /// Factor that lengths are scaled by on average
+    fn scale(&self) -> f32 {
+        let [a, b, c, d, ..] = self.0;
+        (a * d - b * c).abs().sqrt()
+    }
+
+    /// Clockwise rotation of the horizontal axis, in radians
+    fn rotation(&self) -> f32 {
+        self.0[1].atan2(self.0[0])
+    }
+
+    /// Whether shapes keep their proportions, which text needs since it cannot be skewed,
+    /// stretched or mirrored
+    fn is_similarity(&self) -> bool {
+        let [a, b, c, d, ..] = self.0;
+        let tolerance = 1e-3 * self.scale().max(f32::MIN_POSITIVE);
+        (a - d).abs() <= tolerance && (b + c).abs() <= tolerance
+    }
+}
+
+/// Reads the numbers, flags and commands of path data, point lists and transforms
+struct Scanner<'a> {
+    text: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(text: &'a str) -> Self {
+        Scanner {
+            text: text.as_bytes(),
+            at: 0,
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while self
+            .text
+            .get(self.at)
+            .is_some_and(|c| c.is_ascii_whitespace() || *c == b',')
+        {
+            self.at += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_separators();
+        self.text.get(self.at).copied()
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        self.skip_separators();
+        let start = self.at;
+        let digits = |scanner: &mut Self| {
+            let from = scanner.at;
+            while scanner.text.get(scanner.at).is_some_and(u8::is_ascii_digit) {
+                scanner.at += 1;
+            }
+            scanner.at > from
+        };
+        if matches!(self.text.get(self.at), Some(b'+' | b'-')) {
+            self.at += 1;
+        }
+        let mut any = digits(self);
+        if self.text.get(self.at) == Some(&b'.') {
+            self.at += 1;
+            any |= digits(self);
+        }
+        if !any {
+            self.at = start;
+            return None;
+        }
+        if matches!(self.text.get(self.at), Some(b'e' | b'E')) {
+            let mantissa_end = self.at;
+            self.at += 1;
+            if matches!(self.text.get(self.at), Some(b'+' | b'-')) {
+                self.at += 1;
+            }
+            if !digits(self) {
+                self.at = mantissa_end;
+            }
+        }
+        std::str::from_utf8(&self.text[start..self.at])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    /// Arc flags may be written without separators, as in `a1 1 0 011 1`
+    fn flag(&mut self) -> Option<bool> {
+        match self.peek()? {
+            b'0' => {
+                self.at += 1;
+                Some(false)
+            }
+            b'1' => {
+                self.at += 1;
+                Some(true)
+            }
+            _ => None,
+        }
+    }
+
+    fn point(&mut self) -> Option<Position> {
+        Some(Position {
+            x: self.number()?,
+            y: self.number()?,
+        })
+    }
+
+    fn is_done(&mut self) -> bool {
+        self.peek().is_none()
+    }
+}
+
+/// All numbers of a list, `None` if anything else is in it
+fn numbers(text: &str) -> Option<Vec<f32>> {
+    let mut scanner = Scanner::new(text);
+    let mut numbers = vec![];
+    while !scanner.is_done() {
+        numbers.push(scanner.number()?);
+    }
+    Some(numbers)
+}
+
+fn parse_transform(text: &str) -> Option<Matrix> {
+    let mut matrix = Matrix::IDENTITY;
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let open = rest.find('(')?;
+        let close = rest.find(')')?;
+        let name = rest[..open].trim();
+        let arguments = numbers(rest.get(open + 1..close)?)?;
+        let step = match (name, &arguments[..]) {
+            ("matrix", [a, b, c, d, e, f]) => Matrix([*a, *b, *c, *d, *e, *f]),
+            ("translate", [x]) => Matrix::translate(*x, 0.0),
+            ("translate", [x, y]) => Matrix::translate(*x, *y),
+            ("scale", [s]) => Matrix([*s, 0.0, 0.0, *s, 0.0, 0.0]),
+            ("scale", [x, y]) => Matrix([*x, 0.0, 0.0, *y, 0.0, 0.0]),
+            ("rotate", [angle, rest @ ..]) if matches!(rest.len(), 0 | 2) => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                let rotate = Matrix([cos, sin, -sin, cos, 0.0, 0.0]);
+                match rest {
+                    [x, y] => Matrix::translate(*x, *y)
+                        .compose(&rotate)
+                        .compose(&Matrix::translate(-x, -y)),
+                    _ => rotate,
+                }
+            }
+            ("skewX", [angle]) => Matrix([1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0]),
+            ("skewY", [angle]) => Matrix([1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0]),
+            _ => return None,
+        };
+        matrix = matrix.compose(&step);
+        rest = rest[close + 1..].trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+    }
+    Some(matrix)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Paint {
+    None,
+    Color(Color),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Anchor {
+    Start,
+    Middle,
+    End,
+}
+
+/// Presentation properties in effect for an element, inherited from its ancestors
+#[derive(Clone, Debug)]
+struct Style {
+    fill: Paint,
+    fill_opacity: f32,
+    stroke: Paint,
+    stroke_opacity: f32,
+    stroke_width: f32,
+    cap: Cap,
+    join: Join,
+    /// Dash and gap lengths in the units of the drawing, empty for solid lines
+    dash: Vec<f32>,
+    dash_offset: f32,
+    /// Product of the opacities of the element and its ancestors
+    opacity: f32,
+    font_size: f32,
+    text_anchor: Anchor,
+    /// Value of `currentColor`
+    color: Color,
+    visible: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            fill: Paint::Color(Color::BLACK),
+            fill_opacity: 1.0,
+            stroke: Paint::None,
+            stroke_opacity: 1.0,
+            stroke_width: 1.0,
+            cap: Cap::Butt,
+            join: Join::Miter { limit: 4.0 },
+            dash: vec![],
+            dash_offset: 0.0,
+            opacity: 1.0,
+            font_size: DEFAULT_FONT_SIZE,
+            text_anchor: Anchor::Start,
+            color: Color::BLACK,
+            visible: true,
+        }
+    }
+}
+
+/// Length in the units of the drawing, `None` for relative units that depend on a viewport
+fn parse_length(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let units = [
+        ("px", 1.0),
+        ("pt", 4.0 / 3.0),
+        ("pc", 16.0),
+        ("mm", 96.0 / 25.4),
+        ("cm", 96.0 / 2.54),
+        ("in", 96.0),
+    ];
+    for (unit, factor) in units {
+        if let Some(number) = value.strip_suffix(unit) {
+            return number.trim().parse::<f32>().ok().map(|n| n * factor);
+        }
+    }
+    value.parse().ok()
+}
+
+fn parse_opacity(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let opacity = match value.strip_suffix('%') {
+        Some(percent) => percent.parse::<f32>().ok()? / 100.0,
+        None => value.parse().ok()?,
+    };
+    Some(opacity.clamp(0.0, 1.0))
+}
+
+/// Local name of an element of the SVG namespace, `None` for elements of other namespaces such
+/// as the metadata of editors
+fn local_name(node: &Node) -> Option<&str> {
+    match node.name.split_once(':') {
+        Some(("svg", name)) => Some(name),
+        Some(_) => None,
+        None => Some(&node.name),
+    }
+}
+
+/// Declarations of the `style` attribute of `node`
+fn declarations(node: &Node) -> Vec<(String, String)> {
+    let Some(style) = node.attribute("style") else {
+        return vec![];
+    };
+    style
+        .split(';')
+        .filter_map(|declaration| declaration.split_once(':'))
+        .map(|(name, value)| {
+            let value = value.trim().trim_end_matches("!important").trim();
+            (name.trim().to_owned(), value.to_owned())
+        })
+        .collect()
+}
+
+struct Converter {
+    import: Import,
+}
+
+impl Converter {
+    /// Value of the property `name` of `node`, from its style attribute before its presentation
+    /// attributes. `inherit` gives no value, so that the inherited one is kept.
+    fn property<'a>(
+        node: &'a Node,
+        declarations: &'a [(String, String)],
+        name: &str,
+    ) -> Option<&'a str> {
+        declarations
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+            .or_else(|| node.attribute(name))
+            .map(str::trim)
+            .filter(|value| *value != "inherit")
+    }
+
+    fn paint(&mut self, value: &str, current: Color) -> Option<Paint> {
+        if value == "none" {
+            return Some(Paint::None);
+        }
+        if value == "currentColor" {
+            return Some(Paint::Color(current));
+        }
+        if let Some(reference) = value.strip_prefix("url(") {
+            self.import
+                .unsupported("Gradients and patterns, imported as a plain colour");
+            let fallback = reference
+                .split_once(')')
+                .map_or("", |(_, fallback)| fallback.trim());
+            return Some(match fallback {
+                "" => Paint::Color(Color::BLACK),
+                fallback => self.paint(fallback, current)?,
+            });
+        }
+        match parse_color(value) {
+            Some(color) => Some(Paint::Color(color)),
+            None => {
+                self.import.unsupported(format!("The colour {value}"));
+                None
+            }
+        }
+    }
+
+    fn length(&mut self, value: &str) -> Option<f32> {
+        let length = parse_length(value);
+        if length.is_none() {
+            self.import.unsupported(format!("The length {value}"));
+        }
+        length
+    }
+
+    /// Length attribute of `node`, 0 when it is missing as SVG defines
+    fn attribute_length(&mut self, node: &Node, name: &str) -> f32 {
+        node.attribute(name)
+            .and_then(|value| self.length(value))
+            .unwrap_or(0.0)
+    }
+
+    /// Style of `node` given the style of its parent, `None` if it is not displayed at all
+    fn style(&mut self, node: &Node, parent: &Style) -> Option<Style> {
+        let declarations = declarations(node);
+        let property = |name: &str| Self::property(node, &declarations, name);
+        if property("display") == Some("none") {
+            return None;
+        }
+        let mut style = parent.clone();
+        if let Some(color) = property("color").and_then(parse_color) {
+            style.color = color;
+        }
+        if let Some(paint) = property("fill").and_then(|value| self.paint(value, style.color)) {
+            style.fill = paint;
+        }
+        if let Some(paint) = property("stroke").and_then(|value| self.paint(value, style.color)) {
+            style.stroke = paint;
+        }
+        if let Some(opacity) = property("fill-opacity").and_then(parse_opacity) {
+            style.fill_opacity = opacity;
+        }
+        if let Some(opacity) = property("stroke-opacity").and_then(parse_opacity) {
+            style.stroke_opacity = opacity;
+        }
+        if let Some(opacity) = property("opacity").and_then(parse_opacity) {
+            style.opacity *= opacity;
+        }
+        if let Some(width) = property("stroke-width").and_then(|value| self.length(value)) {
+            style.stroke_width = width.max(0.0);
+        }
+        match property("stroke-linecap") {
+            Some("round") => style.cap = Cap::Round,
+            Some("square") => style.cap = Cap::Square,
+            Some("butt") => style.cap = Cap::Butt,
+            _ => (),
+        }
+        let limit = match style.join {
+            Join::Miter { limit } => limit,
+            _ => 4.0,
+        };
+        let limit = property("stroke-miterlimit")
+            .and_then(|value| value.parse::<f32>().ok())
+            .filter(|limit| *limit >= 1.0)
+            .unwrap_or(limit);
+        style.join = match property("stroke-linejoin") {
+            Some("round") => Join::Round,
+            Some("bevel") => Join::Bevel,
+            Some(_) => Join::Miter { limit },
+            None => match style.join {
+                Join::Miter { .. } => Join::Miter { limit },
+                join => join,
+            },
+        };
+        if let Some(value) = property("stroke-dasharray") {
+            style.dash = match value {
+                "none" => vec![],
+                value => {
+                    let lengths = value
+                        .split([',', ' '])
+                        .filter(|part| !part.is_empty())
+                        .map(|part| self.length(part))
+                        .collect::<Option<Vec<_>>>()
+                        .unwrap_or_default();
+                    // An odd number of lengths is repeated to make the pattern even
+                    match lengths.len() % 2 {
+                        0 => lengths,
+                        _ => lengths.repeat(2),
+                    }
+                }
+            };
+        }
+        if let Some(offset) = property("stroke-dashoffset").and_then(|value| self.length(value)) {
+            style.dash_offset = offset;
+        }
+        if let Some(value) = property("font-size") {
+            let size = match value.strip_suffix("em") {
+                Some(em) if !value.ends_with("rem") => em
+                    .trim()
+                    .parse::<f32>()
+                    .ok()
+                    .map(|em| em * parent.font_size),
+                _ => self.length(value),
+            };
+            if let Some(size) = size.filter(|size| *size > 0.0) {
+                style.font_size = size;
+            }
+        }
+        match property("text-anchor") {
+            Some("start") => style.text_anchor = Anchor::Start,
+            Some("middle") => style.text_anchor = Anchor::Middle,
+            Some("end") => style.text_anchor = Anchor::End,
+            _ => (),
+        }
+        match property("visibility") {
+            Some("hidden" | "collapse") => style.visible = false,
+            Some("visible") => style.visible = true,
+            _ => (),
+        }
+        for (name, what) in [
+            ("clip-path", "Clipping paths"),
+            ("mask", "Masks"),
+            ("filter", "Filters"),
+        ] {
+            if property(name).is_some_and(|value| value != "none") {
+                self.import.unsupported(what);
+            }
+        }
+        Some(style)
+    }
+
+    fn node(&mut self, node: &Node, matrix: Matrix, parent: &Style) {
+        let Some(name) = local_name(node) else {
+            return;
+        };
+        if SKIPPED.contains(&name) {
+            return;
+        }
+        let Some(style) = self.style(node, parent) else {
+            return;
+        };
+        let matrix = match node.attribute("transform").map(parse_transform) {
+            Some(Some(transform)) => matrix.compose(&transform),
+            Some(None) => {
+                self.import.unsupported("Malformed transforms, left out");
+                matrix
+            }
+            None => matrix,
+        };
+        match name {
+            "svg" => {
+                let x = self.attribute_length(node, "x");
+                let y = self.attribute_length(node, "y");
+                self.children(node, matrix.compose(&Matrix::translate(x, y)), &style);
+            }
+            "g" | "a" => self.children(node, matrix, &style),
+            "path" => {
+                let (outlines, complete) = path(node.attribute("d").unwrap_or(""));
+                if !complete {
+                    self.import
+                        .unsupported("Malformed path data, imported up to the error");
+                }
+                self.shape(outlines, true, matrix, &style);
+            }
+            "line" => {
+                let mut outline = Outline::default();
+                for (x, y) in [("x1", "y1"), ("x2", "y2")] {
+                    let point = Position {
+                        x: self.attribute_length(node, x),
+                        y: self.attribute_length(node, y),
+                    };
+                    outline.corner(point);
+                }
+                self.shape(vec![outline], false, matrix, &style);
+            }
+            "polyline" | "polygon" => {
+                let mut scanner = Scanner::new(node.attribute("points").unwrap_or(""));
+                let mut outline = Outline::default();
+                while let Some(point) = scanner.point() {
+                    outline.corner(point);
+                }
+                if name == "polygon" {
+                    outline.close();
+                }
+                self.shape(vec![outline], true, matrix, &style);
+            }
+            "rect" => {
+                let origin = Position {
+                    x: self.attribute_length(node, "x"),
+                    y: self.attribute_length(node, "y"),
+                };
+                let width = self.attribute_length(node, "width");
+                let height = self.attribute_length(node, "height");
+                if width <= 0.0 || height <= 0.0 {
+                    return;
+                }
+                // A missing radius takes the value of the other one
+                let rx = node.attribute("rx").and_then(|value| self.length(value));
+                let ry = node.attribute("ry").and_then(|value| self.length(value));
+                let radii = match (rx, ry) {
+                    (Some(rx), Some(ry)) => (rx, ry),
+                    (Some(r), None) | (None, Some(r)) => (r, r),
+                    (None, None) => (0.0, 0.0),
+                };
+                self.shape(
+                    vec![Outline::rect(origin, width, height, radii)],
+                    true,
+                    matrix,
+                    &style,
+                );
+            }
+            "circle" | "ellipse" => {
+                let center = Position {
+                    x: self.attribute_length(node, "cx"),
+                    y: self.attribute_length(node, "cy"),
+                };
+                let radii = match name {
+                    "circle" => {
+                        let r = self.attribute_length(node, "r");
+                        (r, r)
+                    }
+                    _ => (
+                        self.attribute_length(node, "rx"),
+                        self.attribute_length(node, "ry"),
+                    ),
+                };
+                if radii.0 <= 0.0 || radii.1 <= 0.0 {
+                    return;
+                }
+                self.shape(
+                    vec![Outline::ellipse(center, radii, 0.0)],
+                    true,
+                    matrix,
+                    &style,
+                );
+            }
+            "text" => self.text(node, matrix, &style),
+            "style" => self.import.unsupported("Style sheets"),
+            "image" => self.import.unsupported("Images"),
+            "use" => self.import.unsupported("Reused elements (<use>)"),
+            name => self.import.unsupported(format!("<{name}> elements")),
+        }
+    }
+
+    fn children(&mut self, node: &Node, matrix: Matrix, style: &Style) {
+        for child in &node.children {
+            if let Child::Element(child) = child {
+                self.node(child, matrix, style);
+            }
+        }
+    }
+
+    /// Strokes along `outlines`. Shapes that can be `filled` and have a fill get an outline in
+    /// the colour of the fill, unless they are stroked anyway.
+    fn shape(&mut self, outlines: Vec<Outline>, filled: bool, matrix: Matrix, style: &Style) {
+        if !style.visible {
+            return;
+        }
+        let stroke = match style.stroke {
+            Paint::Color(color) if style.stroke_width > 0.0 => Some((
+                with_opacity(color, style.stroke_opacity * style.opacity),
+                style.stroke_width,
+            )),
+            _ => None,
+        };
+        let fill = match style.fill {
+            Paint::Color(color) if filled => {
+                Some(with_opacity(color, style.fill_opacity * style.opacity))
+            }
+            _ => None,
+        }
+        .filter(|color| color.a > 0);
+        let (color, width) = match (stroke, fill) {
+            (Some(stroke), fill) => {
+                if fill.is_some() {
+                    self.import.unsupported(FILLS);
+                }
+                stroke
+            }
+            (None, Some(fill)) => {
+                self.import.unsupported(FILLS);
+                (fill, FILL_OUTLINE_WIDTH)
+            }
+            (None, None) => return,
+        };
+        if color.a == 0 {
+            return;
+        }
+        let dashed = style.dash.iter().all(|d| *d >= 0.0) && style.dash.iter().any(|d| *d > 0.0);
+        let stroke_style = StrokeStyle {
+            join: style.join,
+            cap: style.cap,
+            dash: match dashed {
+                true => style.dash.iter().map(|d| d / width).collect(),
+                false => vec![],
+            },
+            dash_offset: if dashed {
+                style.dash_offset / width
+            } else {
+                0.0
+            },
+        };
+        for outline in outlines {
+            let outline = outline.map(|p| matrix.apply(p));
+            let element = outline.stroke(width * matrix.scale(), color, stroke_style.clone());
+            self.import.elements.extend(element);
+        }
+    }
+
+    fn text(&mut self, node: &Node, matrix: Matrix, style: &Style) {
+        let first = |name: &str| {
+            node.attribute(name)
+                .and_then(|value| numbers(value)?.first().copied())
+        };
+        let mut origin = first("x").zip(first("y"));
+        // Lines are started by the first text and by every <tspan> placed on its own
+        let mut lines = vec![String::new()];
+        let mut stack = vec![node.children.iter()];
+        while let Some(children) = stack.last_mut() {
+            let Some(child) = children.next() else {
+                stack.pop();
+                continue;
+            };
+            match child {
+                Child::Text(text) => lines.last_mut().unwrap().push_str(text),
+                Child::Element(element) => match local_name(element) {
+                    Some("tspan") => {
+                        let placed = ["x", "y", "dy"]
+                            .iter()
+                            .any(|name| element.attribute(name).is_some());
+                        if placed && !lines.last().unwrap().trim().is_empty() {
+                            lines.push(String::new());
+                        }
+                        if origin.is_none() && placed {
+                            let first = |name: &str| {
+                                element
+                                    .attribute(name)
+                                    .and_then(|value| numbers(value)?.first().copied())
+                            };
+                            origin = Some((first("x").unwrap_or(0.0), first("y").unwrap_or(0.0)));
+                        }
+                        stack.push(element.children.iter());
+                    }
+                    Some("textPath") => self.import.unsupported("Text along a path"),
+                    _ => (),
+                },
+            }
+        }
+        let preserve = node.attribute("xml:space") == Some("preserve");
+        let lines = lines
+            .iter()
+            .map(|line| match preserve {
+                true => line.replace(['\n', '\r', '\t'], " "),
+                false => line.split_whitespace().collect::<Vec<_>>().join(" "),
+            })
+            .collect::<Vec<_>>();
+        if !style.visible || lines.iter().all(|line| line.trim().is_empty()) {
+            return;
+        }
+        let color = match style.fill {
+            Paint::Color(color) => with_opacity(color, style.fill_opacity * style.opacity),
+            Paint::None => return,
+        };
+        let (x, y) = origin.unwrap_or((0.0, 0.0));
+        let font_size = style.font_size;
+        let first_baseline = ((Text::LINE_HEIGHT - 1.0) / 2.0 + ASCENT) * font_size;
+        let mut text = Text {
+            position: Position {
+                x,
+                y: y - first_baseline,
+            },
+            content: lines.join("\n"),
+            font_size,
+            color,
+            rotation: 0.0,
+        };
+        let (width, _) = text.size();
+        text.position.x -= match style.text_anchor {
+            Anchor::Start => 0.0,
+            Anchor::Middle => width / 2.0,
+            Anchor::End => width,
+        };
+        if !matrix.is_similarity() {
+            self.import
+                .unsupported("Skewed, stretched or mirrored text");
+        }
+        text.position = matrix.apply(text.position);
+        text.font_size *= matrix.scale();
+        text.rotation = matrix.rotation();
+        self.import.elements.push(Element::Text(text));
+    }
+}
+
+/// Outlines of the subpaths of path data, and whether the data was read to the end. Data after
+/// an error is ignored, as SVG viewers do.
+fn path(data: &str) -> (Vec<Outline>, bool) {
+    let mut scanner = Scanner::new(data);
+    let mut outlines = vec![];
+    let mut outline = Outline::default();
+    let mut current = Position { x: 0.0, y: 0.0 };
+    let mut start = current;
+    // Control point of the previous curve, which `S` and `T` reflect
+    let mut control: Option<(u8, Position)> = None;
+    let mut command = None;
+    let complete = loop {
+        let Some(next) = scanner.peek() else {
+            break true;
+        };
+        if next.is_ascii_alphabetic() {
+            scanner.at += 1;
+            command = Some(next);
+        }
+        let Some(letter) = command else {
+            break false;
+        };
+        let relative = letter.is_ascii_lowercase();
+        let offset = move |p: Position| match relative {
+            true => Position {
+                x: current.x + p.x,
+                y: current.y + p.y,
+            },
+            false => p,
+        };
+        let previous_control = control.take();
+        let reflected = move |kind: &[u8]| match previous_control {
+            Some((k, c)) if kind.contains(&k) => Position {
+                x: 2.0 * current.x - c.x,
+                y: 2.0 * current.y - c.y,
+            },
+            _ => current,
+        };
+        match letter.to_ascii_uppercase() {
+            b'M' => {
+                let Some(point) = scanner.point() else {
+                    break false;
+                };
+                current = offset(point);
+                start = current;
+                outlines.push(std::mem::take(&mut outline));
+                outline.corner(current);
+                // Further pairs are lines
+                command = Some(if relative { b'l' } else { b'L' });
+            }
+            b'L' => {
+                let Some(point) = scanner.point() else {
+                    break false;
+                };
+                line_to(&mut outline, current, offset(point));
+                current = offset(point);
+            }
+            b'H' | b'V' => {
+                let Some(value) = scanner.number() else {
+                    break false;
+                };
+                let horizontal = letter.eq_ignore_ascii_case(&b'H');
+                let to = match (horizontal, relative) {
+                    (true, true) => Position {
+                        x: current.x + value,
+                        ..current
+                    },
+                    (true, false) => Position {
+                        x: value,
+                        ..current
+                    },
+                    (false, true) => Position {
+                        y: current.y + value,
+                        ..current
+                    },
+                    (false, false) => Position {
+                        y: value,
+                        ..current
+                    },
+                };
+                line_to(&mut outline, current, to);
+                current = to;
+            }
+            b'C' | b'S' => {
+                let first = match letter.to_ascii_uppercase() {
+                    b'C' => match scanner.point() {
+                        Some(point) => offset(point),
+                        None => break false,
+                    },
+                    _ => reflected(b"CS"),
+                };
+                let (Some(second), Some(to)) = (scanner.point(), scanner.point()) else {
+                    break false;
+                };
+                let (second, to) = (offset(second), offset(to));
+                line_to(&mut outline, current, current);
+                outline.cubic_to(first, second, to);
+                control = Some((b'C', second));
+                current = to;
+            }
+            b'Q' | b'T' => {
+                let point = match letter.to_ascii_uppercase() {
+                    b'Q' => match scanner.point() {
+                        Some(point) => offset(point),
+                        None => break false,
+                    },
+                    _ => reflected(b"Q"),
+                };
+                let Some(to) = scanner.point() else {
+                    break false;
+                };
+                let to = offset(to);
+                line_to(&mut outline, current, current);
+                outline.quadratic_to(point, to);
+                control = Some((b'Q', point));
+                current = to;
+            }
+            b'A' => {
+                let (Some(rx), Some(ry), Some(angle)) =
+                    (scanner.number(), scanner.number(), scanner.number())
+                else {
+                    break false;
+                };
+                let (Some(large), Some(sweep), Some(to)) =
+                    (scanner.flag(), scanner.flag(), scanner.point())
+                else {
+                    break false;
+                };
+                let to = offset(to);
+                line_to(&mut outline, current, current);
+                arc_to(
+                    &mut outline,
+                    current,
+                    (rx, ry),
+                    angle.to_radians(),
+                    large,
+                    sweep,
+                    to,
+                );
+                current = to;
+            }
+            b'Z' => {
+                outline.close();
+                outlines.push(std::mem::take(&mut outline));
+                current = start;
+                outline.corner(current);
+                // Numbers cannot follow a closed path without a command
+                command = None;
+            }
+            _ => break false,
+        }
+    };
+    outlines.push(outline);
+    outlines.retain(|outline| outline.points.len() > 2);
+    (outlines, complete)
+}
+
+/// Continues `outline` with a segment from `from` to `to`, starting it at `from` if it is empty
+fn line_to(outline: &mut Outline, from: Position, to: Position) {
+    if outline.points.is_empty() {
+        outline.corner(from);
+    }
+    if to != from {
+        outline.corner(to);
+    }
+}
+
+/// Continues `outline` with an SVG arc from `from` to `to`, converted from its end points to
+/// its centre as described in the implementation notes of SVG
+fn arc_to(
+    outline: &mut Outline,
+    from: Position,
+    radii: (f32, f32),
+    rotation: f32,
+    large: bool,
+    sweep: bool,
+    to: Position,
+) {
+    let (mut rx, mut ry) = (radii.0.abs(), radii.1.abs());
+    if from == to {
+        return;
+    }
+    if rx == 0.0 || ry == 0.0 {
+        outline.corner(to);
+        return;
+    }
+    let (sin, cos) = rotation.sin_cos();
+    let (dx, dy) = ((from.x - to.x) / 2.0, (from.y - to.y) / 2.0);
+    let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+    // Radii too small to reach are scaled up until they do
+    let lambda = x1 * x1 / (rx * rx) + y1 * y1 / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+    let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let sign = if large == sweep { -1.0 } else { 1.0 };
+    let coefficient = sign * (numerator / denominator).max(0.0).sqrt();
+    let (cx1, cy1) = (coefficient * rx * y1 / ry, -coefficient * ry * x1 / rx);
+    let center = Position {
+        x: cos * cx1 - sin * cy1 + (from.x + to.x) / 2.0,
+        y: sin * cx1 + cos * cy1 + (from.y + to.y) / 2.0,
+    };
+    let angle =
+        |(ux, uy): (f32, f32), (vx, vy): (f32, f32)| (ux * vy - uy * vx).atan2(ux * vx + uy * vy);
+    let start_vector = ((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let end_vector = ((-x1 - cx1) / rx, (-y1 - cy1) / ry);
+    let start = angle((1.0, 0.0), start_vector);
+    let mut delta = angle(start_vector, end_vector);
+    if !sweep && delta > 0.0 {
+        delta -= TAU;
+    } else if sweep && delta < 0.0 {
+        delta += TAU;
+    }
+    outline.arc(center, (rx, ry), rotation, start, delta);
+    outline.corner(to);
+}
+
+/// Converts an SVG document
+pub fn svg(text: &str) -> Result<Import, ImportError> {
+    let root = xml::parse(text).map_err(ImportError::Syntax)?;
+    if local_name(&root) != Some("svg") {
+        return Err(ImportError::Format(
+            "The file is not an SVG drawing".to_owned(),
+        ));
+    }
+    let mut converter = Converter {
+        import: Import::default(),
+    };
+    converter.node(&root, Matrix::IDENTITY, &Style::default());
+    Ok(converter.import)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::Stroke;
+
+    use super::*;
+
+    fn p(x: f32, y: f32) -> Position {
+        Position { x, y }
+    }
+
+    fn import(body: &str) -> Import {
+        svg(&format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg">{body}</svg>"#
+        ))
+        .unwrap()
+    }
+
+    fn strokes(import: &Import) -> Vec<&Stroke> {
+        import
+            .elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Stroke(stroke) => Some(stroke),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Points of a stroke without the repetitions that keep corners sharp
+    fn corners(stroke: &Stroke) -> Vec<Position> {
+        let mut points = stroke.points.clone();
+        points.dedup();
+        points
+    }
+
+    fn close(a: Position, b: Position) -> bool {
+        (a.x - b.x).abs() < 1e-3 && (a.y - b.y).abs() < 1e-3
+    }
+
+    #[test]
+    fn reads_path_commands() {
+        let (outlines, complete) = path("M10 10h10v10H10zm5-5l1e1.5-5,0");
+        assert!(complete);
+        assert_eq!(outlines.len(), 2);
+        let mut first = outlines[0].points.clone();
+        first.dedup();
+        assert_eq!(
+            first,
+            vec![
+                p(10.0, 10.0),
+                p(20.0, 10.0),
+                p(20.0, 20.0),
+                p(10.0, 20.0),
+                p(10.0, 10.0)
+            ]
+        );
+        let mut second = outlines[1].points.clone();
+        second.dedup();
+        // The relative move starts from where the closed subpath began
+        assert_eq!(second, vec![p(15.0, 5.0), p(25.0, 5.5), p(20.0, 5.5)]);
+    }
+
+    #[test]
+    fn flattens_curves_and_arcs() {
+        let (outlines, _) = path("M0 0C0 50 100 50 100 0S200-50 200 0");
+        let points = &outlines[0].points;
+        assert!(points.len() > 10);
+        assert!(points.iter().all(|p| p.y.abs() <= 37.5 + 1e-3));
+        assert_eq!(points.last(), Some(&p(200.0, 0.0)));
+
+        // The short way around a circle of radius 10, clockwise over the top
+        let (outlines, complete) = path("M0 0A10 10 0 0110 0");
+        assert!(complete);
+        let points = &outlines[0].points;
+        let center = p(5.0, 75f32.sqrt());
+        assert!(points
+            .iter()
+            .all(|q| ((q.x - center.x).hypot(q.y - center.y) - 10.0).abs() < 1e-3));
+        let top = points.iter().map(|q| q.y).fold(f32::INFINITY, f32::min);
+        assert!((top - (center.y - 10.0)).abs() < 0.1, "{top}");
+        // Radii too small to reach are scaled up into a half circle
+        let (outlines, _) = path("M0 0A1 1 0 0 0 10 0");
+        let bottom = outlines[0]
+            .points
+            .iter()
+            .map(|q| q.y)
+            .fold(f32::NEG_INFINITY, f32::max);
+        assert!((bottom - 5.0).abs() < 1e-3, "{bottom}");
+    }
+
+    #[test]
+    fn reports_malformed_path_data() {
+        let (outlines, complete) = path("M0 0L10 0L20");
+        assert!(!complete);
+        assert_eq!(outlines.len(), 1);
+        let imported = import(r#"<path d="M0 0 10 10 x" stroke="red"/>"#);
+        assert_eq!(imported.elements.len(), 1);
+        assert!(imported
+            .unsupported
+            .iter()
+            .any(|u| u.starts_with("Malformed path data")));
+    }
+
+    #[test]
+    fn converts_basic_shapes() {
+        let imported = import(
+            r##"<rect x="1" y="2" width="10" height="5" stroke="#ff0000" stroke-width="2"/>
+            <circle cx="5" cy="5" r="3" stroke="blue" fill="none"/>
+            <ellipse cx="5" cy="5" rx="3" ry="1" stroke="blue" fill="none"/>
+            <line x1="0" y1="0" x2="4" y2="0" stroke="black"/>
+            <polyline points="0,0 1,1 2,0" stroke="black" fill="none"/>
+            <polygon points="0,0 1,1 2,0" stroke="black" fill="none"/>"##,
+        );
+        let strokes = strokes(&imported);
+        assert_eq!(strokes.len(), 6);
+        assert_eq!(
+            corners(strokes[0]),
+            vec![
+                p(1.0, 2.0),
+                p(11.0, 2.0),
+                p(11.0, 7.0),
+                p(1.0, 7.0),
+                p(1.0, 2.0)
+            ]
+        );
+        assert_eq!(
+            strokes[0].color,
+            Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255
+            }
+        );
+        assert_eq!(strokes[0].width, 2.0);
+        assert!(strokes[1]
+            .points
+            .iter()
+            .all(|q| ((q.x - 5.0).hypot(q.y - 5.0) - 3.0).abs() < 1e-3));
+        assert_eq!(corners(strokes[3]), vec![p(0.0, 0.0), p(4.0, 0.0)]);
+        assert_eq!(corners(strokes[4]).len(), 3);
+        assert_eq!(corners(strokes[5]).len(), 4);
+        // The rectangle is also filled black by default
+        assert_eq!(imported.unsupported, vec![FILLS]);
+    }
+
+    #[test]
+    fn fills_become_outlines() {
+        let imported = import(r#"<path d="M0 0H10V10Z" fill="green"/>"#);
+        let strokes = strokes(&imported);
+        assert_eq!(
+            strokes[0].color,
+            Color {
+                r: 0,
+                g: 128,
+                b: 0,
+                a: 255
+            }
+        );
+        assert_eq!(strokes[0].width, FILL_OUTLINE_WIDTH);
+        assert_eq!(imported.unsupported, vec![FILLS]);
+    }
+
+    #[test]
+    fn applies_transforms_and_inherited_styles() {
+        let imported = import(
+            r#"<g transform="translate(100 0) scale(2)" stroke="rgb(0,0,255)" stroke-width="3" opacity="0.5" fill="none">
+              <line x1="0" y1="0" x2="10" y2="0" style="stroke-linecap:round; stroke-dasharray: 6 3" transform="rotate(90)"/>
+            </g>"#,
+        );
+        let strokes = strokes(&imported);
+        assert_eq!(strokes.len(), 1);
+        let points = corners(strokes[0]);
+        assert!(
+            close(points[0], p(100.0, 0.0)) && close(points[1], p(100.0, 20.0)),
+            "{points:?}"
+        );
+        assert_eq!(strokes[0].width, 6.0);
+        assert_eq!(
+            strokes[0].color,
+            Color {
+                r: 0,
+                g: 0,
+                b: 255,
+                a: 128
+            }
+        );
+        assert_eq!(strokes[0].style.cap, Cap::Round);
+        // Dash lengths are relative to the width before it is transformed
+        assert_eq!(strokes[0].style.dash, vec![2.0, 1.0]);
+        assert!(imported.unsupported.is_empty());
+    }
+
+    #[test]
+    fn parses_transform_lists() {
+        let matrix = parse_transform("translate(10,20) rotate(90 5 5) scale(2,1)").unwrap();
+        assert!(
+            close(matrix.apply(p(1.0, 0.0)), p(20.0, 22.0)),
+            "{:?}",
+            matrix.apply(p(1.0, 0.0))
+        );
+        assert!(parse_transform("spin(3)").is_none());
+        assert!(!parse_transform("skewX(30)").unwrap().is_similarity());
+        assert!(!parse_transform("scale(-1 1)").unwrap().is_similarity());
+        assert!(parse_transform("rotate(30) scale(3)")
+            .unwrap()
+            .is_similarity());
+    }
+
+    #[test]
+    fn converts_text() {
+        let imported = import(
+            r##"<text x="10" y="30" font-size="20" fill="#00f" transform="rotate(90 10 30)">
+              <tspan x="10" dy="0">First   line</tspan><tspan x="10" dy="1.2em">Second</tspan>
+            </text>"##,
+        );
+        let [Element::Text(text)] = &imported.elements[..] else {
+            panic!("{:?}", imported.elements);
+        };
+        assert_eq!(text.content, "First line\nSecond");
+        assert_eq!(text.font_size, 20.0);
+        assert_eq!(
+            text.color,
+            Color {
+                r: 0,
+                g: 0,
+                b: 255,
+                a: 255
+            }
+        );
+        assert!((text.rotation - TAU / 4.0).abs() < 1e-5);
+        // The box is above the first baseline, which the rotation turns to the right of it
+        assert!(
+            close(text.position, p(10.0 + 18.0, 30.0)),
+            "{:?}",
+            text.position
+        );
+    }
+
+    #[test]
+    fn reads_board_exports_back() {
+        // The text of a board exported at (10, 20), as the SVG export writes it
+        let imported = import(
+            r##"<text font-family="Raleway, sans-serif" font-size="20" fill="#000000" transform="translate(10 20)" xml:space="preserve"><tspan x="0" y="18">Plan &lt;v2&gt;</tspan><tspan x="0" y="42">  &amp; next</tspan></text>"##,
+        );
+        let [Element::Text(text)] = &imported.elements[..] else {
+            panic!("{:?}", imported.elements);
+        };
+        assert_eq!(text.content, "Plan <v2>\n  & next");
+        assert!(close(text.position, p(10.0, 20.0)), "{:?}", text.position);
+    }
+
+    #[test]
+    fn reports_unsupported_constructs() {
+        let imported = import(
+            r##"<defs><linearGradient id="g"/></defs>
+            <style>path { stroke: red }</style>
+            <title>Drawing</title>
+            <image href="a.png" width="10" height="10"/>
+            <use href="#p"/>
+            <foreignObject/>
+            <rect width="10" height="10" fill="url(#g)" stroke="none" clip-path="url(#c)"/>
+            <rect width="10" height="10" fill="url(#g)" stroke="none"/>
+            <sodipodi:namedview/>"##,
+        );
+        assert_eq!(
+            imported.unsupported,
+            vec![
+                "Style sheets",
+                "Images",
+                "Reused elements (<use>)",
+                "<foreignObject> elements",
+                "Gradients and patterns, imported as a plain colour",
+                "Clipping paths",
+                FILLS,
+            ]
+        );
+        assert_eq!(imported.elements.len(), 2);
+    }
+
+    #[test]
+    fn skips_hidden_elements() {
+        let imported = import(
+            r#"<g display="none"><line x2="10" stroke="black"/></g>
+            <g visibility="hidden"><line x2="10" stroke="black"/><line x2="10" stroke="black" visibility="visible"/></g>
+            <line x2="10" stroke="black" stroke-opacity="0"/>"#,
+        );
+        assert_eq!(imported.elements.len(), 1);
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        assert!(matches!(svg("<html></html>"), Err(ImportError::Format(_))));
+        assert!(matches!(svg("<svg><g></svg>"), Err(ImportError::Syntax(_))));
+    }
+}
@@ -0,0 +1,248 @@
+//! Just enough XML to read SVG files: elements, attributes, text and CDATA. Declarations,
+//! comments, processing instructions and document types are skipped.
+
+/// Deepest nesting of elements accepted, bounds the recursion of code walking the tree
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Child>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Child {
+    Element(Node),
+    Text(String),
+}
+
+impl Node {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Replaces character and predefined entity references. Entities declared in a document type
+/// are not known and are kept as written.
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else { break };
+        let name = &rest[1..end];
+        let replacement = match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => name.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+            }
+        };
+        match replacement {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+fn is_name_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '/' | '>' | '=' | '<' | '"' | '\'')
+}
+
+/// Reads the start tag following a `<`, returning the element, whether it closes itself and
+/// the rest of the input
+fn start_tag(input: &str) -> Result<(Node, bool, &str), String> {
+    let name_end = input.find(|c| !is_name_char(c)).unwrap_or(input.len());
+    if name_end == 0 {
+        return Err("Expected an element name after <".to_owned());
+    }
+    let mut node = Node {
+        name: input[..name_end].to_owned(),
+        attributes: vec![],
+        children: vec![],
+    };
+    let mut rest = &input[name_end..];
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            return Ok((node, true, after));
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return Ok((node, false, after));
+        }
+        let name_end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+        if name_end == 0 {
+            return Err(format!("Unterminated <{}> tag", node.name));
+        }
+        let name = rest[..name_end].to_owned();
+        rest = rest[name_end..].trim_start();
+        rest = rest
+            .strip_prefix('=')
+            .ok_or_else(|| format!("Attribute {name} has no value"))?
+            .trim_start();
+        let quote = rest
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '"' | '\''))
+            .ok_or_else(|| format!("Value of attribute {name} is not quoted"))?;
+        let end = rest[1..]
+            .find(quote)
+            .ok_or_else(|| format!("Value of attribute {name} is not closed"))?;
+        node.attributes.push((name, unescape(&rest[1..end + 1])));
+        rest = &rest[end + 2..];
+    }
+}
+
+/// Skips past `terminator`, failing with a message naming `what` if it never comes
+fn skip_past<'a>(input: &'a str, terminator: &str, what: &str) -> Result<&'a str, String> {
+    let end = input
+        .find(terminator)
+        .ok_or_else(|| format!("Unterminated {what}"))?;
+    Ok(&input[end + terminator.len()..])
+}
+
+/// Skips a document type declaration following `<!DOCTYPE`, including an internal subset
+fn skip_doctype(input: &str) -> Result<&str, String> {
+    let mut depth = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            '>' if depth <= 0 => return Ok(&input[i + 1..]),
+            _ => (),
+        }
+    }
+    Err("Unterminated document type".to_owned())
+}
+
+/// Root element of an XML document
+pub fn parse(input: &str) -> Result<Node, String> {
+    let mut rest = input.strip_prefix('\u{feff}').unwrap_or(input);
+    // Elements whose end tag has not been read yet, innermost last
+    let mut open: Vec<Node> = vec![];
+    let mut root = None;
+    loop {
+        let text_end = rest.find('<').unwrap_or(rest.len());
+        if let Some(parent) = open.last_mut() {
+            if text_end > 0 {
+                parent
+                    .children
+                    .push(Child::Text(unescape(&rest[..text_end])));
+            }
+        } else if !rest[..text_end].trim().is_empty() {
+            return Err("Text outside of the root element".to_owned());
+        }
+        rest = &rest[text_end..];
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = skip_past(after, "-->", "comment")?;
+        } else if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after.find("]]>").ok_or("Unterminated CDATA section")?;
+            if let Some(parent) = open.last_mut() {
+                parent.children.push(Child::Text(after[..end].to_owned()));
+            }
+            rest = &after[end + 3..];
+        } else if let Some(after) = rest.strip_prefix("<!DOCTYPE") {
+            rest = skip_doctype(after)?;
+        } else if let Some(after) = rest.strip_prefix("<?") {
+            rest = skip_past(after, "?>", "processing instruction")?;
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let end = after.find('>').ok_or("Unterminated end tag")?;
+            let name = after[..end].trim();
+            let node = open.pop().ok_or_else(|| format!("Unexpected </{name}>"))?;
+            if node.name != name {
+                return Err(format!("Expected </{}> but found </{name}>", node.name));
+            }
+            rest = &after[end + 1..];
+            match open.last_mut() {
+                Some(parent) => parent.children.push(Child::Element(node)),
+                None => root = Some(node),
+            }
+        } else {
+            if root.is_some() {
+                return Err("More than one root element".to_owned());
+            }
+            let (node, closed, after) = start_tag(&rest[1..])?;
+            rest = after;
+            if !closed {
+                if open.len() >= MAX_DEPTH {
+                    return Err("Elements are nested too deeply".to_owned());
+                }
+                open.push(node);
+            } else if let Some(parent) = open.last_mut() {
+                parent.children.push(Child::Element(node));
+            } else {
+                root = Some(node);
+            }
+        }
+    }
+    if let Some(node) = open.last() {
+        return Err(format!("<{}> is not closed", node.name));
+    }
+    root.ok_or_else(|| "The document has no root element".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_elements_attributes_and_text() {
+        let document = r#"<?xml version="1.0"?>
+<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd">
+<!-- drawn by hand -->
+<svg width='10' a="x &amp; &#x3C;y&gt;"><text>A &lt;b&gt;<![CDATA[ & <c>]]></text><g/></svg>"#;
+        let root = parse(document).unwrap();
+        assert_eq!(root.name, "svg");
+        assert_eq!(root.attribute("width"), Some("10"));
+        assert_eq!(root.attribute("a"), Some("x & <y>"));
+        let [Child::Element(text), Child::Element(g)] = &root.children[..] else {
+            panic!("{:?}", root.children);
+        };
+        assert_eq!(
+            text.children,
+            vec![
+                Child::Text("A <b>".to_owned()),
+                Child::Text(" & <c>".to_owned())
+            ]
+        );
+        assert_eq!(g.name, "g");
+    }
+
+    #[test]
+    fn unknown_entities_are_kept() {
+        assert_eq!(unescape("&ns_svg; &#65; & b"), "&ns_svg; A & b");
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        assert!(parse("<svg><g></svg>").is_err());
+        assert!(parse("<svg>").is_err());
+        assert!(parse("<svg a=1/>").is_err());
+        assert!(parse("not xml").is_err());
+        assert!(parse("<a/><b/>").is_err());
+        assert!(parse(&"<g>".repeat(MAX_DEPTH + 1)).is_err());
+    }
+}
@@ -1,30 +1,412 @@
 pub mod entities {
     use serde::{Deserialize, Serialize};
 
-    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    use crate::spatial::Rect;
+
+    pub type ElementId = u64;
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
     pub struct Position {
         pub x: f32,
         pub y: f32,
     }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Color {
+        pub r: u8,
+        pub g: u8,
+        pub b: u8,
+        pub a: u8,
+    }
+
+    impl Color {
+        pub const BLACK: Color = Color { r: 0, g: 0, b: 0, a: 255 };
+        pub const STICKY_YELLOW: Color = Color { r: 255, g: 235, b: 130, a: 255 };
+
+        /// Formats the colour as a CSS `rgba(...)` value
+        pub fn to_css(&self) -> String {
+            format!("rgba({}, {}, {}, {})", self.r, self.g, self.b, self.a as f32 / 255.0)
+        }
+
+        /// Formats the colour without its alpha as `#rrggbb`, the value of an HTML colour input
+        pub fn to_hex(&self) -> String {
+            format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        }
+
+        /// Parses `#rrggbb` into an opaque colour
+        pub fn from_hex(hex: &str) -> Option<Color> {
+            let hex = hex.strip_prefix('#')?;
+            if hex.len() != 6 || !hex.is_ascii() {
+                return None;
+            }
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            Some(Color { r: channel(0)?, g: channel(2)?, b: channel(4)?, a: 255 })
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct Text {
+        /// Top left corner of the text box in board coordinates
+        pub position: Position,
+        pub content: String,
+        pub font_size: f32,
+        pub color: Color,
+        /// Clockwise rotation around `position`, in radians
+        pub rotation: f32,
+    }
+
+    impl Text {
+        /// Line height relative to the font size, matches `.text-element` in board.css
+        pub const LINE_HEIGHT: f32 = 1.2;
+        /// Average glyph width relative to the font size
+        const CHAR_WIDTH: f32 = 0.55;
+
+        /// Approximate size of the rendered text box in board units
+        pub fn size(&self) -> (f32, f32) {
+            let lines = self.content.split('\n');
+            let longest = lines.clone().map(|l| l.chars().count()).max().unwrap_or(0);
+            (
+                longest.max(1) as f32 * self.font_size * Self::CHAR_WIDTH,
+                lines.count() as f32 * self.font_size * Self::LINE_HEIGHT,
+            )
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct Sticky {
+        /// Top left corner of the note in board coordinates
+        pub position: Position,
+        pub width: f32,
+        pub height: f32,
+        pub color: Color,
+        /// Clockwise rotation around `position`, in radians
+        pub rotation: f32,
+        pub text: String,
+        /// Number of text edits applied by the server, edits are sent against this revision
+        pub revision: u64,
+    }
+
+    /// SHA-256 of a blob stored by the backend, in lowercase hex
+    pub type BlobId = String;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct Image {
+        /// Top left corner of the image in board coordinates
+        pub position: Position,
+        /// Size of the image in pixels, before scaling
+        pub width: f32,
+        pub height: f32,
+        pub scale: f32,
+        /// Clockwise rotation around `position`, in radians
+        pub rotation: f32,
+        pub blob: BlobId,
+    }
+
+    /// Shape of the corners where two segments of a line meet
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    pub enum Join {
+        Round,
+        /// Sharp corner, replaced by a bevel when the miter would reach farther from the corner
+        /// than `limit` times half the thickness
+        Miter { limit: f32 },
+        Bevel,
+    }
+
+    /// Shape of the ends of a line
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Cap {
+        Round,
+        /// Ends flat at the end point
+        Butt,
+        /// Ends flat, half the thickness past the end point
+        Square,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct StrokeStyle {
+        pub join: Join,
+        /// Shape of the ends of the line and of each dash
+        pub cap: Cap,
+        /// Lengths of alternating dashes and gaps as multiples of the stroke width, repeated
+        /// along the line. Empty for a solid line.
+        #[serde(default)]
+        pub dash: Vec<f32>,
+        /// Distance into the dash pattern at which the line starts, as a multiple of the width
+        #[serde(default)]
+        pub dash_offset: f32,
+    }
+
+    impl StrokeStyle {
+        pub const DASHED: [f32; 2] = [3.0, 2.0];
+        /// Zero length dashes, drawn as dots by round and square caps
+        pub const DOTTED: [f32; 2] = [0.0, 2.0];
+    }
+
+    impl Default for StrokeStyle {
+        fn default() -> Self {
+            StrokeStyle {
+                join: Join::Round,
+                cap: Cap::Round,
+                dash: vec![],
+                dash_offset: 0.0,
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct Stroke {
+        pub points: Vec<Position>,
+        /// Full thickness of the line in board units, at full pressure
+        pub width: f32,
+        pub color: Color,
+        /// Pen pressure in `0.0..=1.0` at each point, empty for input without pressure
+        #[serde(default)]
+        pub pressure: Vec<f32>,
+        #[serde(default)]
+        pub style: StrokeStyle,
+    }
+
+    impl Stroke {
+        /// Fraction of `width` the line keeps at zero pressure
+        const MIN_PRESSURE_WIDTH: f32 = 0.2;
+
+        /// Full thickness of the line at each point
+        pub fn widths(&self) -> Vec<f32> {
+            if self.pressure.len() != self.points.len() {
+                return vec![self.width; self.points.len()];
+            }
+            self.pressure
+                .iter()
+                .map(|p| {
+                    let p = p.clamp(0.0, 1.0);
+                    self.width * (Self::MIN_PRESSURE_WIDTH + (1.0 - Self::MIN_PRESSURE_WIDTH) * p)
+                })
+                .collect()
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub enum Element {
+        Text(Text),
+        Sticky(Sticky),
+        Image(Image),
+        Stroke(Stroke),
+    }
+
+    pub type LayerId = u64;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct Layer {
+        pub name: String,
+        pub hidden: bool,
+        /// Elements of a locked layer cannot be created, changed or deleted
+        pub locked: bool,
+    }
+
+    /// Where an element sits in the stacking order of a board
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Placement {
+        /// `None` is the base layer, below all named layers
+        pub layer: Option<LayerId>,
+        /// Elements with higher `z` are drawn on top within a layer
+        pub z: i64,
+    }
+
+    impl Placement {
+        /// Sort key ordering elements from bottom to top, given the layers of the board from
+        /// bottom to top. Elements of unknown layers end up in the base layer.
+        pub fn stacking_key(&self, layers: &[(LayerId, Layer)]) -> (Option<usize>, i64) {
+            let layer = self
+                .layer
+                .and_then(|layer| layers.iter().position(|(id, _)| *id == layer));
+            (layer, self.z)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ZOrder {
+        Front,
+        Back,
+    }
+
+    /// Uniform scale and rotation around `pivot`, followed by a translation
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    pub struct Transform {
+        pub translation: Position,
+        pub pivot: Position,
+        pub scale: f32,
+        /// Clockwise rotation in radians
+        pub rotation: f32,
+    }
+
+    impl Transform {
+        pub const IDENTITY: Transform = Transform {
+            translation: Position { x: 0.0, y: 0.0 },
+            pivot: Position { x: 0.0, y: 0.0 },
+            scale: 1.0,
+            rotation: 0.0,
+        };
+
+        pub fn apply(&self, position: Position) -> Position {
+            let dx = (position.x - self.pivot.x) * self.scale;
+            let dy = (position.y - self.pivot.y) * self.scale;
+            let (sin, cos) = self.rotation.sin_cos();
+            Position {
+                x: self.pivot.x + cos * dx - sin * dy + self.translation.x,
+                y: self.pivot.y + sin * dx + cos * dy + self.translation.y,
+            }
+        }
+    }
+
+    /// Axis aligned bounding box of a rectangle of `width` by `height` rotated around its top left
+    /// corner `origin`
+    fn rotated_bounds(origin: Position, width: f32, height: f32, rotation: f32) -> Option<Rect> {
+        let (sin, cos) = rotation.sin_cos();
+        Rect::around([(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)].map(|(x, y)| Position {
+            x: origin.x + cos * x - sin * y,
+            y: origin.y + sin * x + cos * y,
+        }))
+    }
+
+    impl Element {
+        /// Rectangle covering everything drawn for the element, `None` for strokes without points
+        pub fn bounds(&self) -> Option<Rect> {
+            match self {
+                Element::Text(text) => {
+                    let (width, height) = text.size();
+                    rotated_bounds(text.position, width, height, text.rotation)
+                }
+                Element::Sticky(sticky) => {
+                    rotated_bounds(sticky.position, sticky.width, sticky.height, sticky.rotation)
+                }
+                Element::Image(image) => rotated_bounds(
+                    image.position,
+                    image.width * image.scale,
+                    image.height * image.scale,
+                    image.rotation,
+                ),
+                Element::Stroke(stroke) => {
+                    // Square caps reach half the width diagonally past the ends, miters as far as
+                    // their limit allows
+                    let reach = match stroke.style.join {
+                        Join::Miter { limit } => limit.max(std::f32::consts::SQRT_2),
+                        _ => std::f32::consts::SQRT_2,
+                    };
+                    Rect::around(stroke.points.iter().copied()).map(|r| r.expand(stroke.width / 2.0 * reach))
+                }
+            }
+        }
+
+        pub fn transform(&mut self, transform: &Transform) {
+            match self {
+                Element::Text(text) => {
+                    text.position = transform.apply(text.position);
+                    text.font_size *= transform.scale;
+                    text.rotation += transform.rotation;
+                }
+                Element::Sticky(sticky) => {
+                    sticky.position = transform.apply(sticky.position);
+                    sticky.width *= transform.scale;
+                    sticky.height *= transform.scale;
+                    sticky.rotation += transform.rotation;
+                }
+                Element::Image(image) => {
+                    image.position = transform.apply(image.position);
+                    image.scale *= transform.scale;
+                    image.rotation += transform.rotation;
+                }
+                Element::Stroke(stroke) => {
+                    for point in stroke.points.iter_mut() {
+                        *point = transform.apply(*point);
+                    }
+                    stroke.width *= transform.scale;
+                }
+            }
+        }
+    }
 }
 
+pub mod board_file;
+pub mod import;
+pub mod line_drawing;
+pub mod smoothing;
+pub mod spatial;
+pub mod text_ot;
+
 pub mod api {
     use serde::{Deserialize, Serialize};
 
+    use crate::{board_file::FileElement, entities::{Layer, LayerId}};
+
     #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct Board {
         pub name: String,
     }
+
+    /// Point in the history of a board, its contents after the first `number` changes
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct Version {
+        pub number: u64,
+        /// Milliseconds since the Unix epoch when the change was made
+        pub time: u64,
+        /// Client who made the change, none for changes made outside of a socket
+        pub client: Option<u64>,
+        /// What the change did, as shown to users
+        pub summary: String,
+    }
+
+    /// Named copy of a board, which can be forked into a new board later
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct Snapshot {
+        pub name: String,
+        /// Seconds since the Unix epoch when the snapshot was taken
+        pub saved_at: u64,
+    }
+
+    /// Contents of a board at a past version
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct Preview {
+        pub version: u64,
+        /// Layers from bottom to top
+        pub layers: Vec<(LayerId, Layer)>,
+        pub elements: Vec<FileElement>,
+    }
 }
 
 pub mod websocket {
     use serde::{Deserialize, Serialize};
 
-    use crate::entities::Position;
+    use crate::{
+        entities::{Element, ElementId, Layer, LayerId, Placement, Position, Transform, ZOrder},
+        text_ot::TextOp,
+    };
 
     #[derive(Serialize, Deserialize, Debug, Clone)]
     pub enum ToServer {
+        /// Pointer position in board coordinates
         Move { x: f32, y: f32 },
+        /// Adds an element on top of `layer`
+        CreateElement { element: Element, layer: Option<LayerId> },
+        UpdateElement { id: ElementId, element: Element },
+        DeleteElement { id: ElementId },
+        EditSticky { id: ElementId, revision: u64, ops: Vec<TextOp> },
+        StickyCaret { id: ElementId, position: Option<usize> },
+        /// Moves, scales and rotates all listed elements as one operation
+        Transform { ids: Vec<ElementId>, transform: Transform },
+        Reorder { ids: Vec<ElementId>, to: ZOrder },
+        /// Moves elements to the top of another layer
+        SetLayer { ids: Vec<ElementId>, layer: Option<LayerId> },
+        CreateLayer { name: String },
+        UpdateLayer { id: LayerId, layer: Layer },
+        /// Removes a layer, moving its elements to the base layer
+        DeleteLayer { id: LayerId },
+        /// Moves a layer to `index` in the stack, counted from the bottom
+        MoveLayer { id: LayerId, index: usize },
+        /// Change numbered by its author, the server answers it with `ToClient::Ack` once it
+        /// applied it or with `ToClient::Rejected`. Authors pick `author` at random and keep it
+        /// across connections, and send their changes in the order of `seq`. A change sent again
+        /// after an answer was lost is not applied twice, it is answered with `ToClient::Ack`.
+        Tagged { author: u64, seq: u64, message: Box<ToServer> },
     }
 
     #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -33,5 +415,23 @@ pub mod websocket {
         NewClient { id: u64 },
         ClientMoved { id: u64, x: f32, y: f32 },
         ClientDisconnected { id: u64 },
+        /// All elements of the board. `board` is picked at random when the board is loaded, so
+        /// a different one means that the board was unloaded since and its ids mean nothing now.
+        ElementList { board: u64, elements: Vec<(ElementId, Element, Placement)> },
+        NewElement { id: ElementId, element: Element, placement: Placement },
+        ElementUpdated { id: ElementId, element: Element },
+        ElementDeleted { id: ElementId },
+        ElementsUpdated { elements: Vec<(ElementId, Element)> },
+        /// Sent only to the author of an edit once the server has applied it
+        StickyAck { id: ElementId, revision: u64 },
+        StickyEdited { id: ElementId, revision: u64, ops: Vec<TextOp> },
+        StickyCaret { client: u64, id: ElementId, position: Option<usize> },
+        PlacementsUpdated { placements: Vec<(ElementId, Placement)> },
+        /// All layers of the board, bottom first
+        LayerList { layers: Vec<(LayerId, Layer)> },
+        /// Sent only to the author of a tagged change, after the changes it caused
+        Ack { seq: u64 },
+        /// Sent only to the author of a tagged change that the server did not apply
+        Rejected { seq: u64 },
     }
 }
@@ -0,0 +1,989 @@
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+use crate::{
+    entities::{Cap, Join, Stroke, StrokeStyle},
+    smoothing::smooth_stroke,
+};
+use nalgebra::{Point2, Rotation2, Vector2};
+
+pub type Point = Point2<f64>;
+type Vector = Vector2<f64>;
+
+/// Largest distance in screen pixels between a round part of a stroke and its tessellation
+pub const TOLERANCE: f32 = 0.25;
+/// Distance in screen pixels between the points of smoothed strokes
+pub const SMOOTHING_STEP: f32 = 3.0;
+
+/// Tessellates a line of constant half thickness `width`. Round parts are split finely enough
+/// that their outline stays within `tolerance` of a true circle.
+pub fn line_into_triangle_strip(
+    line: Vec<Point>,
+    width: f64,
+    style: &StrokeStyle,
+    tolerance: f64,
+) -> Vec<Point> {
+    let widths = vec![width; line.len()];
+    tapered_line_into_triangle_strip(line, &widths, style, tolerance)
+}
+
+/// Tessellates a line whose width changes along it, `widths` holds the half thickness at each
+/// point. The outline follows the tangents between the circles around consecutive points.
+pub fn tapered_line_into_triangle_strip(
+    line: Vec<Point>,
+    widths: &[f64],
+    style: &StrokeStyle,
+    tolerance: f64,
+) -> Vec<Point> {
+    let (line, widths) = clean(&line, widths);
+    match (&line[..], &widths[..]) {
+        ([], _) => return vec![],
+        ([a], [width]) => return dot(*a, *width, style.cap, tolerance),
+        _ => (),
+    }
+    let last = line.len() - 1;
+    let edges = (0..last)
+        .map(|i| edge_directions(line[i], widths[i], line[i + 1], widths[i + 1]))
+        .collect::<Vec<_>>();
+
+    let mut result = vec![cap(
+        line[0], line[1], widths[0], edges[0], style.cap, tolerance,
+    )];
+    for i in 0..last {
+        result.push(segment(
+            line[i],
+            widths[i],
+            line[i + 1],
+            widths[i + 1],
+            edges[i],
+        ));
+        if i + 1 < last {
+            result.push(elbow(
+                [line[i], line[i + 1], line[i + 2]],
+                widths[i + 1],
+                edges[i],
+                edges[i + 1],
+                style.join,
+                tolerance,
+            ));
+        }
+    }
+    // Seen from the end, left and right of the last segment swap
+    let (left, right) = edges[last - 1];
+    result.push(cap(
+        line[last],
+        line[last - 1],
+        widths[last],
+        (right, left),
+        style.cap,
+        tolerance,
+    ));
+
+    result.into_iter().flatten().collect()
+}
+
+/// Drops points that are not finite or repeat the previous point, which have no direction to
+/// tessellate along. A repeated point keeps the larger of the widths.
+fn clean(line: &[Point], widths: &[f64]) -> (Vec<Point>, Vec<f64>) {
+    let mut points: Vec<Point> = vec![];
+    let mut result: Vec<f64> = vec![];
+    for (point, width) in line.iter().zip(widths) {
+        if !point.x.is_finite() || !point.y.is_finite() || !width.is_finite() {
+            continue;
+        }
+        let width = width.max(0.0);
+        match points.last() {
+            Some(last) if (point - last).norm() < MIN_SEGMENT_LENGTH => {
+                let last = result.len() - 1;
+                result[last] = result[last].max(width);
+            }
+            _ => {
+                points.push(*point);
+                result.push(width);
+            }
+        }
+    }
+    (points, result)
+}
+
+/// Tessellates the dashes of a line separately, `pattern` holds the lengths of alternating
+/// dashes and gaps and `offset` the distance into the pattern at which the line starts
+pub fn dashed_line_into_triangle_strip(
+    line: Vec<Point>,
+    widths: &[f64],
+    style: &StrokeStyle,
+    pattern: &[f64],
+    offset: f64,
+    tolerance: f64,
+) -> Vec<Point> {
+    let (line, widths) = clean(&line, widths);
+    let strips = dashes(&line, &widths, pattern, offset)
+        .into_iter()
+        .map(|(dash, widths)| tapered_line_into_triangle_strip(dash, &widths, style, tolerance))
+        .collect();
+    join_strips(strips)
+}
+
+/// Splits a line into dashes by arc length. A pattern that cannot be drawn, or would produce
+/// too many dashes, leaves the line solid.
+fn dashes(
+    line: &[Point],
+    widths: &[f64],
+    pattern: &[f64],
+    offset: f64,
+) -> Vec<(Vec<Point>, Vec<f64>)> {
+    let solid = vec![(line.to_vec(), widths.to_vec())];
+    // Like in SVG, an odd number of lengths is repeated to make dashes and gaps alternate
+    let pattern = if pattern.len() % 2 == 1 {
+        [pattern, pattern].concat()
+    } else {
+        pattern.to_vec()
+    };
+    let period = pattern.iter().sum::<f64>();
+    let length = line.windows(2).map(|s| (s[1] - s[0]).norm()).sum::<f64>();
+    if pattern.is_empty()
+        || pattern.iter().any(|l| !l.is_finite() || *l < 0.0)
+        || period <= 0.0
+        || length / period * pattern.len() as f64 > MAX_DASHES as f64
+        || line.len() < 2
+    {
+        return solid;
+    }
+
+    // Find where in the pattern the line starts
+    let mut index = 0;
+    let mut remaining = pattern[0];
+    let mut skip = offset.rem_euclid(period);
+    // A dash of zero length right at the start is drawn, one that ends there is not
+    while skip > remaining || (skip > 0.0 && skip == remaining) {
+        skip -= remaining;
+        index = (index + 1) % pattern.len();
+        remaining = pattern[index];
+    }
+    remaining -= skip;
+
+    let mut result = vec![];
+    let mut current = (index % 2 == 0).then(|| (vec![line[0]], vec![widths[0]]));
+    for i in 0..line.len() - 1 {
+        let (from, to) = (line[i], line[i + 1]);
+        let segment = (to - from).norm();
+        let mut travelled = 0.0;
+        while segment - travelled > remaining {
+            travelled += remaining;
+            let t = travelled / segment;
+            let point = from + (to - from) * t;
+            let width = widths[i] + (widths[i + 1] - widths[i]) * t;
+            match current.take() {
+                Some((mut points, mut dash_widths)) => {
+                    points.push(point);
+                    dash_widths.push(width);
+                    result.push((points, dash_widths));
+                }
+                None => current = Some((vec![point], vec![width])),
+            }
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= segment - travelled;
+        if let Some((points, dash_widths)) = &mut current {
+            points.push(to);
+            dash_widths.push(widths[i + 1]);
+        }
+    }
+    result.extend(current);
+    result
+}
+
+/// Concatenates triangle strips, repeating the vertices where they meet so that the triangles
+/// connecting them have no area
+fn join_strips(strips: Vec<Vec<Point>>) -> Vec<Point> {
+    let mut result: Vec<Point> = vec![];
+    for strip in strips.into_iter().filter(|s| !s.is_empty()) {
+        if let Some(last) = result.last().copied() {
+            result.push(last);
+            result.push(strip[0]);
+        }
+        result.extend(strip);
+    }
+    result
+}
+
+/// Tessellates a board stroke, whose widths are the full thickness of the line, with round parts
+/// accurate to `tolerance` board units
+pub fn stroke_into_triangle_strip(stroke: &Stroke, tolerance: f64) -> Vec<Point> {
+    let line = stroke
+        .points
+        .iter()
+        .map(|p| Point::new(p.x as f64, p.y as f64))
+        .collect();
+    let widths = stroke
+        .widths()
+        .into_iter()
+        .map(|w| w as f64 / 2.0)
+        .collect::<Vec<_>>();
+    let style = &stroke.style;
+    if !style.dash.is_empty() {
+        // Dash lengths are relative to the width, so that they scale along with it
+        let scale = stroke.width as f64;
+        let pattern = style
+            .dash
+            .iter()
+            .map(|l| *l as f64 * scale)
+            .collect::<Vec<_>>();
+        let offset = style.dash_offset as f64 * scale;
+        return dashed_line_into_triangle_strip(line, &widths, style, &pattern, offset, tolerance);
+    }
+    if stroke.pressure.is_empty() {
+        return line_into_triangle_strip(line, stroke.width as f64 / 2.0, style, tolerance);
+    }
+    tapered_line_into_triangle_strip(line, &widths, style, tolerance)
+}
+
+/// Triangle strip of `stroke` as it is displayed at `zoom` screen pixels per board unit: smoothed
+/// through its points, then tessellated finely enough for that zoom. Everything that draws
+/// strokes goes through this, so exports look the same as the board.
+pub fn display_strip(stroke: &Stroke, zoom: f32) -> Vec<Point> {
+    let stroke = smooth_stroke(stroke, SMOOTHING_STEP / zoom);
+    stroke_into_triangle_strip(&stroke, (TOLERANCE / zoom) as f64)
+}
+
+/// Triangles of a triangle strip, all wound counterclockwise like `ccw`, without the ones that
+/// have no area. With a consistent winding the union of the triangles can be filled as
+/// one path with the nonzero rule.
+pub fn strip_triangles(strip: &[Point]) -> impl Iterator<Item = [Point; 3]> + '_ {
+    strip.windows(3).filter_map(|t| {
+        let area = (t[1] - t[0]).perp(&(t[2] - t[0]));
+        if area > 0.0 {
+            Some([t[0], t[1], t[2]])
+        } else if area < 0.0 {
+            Some([t[0], t[2], t[1]])
+        } else {
+            None
+        }
+    })
+}
+
+/// Checks if `point` lies inside any triangle of a triangle strip
+pub fn strip_contains(strip: &[Point], point: Point) -> bool {
+    strip
+        .windows(3)
+        .any(|t| triangle_contains(t[0], t[1], t[2], point))
+}
+
+fn triangle_contains(a: Point, b: Point, c: Point, point: Point) -> bool {
+    if (b - a).perp(&(c - a)) == 0.0 {
+        return false;
+    }
+    let d1 = (b - a).perp(&(point - a));
+    let d2 = (c - b).perp(&(point - b));
+    let d3 = (a - c).perp(&(point - c));
+    let has_negative = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let has_positive = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    !(has_negative && has_positive)
+}
+
+/// Unit vectors pointing from the centre of each circle of a segment to where the left and the
+/// right outline touch it. When one circle contains the other the outline runs perpendicular.
+fn edge_directions(from: Point, from_width: f64, to: Point, to_width: f64) -> (Vector, Vector) {
+    let length = (to - from).norm();
+    let dir = (to - from) / length;
+    let perp = Rotation2::new(FRAC_PI_2) * dir;
+    let sin = (from_width - to_width) / length;
+    if sin.abs() >= 1.0 {
+        return (perp, -perp);
+    }
+    let cos = (1.0 - sin * sin).sqrt();
+    (perp * cos + dir * sin, -perp * cos + dir * sin)
+}
+
+/// Quad covering the part of the outline between two points
+fn segment(
+    from: Point,
+    from_width: f64,
+    to: Point,
+    to_width: f64,
+    (left, right): (Vector, Vector),
+) -> Vec<Point> {
+    vec![
+        from + left * from_width,
+        from + right * from_width,
+        to + left * to_width,
+        to + right * to_width,
+    ]
+}
+
+/// Finest and coarsest angle round parts are split into, whatever the tolerance
+const MIN_ANGLE_STEP: f64 = 0.01;
+const MAX_ANGLE_STEP: f64 = FRAC_PI_2;
+/// Angles this close to none or a full turn are rounding errors
+const MIN_ANGLE: f64 = 0.001;
+/// Points closer than this are merged
+const MIN_SEGMENT_LENGTH: f64 = 1e-9;
+/// Most dashes a line is split into, denser patterns are drawn solid
+const MAX_DASHES: usize = 100_000;
+
+/// Checks if a counterclockwise angle is too close to no rotation at all to be drawn. Rounding
+/// turns almost equal directions into almost full turns, which count as none as well.
+fn negligible(angle: f64) -> bool {
+    !(MIN_ANGLE..=TAU - MIN_ANGLE).contains(&angle)
+}
+
+/// Largest angle of the chords approximating a circle of `radius`, such that they stay within
+/// `tolerance` of it
+fn angle_step(radius: f64, tolerance: f64) -> f64 {
+    if radius <= tolerance {
+        return MAX_ANGLE_STEP;
+    }
+    let step = 2.0 * (1.0 - tolerance / radius).acos();
+    if step.is_nan() {
+        return MIN_ANGLE_STEP;
+    }
+    step.clamp(MIN_ANGLE_STEP, MAX_ANGLE_STEP)
+}
+
+/// End of a line at `from`, whose outline touches the circle around `from` in the directions
+/// `(left, right)` seen when looking towards `to`
+fn cap(
+    from: Point,
+    to: Point,
+    width: f64,
+    (left, right): (Vector, Vector),
+    cap: Cap,
+    tolerance: f64,
+) -> Vec<Point> {
+    match cap {
+        Cap::Round => arc(from + left * width, from, from + right * width, tolerance),
+        Cap::Butt => vec![],
+        Cap::Square => {
+            let back = (from - to).normalize() * width;
+            vec![
+                from + left * width + back,
+                from + right * width + back,
+                from + left * width,
+                from + right * width,
+            ]
+        }
+    }
+}
+
+/// Line made of a single point
+fn dot(a: Point, width: f64, cap: Cap, tolerance: f64) -> Vec<Point> {
+    match cap {
+        Cap::Round => circle(a, width, tolerance),
+        Cap::Butt => vec![],
+        Cap::Square => [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+            .into_iter()
+            .map(|(x, y)| a + Vector::new(x, y) * width)
+            .collect(),
+    }
+}
+
+fn circle(a: Point, width: f64, tolerance: f64) -> Vec<Point> {
+    let segment_count: u32 = (TAU / angle_step(width, tolerance)).ceil() as u32;
+    let start = a + Vector2::new(width, 0.0);
+    let mut points = vec![start, a];
+    let rotation = Rotation2::new(TAU / segment_count as f64);
+    for _ in 0..segment_count {
+        let prev = points[points.len() - 2];
+        points.push(a + rotation * (prev - a));
+        points.push(a);
+    }
+    points.push(start);
+    points
+}
+
+/// Constructs arc centered in `b` ranging from point `a` to `c` in counterclockwise direction.
+/// Arcs shorter than `tolerance` are left out.
+fn arc(a: Point, b: Point, c: Point, tolerance: f64) -> Vec<Point> {
+    let angle = ccw_angle(&(a - b), &(c - b));
+    let radius = (a - b).norm();
+    if negligible(angle) || radius * angle < tolerance {
+        return vec![];
+    }
+    let segment_count = (angle / angle_step(radius, tolerance)).ceil() as u32;
+    let rotation = Rotation2::new(angle / segment_count as f64);
+    let mut points = vec![a, b];
+    for _ in 0..(segment_count - 1) {
+        let prev = points[points.len() - 2];
+        points.push(b + rotation * (prev - b));
+        points.push(b);
+    }
+    points.push(c);
+    points
+}
+
+/// Given line a -- b -- c constructs the outer corner at point b, `incoming` and `outgoing` are
+/// the edge directions of the segments meeting there
+fn elbow(
+    [a, b, c]: [Point; 3],
+    width: f64,
+    incoming: (Vector, Vector),
+    outgoing: (Vector, Vector),
+    join: Join,
+    tolerance: f64,
+) -> Vec<Point> {
+    // Corner from `start` to `end` in counterclockwise direction
+    let (start, end) = if ccw_turn(a, b, c) {
+        (incoming.1, outgoing.1)
+    } else {
+        (outgoing.0, incoming.0)
+    };
+    if negligible(ccw_angle(&start, &end)) {
+        return vec![];
+    }
+    match join {
+        Join::Round => arc(b + start * width, b, b + end * width, tolerance),
+        Join::Bevel => bevel(b, width, start, end),
+        Join::Miter { limit } => {
+            let bisector = start + end;
+            // Directions at 180° to each other have no miter, it would be infinitely long
+            if bisector.norm() < MIN_ANGLE {
+                return bevel(b, width, start, end);
+            }
+            let bisector = bisector.normalize();
+            let length = 1.0 / bisector.dot(&start);
+            if length > limit as f64 {
+                return bevel(b, width, start, end);
+            }
+            vec![
+                b + start * width,
+                b,
+                b + bisector * length * width,
+                b + end * width,
+            ]
+        }
+    }
+}
+
+fn bevel(b: Point, width: f64, start: Vector, end: Vector) -> Vec<Point> {
+    vec![b + start * width, b, b + end * width]
+}
+
+/// Check if shortest rotation from `from` to `to` is counterclockwise
+fn ccw(from: &Vector, to: &Vector) -> bool {
+    from.perp(to) > 0.0
+}
+
+/// Check if line `start` -- `through` -- `end` turns counterclockwise
+fn ccw_turn(start: Point, through: Point, end: Point) -> bool {
+    ccw(&(through - start), &(end - through))
+}
+
+/// Angle from `from` to `to` in counterclockwise direction, zero for equal directions
+fn ccw_angle(from: &Vector, to: &Vector) -> f64 {
+    if from.perp(to) >= 0.0 {
+        from.angle(to)
+    } else {
+        TAU - from.angle(to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// About the accuracy of round joins of 2 units wide lines at a fixed 0.3 radian resolution
+    const TOLERANCE: f64 = 0.02;
+
+    mod ccw {
+        use super::*;
+
+        #[test]
+        fn acute_angle_vectors() {
+            let from = Vector::new(-1.0, 2.0);
+            let to = Vector::new(-2.0, 1.0);
+
+            assert!(ccw(&from, &to));
+            assert!(!ccw(&to, &from));
+        }
+
+        #[test]
+        fn perpendicular_vectors() {
+            let from = Vector::new(1.0, 0.0);
+            let to = Vector::new(0.0, 1.0);
+
+            assert!(ccw(&from, &to));
+            assert!(!ccw(&to, &from));
+        }
+
+        #[test]
+        fn obtuse_angle_vectors() {
+            let from = Vector::new(3.0, 2.0);
+            let to = Vector::new(-2.0, 0.0);
+
+            assert!(ccw(&from, &to));
+            assert!(!ccw(&to, &from));
+        }
+    }
+
+    mod styles {
+        use super::*;
+
+        const WIDTH: f64 = 2.0;
+
+        fn style(join: Join, cap: Cap) -> StrokeStyle {
+            StrokeStyle {
+                join,
+                cap,
+                ..StrokeStyle::default()
+            }
+        }
+
+        fn joins() -> [Join; 3] {
+            [Join::Round, Join::Miter { limit: 4.0 }, Join::Bevel]
+        }
+
+        /// Largest distance of the tessellation from `point`
+        fn reach(strip: &[Point], point: Point) -> f64 {
+            strip.iter().map(|p| (p - point).norm()).fold(0.0, f64::max)
+        }
+
+        #[test]
+        fn collinear_points_need_no_join() {
+            let a = Point::new(0.0, 0.0);
+            let b = Point::new(5.0, 0.0);
+            let c = Point::new(10.0, 0.0);
+            let edges = edge_directions(a, WIDTH, b, WIDTH);
+            for join in joins() {
+                assert!(elbow([a, b, c], WIDTH, edges, edges, join, TOLERANCE).is_empty());
+            }
+        }
+
+        #[test]
+        fn collinear_line_stays_within_width() {
+            let line = vec![
+                Point::new(0.0, 0.0),
+                Point::new(5.0, 0.0),
+                Point::new(10.0, 0.0),
+            ];
+            for join in joins() {
+                let strip = line_into_triangle_strip(
+                    line.clone(),
+                    WIDTH,
+                    &style(join, Cap::Butt),
+                    TOLERANCE,
+                );
+                assert!(strip.iter().all(|p| p.y.abs() <= WIDTH + 1e-9));
+                assert!(strip.iter().all(|p| (-1e-9..=10.0 + 1e-9).contains(&p.x)));
+            }
+        }
+
+        #[test]
+        fn reversal_is_finite_and_bounded() {
+            let a = Point::new(0.0, 0.0);
+            let b = Point::new(5.0, 0.0);
+            let line = vec![a, b, a];
+            for join in joins() {
+                let strip = line_into_triangle_strip(
+                    line.clone(),
+                    WIDTH,
+                    &style(join, Cap::Butt),
+                    TOLERANCE,
+                );
+                assert!(strip.iter().all(|p| p.x.is_finite() && p.y.is_finite()));
+                // A miter at a reversal would be infinitely long and falls back to a bevel
+                assert!(strip.iter().all(|p| p.x <= 5.0 + WIDTH + 1e-9));
+            }
+        }
+
+        #[test]
+        fn reversal_with_round_join_covers_tip() {
+            let line = vec![
+                Point::new(0.0, 0.0),
+                Point::new(5.0, 0.0),
+                Point::new(0.0, 0.0),
+            ];
+            let strip =
+                line_into_triangle_strip(line, WIDTH, &style(Join::Round, Cap::Butt), TOLERANCE);
+            assert!(strip_contains(&strip, Point::new(5.0 + WIDTH * 0.9, 0.0)));
+        }
+
+        #[test]
+        fn right_angle_miter() {
+            let a = Point::new(0.0, 0.0);
+            let b = Point::new(5.0, 0.0);
+            let c = Point::new(5.0, 5.0);
+            let incoming = edge_directions(a, WIDTH, b, WIDTH);
+            let outgoing = edge_directions(b, WIDTH, c, WIDTH);
+
+            let miter = elbow(
+                [a, b, c],
+                WIDTH,
+                incoming,
+                outgoing,
+                Join::Miter { limit: 4.0 },
+                TOLERANCE,
+            );
+            assert!((reach(&miter, b) - WIDTH * 2.0_f64.sqrt()).abs() < 1e-9);
+            assert!(miter.contains(&Point::new(5.0 + WIDTH, -WIDTH)));
+
+            // The miter of a right angle is sqrt(2) times the half thickness long
+            let limited = elbow(
+                [a, b, c],
+                WIDTH,
+                incoming,
+                outgoing,
+                Join::Miter { limit: 1.4 },
+                TOLERANCE,
+            );
+            let bevel = elbow([a, b, c], WIDTH, incoming, outgoing, Join::Bevel, TOLERANCE);
+            assert_eq!(limited, bevel);
+            assert!((reach(&bevel, b) - WIDTH).abs() < 1e-9);
+        }
+
+        #[test]
+        fn caps_extend_past_end_points() {
+            let line = vec![Point::new(0.0, 0.0), Point::new(10.0, 0.0)];
+            let extent = |cap| {
+                let strip = line_into_triangle_strip(
+                    line.clone(),
+                    WIDTH,
+                    &style(Join::Round, cap),
+                    TOLERANCE,
+                );
+                let min = strip.iter().map(|p| p.x).fold(f64::INFINITY, f64::min);
+                let max = strip.iter().map(|p| p.x).fold(f64::NEG_INFINITY, f64::max);
+                (min, max)
+            };
+            let (min, max) = extent(Cap::Butt);
+            assert!(min.abs() < 1e-9 && (max - 10.0).abs() < 1e-9);
+            let (min, max) = extent(Cap::Square);
+            assert!((min + WIDTH).abs() < 1e-9 && (max - 10.0 - WIDTH).abs() < 1e-9);
+            // Arcs are polygons, their vertices do not necessarily hit the far end
+            let (min, max) = extent(Cap::Round);
+            assert!((min + WIDTH).abs() < 0.05 && (max - 10.0 - WIDTH).abs() < 0.05);
+        }
+
+        #[test]
+        fn single_point_caps() {
+            let a = Point::new(1.0, 1.0);
+            let dot =
+                |cap| line_into_triangle_strip(vec![a], WIDTH, &style(Join::Round, cap), TOLERANCE);
+            assert!(dot(Cap::Butt).is_empty());
+            assert!(strip_contains(&dot(Cap::Square), Point::new(2.9, 2.9)));
+            assert!(!strip_contains(&dot(Cap::Round), Point::new(2.9, 2.9)));
+            assert!(strip_contains(&dot(Cap::Round), Point::new(2.9, 1.0)));
+        }
+    }
+
+    mod detail {
+        use super::*;
+
+        #[test]
+        fn chords_stay_within_tolerance() {
+            let center = Point::new(0.0, 0.0);
+            for (radius, tolerance) in [(2.0, 0.02), (50.0, 0.25), (1000.0, 0.01), (0.5, 1.0)] {
+                let rim = circle(center, radius, tolerance)
+                    .into_iter()
+                    .filter(|p| *p != center)
+                    .collect::<Vec<_>>();
+                for pair in rim.windows(2) {
+                    let middle = Point::from((pair[0].coords + pair[1].coords) / 2.0);
+                    let error = radius - (middle - center).norm();
+                    assert!(
+                        error <= tolerance + 1e-9
+                            || error <= radius * (1.0 - (MIN_ANGLE_STEP / 2.0).cos()) + 1e-9
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn coarser_tolerance_gives_fewer_vertices() {
+            let line = vec![
+                Point::new(0.0, 0.0),
+                Point::new(10.0, 0.0),
+                Point::new(10.0, 10.0),
+            ];
+            let count = |tolerance| {
+                line_into_triangle_strip(line.clone(), 5.0, &StrokeStyle::default(), tolerance)
+                    .len()
+            };
+            assert!(count(0.01) > count(0.1));
+            assert!(count(0.1) > count(1.0));
+        }
+
+        #[test]
+        fn invalid_tolerance_is_finite() {
+            for tolerance in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+                let strip = line_into_triangle_strip(
+                    vec![Point::new(0.0, 0.0), Point::new(1.0, 1.0)],
+                    1.0,
+                    &StrokeStyle::default(),
+                    tolerance,
+                );
+                assert!(!strip.is_empty());
+                assert!(strip.iter().all(|p| p.x.is_finite() && p.y.is_finite()));
+            }
+        }
+    }
+
+    mod degenerate {
+        use super::*;
+
+        fn round() -> StrokeStyle {
+            StrokeStyle::default()
+        }
+
+        #[test]
+        fn empty_line() {
+            assert!(line_into_triangle_strip(vec![], 2.0, &round(), TOLERANCE).is_empty());
+        }
+
+        #[test]
+        fn repeated_points() {
+            let a = Point::new(1.0, 1.0);
+            let b = Point::new(4.0, 1.0);
+            let strip = line_into_triangle_strip(vec![a, a, b, b, b], 2.0, &round(), TOLERANCE);
+            assert!(strip.iter().all(|p| p.x.is_finite() && p.y.is_finite()));
+            assert_eq!(
+                strip,
+                line_into_triangle_strip(vec![a, b], 2.0, &round(), TOLERANCE)
+            );
+        }
+
+        #[test]
+        fn only_repeated_points_make_a_dot() {
+            let a = Point::new(1.0, 1.0);
+            let strip = line_into_triangle_strip(vec![a; 4], 2.0, &round(), TOLERANCE);
+            assert_eq!(
+                strip,
+                line_into_triangle_strip(vec![a], 2.0, &round(), TOLERANCE)
+            );
+            assert!(strip_contains(&strip, Point::new(2.5, 1.5)));
+        }
+
+        #[test]
+        fn near_collinear_points() {
+            let line = vec![
+                Point::new(0.0, 0.0),
+                Point::new(5.0, 1e-13),
+                Point::new(10.0, 0.0),
+                Point::new(15.0, -1e-13),
+            ];
+            let strip = line_into_triangle_strip(line, 2.0, &round(), TOLERANCE);
+            // Caps and segments only, no join turns into a full circle
+            let cap = arc(
+                Point::new(0.0, 2.0),
+                Point::new(0.0, 0.0),
+                Point::new(0.0, -2.0),
+                TOLERANCE,
+            );
+            assert_eq!(strip.len(), 2 * cap.len() + 3 * 4);
+        }
+
+        #[test]
+        fn non_finite_points_are_skipped() {
+            let line = vec![
+                Point::new(0.0, 0.0),
+                Point::new(f64::NAN, 1.0),
+                Point::new(5.0, 0.0),
+            ];
+            let strip = line_into_triangle_strip(line, 2.0, &round(), TOLERANCE);
+            assert!(!strip.is_empty());
+            assert!(strip.iter().all(|p| p.x.is_finite() && p.y.is_finite()));
+        }
+    }
+
+    mod properties {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        /// Points on a coarse grid, so that repeated and collinear points are common
+        fn lines() -> impl Strategy<Value = Vec<Point>> {
+            prop::collection::vec((-5i32..5, -5i32..5), 0..20).prop_map(|points| {
+                points
+                    .into_iter()
+                    .map(|(x, y)| Point::new(x as f64, y as f64))
+                    .collect()
+            })
+        }
+
+        fn styles() -> impl Strategy<Value = StrokeStyle> {
+            let join = prop_oneof![
+                Just(Join::Round),
+                Just(Join::Bevel),
+                (1.0f32..10.0).prop_map(|limit| Join::Miter { limit }),
+            ];
+            let cap = prop_oneof![Just(Cap::Round), Just(Cap::Butt), Just(Cap::Square)];
+            (join, cap).prop_map(|(join, cap)| StrokeStyle {
+                join,
+                cap,
+                ..StrokeStyle::default()
+            })
+        }
+
+        fn distinct(line: &[Point]) -> Vec<Point> {
+            let mut line = line.to_vec();
+            line.dedup();
+            line
+        }
+
+        /// Whether the line turns at its `i`th point
+        fn turns(line: &[Point], i: usize) -> bool {
+            (line[i] - line[i - 1]).perp(&(line[i + 1] - line[i])) != 0.0
+                || (line[i] - line[i - 1]).dot(&(line[i + 1] - line[i])) < 0.0
+        }
+
+        proptest! {
+            #[test]
+            fn output_is_finite(line in lines(), width in 0.0f64..10.0, style in styles()) {
+                let strip = line_into_triangle_strip(line, width, &style, TOLERANCE);
+                prop_assert!(strip.iter().all(|p| p.x.is_finite() && p.y.is_finite()));
+            }
+
+            #[test]
+            fn tapered_output_is_finite(
+                line in lines(),
+                widths in prop::collection::vec(0.0f64..10.0, 20),
+                style in styles(),
+            ) {
+                let strip = tapered_line_into_triangle_strip(line, &widths, &style, TOLERANCE);
+                prop_assert!(strip.iter().all(|p| p.x.is_finite() && p.y.is_finite()));
+            }
+
+            #[test]
+            fn butt_bevel_triangle_count(line in lines(), width in 0.1f64..10.0) {
+                let style = StrokeStyle {
+                    join: Join::Bevel,
+                    cap: Cap::Butt,
+                    ..StrokeStyle::default()
+                };
+                let strip = line_into_triangle_strip(line.clone(), width, &style, TOLERANCE);
+                let line = distinct(&line);
+                // Four vertices per segment and three per bevel, a strip has two triangles fewer
+                // than vertices
+                let expected = match line.len() {
+                    0 | 1 => 0,
+                    n => 4 * (n - 1) + 3 * (1..n - 1).filter(|i| turns(&line, *i)).count(),
+                };
+                prop_assert_eq!(strip.len(), expected);
+            }
+
+            #[test]
+            fn covers_its_points(line in lines(), width in 0.1f64..10.0) {
+                let strip = line_into_triangle_strip(line.clone(), width, &StrokeStyle::default(), TOLERANCE);
+                for point in line {
+                    prop_assert!(strip_contains(&strip, point));
+                }
+            }
+        }
+    }
+
+    mod dashes {
+        use super::*;
+
+        fn line() -> Vec<Point> {
+            vec![
+                Point::new(0.0, 0.0),
+                Point::new(10.0, 0.0),
+                Point::new(10.0, 10.0),
+            ]
+        }
+
+        fn lengths(dashes: &[(Vec<Point>, Vec<f64>)]) -> Vec<f64> {
+            dashes
+                .iter()
+                .map(|(points, _)| points.windows(2).map(|s| (s[1] - s[0]).norm()).sum())
+                .collect()
+        }
+
+        #[test]
+        fn split_by_arc_length() {
+            let dashes = dashes(&line(), &[1.0; 3], &[3.0, 2.0], 0.0);
+            assert_eq!(lengths(&dashes), vec![3.0, 3.0, 3.0, 3.0]);
+            assert_eq!(
+                dashes[2].0,
+                vec![Point::new(10.0, 0.0), Point::new(10.0, 3.0)]
+            );
+        }
+
+        #[test]
+        fn dashes_turn_corners() {
+            let dashes = dashes(&line(), &[1.0; 3], &[3.0, 2.0], 1.0);
+            assert_eq!(
+                dashes[2].0,
+                vec![
+                    Point::new(9.0, 0.0),
+                    Point::new(10.0, 0.0),
+                    Point::new(10.0, 2.0)
+                ]
+            );
+        }
+
+        #[test]
+        fn offset_shifts_pattern() {
+            let dashes = |offset| super::dashes(&line(), &[1.0; 3], &[3.0, 2.0], offset);
+            assert_eq!(
+                dashes(2.0)[0].0,
+                vec![Point::new(0.0, 0.0), Point::new(1.0, 0.0)]
+            );
+            // Starting inside a gap
+            assert_eq!(
+                dashes(4.0)[0].0,
+                vec![Point::new(1.0, 0.0), Point::new(4.0, 0.0)]
+            );
+            assert_eq!(dashes(-1.0), dashes(4.0));
+            assert_eq!(dashes(5.0), dashes(0.0));
+        }
+
+        #[test]
+        fn odd_pattern_repeats() {
+            let dashes = dashes(&line(), &[1.0; 3], &[2.0], 0.0);
+            assert_eq!(lengths(&dashes), vec![2.0; 5]);
+            assert_eq!(dashes[1].0[0], Point::new(4.0, 0.0));
+        }
+
+        #[test]
+        fn widths_are_interpolated() {
+            let line = vec![Point::new(0.0, 0.0), Point::new(10.0, 0.0)];
+            let dashes = dashes(&line, &[0.0, 10.0], &[2.0, 3.0], 0.0);
+            assert_eq!(dashes[1].1, vec![5.0, 7.0]);
+        }
+
+        #[test]
+        fn zero_length_dashes_are_dots() {
+            let dashes = dashes(&line(), &[1.0; 3], &[0.0, 5.0], 0.0);
+            assert_eq!(dashes.len(), 4);
+            assert!(dashes.iter().all(|(points, _)| points[0] == points[1]));
+
+            let style = StrokeStyle::default();
+            let strip = dashed_line_into_triangle_strip(
+                line(),
+                &[1.0; 3],
+                &style,
+                &[0.0, 5.0],
+                0.0,
+                TOLERANCE,
+            );
+            assert!(strip_contains(&strip, Point::new(5.5, 0.0)));
+            assert!(!strip_contains(&strip, Point::new(2.5, 0.0)));
+        }
+
+        #[test]
+        fn invalid_patterns_are_solid() {
+            for pattern in [vec![], vec![0.0, 0.0], vec![1.0, -1.0], vec![f64::NAN, 1.0]] {
+                assert_eq!(dashes(&line(), &[1.0; 3], &pattern, 0.0).len(), 1);
+            }
+        }
+
+        #[test]
+        fn gaps_are_empty() {
+            let style = StrokeStyle {
+                cap: Cap::Butt,
+                ..StrokeStyle::default()
+            };
+            let strip = dashed_line_into_triangle_strip(
+                line(),
+                &[1.0; 3],
+                &style,
+                &[3.0, 2.0],
+                0.0,
+                TOLERANCE,
+            );
+            assert!(strip_contains(&strip, Point::new(1.0, 0.5)));
+            assert!(!strip_contains(&strip, Point::new(4.0, 0.5)));
+            assert!(strip.iter().all(|p| p.x.is_finite() && p.y.is_finite()));
+        }
+    }
+}
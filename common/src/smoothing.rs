@@ -0,0 +1,267 @@
+use crate::entities::{Position, Stroke};
+
+/// Most points inserted between two samples when smoothing, bounds the cost at high zoom
+const MAX_SUBDIVISIONS: usize = 32;
+/// Smallest parameter step of the centripetal parametrisation, keeps repeated points finite
+const MIN_KNOT_STEP: f32 = 1e-4;
+
+fn distance(a: Position, b: Position) -> f32 {
+    (b.x - a.x).hypot(b.y - a.y)
+}
+
+/// Distance of `point` from the segment `a` -- `b`
+pub fn segment_distance(point: Position, a: Position, b: Position) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length_squared = dx * dx + dy * dy;
+    if length_squared == 0.0 {
+        return distance(point, a);
+    }
+    let t = (((point.x - a.x) * dx + (point.y - a.y) * dy) / length_squared).clamp(0.0, 1.0);
+    distance(
+        point,
+        Position {
+            x: a.x + t * dx,
+            y: a.y + t * dy,
+        },
+    )
+}
+
+/// Indices of the points kept by Ramer–Douglas–Peucker simplification. Every dropped point lies
+/// within `tolerance` of the simplified line, the first and the last point are always kept.
+pub fn simplify(points: &[Position], tolerance: f32) -> Vec<usize> {
+    if points.len() < 3 {
+        return (0..points.len()).collect();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = ranges.pop() {
+        let farthest = (start + 1..end)
+            .map(|i| (i, segment_distance(points[i], points[start], points[end])))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((i, d)) = farthest {
+            if d > tolerance {
+                keep[i] = true;
+                ranges.push((start, i));
+                ranges.push((i, end));
+            }
+        }
+    }
+    (0..points.len()).filter(|i| keep[*i]).collect()
+}
+
+/// Drops the points of `stroke` that change its shape by less than `tolerance`
+pub fn simplify_stroke(stroke: &mut Stroke, tolerance: f32) {
+    let kept = simplify(&stroke.points, tolerance);
+    stroke.points = kept.iter().map(|i| stroke.points[*i]).collect();
+    if !stroke.pressure.is_empty() {
+        stroke.pressure = kept.iter().map(|i| stroke.pressure[*i]).collect();
+    }
+}
+
+/// Point at `t` of the centripetal Catmull-Rom segment between `p[1]` and `p[2]`, computed with
+/// the Barry–Goldman pyramid
+fn catmull_rom_point(p: [Position; 4], knots: [f32; 4], t: f32) -> Position {
+    let lerp = |a: Position, b: Position, ta: f32, tb: f32| {
+        let s = (t - ta) / (tb - ta);
+        Position {
+            x: a.x + (b.x - a.x) * s,
+            y: a.y + (b.y - a.y) * s,
+        }
+    };
+    let [t0, t1, t2, t3] = knots;
+    let a1 = lerp(p[0], p[1], t0, t1);
+    let a2 = lerp(p[1], p[2], t1, t2);
+    let a3 = lerp(p[2], p[3], t2, t3);
+    let b1 = lerp(a1, a2, t0, t2);
+    let b2 = lerp(a2, a3, t1, t3);
+    lerp(b1, b2, t1, t2)
+}
+
+/// Smooths a polyline with a centripetal Catmull-Rom spline through all of its points, adding
+/// points so that consecutive ones are roughly `max_length` apart. Returns the new points with
+/// the index of the segment each comes from and its position along that segment in `0.0..1.0`.
+fn catmull_rom(points: &[Position], max_length: f32) -> Vec<(Position, usize, f32)> {
+    let n = points.len();
+    if n < 3 {
+        return points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (*p, i, 0.0))
+            .collect();
+    }
+    let mirror = |a: Position, b: Position| Position {
+        x: 2.0 * a.x - b.x,
+        y: 2.0 * a.y - b.y,
+    };
+    let control = |i: isize| match i {
+        -1 => mirror(points[0], points[1]),
+        i if i as usize == n => mirror(points[n - 1], points[n - 2]),
+        i => points[i as usize],
+    };
+
+    let mut result = vec![];
+    for i in 0..n - 1 {
+        let p = [-1, 0, 1, 2].map(|d| control(i as isize + d));
+        let mut knots = [0.0; 4];
+        for k in 1..4 {
+            knots[k] = knots[k - 1] + distance(p[k - 1], p[k]).sqrt().max(MIN_KNOT_STEP);
+        }
+        let subdivisions =
+            ((distance(p[1], p[2]) / max_length).ceil() as usize).clamp(1, MAX_SUBDIVISIONS);
+        for s in 0..subdivisions {
+            let s = s as f32 / subdivisions as f32;
+            let t = knots[1] + (knots[2] - knots[1]) * s;
+            let point = if s == 0.0 {
+                p[1]
+            } else {
+                catmull_rom_point(p, knots, t)
+            };
+            result.push((point, i, s));
+        }
+    }
+    result.push((points[n - 1], n - 1, 0.0));
+    result
+}
+
+/// Stroke with a smooth curve through the points of `stroke`, for display
+pub fn smooth_stroke(stroke: &Stroke, max_length: f32) -> Stroke {
+    let samples = catmull_rom(&stroke.points, max_length);
+    let pressure = if stroke.pressure.len() == stroke.points.len() {
+        samples
+            .iter()
+            .map(|(_, i, s)| {
+                let next = stroke.pressure.get(i + 1).unwrap_or(&stroke.pressure[*i]);
+                stroke.pressure[*i] + (next - stroke.pressure[*i]) * s
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+    Stroke {
+        points: samples.into_iter().map(|(p, _, _)| p).collect(),
+        pressure,
+        ..stroke.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: f32, y: f32) -> Position {
+        Position { x, y }
+    }
+
+    fn zigzag() -> Vec<Position> {
+        (0..50)
+            .map(|i| {
+                p(
+                    i as f32,
+                    (i as f32 * 0.7).sin() * 10.0 + (i % 3) as f32 * 0.3,
+                )
+            })
+            .collect()
+    }
+
+    /// Distance of `point` from the closest segment of `line`
+    fn line_distance(point: Position, line: &[Position]) -> f32 {
+        line.windows(2)
+            .map(|s| segment_distance(point, s[0], s[1]))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    #[test]
+    fn simplify_keeps_endpoints() {
+        let points = zigzag();
+        let kept = simplify(&points, 1000.0);
+        assert_eq!(kept, vec![0, points.len() - 1]);
+    }
+
+    #[test]
+    fn simplify_removes_collinear_points() {
+        let points = (0..10)
+            .map(|i| p(i as f32, 2.0 * i as f32))
+            .collect::<Vec<_>>();
+        assert_eq!(simplify(&points, 0.01), vec![0, 9]);
+    }
+
+    #[test]
+    fn simplify_error_within_tolerance() {
+        let points = zigzag();
+        for tolerance in [0.1, 0.5, 1.0, 3.0] {
+            let kept = simplify(&points, tolerance);
+            let line = kept.iter().map(|i| points[*i]).collect::<Vec<_>>();
+            for point in &points {
+                assert!(line_distance(*point, &line) <= tolerance + 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn simplify_with_zero_tolerance_keeps_corners() {
+        let points = vec![p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0), p(2.0, 1.0)];
+        assert_eq!(simplify(&points, 0.0), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn simplify_stroke_keeps_pressure_aligned() {
+        let mut stroke = Stroke {
+            points: vec![p(0.0, 0.0), p(1.0, 0.0), p(2.0, 0.0), p(2.0, 5.0)],
+            width: 2.0,
+            color: crate::entities::Color::BLACK,
+            pressure: vec![0.1, 0.2, 0.3, 0.4],
+            style: Default::default(),
+        };
+        simplify_stroke(&mut stroke, 0.1);
+        assert_eq!(stroke.points, vec![p(0.0, 0.0), p(2.0, 0.0), p(2.0, 5.0)]);
+        assert_eq!(stroke.pressure, vec![0.1, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn smoothing_passes_through_samples() {
+        let points = zigzag();
+        let smoothed = catmull_rom(&points, 0.25);
+        for point in &points {
+            assert!(smoothed.iter().any(|(s, _, _)| distance(*s, *point) < 1e-4));
+        }
+    }
+
+    #[test]
+    fn smoothing_keeps_straight_lines_straight() {
+        let points = vec![p(0.0, 0.0), p(1.0, 1.0), p(3.0, 3.0), p(4.0, 4.0)];
+        for (point, _, _) in catmull_rom(&points, 0.1) {
+            assert!((point.x - point.y).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn smoothing_stays_close_to_polyline() {
+        // Centripetal Catmull-Rom does not overshoot far from its control polygon
+        let points = zigzag();
+        let smoothed = catmull_rom(&points, 0.25);
+        for (point, _, _) in smoothed {
+            assert!(point.x.is_finite() && point.y.is_finite());
+            assert!(line_distance(point, &points) < 2.0);
+        }
+    }
+
+    #[test]
+    fn smoothing_handles_repeated_points() {
+        let points = vec![p(0.0, 0.0), p(0.0, 0.0), p(1.0, 0.0), p(1.0, 0.0)];
+        for (point, _, _) in catmull_rom(&points, 0.1) {
+            assert!(point.x.is_finite() && point.y.is_finite());
+        }
+    }
+
+    #[test]
+    fn smoothing_subdivides_long_segments() {
+        let points = vec![p(0.0, 0.0), p(10.0, 0.0), p(10.0, 10.0)];
+        let smoothed = catmull_rom(&points, 1.0);
+        assert_eq!(smoothed.len(), 10 + 10 + 1);
+        for pair in smoothed.windows(2) {
+            assert!(distance(pair[0].0, pair[1].0) < 2.0);
+        }
+    }
+}
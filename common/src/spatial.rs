@@ -0,0 +1,326 @@
+//! Quadtree of element bounding boxes, for finding the elements in a region of an unbounded board
+//! without looking at all of them
+
+use std::{collections::HashMap, hash::Hash};
+
+use crate::entities::Position;
+
+/// Most items a node holds before it is split into quadrants
+const MAX_ITEMS: usize = 16;
+/// Deepest level of the tree, below it items pile up in the leaves
+const MAX_DEPTH: usize = 24;
+/// Side of the first node, in board units
+const MIN_SIZE: f32 = 256.0;
+/// Largest coordinate indexed, in board units. Rectangles reaching further are not indexed, so the
+/// root stays small enough to be doubled without overflowing.
+const MAX_COORD: f32 = 1e9;
+
+/// Axis aligned rectangle, `min` is the top left corner
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub min: Position,
+    pub max: Position,
+}
+
+impl Rect {
+    /// Smallest rectangle containing all `points`
+    pub fn around(points: impl IntoIterator<Item = Position>) -> Option<Rect> {
+        points.into_iter().fold(None, |rect, p| {
+            let rect = rect.unwrap_or(Rect { min: p, max: p });
+            Some(Rect {
+                min: Position { x: rect.min.x.min(p.x), y: rect.min.y.min(p.y) },
+                max: Position { x: rect.max.x.max(p.x), y: rect.max.y.max(p.y) },
+            })
+        })
+    }
+
+    pub fn point(p: Position) -> Rect {
+        Rect { min: p, max: p }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.max.x - self.min.x
+    }
+
+    pub fn height(&self) -> f32 {
+        self.max.y - self.min.y
+    }
+
+    /// Grows the rectangle by `margin` on every side
+    pub fn expand(&self, margin: f32) -> Rect {
+        Rect {
+            min: Position { x: self.min.x - margin, y: self.min.y - margin },
+            max: Position { x: self.max.x + margin, y: self.max.y + margin },
+        }
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect {
+            min: Position { x: self.min.x.min(other.min.x), y: self.min.y.min(other.min.y) },
+            max: Position { x: self.max.x.max(other.max.x), y: self.max.y.max(other.max.y) },
+        }
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.min.x <= other.max.x && other.min.x <= self.max.x
+            && self.min.y <= other.max.y && other.min.y <= self.max.y
+    }
+
+    pub fn contains(&self, other: &Rect) -> bool {
+        self.min.x <= other.min.x && other.max.x <= self.max.x
+            && self.min.y <= other.min.y && other.max.y <= self.max.y
+    }
+
+    /// Whether the rectangle is well formed and within `MAX_COORD` of the origin, which also
+    /// excludes non-finite coordinates
+    fn is_indexable(&self) -> bool {
+        [self.min.x, self.min.y, self.max.x, self.max.y].iter().all(|c| c.abs() <= MAX_COORD)
+            && self.min.x <= self.max.x && self.min.y <= self.max.y
+    }
+
+    /// Quadrants of the rectangle: top left, top right, bottom left, bottom right
+    fn quadrants(&self) -> [Rect; 4] {
+        let (min, max) = (self.min, self.max);
+        let center = Position { x: (min.x + max.x) / 2.0, y: (min.y + max.y) / 2.0 };
+        [
+            Rect { min, max: center },
+            Rect { min: Position { x: center.x, y: min.y }, max: Position { x: max.x, y: center.y } },
+            Rect { min: Position { x: min.x, y: center.y }, max: Position { x: center.x, y: max.y } },
+            Rect { min: center, max },
+        ]
+    }
+}
+
+struct Node<K> {
+    rect: Rect,
+    /// Items contained in this node but in none of its quadrants
+    items: Vec<(K, Rect)>,
+    children: Option<Box<[Node<K>; 4]>>,
+}
+
+impl<K: Copy + Eq> Node<K> {
+    fn new(rect: Rect) -> Self {
+        Node { rect, items: vec![], children: None }
+    }
+
+    fn insert(&mut self, key: K, rect: Rect, depth: usize) {
+        let mut children = self.children.iter_mut().flat_map(|c| c.iter_mut());
+        if let Some(child) = children.find(|c| c.rect.contains(&rect)) {
+            return child.insert(key, rect, depth + 1);
+        }
+        self.items.push((key, rect));
+        if self.children.is_none() && self.items.len() > MAX_ITEMS && depth < MAX_DEPTH {
+            self.children = Some(Box::new(self.rect.quadrants().map(Node::new)));
+            for (key, rect) in std::mem::take(&mut self.items) {
+                self.insert(key, rect, depth);
+            }
+        }
+    }
+
+    /// Removes `key`, which was inserted with `rect`
+    fn remove(&mut self, key: K, rect: &Rect) -> bool {
+        if let Some(i) = self.items.iter().position(|(k, _)| *k == key) {
+            self.items.swap_remove(i);
+            return true;
+        }
+        match &mut self.children {
+            Some(children) => {
+                children.iter_mut().filter(|c| c.rect.contains(rect)).any(|c| c.remove(key, rect))
+            }
+            None => false,
+        }
+    }
+
+    fn query(&self, rect: &Rect, found: &mut Vec<K>) {
+        found.extend(self.items.iter().filter(|(_, r)| r.intersects(rect)).map(|(k, _)| *k));
+        for child in self.children.iter().flat_map(|c| c.iter()) {
+            if child.rect.intersects(rect) {
+                child.query(rect, found);
+            }
+        }
+    }
+}
+
+/// Quadtree of the bounding boxes of keys. The root grows to cover everything inserted, so there
+/// are no bounds on where items can be.
+pub struct SpatialIndex<K> {
+    root: Option<Node<K>>,
+    rects: HashMap<K, Rect>,
+}
+
+impl<K> Default for SpatialIndex<K> {
+    fn default() -> Self {
+        SpatialIndex { root: None, rects: HashMap::new() }
+    }
+}
+
+impl<K: Copy + Eq + Hash> SpatialIndex<K> {
+    pub fn len(&self) -> usize {
+        self.rects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&Rect> {
+        self.rects.get(key)
+    }
+
+    /// Adds `key` covering `rect`, replacing its previous rectangle. Rectangles with non-finite
+    /// coordinates or further than `MAX_COORD` from the origin are not indexed.
+    pub fn insert(&mut self, key: K, rect: Rect) {
+        self.remove(&key);
+        if !rect.is_indexable() {
+            return;
+        }
+        let root = self.root.get_or_insert_with(|| {
+            let size = rect.width().max(rect.height()).max(MIN_SIZE);
+            Node::new(Rect { min: rect.min, max: Position { x: rect.min.x + size, y: rect.min.y + size } })
+        });
+        // The root reaches past `MAX_COORD` on every side once it is twice as large
+        while !root.rect.contains(&rect) && root.rect.width() <= 4.0 * MAX_COORD {
+            grow(root, &rect);
+        }
+        root.insert(key, rect, 0);
+        self.rects.insert(key, rect);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<Rect> {
+        let rect = self.rects.remove(key)?;
+        if let Some(root) = &mut self.root {
+            root.remove(*key, &rect);
+        }
+        Some(rect)
+    }
+
+    /// Keys whose rectangle intersects `rect`, in no particular order
+    pub fn query(&self, rect: &Rect) -> Vec<K> {
+        let mut found = vec![];
+        if let Some(root) = &self.root {
+            root.query(rect, &mut found);
+        }
+        found
+    }
+
+    pub fn query_point(&self, point: Position) -> Vec<K> {
+        self.query(&Rect::point(point))
+    }
+}
+
+/// Doubles the size of `root` towards `target`, the old root becomes one of the new quadrants
+fn grow<K: Copy + Eq>(root: &mut Node<K>, target: &Rect) {
+    let old = root.rect;
+    let (width, height) = (old.width(), old.height());
+    let left = target.min.x < old.min.x;
+    let up = target.min.y < old.min.y;
+    let min = Position {
+        x: if left { old.min.x - width } else { old.min.x },
+        y: if up { old.min.y - height } else { old.min.y },
+    };
+    let rect = Rect { min, max: Position { x: min.x + 2.0 * width, y: min.y + 2.0 * height } };
+    let slot = match (left, up) {
+        (false, false) => 0,
+        (true, false) => 1,
+        (false, true) => 2,
+        (true, true) => 3,
+    };
+    let mut children = rect.quadrants().map(Node::new);
+    std::mem::swap(&mut children[slot], root);
+    *root = Node { rect, items: vec![], children: Some(Box::new(children)) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, w: f32, h: f32) -> Rect {
+        Rect { min: Position { x, y }, max: Position { x: x + w, y: y + h } }
+    }
+
+    /// Deterministic pseudo-random rectangles spread over a large area
+    fn scattered(n: usize) -> Vec<Rect> {
+        let mut state = 12345u64;
+        let mut next = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as f32 / (1u64 << 31) as f32
+        };
+        (0..n).map(|_| rect(next() * 20000.0 - 10000.0, next() * 20000.0 - 10000.0, next() * 300.0, next() * 300.0)).collect()
+    }
+
+    fn sorted(mut keys: Vec<usize>) -> Vec<usize> {
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn query_matches_linear_scan() {
+        let rects = scattered(2000);
+        let mut index = SpatialIndex::default();
+        for (i, r) in rects.iter().enumerate() {
+            index.insert(i, *r);
+        }
+        for query in [rect(0.0, 0.0, 500.0, 500.0), rect(-9000.0, 3000.0, 4000.0, 100.0), rect(-20000.0, -20000.0, 40000.0, 40000.0)] {
+            let expected = (0..rects.len()).filter(|i| rects[*i].intersects(&query)).collect::<Vec<_>>();
+            assert_eq!(sorted(index.query(&query)), expected);
+        }
+    }
+
+    #[test]
+    fn removed_and_moved_items() {
+        let rects = scattered(500);
+        let mut index = SpatialIndex::default();
+        for (i, r) in rects.iter().enumerate() {
+            index.insert(i, *r);
+        }
+        for i in (0..500).step_by(2) {
+            index.remove(&i);
+        }
+        index.insert(1, rect(50000.0, 50000.0, 1.0, 1.0));
+        assert_eq!(index.len(), 250);
+        assert_eq!(index.query_point(Position { x: 50000.5, y: 50000.5 }), vec![1]);
+        let all = sorted(index.query(&rect(-1e6, -1e6, 2e6, 2e6)));
+        assert_eq!(all, (1..500).step_by(2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn grows_in_every_direction() {
+        let mut index = SpatialIndex::default();
+        let corners = [rect(0.0, 0.0, 1.0, 1.0), rect(-1e5, 0.0, 1.0, 1.0), rect(0.0, -1e5, 1.0, 1.0), rect(1e5, 1e5, 1.0, 1.0)];
+        for (i, r) in corners.iter().enumerate() {
+            index.insert(i, *r);
+        }
+        for (i, r) in corners.iter().enumerate() {
+            assert_eq!(index.query(r), vec![i]);
+        }
+    }
+
+    #[test]
+    fn ignores_non_finite_rects() {
+        let mut index = SpatialIndex::default();
+        index.insert(0, rect(f32::NAN, 0.0, 1.0, 1.0));
+        index.insert(1, rect(0.0, 0.0, f32::INFINITY, 1.0));
+        assert!(index.is_empty());
+    }
+
+    #[test]
+    fn ignores_extreme_rects() {
+        let mut index = SpatialIndex::default();
+        index.insert(0, rect(0.0, 0.0, 1.0, 1.0));
+        index.insert(1, rect(-3e38, 0.0, 1.0, 1.0));
+        index.insert(2, rect(0.0, 0.0, f32::MAX, f32::MAX));
+        index.insert(3, rect(f32::NEG_INFINITY, f32::NAN, 1.0, 1.0));
+        index.insert(4, rect(-MAX_COORD, -MAX_COORD, 2.0 * MAX_COORD, 2.0 * MAX_COORD));
+        assert_eq!(index.len(), 2);
+        assert_eq!(sorted(index.query_point(Position { x: 0.5, y: 0.5 })), vec![0, 4]);
+    }
+
+    #[test]
+    fn many_identical_rects() {
+        let mut index = SpatialIndex::default();
+        for i in 0..1000 {
+            index.insert(i, rect(5.0, 5.0, 0.0, 0.0));
+        }
+        assert_eq!(index.query_point(Position { x: 5.0, y: 5.0 }).len(), 1000);
+    }
+}
@@ -0,0 +1,302 @@
+//! Operational transformation for plain text.
+//!
+//! Positions and lengths count `char`s, not bytes. An edit is a sequence of [`TextOp`]s applied
+//! one after another. Two edits made concurrently on the same text can be reconciled with
+//! [`transform`], which guarantees that applying `a` then `b'` gives the same text as applying
+//! `b` then `a'`.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TextOp {
+    Insert { position: usize, text: String },
+    Delete { position: usize, length: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+
+fn byte_index(text: &str, position: usize) -> Option<usize> {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .nth(position)
+}
+
+impl TextOp {
+    pub fn apply(&self, text: &mut String) -> Result<(), OutOfRange> {
+        match self {
+            TextOp::Insert { position, text: inserted } => {
+                let at = byte_index(text, *position).ok_or(OutOfRange)?;
+                text.insert_str(at, inserted);
+            }
+            TextOp::Delete { position, length } => {
+                let from = byte_index(text, *position).ok_or(OutOfRange)?;
+                let to = byte_index(text, position + length).ok_or(OutOfRange)?;
+                text.replace_range(from..to, "");
+            }
+        }
+        Ok(())
+    }
+
+    fn is_noop(&self) -> bool {
+        match self {
+            TextOp::Insert { text, .. } => text.is_empty(),
+            TextOp::Delete { length, .. } => *length == 0,
+        }
+    }
+}
+
+/// Applies all `ops` in order, leaving `text` untouched if any of them is out of range
+pub fn apply(ops: &[TextOp], text: &mut String) -> Result<(), OutOfRange> {
+    let mut result = text.clone();
+    for op in ops {
+        op.apply(&mut result)?;
+    }
+    *text = result;
+    Ok(())
+}
+
+/// Edit turning `old` into `new`, found by stripping their common prefix and suffix
+pub fn diff(old: &str, new: &str) -> Vec<TextOp> {
+    let (prefix, old_middle, new_middle) = split_common(old, new);
+    let mut ops = vec![];
+    if old_middle > 0 {
+        ops.push(TextOp::Delete {
+            position: prefix,
+            length: old_middle,
+        });
+    }
+    if !new_middle.is_empty() {
+        ops.push(TextOp::Insert {
+            position: prefix,
+            text: new_middle,
+        });
+    }
+    ops
+}
+
+/// Moves a caret at `position` in `old` to the matching place in `new`
+pub fn map_position(old: &str, new: &str, position: usize) -> usize {
+    let (prefix, old_middle, new_middle) = split_common(old, new);
+    let new_middle = new_middle.chars().count();
+    if position <= prefix {
+        position
+    } else if position >= prefix + old_middle {
+        position + new_middle - old_middle
+    } else {
+        prefix + new_middle
+    }
+}
+
+/// Returns the length of the common prefix, the length of the differing part of `old` and the
+/// differing part of `new`
+fn split_common(old: &str, new: &str) -> (usize, usize, String) {
+    let old = old.chars().collect::<Vec<_>>();
+    let new = new.chars().collect::<Vec<_>>();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = old.len() - prefix - suffix;
+    let new_middle = new[prefix..new.len() - suffix].iter().collect();
+    (prefix, old_middle, new_middle)
+}
+
+/// Transforms concurrent edits `a` and `b` into `(a', b')` where `a'` applies after `b` and
+/// `b'` applies after `a`. When both insert at the same position, the text of `a` goes first if
+/// `a_first` is set.
+pub fn transform(a: &[TextOp], b: &[TextOp], a_first: bool) -> (Vec<TextOp>, Vec<TextOp>) {
+    match (a, b) {
+        ([], _) => (vec![], b.to_vec()),
+        (_, []) => (a.to_vec(), vec![]),
+        ([a], [b]) => transform_op(a, b, a_first),
+        ([a0, a_rest @ ..], _) if !a_rest.is_empty() => {
+            let (a0, b) = transform(std::slice::from_ref(a0), b, a_first);
+            let (a_rest, b) = transform(a_rest, &b, a_first);
+            ([a0, a_rest].concat(), b)
+        }
+        (_, [b0, b_rest @ ..]) => {
+            let (a, b0) = transform(a, std::slice::from_ref(b0), a_first);
+            let (a, b_rest) = transform(&a, b_rest, a_first);
+            (a, [b0, b_rest].concat())
+        }
+    }
+}
+
+fn transform_op(a: &TextOp, b: &TextOp, a_first: bool) -> (Vec<TextOp>, Vec<TextOp>) {
+    use TextOp::*;
+    let (a, b) = match (a, b) {
+        (Insert { .. }, Insert { .. }) => transform_inserts(a, b, a_first),
+        (Insert { position, text }, Delete { position: from, length }) => {
+            transform_insert_delete(*position, text, *from, *length)
+        }
+        (Delete { position: from, length }, Insert { position, text }) => {
+            let (b, a) = transform_insert_delete(*position, text, *from, *length);
+            (a, b)
+        }
+        (
+            Delete { position: a_from, length: a_length },
+            Delete { position: b_from, length: b_length },
+        ) => (
+            vec![delete_after_delete(*a_from, *a_length, *b_from, *b_length)],
+            vec![delete_after_delete(*b_from, *b_length, *a_from, *a_length)],
+        ),
+    };
+    (
+        a.into_iter().filter(|op| !op.is_noop()).collect(),
+        b.into_iter().filter(|op| !op.is_noop()).collect(),
+    )
+}
+
+fn transform_inserts(a: &TextOp, b: &TextOp, a_first: bool) -> (Vec<TextOp>, Vec<TextOp>) {
+    let (
+        TextOp::Insert { position: a_position, text: a_text },
+        TextOp::Insert { position: b_position, text: b_text },
+    ) = (a, b)
+    else {
+        unreachable!()
+    };
+    if a_position < b_position || (a_position == b_position && a_first) {
+        let b = TextOp::Insert {
+            position: b_position + a_text.chars().count(),
+            text: b_text.clone(),
+        };
+        (vec![a.clone()], vec![b])
+    } else {
+        let a = TextOp::Insert {
+            position: a_position + b_text.chars().count(),
+            text: a_text.clone(),
+        };
+        (vec![a], vec![b.clone()])
+    }
+}
+
+/// Inserted text always survives a concurrent delete, even if it lands inside the deleted range
+fn transform_insert_delete(
+    position: usize,
+    text: &str,
+    from: usize,
+    length: usize,
+) -> (Vec<TextOp>, Vec<TextOp>) {
+    let inserted = text.chars().count();
+    let insert = |position| TextOp::Insert {
+        position,
+        text: text.to_owned(),
+    };
+    let delete = |position, length| TextOp::Delete { position, length };
+    if position <= from {
+        (vec![insert(position)], vec![delete(from + inserted, length)])
+    } else if position >= from + length {
+        (vec![insert(position - length)], vec![delete(from, length)])
+    } else {
+        // Delete around the inserted text, the later part first so positions stay valid
+        (
+            vec![insert(from)],
+            vec![
+                delete(position + inserted, from + length - position),
+                delete(from, position - from),
+            ],
+        )
+    }
+}
+
+/// Range `from..from + length` with the already deleted range `other_from..other_from + other_length` removed
+fn delete_after_delete(from: usize, length: usize, other_from: usize, other_length: usize) -> TextOp {
+    let map = |x: usize| {
+        if x <= other_from {
+            x
+        } else if x >= other_from + other_length {
+            x - other_length
+        } else {
+            other_from
+        }
+    };
+    let start = map(from);
+    let end = map(from + length);
+    TextOp::Delete {
+        position: start,
+        length: end - start,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ins(position: usize, text: &str) -> TextOp {
+        TextOp::Insert {
+            position,
+            text: text.to_owned(),
+        }
+    }
+
+    fn del(position: usize, length: usize) -> TextOp {
+        TextOp::Delete { position, length }
+    }
+
+    /// Applies both edits in both orders and checks that the results agree
+    fn converge(base: &str, a: &[TextOp], b: &[TextOp]) -> String {
+        let (a_prime, b_prime) = transform(a, b, true);
+
+        let mut ab = base.to_owned();
+        apply(a, &mut ab).unwrap();
+        apply(&b_prime, &mut ab).unwrap();
+
+        let mut ba = base.to_owned();
+        apply(b, &mut ba).unwrap();
+        apply(&a_prime, &mut ba).unwrap();
+
+        assert_eq!(ab, ba);
+        ab
+    }
+
+    #[test]
+    fn inserts_at_same_position() {
+        assert_eq!(converge("ac", &[ins(1, "x")], &[ins(1, "y")]), "axyc");
+    }
+
+    #[test]
+    fn insert_inside_deleted_range() {
+        assert_eq!(converge("abcdef", &[ins(3, "X")], &[del(1, 4)]), "aXf");
+    }
+
+    #[test]
+    fn overlapping_deletes() {
+        assert_eq!(converge("abcdef", &[del(1, 3)], &[del(2, 3)]), "af");
+    }
+
+    #[test]
+    fn multi_op_edits() {
+        let a = [del(0, 2), ins(0, "Hello")];
+        let b = [ins(4, "!"), del(1, 1), ins(2, "é")];
+        converge("abcd", &a, &b);
+    }
+
+    #[test]
+    fn non_ascii_positions() {
+        let mut text = "zażółć".to_owned();
+        apply(&[del(2, 3), ins(2, "o")], &mut text).unwrap();
+        assert_eq!(text, "zaoć");
+    }
+
+    #[test]
+    fn out_of_range_leaves_text_untouched() {
+        let mut text = "abc".to_owned();
+        assert_eq!(apply(&[ins(1, "x"), del(2, 5)], &mut text), Err(OutOfRange));
+        assert_eq!(text, "abc");
+    }
+
+    #[test]
+    fn diff_and_map_position() {
+        let mut text = "hello world".to_owned();
+        let new = "hello brave new world";
+        apply(&diff(&text, new), &mut text).unwrap();
+        assert_eq!(text, new);
+        assert_eq!(map_position("hello world", new, 8), 18);
+        assert_eq!(map_position("hello world", new, 2), 2);
+    }
+}
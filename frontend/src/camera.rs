@@ -0,0 +1,61 @@
+use common::entities::Position;
+
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 10.0;
+
+/// Transform between board coordinates and screen (CSS pixel) coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    /// Board position shown in the top left corner of the screen
+    pub offset: Position,
+    pub zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            offset: Position { x: 0.0, y: 0.0 },
+            zoom: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn to_screen(self, position: &Position) -> Position {
+        Position {
+            x: (position.x - self.offset.x) * self.zoom,
+            y: (position.y - self.offset.y) * self.zoom,
+        }
+    }
+
+    pub fn to_board(self, position: &Position) -> Position {
+        Position {
+            x: position.x / self.zoom + self.offset.x,
+            y: position.y / self.zoom + self.offset.y,
+        }
+    }
+
+    /// Moves the camera by a distance given in screen pixels
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.offset.x += dx / self.zoom;
+        self.offset.y += dy / self.zoom;
+    }
+
+    /// Scales the view by `factor` keeping the board point under `anchor` (screen coordinates) in place
+    pub fn zoom_at(&mut self, anchor: &Position, factor: f32) {
+        let fixed = self.to_board(anchor);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.offset.x = fixed.x - anchor.x / self.zoom;
+        self.offset.y = fixed.y - anchor.y / self.zoom;
+    }
+
+    /// CSS transform placing an element with board position `position` and clockwise
+    /// `rotation` (in radians) around it on screen
+    pub fn css_transform(self, position: &Position, rotation: f32) -> String {
+        let screen = self.to_screen(position);
+        format!(
+            "transform: translate({}px, {}px) rotate({}rad) scale({})",
+            screen.x, screen.y, rotation, self.zoom
+        )
+    }
+}
@@ -1,140 +1,190 @@
-use std::ops::Deref;
+use std::{collections::HashMap, ops::Deref};
 
+use common::{
+    entities::{Element, ElementId, Position, Stroke},
+    spatial::Rect,
+};
 use itertools::Itertools;
-use leptos::{component, create_effect, create_node_ref, logging::log, view, IntoView};
-use nalgebra::Point2;
-use web_sys::{js_sys, wasm_bindgen::JsCast, WebGl2RenderingContext, WebGlProgram, WebGlShader};
+use leptos::{
+    component, create_effect, create_node_ref, create_signal, ev::PointerEvent, logging,
+    store_value, view, Callable, Callback, IntoView, RwSignal, Signal, SignalGet,
+    SignalGetUntracked, SignalSet, SignalUpdate, SignalWith, StoredValue,
+};
+use leptos_use::{use_device_pixel_ratio, use_element_size, UseElementSizeReturn};
+use web_sys::{HtmlCanvasElement, WebGl2RenderingContext, WebGlTexture};
 
-use crate::{line_drawing::line_into_triangle_strip, Client};
+use crate::{
+    camera::Camera,
+    images::Textures,
+    renderer::{FrameStats, Renderer},
+    spatial::ElementIndex,
+};
+
+/// Margin around the visible area in screen pixels within which elements are still drawn
+const CULL_MARGIN: f32 = 50.0;
 
 #[component]
-pub fn Canvas(client: Client) -> impl IntoView {
+pub fn Canvas(
+    camera: RwSignal<Camera>,
+    #[prop(into)] elements: Signal<HashMap<ElementId, Element>>,
+    /// Spatial index of `elements`
+    index: ElementIndex,
+    /// Drawing order of the elements, lowest first
+    #[prop(into)]
+    ranks: Signal<HashMap<ElementId, usize>>,
+    /// Stroke being drawn by the local user, not yet sent to the server
+    #[prop(into)]
+    drawing: Signal<Option<Stroke>>,
+    #[prop(into)] on_pointer_down: Callback<(Position, PointerEvent)>,
+) -> impl IntoView {
     let canvas = create_node_ref::<leptos::html::Canvas>();
+    let textures = store_value(Textures::default());
+    let (texture_loaded, set_texture_loaded) = create_signal(());
+
+    let renderer = store_value(None::<Renderer>);
+    let (stats, set_stats) = create_signal(FrameStats::default());
+
+    // The canvas fills the board through CSS, its drawing buffer follows at device resolution
+    let UseElementSizeReturn { width, height } = use_element_size(canvas);
+    let pixel_ratio = use_device_pixel_ratio();
 
     create_effect(move |_| {
         let Some(canvas) = canvas.get() else {
             return;
         };
+        let camera = camera.get();
+        texture_loaded.get();
         let canvas = canvas.deref();
-        let context = canvas
-            .get_context("webgl2")
-            .unwrap()
-            .unwrap()
-            .dyn_into::<WebGl2RenderingContext>()
-            .unwrap();
-        let program = create_program(&context).unwrap();
-        context.use_program(Some(&program));
-        context.viewport(0, 0, canvas.width() as i32, canvas.height() as i32);
-        context.clear_color(0.7, 0.7, 0.7, 1.0);
-        context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
-        let vertices = line_into_triangle_strip(
-            vec![
-                Point2::new(0.0, 0.0),
-                Point2::new(100.0, 0.0),
-                Point2::new(0.0, 100.0),
-                Point2::new(-100.0, 50.0),
-                Point2::new(-110.0, 70.0),
-                Point2::new(-150.0, 40.0),
-                Point2::new(-50.0, -20.0)
-            ],
-            10.0,
-        )
-        .into_iter()
-        .flat_map(|p| [p.x as f32, p.y as f32])
-        .collect_vec();
-
-        let position_attribute_location = context.get_attrib_location(&program, "position");
-        let buffer = context.create_buffer().unwrap();
-        context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
+        let ratio = pixel_ratio.get();
+        let (width, height) = (
+            device_pixels(width.get(), ratio),
+            device_pixels(height.get(), ratio),
+        );
+        // Resizing clears the canvas, only do it when the size changed
+        if (canvas.width(), canvas.height()) != (width, height) {
+            canvas.set_width(width);
+            canvas.set_height(height);
+        }
+        renderer.update_value(|renderer| {
+            if renderer.is_none() {
+                match Renderer::new(canvas) {
+                    Ok(created) => *renderer = Some(created),
+                    Err(error) => logging::error!("Unable to start the renderer: {error}"),
+                }
+            }
+            let Some(renderer) = renderer else {
+                return;
+            };
+            let visible = index.query(&viewport(&camera, canvas));
+            elements.with(|elements| {
+                ranks.with(|ranks| {
+                    let ordered = visible
+                        .iter()
+                        .filter_map(|id| elements.get(id).map(|element| (*id, element)))
+                        .sorted_by_key(|(id, _)| (ranks.get(id), *id))
+                        .collect_vec();
+                    drawing.with(|drawing| {
+                        let context = renderer.context().clone();
+                        let frame = renderer.render(
+                            canvas,
+                            &camera,
+                            &ordered,
+                            |id| elements.contains_key(id),
+                            drawing.as_ref(),
+                            |image| {
+                                load_texture(&context, textures, &image.blob, move || {
+                                    set_texture_loaded.set(())
+                                })
+                            },
+                        );
+                        set_stats.set(frame);
+                    });
+                });
+            });
+        });
+    });
 
-        unsafe {
-            let positions_array_buf_view = js_sys::Float32Array::view(&vertices);
-            context.buffer_data_with_array_buffer_view(
-                WebGl2RenderingContext::ARRAY_BUFFER,
-                &positions_array_buf_view,
-                WebGl2RenderingContext::STATIC_DRAW,
-            );
+    let on_wheel = move |e: leptos::ev::WheelEvent| {
+        e.prevent_default();
+        if e.ctrl_key() {
+            let anchor = Position {
+                x: e.offset_x() as f32,
+                y: e.offset_y() as f32,
+            };
+            let factor = (-e.delta_y() as f32 * 0.002).exp();
+            camera.update(|c| c.zoom_at(&anchor, factor));
+        } else {
+            camera.update(|c| c.pan(e.delta_x() as f32, e.delta_y() as f32));
         }
+    };
 
-        let vao = context.create_vertex_array().unwrap();
-        context.bind_vertex_array(Some(&vao));
-        context.vertex_attrib_pointer_with_i32(
-            position_attribute_location as u32,
-            2,
-            WebGl2RenderingContext::FLOAT,
-            false,
-            0,
-            0,
-        );
-        context.enable_vertex_attrib_array(position_attribute_location as u32);
-        let vert_count = (vertices.len() / 2) as i32;
-        context.draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, vert_count);
-    });
-    view! { <canvas _ref=canvas width=500 height=500></canvas> }
-}
+    let on_canvas_pointer_down = move |e: PointerEvent| {
+        let screen = Position {
+            x: e.offset_x() as f32,
+            y: e.offset_y() as f32,
+        };
+        on_pointer_down.call((camera.get_untracked().to_board(&screen), e));
+    };
 
-const VERTEX_SHADER: &'static str = include_str!("shaders/vertex_shader.glsl");
-const FRAGMENT_SHADER: &'static str = include_str!("shaders/fragment_shader.glsl");
+    view! {
+        <canvas
+            _ref=canvas
+            on:wheel=on_wheel
+            on:pointerdown=on_canvas_pointer_down
+        ></canvas>
+        {cfg!(debug_assertions)
+            .then(|| {
+                view! {
+                    <div class="frame-stats no-select">
+                        {move || {
+                            let stats = stats.get();
+                            format!(
+                                "{:.1} ms, {} draws, {} tessellated, {} floats uploaded",
+                                stats.time,
+                                stats.draw_calls,
+                                stats.tessellated,
+                                stats.uploaded,
+                            )
+                        }}
+                    </div>
+                }
+            })}
+    }
+}
 
-fn create_program(context: &WebGl2RenderingContext) -> Result<WebGlProgram, String> {
-    let vertex_shader = compile_shader(
-        &context,
-        WebGl2RenderingContext::VERTEX_SHADER,
-        VERTEX_SHADER,
-    )?;
-    let fragment_shader = compile_shader(
-        &context,
-        WebGl2RenderingContext::FRAGMENT_SHADER,
-        &FRAGMENT_SHADER,
-    )?;
-    link_program(&context, &vertex_shader, &fragment_shader)
+/// Size in device pixels of a length in CSS pixels, at least one pixel so that the drawing
+/// buffer stays valid
+fn device_pixels(css_pixels: f64, ratio: f64) -> u32 {
+    (css_pixels * ratio).round().max(1.0) as u32
 }
 
-fn compile_shader(
-    context: &WebGl2RenderingContext,
-    shader_type: u32,
-    source: &str,
-) -> Result<WebGlShader, String> {
-    let shader = context
-        .create_shader(shader_type)
-        .ok_or("Unable to create shader object")?;
-    context.shader_source(&shader, source);
-    context.compile_shader(&shader);
-    let compiled = context
-        .get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS)
-        .as_bool()
-        .unwrap_or(false);
-    if compiled {
-        Ok(shader)
-    } else {
-        let error = context
-            .get_shader_info_log(&shader)
-            .unwrap_or("Unknown error when compiling shader".to_owned());
-        Err(error)
+/// Board area shown on the canvas, with a margin for smoothed strokes that swing out of the
+/// bounds of their points
+fn viewport(camera: &Camera, canvas: &HtmlCanvasElement) -> Rect {
+    let size = Position {
+        x: canvas.client_width() as f32,
+        y: canvas.client_height() as f32,
+    };
+    Rect {
+        min: camera.to_board(&Position { x: 0.0, y: 0.0 }),
+        max: camera.to_board(&size),
     }
+    .expand(CULL_MARGIN / camera.zoom)
 }
 
-fn link_program(
+/// Returns the texture of `blob`, requesting a redraw through `redraw` once a missing one loads
+fn load_texture(
     context: &WebGl2RenderingContext,
-    vertex_shader: &WebGlShader,
-    fragment_shader: &WebGlShader,
-) -> Result<WebGlProgram, String> {
-    let program = context
-        .create_program()
-        .ok_or("Unable to create shader object")?;
-    context.attach_shader(&program, vertex_shader);
-    context.attach_shader(&program, fragment_shader);
-    context.link_program(&program);
-    let linked = context
-        .get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
-        .as_bool()
-        .unwrap_or(false);
-    if linked {
-        Ok(program)
-    } else {
-        let error = context
-            .get_program_info_log(&program)
-            .unwrap_or("Unknown error when linking program".to_owned());
-        Err(error)
-    }
+    textures: StoredValue<Textures>,
+    blob: &str,
+    redraw: impl Fn() + 'static,
+) -> Option<WebGlTexture> {
+    textures
+        .try_update_value(|t| {
+            t.get(context, blob, move |blob, texture| {
+                textures.update_value(|t| t.insert(blob, texture));
+                redraw();
+            })
+        })
+        .flatten()
 }
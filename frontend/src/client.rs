@@ -1,84 +1,136 @@
-use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+use std::{cell::{Cell, RefCell}, collections::VecDeque, rc::Rc};
 
 use common::websocket::{ToClient, ToServer};
-use leptos::{create_signal, logging::log, window, ReadSignal, SignalGet, SignalSet};
+use leptos::{create_rw_signal, logging::warn, window, RwSignal, SignalGet, SignalGetUntracked, SignalSet, SignalUpdate};
 use reqwest::StatusCode;
 use web_sys::{js_sys::{ArrayBuffer, Uint8Array}, wasm_bindgen::{closure::Closure, JsCast}, BinaryType, Event, MessageEvent, WebSocket};
 
+use crate::pending::{self, Pending};
+
+/// Name of the board the client opens
+pub const BOARD: &str = "general";
+
+/// Connection to the board, which is opened again by `connect` whenever it closes
 #[derive(Clone)]
 pub struct Client {
-    websocket: WebSocket,
-    message: ReadSignal<Option<ToClient>>,
-    connected: ReadSignal<bool>,
+    /// Socket of the latest connection
+    websocket: Rc<RefCell<Option<WebSocket>>>,
+    /// Path of the board on the server, its HTTP routes are below it
+    url: Rc<RefCell<String>>,
+    message: RwSignal<Option<ToClient>>,
+    connected: RwSignal<bool>,
+    /// Whether a connection is being opened
+    connecting: Rc<Cell<bool>>,
+    /// Changes of elements shown, but not answered by the server yet
+    pending: RwSignal<Pending>,
     message_queue: Rc<RefCell<VecDeque<ToServer>>>
 }
 
 impl PartialEq for Client {
     fn eq(&self, other: &Self) -> bool {
-        self.websocket == other.websocket
+        Rc::ptr_eq(&self.websocket, &other.websocket)
     }
 }
 
+fn transmit(websocket: &WebSocket, message: &ToServer) {
+    websocket.send_with_u8_array(&serde_cbor::to_vec(message).unwrap()).unwrap();
+}
+
 impl Client {
-    pub async fn new() -> Option<Client>{
+    /// Client without a connection yet, `url` is where the board was last found
+    pub fn new(url: String, pending: Pending) -> Client {
+        Client {
+            websocket: Rc::new(RefCell::new(None)),
+            url: Rc::new(RefCell::new(url)),
+            message: create_rw_signal(None),
+            connected: create_rw_signal(false),
+            connecting: Rc::new(Cell::new(false)),
+            pending: create_rw_signal(pending),
+            message_queue: Rc::new(RefCell::new(VecDeque::new())),
+        }
+    }
+
+    /// Opens a connection unless one is open or being opened
+    pub async fn connect(&self) {
+        if self.connected.get_untracked() || self.connecting.replace(true) {
+            return;
+        }
+        if self.open().await.is_none() {
+            self.connecting.set(false);
+        }
+    }
+
+    async fn open(&self) -> Option<()> {
         let host = window().location().host().unwrap();
         let protocol = window().location().protocol().unwrap();
         let base = format!("{protocol}//{host}");
-        let res = reqwest::get(format!("{base}/api/board_url?name=general")).await.ok()?;
+        let res = reqwest::get(format!("{base}/api/board_url?name={BOARD}")).await.ok()?;
         if res.status() != StatusCode::OK  { return None; }
         let url = res.text().await.ok()?;
-        let (message, set_message) = create_signal(None);
-        let (connected, set_connected) = create_signal(false);
         let websocket = WebSocket::new(&url).ok()?;
         websocket.set_binary_type(BinaryType::Arraybuffer);
 
-        let message_queue = Rc::new(RefCell::new(VecDeque::new()));
-
+        let message = self.message;
         let onmessage = Closure::<dyn FnMut(_)>::new(move |e: MessageEvent| {
-            let data = e.data().dyn_into::<ArrayBuffer>().unwrap();
+            let Ok(data) = e.data().dyn_into::<ArrayBuffer>() else {
+                warn!("Ignoring a message that is not binary");
+                return;
+            };
             let data = Uint8Array::new(&data).to_vec();
-            let message = serde_cbor::from_slice::<ToClient>(&data).unwrap();
-            set_message.set(Some(message));
+            match serde_cbor::from_slice::<ToClient>(&data) {
+                Ok(received) => message.set(Some(received)),
+                Err(error) => warn!("Ignoring a message that could not be decoded: {error}"),
+            }
         });
 
         websocket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
         onmessage.forget();
 
-        let websocket_clone = websocket.clone();
-        let message_queue_clone = message_queue.clone();
-
+        let client = self.clone();
         let onopen = Closure::<dyn FnMut(_)>::new(move |_: Event| {
-            let mut message_queue = message_queue_clone.borrow_mut();
-            while let Some(message) = message_queue.pop_front() {
-                websocket_clone.send_with_u8_array(&serde_cbor::to_vec(&message).unwrap()).unwrap();
+            client.connecting.set(false);
+            client.connected.set(true);
+            if let Some(websocket) = &*client.websocket.borrow() {
+                let mut message_queue = client.message_queue.borrow_mut();
+                while let Some(message) = message_queue.pop_front() {
+                    transmit(websocket, &message);
+                }
             }
-            set_connected.set(true);
         });
 
         websocket.set_onopen(Some(onopen.as_ref().unchecked_ref()));
         onopen.forget();
 
+        let client = self.clone();
+        let socket = websocket.clone();
         let onclose = Closure::<dyn FnMut(_)>::new(move |_: Event| {
-            set_connected.set(false);
+            // Events of sockets that were replaced no longer matter
+            if client.websocket.borrow().as_ref() == Some(&socket) {
+                client.connecting.set(false);
+                client.connected.set(false);
+                client.message_queue.borrow_mut().clear();
+                client.pending.update(Pending::connection_lost);
+            }
         });
-        
+
         websocket.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+        websocket.set_onerror(Some(onclose.as_ref().unchecked_ref()));
         onclose.forget();
 
-        let onerror = Closure::<dyn FnMut(_)>::new(move |_: Event| {
-            set_connected.set(false);
-        });
-        websocket.set_onerror(Some(onerror.as_ref().unchecked_ref()));
-        onerror.forget();
-
+        *self.url.borrow_mut() = url;
+        *self.websocket.borrow_mut() = Some(websocket);
+        Some(())
+    }
 
+    /// Absolute URL of `route` of the board, such as `history`
+    pub fn board_route(&self, route: &str) -> String {
+        let origin = window().location().origin().unwrap();
+        format!("{origin}{}/{route}", self.url.borrow())
+    }
 
-        Some(Client {
-            websocket,
-            message,
-            message_queue,
-            connected,
-        })
+    /// Path of the board on the server, empty until it is known
+    pub fn url(&self) -> String {
+        self.url.borrow().clone()
     }
 
     pub fn connected(&self) -> bool {
@@ -89,11 +141,47 @@ impl Client {
         self.message.get()
     }
 
+    pub fn pending(&self) -> RwSignal<Pending> {
+        self.pending
+    }
+
+    /// Sends the changes of elements that waited for the contents of `board`, which arrived
+    pub fn reloaded(&self, board: u64) {
+        let dropped = self
+            .pending
+            .try_update(|pending| pending.reloaded(board))
+            .unwrap_or(0);
+        if dropped > 0 {
+            warn!("Dropped {dropped} changes to a board that was unloaded since they were made");
+        }
+        self.flush();
+    }
+
+    /// Sends the changes of elements made while there was no connection
+    fn flush(&self) {
+        let websocket = self.websocket.borrow();
+        let Some(websocket) = websocket.as_ref().filter(|w| w.ready_state() == WebSocket::OPEN) else {
+            return;
+        };
+        for message in self.pending.try_update(Pending::send_unsent).unwrap_or_default() {
+            transmit(websocket, &message);
+        }
+    }
+
+    /// Sends a message to the board. Changes of elements are shown straight away through
+    /// `pending` and kept until they are sent, even without a connection. Other messages are
+    /// dropped while there is none.
     pub fn send(&self, message: ToServer) {
-        if self.websocket.ready_state() == WebSocket::OPEN {
-            self.websocket.send_with_u8_array(&serde_cbor::to_vec(&message).unwrap()).unwrap();
-        } else {
-            self.message_queue.borrow_mut().push_back(message);
+        if pending::is_optimistic(&message) {
+            self.pending.update(|pending| pending.push(message));
+            self.flush();
+            return;
+        }
+        let websocket = self.websocket.borrow();
+        match websocket.as_ref().map(WebSocket::ready_state) {
+            Some(WebSocket::OPEN) => transmit(websocket.as_ref().unwrap(), &message),
+            Some(WebSocket::CONNECTING) => self.message_queue.borrow_mut().push_back(message),
+            _ => (),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use common::{
+    api::{self, Version},
+    entities::{Element, ElementId},
+};
+use leptos::*;
+use leptos_use::{use_interval_fn_with_options, UseIntervalFnOptions};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use web_sys::{js_sys::Date, wasm_bindgen::JsValue};
+
+use crate::{
+    layers::{Layers, Placements},
+    Client,
+};
+
+/// Time each version is shown for while the history plays
+const PLAYBACK_INTERVAL: u64 = 250;
+
+/// Contents of the board at a past version, shown instead of the live board
+#[derive(Clone, PartialEq)]
+pub struct Preview {
+    pub version: u64,
+    pub elements: HashMap<ElementId, Element>,
+    pub placements: Placements,
+    pub layers: Layers,
+}
+
+impl From<api::Preview> for Preview {
+    fn from(preview: api::Preview) -> Self {
+        Preview {
+            version: preview.version,
+            placements: preview
+                .elements
+                .iter()
+                .map(|e| (e.id, e.placement))
+                .collect(),
+            elements: preview
+                .elements
+                .into_iter()
+                .map(|e| (e.id, e.element))
+                .collect(),
+            layers: preview.layers,
+        }
+    }
+}
+
+async fn get_json<T: DeserializeOwned>(url: String) -> Option<T> {
+    let res = reqwest::get(url).await.ok()?;
+    if res.status() != StatusCode::OK {
+        return None;
+    }
+    serde_json::from_str(&res.text().await.ok()?).ok()
+}
+
+fn format_time(time: u64) -> String {
+    let date = Date::new(&JsValue::from_f64(time as f64));
+    format!(
+        "{} {}",
+        date.to_locale_date_string("default", &JsValue::UNDEFINED),
+        date.to_locale_time_string("default")
+    )
+}
+
+/// Scrubber over the versions of the board. Moving it shows the board as it was at that version
+/// through `preview`, which is `None` while the live board is shown. The server keeps the versions
+/// of the current session only, they go when everyone has left the board.
+#[component]
+pub fn HistoryPanel(preview: RwSignal<Option<Preview>>, client: Client) -> impl IntoView {
+    let client = store_value(client);
+    let open = create_rw_signal(false);
+    let versions = create_rw_signal(Vec::<Version>::new());
+    let selected = create_rw_signal(0_u64);
+    let latest = move || versions.with(|v| v.len().saturating_sub(1) as u64);
+
+    let load_versions = move || {
+        let url = client.with_value(|client| client.board_route("history"));
+        spawn_local(async move {
+            if let Some(list) = get_json::<Vec<Version>>(url).await {
+                selected.set(list.len().saturating_sub(1) as u64);
+                versions.set(list);
+                preview.set(None);
+            }
+        });
+    };
+
+    // The latest version is the live board, earlier ones are fetched. Answers to requests for
+    // versions that are no longer selected are dropped.
+    let show = move |version: u64| {
+        selected.set(version);
+        if version >= latest() {
+            preview.set(None);
+            return;
+        }
+        let url = client.with_value(|client| client.board_route(&format!("history/{version}")));
+        spawn_local(async move {
+            if let Some(fetched) = get_json::<api::Preview>(url).await {
+                if selected.get_untracked() == fetched.version {
+                    preview.set(Some(fetched.into()));
+                }
+            }
+        });
+    };
+
+    let playback = use_interval_fn_with_options(
+        move || {
+            let next = selected.get_untracked() + 1;
+            show(next.min(latest()));
+        },
+        PLAYBACK_INTERVAL,
+        UseIntervalFnOptions::default().immediate(false),
+    );
+    let playing = playback.is_active;
+    let (pause, resume) = (playback.pause, playback.resume);
+    {
+        let pause = pause.clone();
+        create_effect(move |_| {
+            if playing.get() && selected.get() >= latest() {
+                pause();
+            }
+        });
+    }
+
+    let toggle = {
+        let pause = pause.clone();
+        move |_: ev::MouseEvent| {
+            pause();
+            if open.get_untracked() {
+                open.set(false);
+                preview.set(None);
+            } else {
+                open.set(true);
+                load_versions();
+            }
+        }
+    };
+
+    let play = move |_: ev::MouseEvent| {
+        if playing.get_untracked() {
+            pause();
+            return;
+        }
+        if selected.get_untracked() >= latest() {
+            show(0);
+        }
+        resume();
+    };
+
+    let restore = move |_: ev::MouseEvent| {
+        let version = selected.get_untracked();
+        let url =
+            client.with_value(|client| client.board_route(&format!("history/{version}/restore")));
+        spawn_local(async move {
+            let restored = reqwest::Client::new().post(url).send().await;
+            if restored.is_ok_and(|res| res.status() == StatusCode::NO_CONTENT) {
+                load_versions();
+            } else {
+                let _ = window().alert_with_message("The version could not be restored");
+            }
+        });
+    };
+
+    let description = move || {
+        versions.with(|versions| {
+            let version = versions.get(selected.get() as usize)?;
+            let author = match version.client {
+                Some(client) => format!(" by {client}"),
+                None => String::new(),
+            };
+            Some(format!(
+                "{}: {}{author}, {}",
+                version.number,
+                version.summary,
+                format_time(version.time)
+            ))
+        })
+    };
+
+    view! {
+        <div class="history-panel no-select">
+            <button
+                class:selected=open
+                title="Changes since the board was opened, kept until everyone has left it"
+                on:click=toggle
+            >
+                "History"
+            </button>
+            <Show when=move || open.get()>
+                <input
+                    type="range"
+                    min="0"
+                    max=latest
+                    prop:value=move || selected.get()
+                    on:input=move |e| {
+                        if let Ok(version) = event_target_value(&e).parse::<u64>() {
+                            show(version);
+                        }
+                    }
+                />
+                <span class="history-description">{description}</span>
+                <button on:click=play.clone()>{move || if playing.get() { "Pause" } else { "Play" }}</button>
+                <button disabled=move || { selected.get() >= latest() } on:click=restore>
+                    "Restore"
+                </button>
+            </Show>
+        </div>
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use common::{
+    entities::{BlobId, Element, Image, LayerId, Position},
+    websocket::ToServer,
+};
+use leptos::{spawn_local, window};
+use reqwest::StatusCode;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    js_sys::Uint8Array,
+    wasm_bindgen::{closure::Closure, JsCast},
+    DataTransfer, File, HtmlImageElement, ImageBitmap, WebGl2RenderingContext, WebGlTexture,
+};
+
+use crate::Client;
+
+/// Offset between images dropped or pasted together, in board units
+const STACK_OFFSET: f32 = 20.0;
+
+pub fn blob_url(blob: &str) -> String {
+    format!("/api/blobs/{blob}")
+}
+
+/// Image files carried by a paste or drop event. SVG files are drawings, which are imported as
+/// elements instead.
+pub fn image_files(data: Option<DataTransfer>) -> Vec<File> {
+    let Some(files) = data.and_then(|data| data.files()) else {
+        return vec![];
+    };
+    (0..files.length())
+        .filter_map(|i| files.get(i))
+        .filter(|file| file.type_().starts_with("image/") && file.type_() != "image/svg+xml")
+        .collect()
+}
+
+/// Uploads `files` and places them on `layer` with their top left corner at `position`
+pub fn add_images(files: Vec<File>, position: Position, layer: Option<LayerId>, client: Client) {
+    spawn_local(async move {
+        for (i, file) in files.into_iter().enumerate() {
+            let Some((blob, width, height)) = upload(&file).await else {
+                continue;
+            };
+            let offset = i as f32 * STACK_OFFSET;
+            client.send(ToServer::CreateElement {
+                element: Element::Image(Image {
+                    position: Position {
+                        x: position.x + offset,
+                        y: position.y + offset,
+                    },
+                    width,
+                    height,
+                    scale: 1.0,
+                    rotation: 0.0,
+                    blob,
+                }),
+                layer,
+            });
+        }
+    });
+}
+
+async fn upload(file: &File) -> Option<(BlobId, f32, f32)> {
+    let data = JsFuture::from(file.array_buffer()).await.ok()?;
+    let data = Uint8Array::new(&data).to_vec();
+    let bitmap = JsFuture::from(window().create_image_bitmap_with_blob(file).ok()?)
+        .await
+        .ok()?
+        .dyn_into::<ImageBitmap>()
+        .ok()?;
+
+    let origin = window().location().origin().ok()?;
+    let res = reqwest::Client::new()
+        .post(format!("{origin}/api/blobs"))
+        .header(reqwest::header::CONTENT_TYPE, file.type_())
+        .body(data)
+        .send()
+        .await
+        .ok()?;
+    if res.status() != StatusCode::OK {
+        return None;
+    }
+    let blob = res.text().await.ok()?;
+    Some((blob, bitmap.width() as f32, bitmap.height() as f32))
+}
+
+/// WebGL textures of image blobs, loaded lazily the first time they are drawn
+#[derive(Default)]
+pub struct Textures {
+    textures: HashMap<BlobId, Option<WebGlTexture>>,
+}
+
+impl Textures {
+    /// Returns the texture for `blob` if it is loaded, otherwise starts loading it and calls
+    /// `on_load` once it is ready
+    pub fn get(
+        &mut self,
+        context: &WebGl2RenderingContext,
+        blob: &str,
+        on_load: impl Fn(BlobId, WebGlTexture) + 'static,
+    ) -> Option<WebGlTexture> {
+        if let Some(texture) = self.textures.get(blob) {
+            return texture.clone();
+        }
+        self.textures.insert(blob.to_owned(), None);
+
+        let image = HtmlImageElement::new().ok()?;
+        let url = blob_url(blob);
+        let context = context.clone();
+        let blob = blob.to_owned();
+        let loaded = image.clone();
+        let onload = Closure::once_into_js(move || {
+            if let Some(texture) = create_texture(&context, &loaded) {
+                on_load(blob, texture);
+            }
+        });
+        image.set_onload(Some(onload.unchecked_ref()));
+        image.set_src(&url);
+        None
+    }
+
+    pub fn insert(&mut self, blob: BlobId, texture: WebGlTexture) {
+        self.textures.insert(blob, Some(texture));
+    }
+}
+
+fn create_texture(
+    context: &WebGl2RenderingContext,
+    image: &HtmlImageElement,
+) -> Option<WebGlTexture> {
+    let texture = context.create_texture()?;
+    context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+    context
+        .tex_image_2d_with_u32_and_u32_and_html_image_element(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0,
+            WebGl2RenderingContext::RGBA as i32,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            image,
+        )
+        .ok()?;
+    for (parameter, value) in [
+        (
+            WebGl2RenderingContext::TEXTURE_MIN_FILTER,
+            WebGl2RenderingContext::LINEAR,
+        ),
+        (
+            WebGl2RenderingContext::TEXTURE_MAG_FILTER,
+            WebGl2RenderingContext::LINEAR,
+        ),
+        (
+            WebGl2RenderingContext::TEXTURE_WRAP_S,
+            WebGl2RenderingContext::CLAMP_TO_EDGE,
+        ),
+        (
+            WebGl2RenderingContext::TEXTURE_WRAP_T,
+            WebGl2RenderingContext::CLAMP_TO_EDGE,
+        ),
+    ] {
+        context.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, parameter, value as i32);
+    }
+    Some(texture)
+}
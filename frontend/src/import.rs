@@ -0,0 +1,140 @@
+use common::{
+    board_file,
+    entities::{LayerId, Position},
+    import::{self, Import, ImportError},
+    websocket::ToServer,
+};
+use leptos::{spawn_local, window};
+use reqwest::StatusCode;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{DataTransfer, File};
+
+use crate::Client;
+
+/// Offset between drawings dropped or pasted together, in board units
+const STACK_OFFSET: f32 = 20.0;
+
+#[derive(Clone, Copy)]
+enum Format {
+    Svg,
+    Excalidraw,
+}
+
+impl Format {
+    fn of(file: &File) -> Option<Format> {
+        let name = file.name().to_lowercase();
+        if file.type_() == "image/svg+xml" || name.ends_with(".svg") {
+            Some(Format::Svg)
+        } else if name.ends_with(".excalidraw") || name.ends_with(".json") {
+            Some(Format::Excalidraw)
+        } else {
+            None
+        }
+    }
+
+    fn import(self, text: &str) -> Result<Import, ImportError> {
+        match self {
+            Format::Svg => import::svg(text),
+            Format::Excalidraw => import::excalidraw(text),
+        }
+    }
+}
+
+fn is_board_file(file: &File) -> bool {
+    let extension = format!(".{}", board_file::EXTENSION);
+    file.name().to_lowercase().ends_with(&extension)
+}
+
+/// Files carried by a paste or drop event that are drawings of other tools or board files
+pub fn drawing_files(data: Option<DataTransfer>) -> Vec<File> {
+    let Some(files) = data.and_then(|data| data.files()) else {
+        return vec![];
+    };
+    (0..files.length())
+        .filter_map(|i| files.get(i))
+        .filter(|file| is_board_file(file) || Format::of(file).is_some())
+        .collect()
+}
+
+async fn read_text(file: &File) -> Option<String> {
+    JsFuture::from(file.text()).await.ok()?.as_string()
+}
+
+/// Replaces the contents of the board with those of a board file, if the user agrees
+async fn open_board_file(file: &File, client: &Client) -> Result<(), String> {
+    let too_large = format!(
+        "the file is larger than the {} MB the server accepts",
+        board_file::MAX_FILE_SIZE / (1024 * 1024)
+    );
+    if file.size() > board_file::MAX_FILE_SIZE as f64 {
+        return Err(too_large);
+    }
+    let question = format!("Replace the contents of the board with {}?", file.name());
+    if !window().confirm_with_message(&question).unwrap_or(false) {
+        return Ok(());
+    }
+    let text = read_text(file)
+        .await
+        .ok_or_else(|| "the file cannot be read".to_owned())?;
+    let res = reqwest::Client::new()
+        .put(client.board_route("file"))
+        .body(text)
+        .send()
+        .await
+        .map_err(|_| "the server cannot be reached".to_owned())?;
+    match res.status() {
+        status if status.is_success() => Ok(()),
+        // Proxies in front of the server answer this with a page of their own
+        StatusCode::PAYLOAD_TOO_LARGE => Err(too_large),
+        _ => Err(res.text().await.unwrap_or_default()),
+    }
+}
+
+/// Converts `files` into board elements and places them on `layer` with their top left corner at
+/// `position`, board files replace the whole board instead. What could not be imported is told to
+/// the user.
+pub fn add_drawings(files: Vec<File>, position: Position, layer: Option<LayerId>, client: Client) {
+    spawn_local(async move {
+        let mut problems = vec![];
+        for (i, file) in files.into_iter().enumerate() {
+            if is_board_file(&file) {
+                if let Err(problem) = open_board_file(&file, &client).await {
+                    problems.push(format!("{}: {problem}", file.name()));
+                }
+                continue;
+            }
+            let Some(format) = Format::of(&file) else {
+                continue;
+            };
+            let Some(text) = read_text(&file).await else {
+                problems.push(format!("{}: the file cannot be read", file.name()));
+                continue;
+            };
+            let mut drawing = match format.import(&text) {
+                Ok(drawing) => drawing,
+                Err(error) => {
+                    problems.push(format!("{}: {error}", file.name()));
+                    continue;
+                }
+            };
+            let offset = i as f32 * STACK_OFFSET;
+            drawing.place_at(Position {
+                x: position.x + offset,
+                y: position.y + offset,
+            });
+            for element in drawing.elements {
+                client.send(ToServer::CreateElement { element, layer });
+            }
+            if !drawing.unsupported.is_empty() {
+                problems.push(format!(
+                    "Parts of {} could not be imported as they are:\n- {}",
+                    file.name(),
+                    drawing.unsupported.join("\n- ")
+                ));
+            }
+        }
+        if !problems.is_empty() {
+            let _ = window().alert_with_message(&problems.join("\n\n"));
+        }
+    });
+}
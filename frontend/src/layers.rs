@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use common::{
+    entities::{Element, ElementId, Layer, LayerId, Placement, ZOrder},
+    websocket::ToServer,
+};
+use leptos::*;
+
+use crate::{selection::Selection, Client};
+
+pub type Placements = HashMap<ElementId, Placement>;
+
+/// Layers of the board, bottom first
+pub type Layers = Vec<(LayerId, Layer)>;
+
+/// Settings of `layer`, there are none for the base layer which is never hidden or locked
+fn layer_of(layers: &Layers, layer: Option<LayerId>) -> Option<&Layer> {
+    let layer = layer?;
+    layers.iter().find(|(id, _)| *id == layer).map(|(_, l)| l)
+}
+
+pub fn is_hidden(layers: &Layers, placement: Option<&Placement>) -> bool {
+    layer_of(layers, placement.and_then(|p| p.layer)).is_some_and(|l| l.hidden)
+}
+
+pub fn is_locked(layers: &Layers, placement: Option<&Placement>) -> bool {
+    layer_of(layers, placement.and_then(|p| p.layer)).is_some_and(|l| l.locked)
+}
+
+/// Position of every element in the drawing order, from bottom to top
+pub fn ranks(
+    elements: &HashMap<ElementId, Element>,
+    placements: &Placements,
+    layers: &Layers,
+) -> HashMap<ElementId, usize> {
+    let mut ids = elements.keys().copied().collect::<Vec<_>>();
+    ids.sort_by_key(|id| {
+        let key = placements.get(id).map(|p| p.stacking_key(layers));
+        (key, *id)
+    });
+    ids.into_iter()
+        .enumerate()
+        .map(|(rank, id)| (id, rank))
+        .collect()
+}
+
+#[component]
+pub fn LayerPanel(
+    layers: RwSignal<Layers>,
+    /// Layer new elements are created in
+    active: RwSignal<Option<LayerId>>,
+    selection: RwSignal<Selection>,
+    client: Client,
+) -> impl IntoView {
+    let client = store_value(client);
+    let send = move |message: ToServer| client.with_value(|client| client.send(message));
+    let update = move |id: LayerId, change: fn(&mut Layer)| {
+        let layer = layers.with_untracked(|layers| layer_of(layers, Some(id)).cloned());
+        if let Some(mut layer) = layer {
+            change(&mut layer);
+            send(ToServer::UpdateLayer { id, layer });
+        }
+    };
+    let selected = move || selection.with_untracked(|s| s.ids.iter().copied().collect::<Vec<_>>());
+
+    let rows = move || {
+        let list = layers.get();
+        let count = list.len();
+        list.into_iter()
+            .enumerate()
+            .rev()
+            .map(|(index, (id, layer))| {
+                view! {
+                    <li class:active=move || active.get() == Some(id)>
+                        <input
+                            type="text"
+                            prop:value=layer.name.clone()
+                            on:focus=move |_| active.set(Some(id))
+                            on:change=move |e| {
+                                let name = event_target_value(&e);
+                                let layer = layers.with_untracked(|layers| {
+                                    layer_of(layers, Some(id)).cloned()
+                                });
+                                if let Some(layer) = layer {
+                                    send(ToServer::UpdateLayer { id, layer: Layer { name, ..layer } });
+                                }
+                            }
+                        />
+                        <button
+                            class:selected=layer.hidden
+                            title="Hide"
+                            on:click=move |_| update(id, |l| l.hidden = !l.hidden)
+                        >
+                            "Hide"
+                        </button>
+                        <button
+                            class:selected=layer.locked
+                            title="Lock"
+                            on:click=move |_| update(id, |l| l.locked = !l.locked)
+                        >
+                            "Lock"
+                        </button>
+                        <button
+                            title="Move up"
+                            disabled=index + 1 == count
+                            on:click=move |_| send(ToServer::MoveLayer { id, index: index + 1 })
+                        >
+                            "↑"
+                        </button>
+                        <button
+                            title="Move down"
+                            disabled=index == 0
+                            on:click=move |_| send(ToServer::MoveLayer { id, index: index - 1 })
+                        >
+                            "↓"
+                        </button>
+                        <button title="Delete" on:click=move |_| send(ToServer::DeleteLayer { id })>
+                            "×"
+                        </button>
+                    </li>
+                }
+            })
+            .collect_view()
+    };
+
+    view! {
+        <div class=move || {
+            let offline = !client.with_value(Client::connected);
+            format!("layer-panel no-select{}", if offline { " offline" } else { "" })
+        }>
+            <ul>
+                {rows}
+                <li class:active=move || active.get().is_none() on:click=move |_| active.set(None)>
+                    "Base"
+                </li>
+            </ul>
+            <button on:click=move |_| {
+                let name = format!("Layer {}", layers.with_untracked(|l| l.len()) + 1);
+                send(ToServer::CreateLayer { name })
+            }>"Add layer"</button>
+            <Show when=move || selection.with(|s| !s.ids.is_empty())>
+                <div class="layer-actions">
+                    <button on:click=move |_| {
+                        send(ToServer::Reorder { ids: selected(), to: ZOrder::Front })
+                    }>"Bring to front"</button>
+                    <button on:click=move |_| {
+                        send(ToServer::Reorder { ids: selected(), to: ZOrder::Back })
+                    }>"Send to back"</button>
+                    <button on:click=move |_| {
+                        send(ToServer::SetLayer { ids: selected(), layer: active.get_untracked() })
+                    }>"Move to active layer"</button>
+                </div>
+            </Show>
+        </div>
+    }
+}
@@ -0,0 +1,137 @@
+use std::{collections::HashMap, hash::Hash};
+
+use common::entities::Stroke;
+
+/// Tessellations kept per stroke, so that zooming back and forth does not tessellate again
+const MAX_VARIANTS: usize = 3;
+
+/// Level of detail for `zoom`. Levels are powers of two and rounded up, so a tessellation made
+/// for a level is at least as fine as any zoom of that level needs.
+pub fn level(zoom: f32) -> i32 {
+    zoom.log2().ceil() as i32
+}
+
+/// Zoom a level is tessellated for
+pub fn level_zoom(level: i32) -> f32 {
+    2f32.powi(level)
+}
+
+struct Entry<V> {
+    /// Stroke the variants were made from
+    stroke: Stroke,
+    /// Tessellations by level, least recently used first
+    variants: Vec<(i32, V)>,
+}
+
+/// Tessellations of strokes at several levels of detail
+pub struct LodCache<K, V> {
+    entries: HashMap<K, Entry<V>>,
+}
+
+impl<K, V> Default for LodCache<K, V> {
+    fn default() -> Self {
+        LodCache {
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash, V> LodCache<K, V> {
+    /// Tessellation of `stroke` at `level`, made with `tessellate` unless it is cached. Also
+    /// returns whether it had to be made.
+    pub fn get(
+        &mut self,
+        key: K,
+        stroke: &Stroke,
+        level: i32,
+        tessellate: impl FnOnce() -> V,
+    ) -> (&V, bool) {
+        let entry = self.entries.entry(key).or_insert_with(|| Entry {
+            stroke: stroke.clone(),
+            variants: vec![],
+        });
+        if entry.stroke != *stroke {
+            entry.stroke = stroke.clone();
+            entry.variants.clear();
+        }
+        let made = match entry.variants.iter().position(|(l, _)| *l == level) {
+            Some(i) => {
+                let variant = entry.variants.remove(i);
+                entry.variants.push(variant);
+                false
+            }
+            None => {
+                if entry.variants.len() == MAX_VARIANTS {
+                    entry.variants.remove(0);
+                }
+                entry.variants.push((level, tessellate()));
+                true
+            }
+        };
+        let (_, variant) = entry.variants.last().expect("a variant was just added");
+        (variant, made)
+    }
+
+    pub fn retain(&mut self, keep: impl Fn(&K) -> bool) {
+        self.entries.retain(|key, _| keep(key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::entities::{Color, Position};
+
+    use super::*;
+
+    fn stroke(width: f32) -> Stroke {
+        Stroke {
+            points: vec![Position { x: 0.0, y: 0.0 }, Position { x: 1.0, y: 1.0 }],
+            width,
+            color: Color::BLACK,
+            pressure: vec![],
+            style: Default::default(),
+        }
+    }
+
+    #[test]
+    fn levels_round_up() {
+        assert_eq!(level(1.0), 0);
+        assert_eq!(level(1.5), 1);
+        assert_eq!(level(0.3), -1);
+        for zoom in [0.1, 0.7, 1.0, 3.3, 10.0] {
+            assert!(level_zoom(level(zoom)) >= zoom);
+            assert!(level_zoom(level(zoom)) < zoom * 2.0);
+        }
+    }
+
+    #[test]
+    fn variants_are_cached_per_level() {
+        let mut cache = LodCache::default();
+        let s = stroke(2.0);
+        assert_eq!(cache.get(1, &s, 0, || "a"), (&"a", true));
+        assert_eq!(cache.get(1, &s, 1, || "b"), (&"b", true));
+        assert_eq!(cache.get(1, &s, 0, || "c"), (&"a", false));
+        assert_eq!(cache.get(2, &s, 0, || "d"), (&"d", true));
+    }
+
+    #[test]
+    fn changed_strokes_are_tessellated_again() {
+        let mut cache = LodCache::default();
+        cache.get(1, &stroke(2.0), 0, || "a");
+        assert_eq!(cache.get(1, &stroke(3.0), 0, || "b"), (&"b", true));
+    }
+
+    #[test]
+    fn least_recently_used_variant_is_dropped() {
+        let mut cache = LodCache::default();
+        let s = stroke(2.0);
+        for level in 0..MAX_VARIANTS as i32 {
+            cache.get(1, &s, level, || level);
+        }
+        cache.get(1, &s, 0, || -1);
+        cache.get(1, &s, 10, || 10);
+        // Level 1 was used least recently
+        assert_eq!(cache.get(1, &s, 0, || -1), (&0, false));
+        assert_eq!(cache.get(1, &s, 1, || -1), (&-1, true));
+    }
+}
@@ -1,27 +1,64 @@
 #![allow(non_snake_case)]
+mod camera;
 mod canvas;
 mod client;
-mod line_drawing;
+mod history;
+mod images;
+mod import;
+mod layers;
+mod lod;
+mod offline;
+mod pending;
+mod renderer;
+mod selection;
+mod spatial;
+mod sticky;
+mod text;
+mod toolbar;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use camera::Camera;
 use canvas::Canvas;
 use client::*;
 use common::{
-    entities::Position,
+    entities::{Color, Element, ElementId, LayerId, Position, Sticky, Stroke, StrokeStyle, Text},
+    smoothing, text_ot,
     websocket::{ToClient, ToServer},
 };
-use ev::mousemove;
+use ev::{keydown, mousemove, paste, pointermove, pointerup};
+use history::{HistoryPanel, Preview};
+use layers::{LayerPanel, Placements};
 use leptos::*;
 use leptos_use::*;
-use logging::log;
+use offline::{Cache, OfflineIndicator, Store};
+use pending::Pending;
+use selection::{Selection, SelectionLayer};
+use spatial::ElementIndex;
+use sticky::{map_carets, Carets, StickyNotes, StickySync};
+use text::{TextDraft, TextEditor, TextLayer};
+use toolbar::{Tool, Toolbar};
+use web_sys::{js_sys, wasm_bindgen::JsCast};
 
+/// Largest distance in screen pixels a drawn stroke may move when simplified before sending
+const SIMPLIFY_TOLERANCE: f32 = 0.75;
+/// Milliseconds between attempts to connect while there is no connection
+const RECONNECT_INTERVAL: u64 = 500;
+/// Milliseconds the board must stay unchanged before it is saved in the browser
+const SAVE_DELAY: f64 = 500.0;
+
+/// Pointer of another client, `position` is in board coordinates
 #[component]
-fn Cursor(name: String, position: Signal<Position>) -> impl IntoView {
-    let x = move || position.get().x;
-    let y = move || position.get().y;
+fn Cursor(name: String, position: Signal<Position>, camera: RwSignal<Camera>) -> impl IntoView {
+    let screen = move || camera.get().to_screen(&position.get());
     view! {
-        <div class="cursor" style=move || { format!("transform: translate({}px, {}px)", x(), y()) }>
+        <div
+            class="cursor"
+            style=move || {
+                let screen = screen();
+                format!("transform: translate({}px, {}px)", screen.x, screen.y)
+            }
+        >
             <img class="image" src="/assets/img/pencil.svg" width="30" height="30"/>
             <div class="label">
                 <p>{name}</p>
@@ -30,6 +67,16 @@ fn Cursor(name: String, position: Signal<Position>) -> impl IntoView {
     }
 }
 
+/// Replaces an element with a version received from the server. The local text of a sticky note
+/// is kept, it may be ahead of the server because of pending edits.
+fn merge_update(elements: &mut HashMap<ElementId, Element>, id: ElementId, mut element: Element) {
+    if let (Some(Element::Sticky(old)), Element::Sticky(new)) = (elements.get(&id), &mut element) {
+        new.text = old.text.clone();
+        new.revision = old.revision;
+    }
+    elements.insert(id, element);
+}
+
 #[component]
 fn LoadingSpinner(text: &'static str) -> impl IntoView {
     view! {
@@ -44,8 +91,9 @@ fn LoadingSpinner(text: &'static str) -> impl IntoView {
     }
 }
 
+/// `cache` is the board as `store` kept it on an earlier visit
 #[component]
-fn App() -> impl IntoView {
+fn App(store: Store, cache: Option<Cache>) -> impl IntoView {
     let (x, set_x) = create_signal(0);
     let (y, set_y) = create_signal(0);
 
@@ -54,48 +102,110 @@ fn App() -> impl IntoView {
         set_y.set(e.client_y());
     });
 
-    let client = create_local_resource(|| (), |_| Client::new());
-
-    let messaged = create_memo(move |_| {
-        if let Some(Some(client)) = client.get() {
-            client.message().is_some()
-        } else {
-            false
-        }
+    let camera = create_rw_signal(Camera::default());
+    // The board as it was last seen shows until the server sends it, or for as long as there is
+    // no connection
+    let cached = cache.is_some();
+    let Cache {
+        url,
+        layers: cached_layers,
+        elements: cached_elements,
+        pending,
+    } = cache.unwrap_or_else(|| Cache {
+        // The server tells the changes of this browser apart by a number picked at random
+        pending: Pending::new((js_sys::Math::random() * 2_f64.powi(53)) as u64),
+        ..Cache::default()
     });
+    let connection = Client::new(url, pending);
 
-    let check_connection = {
-        let UseIntervalReturn { counter, .. } = use_interval(500);
-        counter
-    };
+    let (clients, set_clients) = create_signal(HashMap::<u64, Position>::new());
+    let (elements, set_elements) = create_signal(
+        cached_elements
+            .iter()
+            .map(|(id, e, _)| (*id, e.clone()))
+            .collect::<HashMap<_, _>>(),
+    );
+    let placements = create_rw_signal(
+        cached_elements
+            .iter()
+            .map(|(id, _, p)| (*id, *p))
+            .collect::<Placements>(),
+    );
+    let layers = create_rw_signal(cached_layers);
+    let active_layer = create_rw_signal(None::<LayerId>);
+    let carets = create_rw_signal(Carets::new());
+    let selection = create_rw_signal(Selection::default());
+    let sticky_sync = store_value(StickySync::default());
+    // Past version of the board shown instead of the live one, which cannot be edited meanwhile
+    let preview = create_rw_signal(None::<Preview>);
 
-    let client_memo = create_memo(move |_| client.get());
+    let client = {
+        let connection = connection.clone();
+        create_memo(move |_| (cached || connection.message().is_some()).then(|| connection.clone()))
+    };
 
-    create_effect(move |_| {
-        let _ = check_connection.get();
-        match client_memo.get() {
-            Some(Some(cl)) => {
-                if !cl.connected() && messaged.get() {
-                    client.refetch();
-                }
+    let UseIntervalReturn {
+        counter: reconnect, ..
+    } = use_interval(RECONNECT_INTERVAL);
+    {
+        let connection = connection.clone();
+        create_effect(move |_| {
+            reconnect.track();
+            if !connection.connected() {
+                let connection = connection.clone();
+                spawn_local(async move { connection.connect().await });
             }
-            Some(None) => client.refetch(),
-            _ => (),
-        }
-    });
-
-    let (clients, set_clients) = create_signal(HashMap::<u64, Position>::new());
+        });
+    }
 
-    let client = create_memo(move |_| match client.get() {
-        Some(Some(client)) => {
-            if client.message().is_some() {
-                Some(client)
-            } else {
-                None
+    // Others and their carets are not seen without a connection
+    {
+        let connection = connection.clone();
+        create_effect(move |_| {
+            if !connection.connected() {
+                set_clients.update(HashMap::clear);
+                carets.update(HashMap::clear);
             }
-        }
-        _ => None,
-    });
+        });
+    }
+
+    let save_failed = create_rw_signal(!store.available());
+    let save = {
+        let connection = connection.clone();
+        use_debounce_fn(
+            move || {
+                let saving = elements.with_untracked(|elements| {
+                    placements.with_untracked(|placements| {
+                        store.save(
+                            connection.url(),
+                            layers.get_untracked(),
+                            elements,
+                            placements,
+                            connection.pending().get_untracked(),
+                        )
+                    })
+                });
+                spawn_local(async move {
+                    let saved = saving.await;
+                    if let Err(error) = &saved {
+                        logging::warn!("Board could not be saved in the browser: {error:?}");
+                    }
+                    save_failed.set(saved.is_err());
+                });
+            },
+            SAVE_DELAY,
+        )
+    };
+    {
+        let pending = connection.pending();
+        create_effect(move |_| {
+            elements.track();
+            placements.track();
+            layers.track();
+            pending.track();
+            save();
+        });
+    }
 
     create_effect(move |_| {
         let Some(client) = client.get() else {
@@ -119,6 +229,9 @@ fn App() -> impl IntoView {
                 set_clients.update(|clients| {
                     clients.remove(&id);
                 });
+                carets.update(|carets| {
+                    carets.remove(&id);
+                });
             }
             ToClient::ClientList { clients } => set_clients.update(|clients_map| {
                 clients_map.clear();
@@ -126,9 +239,126 @@ fn App() -> impl IntoView {
                     clients_map.insert(id, pos);
                 }
             }),
+            ToClient::ElementList { board, elements } => {
+                sticky_sync.update_value(|sync| sync.clear());
+                placements.set(elements.iter().map(|(id, _, p)| (*id, *p)).collect());
+                set_elements.set(elements.into_iter().map(|(id, e, _)| (id, e)).collect());
+                client.reloaded(board);
+            }
+            ToClient::NewElement {
+                id,
+                element,
+                placement,
+            } => {
+                placements.update(|placements| {
+                    placements.insert(id, placement);
+                });
+                set_elements.update(|elements| {
+                    elements.insert(id, element);
+                });
+            }
+            ToClient::ElementUpdated { id, element } => {
+                set_elements.update(|elements| merge_update(elements, id, element));
+            }
+            ToClient::ElementsUpdated { elements: updated } => {
+                set_elements.update(|elements| {
+                    for (id, element) in updated {
+                        merge_update(elements, id, element);
+                    }
+                });
+            }
+            ToClient::ElementDeleted { id } => {
+                sticky_sync.update_value(|sync| sync.remove(id));
+                selection.update(|selection| {
+                    selection.ids.remove(&id);
+                });
+                set_elements.update(|elements| {
+                    elements.remove(&id);
+                });
+                placements.update(|placements| {
+                    placements.remove(&id);
+                });
+            }
+            ToClient::StickyAck { id, revision } => {
+                set_elements.update(|elements| {
+                    if let Some(Element::Sticky(sticky)) = elements.get_mut(&id) {
+                        sticky.revision = revision;
+                    }
+                });
+                sticky_sync.update_value(|sync| sync.ack(id, revision, &client));
+            }
+            ToClient::StickyEdited { id, revision, ops } => {
+                let ops = sticky_sync
+                    .try_update_value(|sync| sync.remote_edit(id, ops))
+                    .unwrap_or_default();
+                set_elements.update(|elements| {
+                    let Some(Element::Sticky(sticky)) = elements.get_mut(&id) else {
+                        return;
+                    };
+                    let old = sticky.text.clone();
+                    if text_ot::apply(&ops, &mut sticky.text).is_ok() {
+                        carets.update(|carets| map_carets(carets, id, &old, &sticky.text));
+                    }
+                    sticky.revision = revision;
+                });
+            }
+            ToClient::StickyCaret {
+                client,
+                id,
+                position,
+            } => {
+                carets.update(|carets| match position {
+                    Some(position) => {
+                        carets.insert(client, (id, position));
+                    }
+                    None => {
+                        carets.remove(&client);
+                    }
+                });
+            }
+            ToClient::PlacementsUpdated {
+                placements: updated,
+            } => {
+                placements.update(|placements| placements.extend(updated));
+            }
+            ToClient::Ack { seq } | ToClient::Rejected { seq } => {
+                client.pending().update(|pending| pending.settle(seq));
+            }
+            ToClient::LayerList { layers: list } => {
+                if !list
+                    .iter()
+                    .any(|(id, _)| Some(*id) == active_layer.get_untracked())
+                {
+                    active_layer.set(None);
+                }
+                layers.set(list);
+            }
         }
     });
 
+    let _ = use_event_listener(use_document(), paste, move |e| {
+        let Some(client) = client.get_untracked() else {
+            return;
+        };
+        let Some(e) = e.dyn_ref::<web_sys::ClipboardEvent>() else {
+            return;
+        };
+        let files = images::image_files(e.clipboard_data());
+        let drawings = import::drawing_files(e.clipboard_data());
+        if files.is_empty() && drawings.is_empty() {
+            return;
+        }
+        e.prevent_default();
+        let pointer = Position {
+            x: x.get_untracked() as f32,
+            y: y.get_untracked() as f32,
+        };
+        let position = camera.get_untracked().to_board(&pointer);
+        let layer = active_layer.get_untracked();
+        import::add_drawings(drawings, position, layer, client.clone());
+        images::add_images(files, position, layer, client);
+    });
+
     let UseIntervalReturn { counter, .. } = use_interval(50);
 
     create_effect(move |_| {
@@ -136,28 +366,374 @@ fn App() -> impl IntoView {
             return;
         };
         let _ = counter.get();
-        let x = x.get_untracked();
-        let y = y.get_untracked();
+        // Sent in board coordinates, so that others see it over the same content at any zoom
+        let position = camera.get_untracked().to_board(&Position {
+            x: x.get_untracked() as f32,
+            y: y.get_untracked() as f32,
+        });
         client.send(ToServer::Move {
-            x: x as f32,
-            y: y as f32,
+            x: position.x,
+            y: position.y,
+        });
+    });
+
+    let tool = create_rw_signal(Tool::Select);
+    let font_size = create_rw_signal(24.0);
+    let pen_width = create_rw_signal(4.0);
+    let stroke_style = create_rw_signal(StrokeStyle::default());
+    let color = create_rw_signal(Color::BLACK);
+    let editing = create_rw_signal(None::<TextDraft>);
+    let drawing = create_rw_signal(None::<Stroke>);
+
+    // Contents confirmed by the server with the local changes it has not answered yet on top
+    let contents = create_memo(move |_| {
+        let mut elements = elements.get();
+        let mut placements = placements.get();
+        if let Some(client) = client.get() {
+            client.pending().with(|pending| {
+                layers.with(|layers| pending.apply(&mut elements, &mut placements, layers))
+            });
+        }
+        (elements, placements)
+    });
+
+    // A colour picked while text boxes are selected applies to them
+    create_effect(move |ran: Option<()>| {
+        let color = color.get();
+        let Some(client) = client.get_untracked().filter(|_| ran.is_some()) else {
+            return;
+        };
+        let recoloured = selection.with_untracked(|selection| {
+            contents.with_untracked(|(elements, _)| {
+                selection
+                    .ids
+                    .iter()
+                    .filter_map(|id| match elements.get(id) {
+                        Some(Element::Text(text)) if text.color != color => {
+                            Some((*id, Element::Text(Text { color, ..text.clone() })))
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+        });
+        for (id, element) in recoloured {
+            client.send(ToServer::UpdateElement { id, element });
+        }
+    });
+
+    // Elements as displayed, without hidden layers and with the selection moved along while it
+    // is being dragged
+    let displayed = create_memo(move |_| {
+        if let Some(preview) = preview.get() {
+            let Preview {
+                mut elements,
+                placements,
+                layers,
+                ..
+            } = preview;
+            elements.retain(|id, _| !layers::is_hidden(&layers, placements.get(id)));
+            return elements;
+        }
+        let (mut displayed, placements) = contents.get();
+        layers.with(|layers| {
+            displayed.retain(|id, _| !layers::is_hidden(layers, placements.get(id)))
         });
+        selection.with(|selection| {
+            let Some(transform) = selection.preview() else {
+                return;
+            };
+            for id in &selection.ids {
+                if let Some(element) = displayed.get_mut(id) {
+                    element.transform(&transform);
+                }
+            }
+        });
+        displayed
+    });
+
+    let ranks = create_memo(move |_| {
+        displayed.with(|displayed| {
+            preview.with(|preview| match preview {
+                Some(preview) => layers::ranks(displayed, &preview.placements, &preview.layers),
+                None => contents.with(|(_, placements)| {
+                    layers.with(|layers| layers::ranks(displayed, placements, layers))
+                }),
+            })
+        })
     });
 
+    // Displayed elements that are not on a locked layer and that the server has created
+    let index = ElementIndex::new(displayed.into());
+    let selectable = create_memo(move |_| {
+        if preview.with(Option::is_some) {
+            return HashMap::new();
+        }
+        let mut selectable = displayed.get();
+        contents.with(|(_, placements)| {
+            layers.with(|layers| {
+                selectable.retain(|id, _| {
+                    !layers::is_locked(layers, placements.get(id)) && !pending::is_provisional(*id)
+                })
+            })
+        });
+        selectable
+    });
+
+    create_effect(move |_| {
+        let ids = selectable.with(|s| s.keys().copied().collect::<HashSet<_>>());
+        if selection.with_untracked(|s| s.ids.iter().any(|id| !ids.contains(id))) {
+            selection.update(|s| s.ids.retain(|id| ids.contains(id)));
+        }
+    });
+
+    let pointer_position = move |e: &ev::PointerEvent| {
+        camera.get_untracked().to_board(&Position {
+            x: e.client_x() as f32,
+            y: e.client_y() as f32,
+        })
+    };
+
+    let _ = use_event_listener(use_document(), pointermove, move |e| {
+        let position = pointer_position(&e);
+        match tool.get_untracked() {
+            Tool::Select => selection::pointer_move(selection, position),
+            Tool::Pen if drawing.with_untracked(|d| d.is_some()) => drawing.update(|drawing| {
+                if let Some(stroke) = drawing {
+                    if stroke.points.last() != Some(&position) {
+                        stroke.points.push(position);
+                        if !stroke.pressure.is_empty() {
+                            stroke.pressure.push(e.pressure());
+                        }
+                    }
+                }
+            }),
+            _ => (),
+        }
+    });
+
+    let _ = use_event_listener(use_document(), pointerup, move |_| {
+        let Some(client) = client.get_untracked() else {
+            return;
+        };
+        match tool.get_untracked() {
+            Tool::Select => selectable.with_untracked(|selectable| {
+                selection::pointer_up(selection, selectable, &index, &client)
+            }),
+            Tool::Pen => {
+                if let Some(mut stroke) = drawing.get_untracked() {
+                    drawing.set(None);
+                    let tolerance = SIMPLIFY_TOLERANCE / camera.get_untracked().zoom;
+                    smoothing::simplify_stroke(&mut stroke, tolerance);
+                    client.send(ToServer::CreateElement {
+                        element: Element::Stroke(stroke),
+                        layer: active_layer.get_untracked(),
+                    });
+                }
+            }
+            _ => (),
+        }
+    });
+
+    let _ = use_event_listener(use_document(), keydown, move |e| {
+        let editing_text = e
+            .target()
+            .and_then(|t| t.dyn_into::<web_sys::Element>().ok())
+            .is_some_and(|t| matches!(t.tag_name().as_str(), "INPUT" | "TEXTAREA"));
+        if editing_text || tool.get_untracked() != Tool::Select {
+            return;
+        }
+        if let ("Delete" | "Backspace", Some(client)) = (e.key().as_str(), client.get_untracked()) {
+            selection::delete_selected(selection, &client);
+        }
+    });
+
+    create_effect(move |_| {
+        if tool.get() != Tool::Select {
+            selection.set(Selection::default());
+        }
+    });
+
+    let on_select = move |(id, e): (ElementId, ev::PointerEvent)| {
+        if preview.with_untracked(Option::is_some) {
+            return;
+        }
+        let position = pointer_position(&e);
+        selectable.with_untracked(|selectable| {
+            ranks.with_untracked(|ranks| {
+                selection::pointer_down(
+                    selection,
+                    selectable,
+                    &index,
+                    ranks,
+                    position,
+                    e.shift_key(),
+                    Some(id),
+                )
+            })
+        });
+    };
+
+    let on_canvas_pointer_down = move |position: Position, e: ev::PointerEvent, client: &Client| {
+        if preview.with_untracked(Option::is_some) {
+            return;
+        }
+        match tool.get_untracked() {
+            Tool::Select => selectable.with_untracked(|selectable| {
+                ranks.with_untracked(|ranks| {
+                    selection::pointer_down(
+                        selection,
+                        selectable,
+                        &index,
+                        ranks,
+                        position,
+                        e.shift_key(),
+                        None,
+                    )
+                })
+            }),
+            Tool::Pen => drawing.set(Some(Stroke {
+                points: vec![position],
+                width: pen_width.get_untracked(),
+                color: color.get_untracked(),
+                style: stroke_style.get_untracked(),
+                // Only styluses report a meaningful pressure, mice always report 0.5
+                pressure: if e.pointer_type() == "pen" {
+                    vec![e.pressure()]
+                } else {
+                    vec![]
+                },
+            })),
+            Tool::Text if editing.get_untracked().is_none() => {
+                editing.set(Some(TextDraft {
+                    id: None,
+                    text: Text {
+                        position,
+                        content: String::new(),
+                        font_size: font_size.get_untracked(),
+                        color: color.get_untracked(),
+                        rotation: 0.0,
+                    },
+                }));
+            }
+            Tool::Sticky => client.send(ToServer::CreateElement {
+                element: Element::Sticky(Sticky {
+                    position,
+                    width: 200.0,
+                    height: 200.0,
+                    color: Color::STICKY_YELLOW,
+                    rotation: 0.0,
+                    text: String::new(),
+                    revision: 0,
+                }),
+                layer: active_layer.get_untracked(),
+            }),
+            _ => (),
+        }
+    };
+
     view! {
         {move || {
             match client.get() {
                 Some(client) => {
+                    let client_clone = client.clone();
+                    let drop_client = client.clone();
+                    let on_drop = move |e: ev::DragEvent| {
+                        let files = images::image_files(e.data_transfer());
+                        let drawings = import::drawing_files(e.data_transfer());
+                        if files.is_empty() && drawings.is_empty() {
+                            return;
+                        }
+                        e.prevent_default();
+                        let pointer = Position {
+                            x: e.client_x() as f32,
+                            y: e.client_y() as f32,
+                        };
+                        let position = camera.get_untracked().to_board(&pointer);
+                        let layer = active_layer.get_untracked();
+                        import::add_drawings(drawings, position, layer, drop_client.clone());
+                        images::add_images(files, position, layer, drop_client.clone());
+                    };
                     view! {
-                        <Canvas client=client/>
+                        <div
+                            class=move || {
+                                let previewing = preview.with(Option::is_some);
+                                format!(
+                                    "board {}{}",
+                                    tool.get().class(),
+                                    if previewing { " previewing" } else { "" }
+                                )
+                            }
+                            on:dragover=|e: ev::DragEvent| e.prevent_default()
+                            on:drop=on_drop
+                        >
+                            <Canvas
+                                camera=camera
+                                elements=displayed
+                                index=index
+                                ranks=ranks
+                                drawing=drawing
+                                on_pointer_down=move |(position, e)| {
+                                    on_canvas_pointer_down(position, e, &client_clone)
+                                }
+                            />
+                            <div class="element-overlays">
+                                <StickyNotes
+                                    elements=displayed
+                                    ranks=ranks
+                                    set_elements=set_elements
+                                    carets=carets
+                                    sync=sticky_sync
+                                    camera=camera
+                                    client=client.clone()
+                                    on_select=on_select
+                                />
+                                <TextLayer
+                                    elements=displayed
+                                    ranks=ranks
+                                    camera=camera
+                                    tool=tool
+                                    editing=editing
+                                    on_select=on_select
+                                />
+                            </div>
+                            <TextEditor
+                                editing=editing
+                                camera=camera
+                                layer=active_layer
+                                client=client.clone()
+                            />
+                            <SelectionLayer elements=displayed selection=selection camera=camera/>
+                        </div>
+                        <Toolbar
+                            tool=tool
+                            font_size=font_size
+                            pen_width=pen_width
+                            stroke_style=stroke_style
+                            color=color
+                        />
+                        <LayerPanel
+                            layers=layers
+                            active=active_layer
+                            selection=selection
+                            client=client.clone()
+                        />
+                        <HistoryPanel preview=preview client=client.clone()/>
+                        <OfflineIndicator client=client save_failed=save_failed/>
                         <For
                             each=move || clients.get()
-                            key=move |(id, _)| id.clone()
+                            key=move |(id, _)| *id
                             children=move |(id, _)| {
                                 let position = create_memo(move |_| {
                                     clients.with(|clients| clients.get(&id).unwrap().to_owned())
                                 });
-                                view! { <Cursor name=format!("{}", id) position=position.into()/> }
+                                view! {
+                                    <Cursor
+                                        name=format!("{}", id)
+                                        position=position.into()
+                                        camera=camera
+                                    />
+                                }
                             }
                         />
                     }
@@ -172,5 +748,10 @@ fn App() -> impl IntoView {
 }
 fn main() {
     console_error_panic_hook::set_once();
-    mount_to_body(|| view! { <App/> })
+    // The board kept in the browser is read first, so that it shows straight away
+    spawn_local(async {
+        let store = Store::open().await;
+        let cache = store.load().await;
+        mount_to_body(move || view! { <App store=store cache=cache/> })
+    })
 }
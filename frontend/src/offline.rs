@@ -0,0 +1,298 @@
+use std::{cell::RefCell, collections::HashMap, future::Future, rc::Rc};
+
+use common::entities::{Element, ElementId, Placement};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    js_sys::{Array, Promise},
+    wasm_bindgen::{closure::Closure, JsCast, JsValue},
+    IdbDatabase, IdbKeyRange, IdbRequest, IdbTransaction, IdbTransactionMode,
+};
+
+use crate::{
+    client::{Client, BOARD},
+    layers::{Layers, Placements},
+    pending::Pending,
+};
+
+const DATABASE: &str = "coboard";
+/// Object store of the url, layers and pending changes of boards, by name of the board
+const BOARDS: &str = "boards";
+/// Object store of the elements of boards, each under `{board}/{id}`
+const ELEMENTS: &str = "elements";
+
+/// Copy of the board kept in the browser, so that the board shows and can be drawn on without a
+/// connection. The contents are as the server last sent them, the local changes are on top.
+#[derive(Default)]
+pub struct Cache {
+    /// Path of the board on the server
+    pub url: String,
+    pub layers: Layers,
+    pub elements: Vec<(ElementId, Element, Placement)>,
+    pub pending: Pending,
+}
+
+/// What is saved of a board besides its elements, which are saved one by one
+#[derive(Serialize, Deserialize)]
+struct Header {
+    url: String,
+    layers: Layers,
+    pending: Pending,
+}
+
+/// Element as it is saved, with its place in the stacking order
+type Saved = (Element, Placement);
+
+/// Cache of the board in the IndexedDB of the browser
+#[derive(Clone)]
+pub struct Store {
+    /// Missing when the browser has no IndexedDB or does not allow using it
+    database: Option<IdbDatabase>,
+    /// Elements as they were last written, a save only writes the ones that changed since
+    saved: Rc<RefCell<HashMap<ElementId, Saved>>>,
+}
+
+fn js_error(error: serde_json::Error) -> JsValue {
+    JsValue::from_str(&error.to_string())
+}
+
+fn element_key(id: ElementId) -> JsValue {
+    format!("{BOARD}/{id}").into()
+}
+
+/// Waits for `request` to succeed and gives its result
+async fn finished(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let succeeded = request.clone();
+        let onsuccess = Closure::once_into_js(move || {
+            let _ = resolve.call1(
+                &JsValue::NULL,
+                &succeeded.result().unwrap_or(JsValue::UNDEFINED),
+            );
+        });
+        let failed = request.clone();
+        let onerror = Closure::once_into_js(move || {
+            let error = failed
+                .error()
+                .ok()
+                .flatten()
+                .map_or(JsValue::UNDEFINED, JsValue::from);
+            let _ = reject.call1(&JsValue::NULL, &error);
+        });
+        request.set_onsuccess(Some(onsuccess.unchecked_ref()));
+        request.set_onerror(Some(onerror.unchecked_ref()));
+    });
+    JsFuture::from(promise).await
+}
+
+/// Waits for the writes of `transaction` to be stored. A failed request, such as one over the
+/// storage quota, aborts the whole transaction.
+async fn committed(transaction: IdbTransaction) -> Result<(), JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let oncomplete = Closure::once_into_js(move || {
+            let _ = resolve.call0(&JsValue::NULL);
+        });
+        let failed = transaction.clone();
+        let onabort = Closure::once_into_js(move || {
+            let _ = reject.call1(
+                &JsValue::NULL,
+                &failed.error().map_or(JsValue::UNDEFINED, JsValue::from),
+            );
+        });
+        transaction.set_oncomplete(Some(oncomplete.unchecked_ref()));
+        transaction.set_onabort(Some(onabort.unchecked_ref()));
+    });
+    JsFuture::from(promise).await.map(|_| ())
+}
+
+async fn open_database() -> Result<IdbDatabase, JsValue> {
+    let factory = window().indexed_db()?.ok_or(JsValue::UNDEFINED)?;
+    let request = factory.open_with_u32(DATABASE, 1)?;
+    let upgrading = request.clone();
+    // There is only one version, so an upgrade is always from an empty database
+    let onupgradeneeded = Closure::once_into_js(move || {
+        if let Ok(database) = upgrading.result().and_then(JsCast::dyn_into::<IdbDatabase>) {
+            let _ = database.create_object_store(BOARDS);
+            let _ = database.create_object_store(ELEMENTS);
+        }
+    });
+    request.set_onupgradeneeded(Some(onupgradeneeded.unchecked_ref()));
+    finished(&request).await?.dyn_into()
+}
+
+impl Store {
+    pub async fn open() -> Store {
+        let database = open_database()
+            .await
+            .inspect_err(|error| logging::warn!("Board cannot be kept in the browser: {error:?}"))
+            .ok();
+        Store {
+            database,
+            saved: Rc::default(),
+        }
+    }
+
+    pub fn available(&self) -> bool {
+        self.database.is_some()
+    }
+
+    fn transaction(&self, mode: IdbTransactionMode) -> Result<IdbTransaction, JsValue> {
+        let database = self.database.as_ref().ok_or(JsValue::UNDEFINED)?;
+        let stores = Array::of2(&BOARDS.into(), &ELEMENTS.into());
+        database.transaction_with_str_sequence_and_mode(&stores, mode)
+    }
+
+    /// Cache saved by an earlier visit. The changes it sent are treated as lost with their
+    /// connection, they are sent again once the board arrives.
+    pub async fn load(&self) -> Option<Cache> {
+        let transaction = self.transaction(IdbTransactionMode::Readonly).ok()?;
+        let header = transaction
+            .object_store(BOARDS)
+            .ok()?
+            .get(&BOARD.into())
+            .ok()?;
+        // Element keys of the board are those between `{BOARD}/` and `{BOARD}0`, as '0' follows '/'
+        let range =
+            IdbKeyRange::bound(&format!("{BOARD}/").into(), &format!("{BOARD}0").into()).ok()?;
+        let elements = transaction
+            .object_store(ELEMENTS)
+            .ok()?
+            .get_all_with_key(&range)
+            .ok()?;
+
+        let header = finished(&header).await.ok()?.as_string()?;
+        let Header {
+            url,
+            layers,
+            mut pending,
+        } = serde_json::from_str(&header).ok()?;
+        let elements = Array::from(&finished(&elements).await.ok()?)
+            .iter()
+            .filter_map(|element| {
+                serde_json::from_str::<(ElementId, Element, Placement)>(&element.as_string()?).ok()
+            })
+            .collect::<Vec<_>>();
+        *self.saved.borrow_mut() = elements
+            .iter()
+            .map(|(id, e, p)| (*id, (e.clone(), *p)))
+            .collect();
+        pending.connection_lost();
+        Some(Cache {
+            url,
+            layers,
+            elements,
+            pending,
+        })
+    }
+
+    /// Replaces the saved board. The returned future fails when the board could not be stored,
+    /// such as when it does not fit in the storage the browser allows.
+    pub fn save(
+        &self,
+        url: String,
+        layers: Layers,
+        elements: &HashMap<ElementId, Element>,
+        placements: &Placements,
+        pending: Pending,
+    ) -> impl Future<Output = Result<(), JsValue>> {
+        let written = self
+            .transaction(IdbTransactionMode::Readwrite)
+            .and_then(|transaction| {
+                let header = Header {
+                    url,
+                    layers,
+                    pending,
+                };
+                match self.write(&transaction, &header, elements, placements) {
+                    Ok(changes) => Ok((transaction, changes)),
+                    Err(error) => {
+                        let _ = transaction.abort();
+                        Err(error)
+                    }
+                }
+            });
+        let saved = self.saved.clone();
+        async move {
+            let (transaction, changes) = written?;
+            committed(transaction).await?;
+            let mut saved = saved.borrow_mut();
+            for (id, change) in changes {
+                match change {
+                    Some(element) => saved.insert(id, element),
+                    None => saved.remove(&id),
+                };
+            }
+            Ok(())
+        }
+    }
+
+    /// Puts the changes since the last save into `transaction`, and gives them
+    fn write(
+        &self,
+        transaction: &IdbTransaction,
+        header: &Header,
+        elements: &HashMap<ElementId, Element>,
+        placements: &Placements,
+    ) -> Result<Vec<(ElementId, Option<Saved>)>, JsValue> {
+        let header = serde_json::to_string(header).map_err(js_error)?;
+        transaction
+            .object_store(BOARDS)?
+            .put_with_key(&header.into(), &BOARD.into())?;
+
+        let store = transaction.object_store(ELEMENTS)?;
+        let saved = self.saved.borrow();
+        let mut changes = vec![];
+        for (id, element) in elements {
+            let Some(placement) = placements.get(id) else {
+                continue;
+            };
+            if saved
+                .get(id)
+                .is_some_and(|(e, p)| e == element && p == placement)
+            {
+                continue;
+            }
+            let json = serde_json::to_string(&(id, element, placement)).map_err(js_error)?;
+            store.put_with_key(&json.into(), &element_key(*id))?;
+            changes.push((*id, Some((element.clone(), *placement))));
+        }
+        for id in saved
+            .keys()
+            .filter(|id| !elements.contains_key(id) || !placements.contains_key(id))
+        {
+            store.delete(&element_key(*id))?;
+            changes.push((*id, None));
+        }
+        Ok(changes)
+    }
+}
+
+/// Shown while there is no connection, with the number of changes waiting for one, or when the
+/// board cannot be kept in the browser
+#[component]
+pub fn OfflineIndicator(client: Client, save_failed: RwSignal<bool>) -> impl IntoView {
+    let pending = client.pending();
+    let client = store_value(client);
+    let offline = move || client.with_value(|client| !client.connected());
+    let text = move || {
+        let mut text = match (offline(), pending.with(Pending::waiting)) {
+            (false, _) => String::new(),
+            (true, 0) => "Offline".to_owned(),
+            (true, 1) => "Offline, 1 change waiting".to_owned(),
+            (true, n) => format!("Offline, {n} changes waiting"),
+        };
+        if save_failed.get() {
+            if !text.is_empty() {
+                text.push_str(". ");
+            }
+            text.push_str("Changes cannot be kept in this browser");
+        }
+        text
+    };
+    view! {
+        <Show when=move || offline() || save_failed.get()>
+            <div class="offline-indicator no-select">{text}</div>
+        </Show>
+    }
+}
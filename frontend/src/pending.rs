@@ -0,0 +1,907 @@
+use std::collections::HashMap;
+
+use common::{
+    entities::{Element, ElementId, LayerId, Placement, ZOrder},
+    websocket::ToServer,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    layers::{Layers, Placements},
+    merge_update,
+};
+
+/// Whether `message` changes elements in a way that is shown before the server answers it
+pub fn is_optimistic(message: &ToServer) -> bool {
+    matches!(
+        message,
+        ToServer::CreateElement { .. }
+            | ToServer::UpdateElement { .. }
+            | ToServer::DeleteElement { .. }
+            | ToServer::Transform { .. }
+            | ToServer::Reorder { .. }
+            | ToServer::SetLayer { .. }
+    )
+}
+
+/// Id an element created by the pending change `seq` is shown with until the server gives it
+/// one. Server ids count up from zero, these count down from the largest id.
+fn provisional_id(seq: u64) -> ElementId {
+    ElementId::MAX - seq
+}
+
+/// Whether `id` belongs to an element the server has not created yet, which cannot be changed
+pub fn is_provisional(id: ElementId) -> bool {
+    id > ElementId::MAX / 2
+}
+
+fn top_z(placements: &Placements, layer: Option<LayerId>) -> i64 {
+    placements
+        .values()
+        .filter(|placement| placement.layer == layer)
+        .map(|placement| placement.z + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+fn bottom_z(placements: &Placements, layer: Option<LayerId>) -> i64 {
+    placements
+        .values()
+        .filter(|placement| placement.layer == layer)
+        .map(|placement| placement.z - 1)
+        .min()
+        .unwrap_or(0)
+}
+
+/// Elements among `ids` that exist, without duplicates, from bottom to top
+fn stacked(mut ids: Vec<ElementId>, placements: &Placements, layers: &Layers) -> Vec<ElementId> {
+    ids.sort();
+    ids.dedup();
+    ids.retain(|id| placements.contains_key(id));
+    ids.sort_by_key(|id| placements[id].stacking_key(layers));
+    ids
+}
+
+/// Whether a change refers to elements or layers of the board it was made on, rather than only
+/// to elements it creates
+fn refers_to_board(message: &ToServer) -> bool {
+    match message {
+        ToServer::CreateElement { .. } => false,
+        ToServer::UpdateElement { id, .. } | ToServer::DeleteElement { id } => !is_provisional(*id),
+        ToServer::Transform { ids, .. }
+        | ToServer::Reorder { ids, .. }
+        | ToServer::SetLayer { ids, .. } => ids.iter().any(|id| !is_provisional(*id)),
+        _ => true,
+    }
+}
+
+/// How far a pending change got on its way to the server
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+enum Delivery {
+    /// Waiting for a connection
+    Unsent,
+    /// Sent on the current connection, the answer comes on it
+    Sent,
+    /// Sent on a connection that closed before the answer came. The server may have applied it
+    /// or not, it is sent again once the contents arrive on the next connection.
+    Lost,
+    /// Lost and sent again. Not shown, as the contents include it if the server had applied it,
+    /// and its effect arrives before the answer otherwise.
+    Resent,
+}
+
+/// Local changes not answered by the server yet.
+///
+/// The contents the server confirmed are kept apart, and the pending changes are applied on top
+/// of them each time they are shown, the way the server is expected to apply them. When the
+/// server answers, the change is dropped: if it was applied, its effect arrived just before the
+/// answer, and if it was rejected, it disappears. Changes of others that arrive meanwhile end up
+/// below the pending ones, as they do on the server.
+///
+/// Changes made without a connection wait until there is one and the contents arrived on it.
+/// Changes whose connection closed are sent again then, the server recognises the ones it
+/// already answered by `author` and `seq` and does not apply them twice.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Pending {
+    /// Picked at random for this browser, the server tells authors of changes apart by it
+    author: u64,
+    next_seq: u64,
+    changes: Vec<(u64, ToServer, Delivery)>,
+    /// Board the changes were made on, as its contents named it, unknown before they first arrive
+    board: Option<u64>,
+    /// Whether the contents arrived on the current connection, which changes are sent after
+    #[serde(skip)]
+    loaded: bool,
+}
+
+impl Pending {
+    pub fn new(author: u64) -> Pending {
+        Pending {
+            author,
+            ..Pending::default()
+        }
+    }
+
+    /// Records a change to send
+    pub fn push(&mut self, message: ToServer) {
+        self.changes
+            .push((self.next_seq, message, Delivery::Unsent));
+        self.next_seq += 1;
+    }
+
+    /// Changes to send now that there is a connection and the contents arrived on it, tagged
+    /// with their numbers and in their order. Those lost with an earlier connection are sent
+    /// again.
+    pub fn send_unsent(&mut self) -> Vec<ToServer> {
+        if !self.loaded {
+            return vec![];
+        }
+        let author = self.author;
+        self.changes
+            .iter_mut()
+            .filter(|(_, _, delivery)| matches!(delivery, Delivery::Unsent | Delivery::Lost))
+            .map(|(seq, message, delivery)| {
+                *delivery = match delivery {
+                    Delivery::Lost => Delivery::Resent,
+                    _ => Delivery::Sent,
+                };
+                ToServer::Tagged {
+                    author,
+                    seq: *seq,
+                    message: Box::new(message.clone()),
+                }
+            })
+            .collect()
+    }
+
+    /// Drops change `seq` once the server acknowledged or rejected it
+    pub fn settle(&mut self, seq: u64) {
+        self.changes.retain(|(s, _, _)| *s != seq);
+    }
+
+    /// Notes that no answers will come for the changes sent so far
+    pub fn connection_lost(&mut self) {
+        self.loaded = false;
+        for (_, _, delivery) in &mut self.changes {
+            if matches!(delivery, Delivery::Sent | Delivery::Resent) {
+                *delivery = Delivery::Lost;
+            }
+        }
+    }
+
+    /// Lets the changes be sent once the contents of `board` arrived. When it is another board
+    /// than the changes were made on, the old one was unloaded and its elements and layers are
+    /// gone: new elements go to the base layer of this one, and the other changes are dropped.
+    /// Returns how many were.
+    pub fn reloaded(&mut self, board: u64) -> usize {
+        self.loaded = true;
+        if self.board.replace(board).is_none_or(|old| old == board) {
+            return 0;
+        }
+        let before = self.changes.len();
+        self.changes
+            .retain(|(_, message, _)| !refers_to_board(message));
+        for (_, message, delivery) in &mut self.changes {
+            if let ToServer::CreateElement { layer, .. } = message {
+                *layer = None;
+            }
+            // The new board cannot have applied them
+            if *delivery == Delivery::Lost {
+                *delivery = Delivery::Unsent;
+            }
+        }
+        before - self.changes.len()
+    }
+
+    /// Number of changes the server has not answered
+    pub fn waiting(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Applies the pending changes to the confirmed contents
+    pub fn apply(
+        &self,
+        elements: &mut HashMap<ElementId, Element>,
+        placements: &mut Placements,
+        layers: &Layers,
+    ) {
+        let shown = self
+            .changes
+            .iter()
+            .filter(|(_, _, delivery)| *delivery != Delivery::Resent);
+        for (seq, message, _) in shown {
+            match message {
+                ToServer::CreateElement { element, layer } => {
+                    let id = provisional_id(*seq);
+                    let z = top_z(placements, *layer);
+                    placements.insert(id, Placement { layer: *layer, z });
+                    elements.insert(id, element.clone());
+                }
+                ToServer::UpdateElement { id, element } if elements.contains_key(id) => {
+                    merge_update(elements, *id, element.clone());
+                }
+                ToServer::DeleteElement { id } => {
+                    elements.remove(id);
+                    placements.remove(id);
+                }
+                ToServer::Transform { ids, transform } => {
+                    for id in ids {
+                        if let Some(element) = elements.get_mut(id) {
+                            element.transform(transform);
+                        }
+                    }
+                }
+                ToServer::Reorder { ids, to } => {
+                    let mut ids = stacked(ids.clone(), placements, layers);
+                    if *to == ZOrder::Back {
+                        ids.reverse();
+                    }
+                    for id in ids {
+                        let layer = placements[&id].layer;
+                        let z = match to {
+                            ZOrder::Front => top_z(placements, layer),
+                            ZOrder::Back => bottom_z(placements, layer),
+                        };
+                        placements.insert(id, Placement { layer, z });
+                    }
+                }
+                ToServer::SetLayer { ids, layer } => {
+                    for id in stacked(ids.clone(), placements, layers) {
+                        let z = top_z(placements, *layer);
+                        placements.insert(id, Placement { layer: *layer, z });
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use common::{
+        entities::{Color, Layer, Position, Stroke, StrokeStyle, Transform},
+        websocket::ToClient,
+    };
+
+    use super::*;
+
+    /// Stand-in for the board on the server: applies element changes in arrival order, refuses
+    /// those on locked layers, and answers tagged changes after broadcasting their effect, once
+    /// for each change
+    struct Server {
+        board: u64,
+        elements: HashMap<ElementId, Element>,
+        placements: Placements,
+        layers: Layers,
+        next_id: ElementId,
+        /// Next change expected of each author
+        answered: HashMap<u64, u64>,
+    }
+
+    impl Server {
+        fn editable(&self, id: &ElementId) -> bool {
+            self.placements
+                .get(id)
+                .is_some_and(|p| !crate::layers::is_locked(&self.layers, Some(p)))
+        }
+
+        /// Messages to send to the author and to everyone, in order
+        fn receive(&mut self, message: ToServer) -> Vec<(bool, ToClient)> {
+            let ToServer::Tagged {
+                author,
+                seq,
+                message,
+            } = message
+            else {
+                panic!("untagged change {message:?}");
+            };
+            let next = self.answered.entry(author).or_default();
+            if seq < *next {
+                return vec![(true, ToClient::Ack { seq })];
+            }
+            *next = seq + 1;
+            let mut broadcast = self.apply(*message);
+            let answer = if broadcast.is_empty() {
+                ToClient::Rejected { seq }
+            } else {
+                ToClient::Ack { seq }
+            };
+            broadcast.push((true, answer));
+            broadcast
+        }
+
+        fn apply(&mut self, message: ToServer) -> Vec<(bool, ToClient)> {
+            let everyone = |message| vec![(false, message)];
+            match message {
+                ToServer::CreateElement { element, layer } => {
+                    if crate::layers::is_locked(&self.layers, Some(&Placement { layer, z: 0 })) {
+                        return vec![];
+                    }
+                    let id = self.next_id;
+                    self.next_id += 1;
+                    let placement = Placement {
+                        layer,
+                        z: top_z(&self.placements, layer),
+                    };
+                    self.elements.insert(id, element.clone());
+                    self.placements.insert(id, placement);
+                    everyone(ToClient::NewElement {
+                        id,
+                        element,
+                        placement,
+                    })
+                }
+                ToServer::DeleteElement { id } if self.editable(&id) => {
+                    self.elements.remove(&id);
+                    self.placements.remove(&id);
+                    everyone(ToClient::ElementDeleted { id })
+                }
+                ToServer::Transform { ids, transform } => {
+                    let mut updated = vec![];
+                    for id in stacked(ids, &self.placements, &self.layers) {
+                        if self.editable(&id) {
+                            let element = self.elements.get_mut(&id).unwrap();
+                            element.transform(&transform);
+                            updated.push((id, element.clone()));
+                        }
+                    }
+                    if updated.is_empty() {
+                        return vec![];
+                    }
+                    everyone(ToClient::ElementsUpdated { elements: updated })
+                }
+                ToServer::Reorder {
+                    ids,
+                    to: ZOrder::Front,
+                } => {
+                    let mut ids = stacked(ids, &self.placements, &self.layers);
+                    ids.retain(|id| self.editable(id));
+                    let mut placements = vec![];
+                    for id in ids {
+                        let layer = self.placements[&id].layer;
+                        let placement = Placement {
+                            layer,
+                            z: top_z(&self.placements, layer),
+                        };
+                        self.placements.insert(id, placement);
+                        placements.push((id, placement));
+                    }
+                    if placements.is_empty() {
+                        return vec![];
+                    }
+                    everyone(ToClient::PlacementsUpdated { placements })
+                }
+                _ => vec![],
+            }
+        }
+    }
+
+    /// Browser of one user, which sees its own changes at once and the server's answers later
+    #[derive(Default)]
+    struct Peer {
+        elements: HashMap<ElementId, Element>,
+        placements: Placements,
+        pending: Pending,
+    }
+
+    impl Peer {
+        /// Takes a message from the server, returns whether changes can be sent now
+        fn receive(&mut self, message: ToClient) -> bool {
+            match message {
+                ToClient::NewElement {
+                    id,
+                    element,
+                    placement,
+                } => {
+                    self.elements.insert(id, element);
+                    self.placements.insert(id, placement);
+                }
+                ToClient::ElementsUpdated { elements } => {
+                    for (id, element) in elements {
+                        merge_update(&mut self.elements, id, element);
+                    }
+                }
+                ToClient::ElementDeleted { id } => {
+                    self.elements.remove(&id);
+                    self.placements.remove(&id);
+                }
+                ToClient::PlacementsUpdated { placements } => self.placements.extend(placements),
+                ToClient::ElementList { board, elements } => {
+                    self.placements = elements.iter().map(|(id, _, p)| (*id, *p)).collect();
+                    self.elements = elements.into_iter().map(|(id, e, _)| (id, e)).collect();
+                    self.pending.reloaded(board);
+                    return true;
+                }
+                ToClient::Ack { seq } | ToClient::Rejected { seq } => self.pending.settle(seq),
+                message => panic!("unexpected {message:?}"),
+            }
+            false
+        }
+
+        fn shown(&self, layers: &Layers) -> (HashMap<ElementId, Element>, Placements) {
+            let mut elements = self.elements.clone();
+            let mut placements = self.placements.clone();
+            self.pending.apply(&mut elements, &mut placements, layers);
+            (elements, placements)
+        }
+    }
+
+    /// Message in flight, delivered at tick `at`
+    struct Packet<T> {
+        at: u64,
+        peer: usize,
+        message: T,
+    }
+
+    /// Peers connected to one server, each through a link that delays messages by its latency
+    /// in ticks in both directions. Links keep the order of messages, as websockets do.
+    /// Messages to offline peers are lost.
+    struct Network {
+        server: Server,
+        peers: Vec<Peer>,
+        latencies: Vec<u64>,
+        online: Vec<bool>,
+        now: u64,
+        up: VecDeque<Packet<ToServer>>,
+        down: Vec<VecDeque<Packet<ToClient>>>,
+    }
+
+    impl Network {
+        fn new(latencies: &[u64], layers: Layers) -> Self {
+            let peer = |author| {
+                let mut pending = Pending::new(author);
+                pending.reloaded(0);
+                Peer {
+                    pending,
+                    ..Peer::default()
+                }
+            };
+            Network {
+                server: Server {
+                    board: 0,
+                    elements: HashMap::new(),
+                    placements: Placements::new(),
+                    layers,
+                    next_id: 0,
+                    answered: HashMap::new(),
+                },
+                peers: (0..latencies.len() as u64).map(peer).collect(),
+                latencies: latencies.to_vec(),
+                online: vec![true; latencies.len()],
+                now: 0,
+                up: VecDeque::new(),
+                down: latencies.iter().map(|_| VecDeque::new()).collect(),
+            }
+        }
+
+        fn send(&mut self, peer: usize, message: ToServer) {
+            self.peers[peer].pending.push(message);
+            if self.online[peer] {
+                self.flush(peer);
+            }
+        }
+
+        fn flush(&mut self, peer: usize) {
+            let at = self.now + self.latencies[peer];
+            for message in self.peers[peer].pending.send_unsent() {
+                // Messages reach the server in the order they arrive, whoever sent them
+                let index = self.up.partition_point(|packet| packet.at <= at);
+                self.up.insert(index, Packet { at, peer, message });
+            }
+        }
+
+        fn deliver(&mut self, Packet { peer, message, .. }: Packet<ToServer>) {
+            for (author_only, answer) in self.server.receive(message) {
+                for to in 0..self.peers.len() {
+                    if self.online[to] && (!author_only || to == peer) {
+                        self.down[to].push_back(Packet {
+                            at: self.now + self.latencies[to],
+                            peer: to,
+                            message: answer.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        fn tick(&mut self) {
+            self.now += 1;
+            while self.up.front().is_some_and(|p| p.at <= self.now) {
+                let packet = self.up.pop_front().unwrap();
+                self.deliver(packet);
+            }
+            let mut loaded = vec![];
+            for (i, (link, peer)) in self.down.iter_mut().zip(&mut self.peers).enumerate() {
+                while link.front().is_some_and(|p| p.at <= self.now) {
+                    if peer.receive(link.pop_front().unwrap().message) {
+                        loaded.push(i);
+                    }
+                }
+            }
+            for peer in loaded {
+                self.flush(peer);
+            }
+        }
+
+        /// Closes the connection of `peer`. What it sent last reaches the server before it
+        /// notices if `delivered`, without answers, and is lost otherwise.
+        fn disconnect(&mut self, peer: usize, delivered: bool) {
+            self.online[peer] = false;
+            self.down[peer].clear();
+            let (sent, others) = self.up.drain(..).partition::<Vec<_>, _>(|p| p.peer == peer);
+            self.up = others.into();
+            if delivered {
+                for packet in sent {
+                    self.deliver(packet);
+                }
+            }
+            self.peers[peer].pending.connection_lost();
+        }
+
+        /// Unloads the board while nobody is connected, the server loads an empty one next
+        fn unload(&mut self) {
+            assert!(self.online.iter().all(|online| !online));
+            self.server = Server {
+                board: self.server.board + 1,
+                elements: HashMap::new(),
+                placements: Placements::new(),
+                layers: vec![],
+                next_id: 0,
+                answered: HashMap::new(),
+            };
+        }
+
+        /// Opens a new connection for `peer`, on which the server first sends the contents.
+        /// Changes are sent once they arrived.
+        fn reconnect(&mut self, peer: usize) {
+            self.online[peer] = true;
+            let elements = self
+                .server
+                .elements
+                .iter()
+                .map(|(id, e)| (*id, e.clone(), self.server.placements[id]))
+                .collect();
+            self.down[peer].push_back(Packet {
+                at: self.now + self.latencies[peer],
+                peer,
+                message: ToClient::ElementList {
+                    board: self.server.board,
+                    elements,
+                },
+            });
+        }
+
+        fn settle(&mut self) {
+            while !self.up.is_empty() || self.down.iter().any(|link| !link.is_empty()) {
+                self.tick();
+            }
+        }
+
+        fn shown(&self, peer: usize) -> (HashMap<ElementId, Element>, Placements) {
+            self.peers[peer].shown(&self.server.layers)
+        }
+
+        /// Every peer shows what the server holds and waits for nothing
+        fn assert_converged(&self) {
+            let server = (self.server.elements.clone(), self.server.placements.clone());
+            for (i, peer) in self.peers.iter().enumerate() {
+                assert!(self.online[i], "peer {i} is offline");
+                assert!(peer.pending.changes.is_empty(), "peer {i} still waits");
+                assert_eq!(self.shown(i), server, "peer {i}");
+            }
+        }
+    }
+
+    fn stroke(x: f32) -> Element {
+        Element::Stroke(Stroke {
+            points: vec![Position { x, y: 0.0 }, Position { x, y: 10.0 }],
+            width: 2.0,
+            color: Color::BLACK,
+            pressure: vec![],
+            style: StrokeStyle::default(),
+        })
+    }
+
+    fn translation(x: f32) -> Transform {
+        Transform {
+            translation: Position { x, y: 0.0 },
+            ..Transform::IDENTITY
+        }
+    }
+
+    #[test]
+    fn own_strokes_show_before_the_server_answers() {
+        let mut network = Network::new(&[10], vec![]);
+        network.send(
+            0,
+            ToServer::CreateElement {
+                element: stroke(1.0),
+                layer: None,
+            },
+        );
+        let (elements, _) = network.shown(0);
+        assert_eq!(elements.values().collect::<Vec<_>>(), [&stroke(1.0)]);
+        assert!(elements.keys().all(|id| is_provisional(*id)));
+        for _ in 0..19 {
+            network.tick();
+            assert_eq!(network.shown(0).0.len(), 1, "tick {}", network.now);
+        }
+        network.settle();
+        assert_eq!(network.shown(0).0.keys().collect::<Vec<_>>(), [&0]);
+        network.assert_converged();
+    }
+
+    #[test]
+    fn rejected_changes_are_rolled_back() {
+        let locked = Layer {
+            name: "Locked".to_owned(),
+            hidden: false,
+            locked: true,
+        };
+        let mut network = Network::new(&[5], vec![(0, locked)]);
+        network.send(
+            0,
+            ToServer::CreateElement {
+                element: stroke(1.0),
+                layer: Some(0),
+            },
+        );
+        assert_eq!(network.shown(0).0.len(), 1);
+        network.settle();
+        assert!(network.shown(0).0.is_empty());
+        network.assert_converged();
+    }
+
+    #[test]
+    fn transforms_show_once_while_others_draw() {
+        let mut network = Network::new(&[4, 9], vec![]);
+        network.send(
+            1,
+            ToServer::CreateElement {
+                element: stroke(0.0),
+                layer: None,
+            },
+        );
+        network.settle();
+        network.send(
+            0,
+            ToServer::Transform {
+                ids: vec![0],
+                transform: translation(5.0),
+            },
+        );
+        network.send(
+            1,
+            ToServer::Transform {
+                ids: vec![0],
+                transform: translation(1.0),
+            },
+        );
+        network.send(
+            1,
+            ToServer::CreateElement {
+                element: stroke(3.0),
+                layer: None,
+            },
+        );
+        let mut expected = stroke(0.0);
+        expected.transform(&translation(5.0));
+        assert_eq!(network.shown(0).0[&0], expected);
+        // Each client sees its own change at once, and the other's on top once it arrives
+        while !network.peers[0].pending.changes.is_empty() {
+            network.tick();
+            let (elements, _) = network.shown(0);
+            let Element::Stroke(moved) = &elements[&0] else {
+                unreachable!()
+            };
+            assert!([5.0, 6.0].contains(&moved.points[0].x), "{moved:?}");
+        }
+        network.settle();
+        network.assert_converged();
+        let Element::Stroke(moved) = &network.shown(1).0[&0] else {
+            unreachable!()
+        };
+        assert_eq!(moved.points[0].x, 6.0);
+    }
+
+    #[test]
+    fn clients_converge_under_latency() {
+        let mut network = Network::new(&[1, 3, 7, 20], vec![]);
+        for round in 0..30_u64 {
+            for peer in 0..4 {
+                let existing = network
+                    .shown(peer)
+                    .0
+                    .keys()
+                    .copied()
+                    .filter(|id| !is_provisional(*id))
+                    .min();
+                let message = match (round + peer as u64) % 4 {
+                    0 => ToServer::CreateElement {
+                        element: stroke(round as f32),
+                        layer: None,
+                    },
+                    1 => match existing {
+                        Some(id) => ToServer::Transform {
+                            ids: vec![id],
+                            transform: translation(peer as f32),
+                        },
+                        None => continue,
+                    },
+                    2 => match existing {
+                        Some(id) => ToServer::Reorder {
+                            ids: vec![id],
+                            to: ZOrder::Front,
+                        },
+                        None => continue,
+                    },
+                    _ => match existing {
+                        // Several clients may delete the same element, all but the first fail
+                        Some(id) if round % 3 == 0 => ToServer::DeleteElement { id },
+                        _ => continue,
+                    },
+                };
+                network.send(peer, message);
+            }
+            network.tick();
+        }
+        network.settle();
+        network.assert_converged();
+        assert!(!network.server.elements.is_empty());
+    }
+
+    #[test]
+    fn changes_made_offline_are_sent_on_reconnect() {
+        let mut network = Network::new(&[3, 5], vec![]);
+        let draw = |network: &mut Network, peer, x| {
+            network.send(
+                peer,
+                ToServer::CreateElement {
+                    element: stroke(x),
+                    layer: None,
+                },
+            )
+        };
+        draw(&mut network, 0, 1.0);
+        network.tick();
+        network.disconnect(0, true);
+        draw(&mut network, 0, 2.0);
+        draw(&mut network, 0, 3.0);
+        draw(&mut network, 1, 9.0);
+        network.settle();
+        assert_eq!(network.shown(0).0.len(), 3);
+        assert_eq!(network.server.elements.len(), 2);
+
+        network.reconnect(0);
+        network.settle();
+        network.assert_converged();
+        // The change that arrived before the connection closed is not applied twice
+        let mut xs = network
+            .server
+            .elements
+            .values()
+            .map(|e| match e {
+                Element::Stroke(stroke) => stroke.points[0].x,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        xs.sort_by(f32::total_cmp);
+        assert_eq!(xs, [1.0, 2.0, 3.0, 9.0]);
+    }
+
+    #[test]
+    fn changes_lost_with_the_connection_are_sent_again() {
+        let mut network = Network::new(&[3], vec![]);
+        network.send(
+            0,
+            ToServer::CreateElement {
+                element: stroke(1.0),
+                layer: None,
+            },
+        );
+        network.disconnect(0, false);
+        assert_eq!(network.shown(0).0.len(), 1);
+        network.reconnect(0);
+        network.settle();
+        network.assert_converged();
+        assert_eq!(network.server.elements.len(), 1);
+    }
+
+    #[test]
+    fn changes_resent_are_not_shown_twice() {
+        let mut network = Network::new(&[3], vec![]);
+        network.send(
+            0,
+            ToServer::CreateElement {
+                element: stroke(1.0),
+                layer: None,
+            },
+        );
+        network.settle();
+        network.send(
+            0,
+            ToServer::Transform {
+                ids: vec![0],
+                transform: translation(5.0),
+            },
+        );
+        network.disconnect(0, true);
+        network.reconnect(0);
+        while !network.peers[0].pending.changes.is_empty() {
+            network.tick();
+            let Element::Stroke(moved) = &network.shown(0).0[&0] else {
+                unreachable!()
+            };
+            assert_eq!(moved.points[0].x, 6.0, "tick {}", network.now);
+        }
+        network.assert_converged();
+    }
+
+    #[test]
+    fn changes_to_elements_of_an_unloaded_board_are_dropped() {
+        let mut network = Network::new(&[3], vec![]);
+        network.send(
+            0,
+            ToServer::CreateElement {
+                element: stroke(1.0),
+                layer: None,
+            },
+        );
+        network.settle();
+        network.disconnect(0, true);
+        network.send(
+            0,
+            ToServer::Transform {
+                ids: vec![0],
+                transform: translation(5.0),
+            },
+        );
+        network.send(
+            0,
+            ToServer::CreateElement {
+                element: stroke(2.0),
+                layer: Some(4),
+            },
+        );
+        network.unload();
+        network.send(0, ToServer::DeleteElement { id: 0 });
+        network.reconnect(0);
+        network.settle();
+        network.assert_converged();
+        assert_eq!(
+            network.server.elements.values().collect::<Vec<_>>(),
+            [&stroke(2.0)]
+        );
+        assert_eq!(network.server.placements[&0].layer, None);
+    }
+
+    #[test]
+    fn stored_changes_wait_for_the_next_connection() {
+        let mut pending = Pending::new(7);
+        pending.reloaded(0);
+        pending.push(ToServer::DeleteElement { id: 1 });
+        pending.send_unsent();
+        pending.push(ToServer::DeleteElement { id: 2 });
+        let json = serde_json::to_string(&pending).unwrap();
+        let mut pending = serde_json::from_str::<Pending>(&json).unwrap();
+        pending.connection_lost();
+        assert!(pending.send_unsent().is_empty());
+        assert_eq!(pending.reloaded(0), 0);
+        let sent = pending.send_unsent();
+        let seqs = sent
+            .iter()
+            .map(|message| match message {
+                ToServer::Tagged { author: 7, seq, .. } => *seq,
+                message => panic!("{message:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(seqs, [0, 1]);
+        assert_eq!(pending.waiting(), 2);
+        pending.connection_lost();
+        assert_eq!(pending.reloaded(1), 2);
+        assert_eq!(pending.waiting(), 0);
+    }
+}
@@ -0,0 +1,792 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    ops::Range,
+};
+
+use common::{
+    entities::{Element, ElementId, Image, Stroke},
+    line_drawing::display_strip,
+};
+use itertools::Itertools;
+use web_sys::{
+    js_sys, wasm_bindgen::JsCast, HtmlCanvasElement, WebGl2RenderingContext, WebGlBuffer,
+    WebGlContextAttributes, WebGlProgram, WebGlShader, WebGlTexture, WebGlVertexArrayObject,
+};
+
+use crate::{
+    camera::Camera,
+    lod::{self, LodCache},
+};
+
+/// Frames averaged in the reported frame time
+const FRAME_TIME_WINDOW: usize = 60;
+
+/// Position and RGBA colour
+const STROKE_ATTRIBUTES: [(&str, i32); 2] = [("position", 2), ("color", 4)];
+/// Position and texture coordinates
+const IMAGE_ATTRIBUTES: [(&str, i32); 2] = [("position", 2), ("uv", 2)];
+
+/// Floats of one vertex with `attributes`
+const fn vertex_size(attributes: &[(&str, i32)]) -> usize {
+    let mut size = 0;
+    let mut i = 0;
+    while i < attributes.len() {
+        size += attributes[i].1 as usize;
+        i += 1;
+    }
+    size
+}
+
+const STROKE_VERTEX: usize = vertex_size(&STROKE_ATTRIBUTES);
+const IMAGE_VERTEX: usize = vertex_size(&IMAGE_ATTRIBUTES);
+
+/// Measurements of the last frame, shown in development builds
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameStats {
+    /// CPU time spent preparing and submitting the frame in milliseconds, averaged over the last
+    /// frames
+    pub time: f64,
+    pub draw_calls: usize,
+    /// Strokes tessellated because they are new, changed or seen at a new level of detail
+    pub tessellated: usize,
+    /// Floats copied to the GPU
+    pub uploaded: usize,
+}
+
+/// Vertex data of many elements packed into one array, mirrored into a GPU buffer. An element
+/// whose vertex count stays the same is overwritten in place, others move to the end of the array
+/// and leave a hole, which is reclaimed by compacting once holes fill half of the array.
+struct Packed<K> {
+    data: Vec<f32>,
+    ranges: HashMap<K, Range<usize>>,
+    /// Floats in holes left by removed or moved elements
+    unused: usize,
+    /// Part of `data` changed since the last upload
+    dirty: Option<Range<usize>>,
+}
+
+impl<K> Default for Packed<K> {
+    fn default() -> Self {
+        Packed {
+            data: vec![],
+            ranges: HashMap::new(),
+            unused: 0,
+            dirty: None,
+        }
+    }
+}
+
+impl<K: Copy + Eq + Hash> Packed<K> {
+    fn insert(&mut self, key: K, vertices: &[f32]) {
+        let same_size = self
+            .ranges
+            .get(&key)
+            .filter(|range| range.len() == vertices.len())
+            .cloned();
+        let range = match same_size {
+            Some(range) => {
+                self.data[range.clone()].copy_from_slice(vertices);
+                range
+            }
+            None => {
+                self.remove(key);
+                let range = self.data.len()..self.data.len() + vertices.len();
+                self.data.extend_from_slice(vertices);
+                self.ranges.insert(key, range.clone());
+                range
+            }
+        };
+        self.mark_dirty(range);
+    }
+
+    fn remove(&mut self, key: K) {
+        if let Some(range) = self.ranges.remove(&key) {
+            self.unused += range.len();
+        }
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&K) -> bool) {
+        let removed = self
+            .ranges
+            .keys()
+            .filter(|key| !keep(key))
+            .copied()
+            .collect_vec();
+        for key in removed {
+            self.remove(key);
+        }
+    }
+
+    fn range(&self, key: &K) -> Option<Range<usize>> {
+        self.ranges.get(key).cloned()
+    }
+
+    /// Closes the holes once they waste half of the array, keeping the elements in order so that
+    /// neighbours stay neighbours
+    fn compact(&mut self) {
+        if self.unused * 2 <= self.data.len() {
+            return;
+        }
+        let mut data = Vec::with_capacity(self.data.len() - self.unused);
+        for range in self.ranges.values_mut().sorted_by_key(|range| range.start) {
+            let start = data.len();
+            data.extend_from_slice(&self.data[range.clone()]);
+            *range = start..data.len();
+        }
+        self.data = data;
+        self.unused = 0;
+        self.dirty = Some(0..self.data.len());
+    }
+
+    fn mark_dirty(&mut self, range: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(range.start)..dirty.end.max(range.end),
+            None => range,
+        });
+    }
+}
+
+/// GPU buffer and vertex array for the vertex layout of a program
+struct VertexBuffer {
+    buffer: WebGlBuffer,
+    vao: WebGlVertexArrayObject,
+    /// Size of the GPU buffer in floats
+    capacity: usize,
+}
+
+impl VertexBuffer {
+    fn new(
+        context: &WebGl2RenderingContext,
+        program: &WebGlProgram,
+        attributes: &[(&str, i32)],
+    ) -> Result<VertexBuffer, String> {
+        let buffer = context.create_buffer().ok_or("Unable to create buffer")?;
+        let vao = context
+            .create_vertex_array()
+            .ok_or("Unable to create vertex array")?;
+        context.bind_vertex_array(Some(&vao));
+        context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
+        let stride = vertex_size(attributes) as i32 * 4;
+        let mut offset = 0;
+        for (name, size) in attributes {
+            let location = context.get_attrib_location(program, name) as u32;
+            context.vertex_attrib_pointer_with_i32(
+                location,
+                *size,
+                WebGl2RenderingContext::FLOAT,
+                false,
+                stride,
+                offset,
+            );
+            context.enable_vertex_attrib_array(location);
+            offset += size * 4;
+        }
+        context.bind_vertex_array(None);
+        Ok(VertexBuffer {
+            buffer,
+            vao,
+            capacity: 0,
+        })
+    }
+
+    /// Copies the `dirty` part of `data` to the GPU, or all of it when the buffer has to grow.
+    /// Returns the number of floats copied.
+    fn upload(
+        &mut self,
+        context: &WebGl2RenderingContext,
+        data: &[f32],
+        mut dirty: Range<usize>,
+    ) -> usize {
+        context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.buffer));
+        if data.len() > self.capacity {
+            self.capacity = data.len().next_power_of_two();
+            context.buffer_data_with_i32(
+                WebGl2RenderingContext::ARRAY_BUFFER,
+                self.capacity as i32 * 4,
+                WebGl2RenderingContext::DYNAMIC_DRAW,
+            );
+            dirty = 0..data.len();
+        }
+        if dirty.is_empty() {
+            return 0;
+        }
+        unsafe {
+            let view = js_sys::Float32Array::view(&data[dirty.clone()]);
+            context.buffer_sub_data_with_i32_and_array_buffer_view(
+                WebGl2RenderingContext::ARRAY_BUFFER,
+                dirty.start as i32 * 4,
+                &view,
+            );
+        }
+        dirty.len()
+    }
+
+    fn upload_packed<K: Copy + Eq + Hash>(
+        &mut self,
+        context: &WebGl2RenderingContext,
+        packed: &mut Packed<K>,
+    ) -> usize {
+        match packed.dirty.take() {
+            Some(dirty) => self.upload(context, &packed.data, dirty),
+            None => 0,
+        }
+    }
+}
+
+/// One draw call, ranges count vertices
+#[derive(Debug, PartialEq)]
+enum Draw<'a> {
+    /// Strokes following each other in the stroke buffer
+    Strokes {
+        vertices: Range<usize>,
+        translucent: bool,
+    },
+    Image {
+        vertices: Range<usize>,
+        image: &'a Image,
+    },
+}
+
+/// Adds a stroke to the draw calls, joining it with the previous call when both are opaque and
+/// their vertices are adjacent. Translucent strokes are drawn one by one to keep their stencil.
+fn push_stroke(draws: &mut Vec<Draw>, vertices: Range<usize>, translucent: bool) {
+    if let (
+        false,
+        Some(Draw::Strokes {
+            vertices: last,
+            translucent: false,
+        }),
+    ) = (translucent, draws.last_mut())
+    {
+        if last.end == vertices.start {
+            last.end = vertices.end;
+            return;
+        }
+    }
+    draws.push(Draw::Strokes {
+        vertices,
+        translucent,
+    });
+}
+
+/// Interleaved vertices of the triangle strip of `stroke`. The first and the last vertex are
+/// repeated so that strips next to each other in a buffer can be drawn as one, the triangles
+/// between them have no area. The detail is chosen for display at `zoom`.
+fn stroke_vertices(stroke: &Stroke, zoom: f32) -> Vec<f32> {
+    if stroke.points.is_empty() {
+        return vec![];
+    }
+    let strip = display_strip(stroke, zoom);
+    let (Some(first), Some(last)) = (strip.first(), strip.last()) else {
+        return vec![];
+    };
+    let color = stroke.color;
+    let color = [color.r, color.g, color.b, color.a].map(|c| c as f32 / 255.0);
+    [*first]
+        .iter()
+        .chain(&strip)
+        .chain([last])
+        .flat_map(|p| [[p.x as f32, p.y as f32].as_slice(), &color].concat())
+        .collect()
+}
+
+/// Interleaved vertices of the quad of `image`, as a triangle strip
+fn image_vertices(image: &Image) -> Vec<f32> {
+    let width = image.width * image.scale;
+    let height = image.height * image.scale;
+    let (sin, cos) = image.rotation.sin_cos();
+    [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)]
+        .into_iter()
+        .flat_map(|(u, v)| {
+            let (x, y) = (u * width, v * height);
+            [
+                image.position.x + cos * x - sin * y,
+                image.position.y + sin * x + cos * y,
+                u,
+                v,
+            ]
+        })
+        .collect()
+}
+
+/// Gives every stroke its own stencil value and only lets it draw where the stencil buffer does
+/// not hold it yet. Each pixel is then blended once per stroke, so translucent strokes do not get
+/// darker where the triangles of their strip overlap.
+struct StrokeStencil {
+    next: i32,
+}
+
+impl Default for StrokeStencil {
+    fn default() -> Self {
+        StrokeStencil { next: 1 }
+    }
+}
+
+impl StrokeStencil {
+    /// Largest value of an 8 bit stencil buffer
+    const MAX: i32 = 0xff;
+
+    fn next(&mut self, context: &WebGl2RenderingContext) {
+        if self.next > Self::MAX {
+            context.clear(WebGl2RenderingContext::STENCIL_BUFFER_BIT);
+            self.next = 1;
+        }
+        context.stencil_func(
+            WebGl2RenderingContext::NOTEQUAL,
+            self.next,
+            Self::MAX as u32,
+        );
+        self.next += 1;
+    }
+
+    /// Draws without testing, for geometry that never overlaps itself in a visible way
+    fn disable(context: &WebGl2RenderingContext) {
+        context.stencil_func(WebGl2RenderingContext::ALWAYS, 0, Self::MAX as u32);
+    }
+}
+
+/// Draws the elements of a board with WebGL. Tessellated strokes and image quads are kept in
+/// large buffers between frames and only changed elements are tessellated and uploaded again.
+pub struct Renderer {
+    context: WebGl2RenderingContext,
+    stroke_program: WebGlProgram,
+    image_program: WebGlProgram,
+    strokes: Packed<ElementId>,
+    stroke_buffer: VertexBuffer,
+    lods: LodCache<ElementId, Vec<f32>>,
+    /// Level of detail of each stroke in `strokes`
+    buffered: HashMap<ElementId, i32>,
+    images: Packed<ElementId>,
+    image_buffer: VertexBuffer,
+    placed: HashMap<ElementId, Image>,
+    /// Stroke being drawn, uploaded again every frame
+    drawing_buffer: VertexBuffer,
+    frame_times: VecDeque<f64>,
+}
+
+impl Renderer {
+    pub fn new(canvas: &HtmlCanvasElement) -> Result<Renderer, String> {
+        let context = canvas
+            .get_context_with_context_options("webgl2", WebGlContextAttributes::new().stencil(true))
+            .ok()
+            .flatten()
+            .ok_or("WebGL2 is not available")?
+            .dyn_into::<WebGl2RenderingContext>()
+            .map_err(|_| "Unexpected context type")?;
+        let stroke_program = create_program(&context, VERTEX_SHADER, FRAGMENT_SHADER)?;
+        let image_program = create_program(&context, IMAGE_VERTEX_SHADER, IMAGE_FRAGMENT_SHADER)?;
+        Ok(Renderer {
+            stroke_buffer: VertexBuffer::new(&context, &stroke_program, &STROKE_ATTRIBUTES)?,
+            drawing_buffer: VertexBuffer::new(&context, &stroke_program, &STROKE_ATTRIBUTES)?,
+            image_buffer: VertexBuffer::new(&context, &image_program, &IMAGE_ATTRIBUTES)?,
+            context,
+            stroke_program,
+            image_program,
+            strokes: Packed::default(),
+            lods: LodCache::default(),
+            buffered: HashMap::new(),
+            images: Packed::default(),
+            placed: HashMap::new(),
+            frame_times: VecDeque::new(),
+        })
+    }
+
+    pub fn context(&self) -> &WebGl2RenderingContext {
+        &self.context
+    }
+
+    /// Draws `elements` from bottom to top with `drawing` on top. Geometry of elements not drawn
+    /// is kept for later frames as long as they `exist`. `texture` returns the texture of an
+    /// image, or `None` while it is loading.
+    pub fn render(
+        &mut self,
+        canvas: &HtmlCanvasElement,
+        camera: &Camera,
+        elements: &[(ElementId, &Element)],
+        exists: impl Fn(&ElementId) -> bool,
+        drawing: Option<&Stroke>,
+        mut texture: impl FnMut(&Image) -> Option<WebGlTexture>,
+    ) -> FrameStats {
+        let start = now();
+        let mut stats = FrameStats::default();
+        self.update_geometry(elements, exists, camera.zoom, &mut stats);
+
+        let context = &self.context;
+        context.viewport(0, 0, canvas.width() as i32, canvas.height() as i32);
+        context.clear_color(0.7, 0.7, 0.7, 1.0);
+        context.clear_stencil(0);
+        context.clear(
+            WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::STENCIL_BUFFER_BIT,
+        );
+        context.enable(WebGl2RenderingContext::BLEND);
+        // Keep the canvas itself opaque, the page would show through translucent pixels
+        context.blend_func_separate(
+            WebGl2RenderingContext::SRC_ALPHA,
+            WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+            WebGl2RenderingContext::ONE,
+            WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+        );
+        context.enable(WebGl2RenderingContext::STENCIL_TEST);
+        context.stencil_op(
+            WebGl2RenderingContext::KEEP,
+            WebGl2RenderingContext::KEEP,
+            WebGl2RenderingContext::REPLACE,
+        );
+        for program in [&self.image_program, &self.stroke_program] {
+            context.use_program(Some(program));
+            set_camera_uniforms(context, program, camera, canvas);
+        }
+
+        let mut stencil = StrokeStencil::default();
+        for draw in self.draws(elements) {
+            stats.draw_calls += 1;
+            match draw {
+                Draw::Strokes {
+                    vertices,
+                    translucent,
+                } => {
+                    context.use_program(Some(&self.stroke_program));
+                    context.bind_vertex_array(Some(&self.stroke_buffer.vao));
+                    if translucent {
+                        stencil.next(context);
+                    } else {
+                        StrokeStencil::disable(context);
+                    }
+                    context.draw_arrays(
+                        WebGl2RenderingContext::TRIANGLE_STRIP,
+                        vertices.start as i32,
+                        vertices.len() as i32,
+                    );
+                }
+                Draw::Image { vertices, image } => {
+                    let Some(texture) = texture(image) else {
+                        stats.draw_calls -= 1;
+                        continue;
+                    };
+                    context.use_program(Some(&self.image_program));
+                    context.bind_vertex_array(Some(&self.image_buffer.vao));
+                    StrokeStencil::disable(context);
+                    context.active_texture(WebGl2RenderingContext::TEXTURE0);
+                    context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+                    context.uniform1i(
+                        context
+                            .get_uniform_location(&self.image_program, "image")
+                            .as_ref(),
+                        0,
+                    );
+                    context.draw_arrays(
+                        WebGl2RenderingContext::TRIANGLE_STRIP,
+                        vertices.start as i32,
+                        vertices.len() as i32,
+                    );
+                }
+            }
+        }
+
+        if let Some(stroke) = drawing {
+            let vertices = stroke_vertices(stroke, lod::level_zoom(lod::level(camera.zoom)));
+            stats.uploaded += self
+                .drawing_buffer
+                .upload(context, &vertices, 0..vertices.len());
+            context.use_program(Some(&self.stroke_program));
+            context.bind_vertex_array(Some(&self.drawing_buffer.vao));
+            stencil.next(context);
+            context.draw_arrays(
+                WebGl2RenderingContext::TRIANGLE_STRIP,
+                0,
+                (vertices.len() / STROKE_VERTEX) as i32,
+            );
+            stats.draw_calls += 1;
+        }
+        context.bind_vertex_array(None);
+
+        if self.frame_times.len() == FRAME_TIME_WINDOW {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(now() - start);
+        stats.time = self.frame_times.iter().sum::<f64>() / self.frame_times.len() as f64;
+        stats
+    }
+
+    /// Tessellates new and changed elements, drops removed ones and uploads the changes
+    fn update_geometry(
+        &mut self,
+        elements: &[(ElementId, &Element)],
+        exists: impl Fn(&ElementId) -> bool,
+        zoom: f32,
+        stats: &mut FrameStats,
+    ) {
+        let level = lod::level(zoom);
+        for (id, element) in elements {
+            match element {
+                Element::Stroke(stroke) => {
+                    let (vertices, made) = self.lods.get(*id, stroke, level, || {
+                        stroke_vertices(stroke, lod::level_zoom(level))
+                    });
+                    if made {
+                        stats.tessellated += 1;
+                    }
+                    if made || self.buffered.get(id) != Some(&level) {
+                        self.strokes.insert(*id, vertices);
+                        self.buffered.insert(*id, level);
+                    }
+                }
+                Element::Image(image) if self.placed.get(id) != Some(image) => {
+                    self.images.insert(*id, &image_vertices(image));
+                    self.placed.insert(*id, image.clone());
+                }
+                _ => (),
+            }
+        }
+        self.strokes.retain(&exists);
+        self.lods.retain(&exists);
+        self.buffered.retain(|id, _| exists(id));
+        self.images.retain(&exists);
+        self.placed.retain(|id, _| exists(id));
+        self.strokes.compact();
+        self.images.compact();
+        stats.uploaded += self
+            .stroke_buffer
+            .upload_packed(&self.context, &mut self.strokes);
+        stats.uploaded += self
+            .image_buffer
+            .upload_packed(&self.context, &mut self.images);
+    }
+
+    fn draws<'a>(&self, elements: &[(ElementId, &'a Element)]) -> Vec<Draw<'a>> {
+        let mut draws = vec![];
+        for (id, element) in elements {
+            match element {
+                Element::Stroke(stroke) => {
+                    if let Some(range) = self.strokes.range(id).filter(|r| !r.is_empty()) {
+                        let vertices = range.start / STROKE_VERTEX..range.end / STROKE_VERTEX;
+                        push_stroke(&mut draws, vertices, stroke.color.a < u8::MAX);
+                    }
+                }
+                Element::Image(image) => {
+                    if let Some(range) = self.images.range(id) {
+                        draws.push(Draw::Image {
+                            vertices: range.start / IMAGE_VERTEX..range.end / IMAGE_VERTEX,
+                            image,
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+        draws
+    }
+}
+
+fn now() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or(0.0, |performance| performance.now())
+}
+
+/// Passes the camera transform to the vertex shader, `resolution` is the displayed size in CSS pixels
+fn set_camera_uniforms(
+    context: &WebGl2RenderingContext,
+    program: &WebGlProgram,
+    camera: &Camera,
+    canvas: &HtmlCanvasElement,
+) {
+    let resolution = context.get_uniform_location(program, "resolution");
+    let offset = context.get_uniform_location(program, "offset");
+    let zoom = context.get_uniform_location(program, "zoom");
+    context.uniform2f(
+        resolution.as_ref(),
+        canvas.client_width() as f32,
+        canvas.client_height() as f32,
+    );
+    context.uniform2f(offset.as_ref(), camera.offset.x, camera.offset.y);
+    context.uniform1f(zoom.as_ref(), camera.zoom);
+}
+
+const VERTEX_SHADER: &str = include_str!("shaders/vertex_shader.glsl");
+const FRAGMENT_SHADER: &str = include_str!("shaders/fragment_shader.glsl");
+const IMAGE_VERTEX_SHADER: &str = include_str!("shaders/image_vertex_shader.glsl");
+const IMAGE_FRAGMENT_SHADER: &str = include_str!("shaders/image_fragment_shader.glsl");
+
+fn create_program(
+    context: &WebGl2RenderingContext,
+    vertex_source: &str,
+    fragment_source: &str,
+) -> Result<WebGlProgram, String> {
+    let vertex_shader = compile_shader(
+        context,
+        WebGl2RenderingContext::VERTEX_SHADER,
+        vertex_source,
+    )?;
+    let fragment_shader = compile_shader(
+        context,
+        WebGl2RenderingContext::FRAGMENT_SHADER,
+        fragment_source,
+    )?;
+    link_program(context, &vertex_shader, &fragment_shader)
+}
+
+fn compile_shader(
+    context: &WebGl2RenderingContext,
+    shader_type: u32,
+    source: &str,
+) -> Result<WebGlShader, String> {
+    let shader = context
+        .create_shader(shader_type)
+        .ok_or("Unable to create shader object")?;
+    context.shader_source(&shader, source);
+    context.compile_shader(&shader);
+    let compiled = context
+        .get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS)
+        .as_bool()
+        .unwrap_or(false);
+    if compiled {
+        Ok(shader)
+    } else {
+        let error = context
+            .get_shader_info_log(&shader)
+            .unwrap_or("Unknown error when compiling shader".to_owned());
+        Err(error)
+    }
+}
+
+fn link_program(
+    context: &WebGl2RenderingContext,
+    vertex_shader: &WebGlShader,
+    fragment_shader: &WebGlShader,
+) -> Result<WebGlProgram, String> {
+    let program = context
+        .create_program()
+        .ok_or("Unable to create shader object")?;
+    context.attach_shader(&program, vertex_shader);
+    context.attach_shader(&program, fragment_shader);
+    context.link_program(&program);
+    let linked = context
+        .get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
+        .as_bool()
+        .unwrap_or(false);
+    if linked {
+        Ok(program)
+    } else {
+        let error = context
+            .get_program_info_log(&program)
+            .unwrap_or("Unknown error when linking program".to_owned());
+        Err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::entities::{Color, Position};
+
+    use super::*;
+
+    fn packed(entries: &[(u64, &[f32])]) -> Packed<u64> {
+        let mut packed = Packed::default();
+        for (key, data) in entries {
+            packed.insert(*key, data);
+        }
+        packed
+    }
+
+    #[test]
+    fn same_size_updates_in_place() {
+        let mut packed = packed(&[(1, &[1.0, 2.0]), (2, &[3.0, 4.0])]);
+        packed.dirty = None;
+        packed.insert(1, &[5.0, 6.0]);
+        assert_eq!(packed.data, vec![5.0, 6.0, 3.0, 4.0]);
+        assert_eq!(packed.dirty, Some(0..2));
+        assert_eq!(packed.unused, 0);
+    }
+
+    #[test]
+    fn resized_elements_move_to_the_end() {
+        let mut packed = packed(&[(1, &[1.0, 2.0]), (2, &[3.0, 4.0])]);
+        packed.dirty = None;
+        packed.insert(1, &[5.0, 6.0, 7.0]);
+        assert_eq!(packed.range(&1), Some(4..7));
+        assert_eq!(packed.dirty, Some(4..7));
+        assert_eq!(packed.unused, 2);
+    }
+
+    #[test]
+    fn compacting_keeps_order() {
+        let mut packed = packed(&[
+            (1, &[1.0]),
+            (2, &[2.0]),
+            (3, &[3.0]),
+            (4, &[4.0]),
+            (5, &[5.0]),
+        ]);
+        packed.retain(|key| *key == 2 || *key == 4);
+        packed.compact();
+        assert_eq!(packed.data, vec![2.0, 4.0]);
+        assert_eq!(packed.range(&2), Some(0..1));
+        assert_eq!(packed.range(&4), Some(1..2));
+        assert_eq!(packed.dirty, Some(0..2));
+    }
+
+    #[test]
+    fn small_holes_are_kept() {
+        let mut packed = packed(&[(1, &[1.0]), (2, &[2.0]), (3, &[3.0])]);
+        packed.remove(2);
+        packed.compact();
+        assert_eq!(packed.data.len(), 3);
+        assert_eq!(packed.range(&3), Some(2..3));
+    }
+
+    #[test]
+    fn adjacent_opaque_strokes_share_a_draw() {
+        let mut draws = vec![];
+        push_stroke(&mut draws, 0..4, false);
+        push_stroke(&mut draws, 4..10, false);
+        push_stroke(&mut draws, 20..24, false);
+        push_stroke(&mut draws, 24..30, true);
+        push_stroke(&mut draws, 30..34, true);
+        push_stroke(&mut draws, 34..40, false);
+        let ranges = draws
+            .iter()
+            .map(|draw| match draw {
+                Draw::Strokes {
+                    vertices,
+                    translucent,
+                } => (vertices.clone(), *translucent),
+                Draw::Image { .. } => unreachable!(),
+            })
+            .collect_vec();
+        assert_eq!(
+            ranges,
+            vec![
+                (0..10, false),
+                (20..24, false),
+                (24..30, true),
+                (30..34, true),
+                (34..40, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn stroke_vertices_are_padded() {
+        let stroke = Stroke {
+            points: vec![Position { x: 0.0, y: 0.0 }, Position { x: 10.0, y: 0.0 }],
+            width: 2.0,
+            color: Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            },
+            pressure: vec![],
+            style: Default::default(),
+        };
+        let vertices = stroke_vertices(&stroke, 1.0);
+        assert_eq!(vertices.len() % STROKE_VERTEX, 0);
+        let vertex = |i: usize| &vertices[i * STROKE_VERTEX..(i + 1) * STROKE_VERTEX];
+        let count = vertices.len() / STROKE_VERTEX;
+        assert_eq!(vertex(0), vertex(1));
+        assert_eq!(vertex(count - 1), vertex(count - 2));
+        assert_eq!(&vertex(0)[2..], &[1.0, 0.0, 0.0, 1.0]);
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::{HashMap, HashSet};
+
+use common::{
+    entities::{Element, ElementId, Position, Transform},
+    line_drawing::{strip_contains, stroke_into_triangle_strip},
+    spatial::Rect,
+    websocket::ToServer,
+};
+use leptos::*;
+use nalgebra::Point2;
+
+use crate::{
+    camera::Camera,
+    spatial::ElementIndex,
+    Client,
+};
+
+/// Smallest factor a selection can be scaled by in a single drag
+const MIN_SCALE: f32 = 0.05;
+/// Distance of the rotation handle above the selection box, in screen pixels
+const ROTATE_HANDLE_OFFSET: f32 = 30.0;
+/// Accuracy of the stroke outlines used for hit-testing, in board units
+const HIT_TOLERANCE: f64 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Drag {
+    Move {
+        start: Position,
+        current: Position,
+    },
+    Scale {
+        pivot: Position,
+        start: Position,
+        current: Position,
+    },
+    Rotate {
+        pivot: Position,
+        start: Position,
+        current: Position,
+    },
+    Marquee {
+        start: Position,
+        current: Position,
+    },
+}
+
+impl Drag {
+    fn update(&mut self, position: Position) {
+        match self {
+            Drag::Move { current, .. }
+            | Drag::Scale { current, .. }
+            | Drag::Rotate { current, .. }
+            | Drag::Marquee { current, .. } => *current = position,
+        }
+    }
+
+    /// Transform of the selected elements described by the drag so far
+    fn transform(&self) -> Option<Transform> {
+        match *self {
+            Drag::Move { start, current } => Some(Transform {
+                translation: Position {
+                    x: current.x - start.x,
+                    y: current.y - start.y,
+                },
+                ..Transform::IDENTITY
+            }),
+            Drag::Scale {
+                pivot,
+                start,
+                current,
+            } => {
+                let (sx, sy) = (start.x - pivot.x, start.y - pivot.y);
+                let (cx, cy) = (current.x - pivot.x, current.y - pivot.y);
+                let length = sx * sx + sy * sy;
+                if length == 0.0 {
+                    return None;
+                }
+                Some(Transform {
+                    pivot,
+                    scale: ((sx * cx + sy * cy) / length).max(MIN_SCALE),
+                    ..Transform::IDENTITY
+                })
+            }
+            Drag::Rotate {
+                pivot,
+                start,
+                current,
+            } => {
+                let angle = |p: Position| (p.y - pivot.y).atan2(p.x - pivot.x);
+                Some(Transform {
+                    pivot,
+                    rotation: angle(current) - angle(start),
+                    ..Transform::IDENTITY
+                })
+            }
+            Drag::Marquee { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selection {
+    pub ids: HashSet<ElementId>,
+    pub drag: Option<Drag>,
+}
+
+impl Selection {
+    /// Transform to apply to selected elements while they are being dragged
+    pub fn preview(&self) -> Option<Transform> {
+        self.drag.and_then(|drag| drag.transform())
+    }
+}
+
+/// Rotated rectangle covering an element, as its top left corner, size and rotation
+fn rectangle(element: &Element) -> Option<(Position, f32, f32, f32)> {
+    match element {
+        Element::Text(text) => {
+            let (width, height) = text.size();
+            Some((text.position, width, height, text.rotation))
+        }
+        Element::Sticky(sticky) => Some((
+            sticky.position,
+            sticky.width,
+            sticky.height,
+            sticky.rotation,
+        )),
+        Element::Image(image) => Some((
+            image.position,
+            image.width * image.scale,
+            image.height * image.scale,
+            image.rotation,
+        )),
+        Element::Stroke(_) => None,
+    }
+}
+
+fn rotate(x: f32, y: f32, rotation: f32) -> (f32, f32) {
+    let (sin, cos) = rotation.sin_cos();
+    (cos * x - sin * y, sin * x + cos * y)
+}
+
+/// Corners of a polygon covering the element in board coordinates
+pub fn element_corners(element: &Element) -> Vec<Position> {
+    if let Some((origin, width, height, rotation)) = rectangle(element) {
+        return [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+            .into_iter()
+            .map(|(x, y)| {
+                let (x, y) = rotate(x, y, rotation);
+                Position {
+                    x: origin.x + x,
+                    y: origin.y + y,
+                }
+            })
+            .collect();
+    }
+    let Element::Stroke(stroke) = element else {
+        return vec![];
+    };
+    let strip = stroke_into_triangle_strip(stroke, HIT_TOLERANCE);
+    let points = strip.iter().map(|p| Position {
+        x: p.x as f32,
+        y: p.y as f32,
+    });
+    match bounds(points) {
+        Some((min, max)) => vec![
+            min,
+            Position { x: max.x, y: min.y },
+            max,
+            Position { x: min.x, y: max.y },
+        ],
+        None => vec![],
+    }
+}
+
+/// Axis aligned bounding box of `points` as its minimum and maximum corner
+fn bounds(points: impl IntoIterator<Item = Position>) -> Option<(Position, Position)> {
+    points.into_iter().fold(None, |bounds, p| {
+        let (min, max) = bounds.unwrap_or((p, p));
+        Some((
+            Position {
+                x: min.x.min(p.x),
+                y: min.y.min(p.y),
+            },
+            Position {
+                x: max.x.max(p.x),
+                y: max.y.max(p.y),
+            },
+        ))
+    })
+}
+
+pub fn selection_bounds(
+    elements: &HashMap<ElementId, Element>,
+    ids: &HashSet<ElementId>,
+) -> Option<(Position, Position)> {
+    bounds(
+        ids.iter()
+            .filter_map(|id| elements.get(id))
+            .flat_map(element_corners),
+    )
+}
+
+pub fn hit_test(element: &Element, point: Position) -> bool {
+    if let Some((origin, width, height, rotation)) = rectangle(element) {
+        let (x, y) = rotate(point.x - origin.x, point.y - origin.y, -rotation);
+        return (0.0..=width).contains(&x) && (0.0..=height).contains(&y);
+    }
+    match element {
+        Element::Stroke(stroke) => strip_contains(
+            &stroke_into_triangle_strip(stroke, HIT_TOLERANCE),
+            Point2::new(point.x as f64, point.y as f64),
+        ),
+        _ => false,
+    }
+}
+
+/// Element of `elements` under `point` drawn on top of the others
+fn topmost_hit(
+    elements: &HashMap<ElementId, Element>,
+    index: &ElementIndex,
+    ranks: &HashMap<ElementId, usize>,
+    point: Position,
+) -> Option<ElementId> {
+    index
+        .query_untracked(&Rect::point(point))
+        .into_iter()
+        .filter(|id| elements.get(id).is_some_and(|e| hit_test(e, point)))
+        .max_by_key(|id| ranks.get(id))
+}
+
+/// Starts a move of the element `hit` (or whatever is under `point`) or a marquee selection.
+/// Only elements in `elements` can be selected.
+pub fn pointer_down(
+    selection: RwSignal<Selection>,
+    elements: &HashMap<ElementId, Element>,
+    index: &ElementIndex,
+    ranks: &HashMap<ElementId, usize>,
+    point: Position,
+    shift: bool,
+    hit: Option<ElementId>,
+) {
+    let hit = hit
+        .filter(|id| elements.contains_key(id))
+        .or_else(|| topmost_hit(elements, index, ranks, point));
+    selection.update(|selection| match hit {
+        Some(id) if shift && selection.ids.contains(&id) => {
+            selection.ids.remove(&id);
+        }
+        Some(id) => {
+            if !shift && !selection.ids.contains(&id) {
+                selection.ids.clear();
+            }
+            selection.ids.insert(id);
+            selection.drag = Some(Drag::Move {
+                start: point,
+                current: point,
+            });
+        }
+        None => {
+            if !shift {
+                selection.ids.clear();
+            }
+            selection.drag = Some(Drag::Marquee {
+                start: point,
+                current: point,
+            });
+        }
+    });
+}
+
+pub fn pointer_move(selection: RwSignal<Selection>, point: Position) {
+    if selection.with_untracked(|s| s.drag.is_some()) {
+        selection.update(|selection| {
+            if let Some(drag) = &mut selection.drag {
+                drag.update(point);
+            }
+        });
+    }
+}
+
+/// Finishes the current drag and sends the resulting transform, which the client shows straight
+/// away
+pub fn pointer_up(
+    selection: RwSignal<Selection>,
+    elements: &HashMap<ElementId, Element>,
+    index: &ElementIndex,
+    client: &Client,
+) {
+    let Some(drag) = selection.with_untracked(|s| s.drag) else {
+        return;
+    };
+    match drag {
+        Drag::Marquee { start, current } => {
+            let min = Position {
+                x: start.x.min(current.x),
+                y: start.y.min(current.y),
+            };
+            let max = Position {
+                x: start.x.max(current.x),
+                y: start.y.max(current.y),
+            };
+            let inside =
+                |p: &Position| (min.x..=max.x).contains(&p.x) && (min.y..=max.y).contains(&p.y);
+            let selected = index
+                .query_untracked(&Rect { min, max })
+                .into_iter()
+                .filter(|id| {
+                    elements
+                        .get(id)
+                        .is_some_and(|e| element_corners(e).iter().all(inside))
+                })
+                .collect::<Vec<_>>();
+            selection.update(|s| {
+                s.ids.extend(selected);
+                s.drag = None;
+            });
+        }
+        _ => {
+            let ids = selection.with_untracked(|s| s.ids.iter().copied().collect::<Vec<_>>());
+            selection.update(|s| s.drag = None);
+            let Some(transform) = drag.transform() else {
+                return;
+            };
+            if transform == Transform::IDENTITY || ids.is_empty() {
+                return;
+            }
+            client.send(ToServer::Transform { ids, transform });
+        }
+    }
+}
+
+pub fn delete_selected(selection: RwSignal<Selection>, client: &Client) {
+    let ids = selection.with_untracked(|s| s.ids.clone());
+    for id in ids {
+        client.send(ToServer::DeleteElement { id });
+    }
+    selection.set(Selection::default());
+}
+
+fn screen_rect(camera: &Camera, min: Position, max: Position) -> String {
+    let min = camera.to_screen(&min);
+    let max = camera.to_screen(&max);
+    format!(
+        "left: {}px; top: {}px; width: {}px; height: {}px",
+        min.x,
+        min.y,
+        max.x - min.x,
+        max.y - min.y
+    )
+}
+
+/// Selection box with scale and rotate handles, and the marquee while it is dragged
+#[component]
+pub fn SelectionLayer(
+    #[prop(into)] elements: Signal<HashMap<ElementId, Element>>,
+    selection: RwSignal<Selection>,
+    camera: RwSignal<Camera>,
+) -> impl IntoView {
+    let selected_bounds = create_memo(move |_| {
+        selection.with(|s| elements.with(|elements| selection_bounds(elements, &s.ids)))
+    });
+
+    let pointer = move |e: &ev::PointerEvent| {
+        camera.get_untracked().to_board(&Position {
+            x: e.client_x() as f32,
+            y: e.client_y() as f32,
+        })
+    };
+
+    let corner_handle = move |corner: usize| {
+        let position = move || {
+            let (min, max) = selected_bounds.get()?;
+            let corners = [
+                min,
+                Position { x: max.x, y: min.y },
+                max,
+                Position { x: min.x, y: max.y },
+            ];
+            Some((corners[corner], corners[(corner + 2) % 4]))
+        };
+        view! {
+            <div
+                class="selection-handle scale"
+                style=move || {
+                    position()
+                        .map(|(p, _)| {
+                            let p = camera.get().to_screen(&p);
+                            format!("left: {}px; top: {}px", p.x, p.y)
+                        })
+                        .unwrap_or_default()
+                }
+                on:pointerdown=move |e| {
+                    let Some((_, pivot)) = position() else {
+                        return;
+                    };
+                    e.stop_propagation();
+                    let start = pointer(&e);
+                    selection.update(|s| {
+                        s.drag = Some(Drag::Scale { pivot, start, current: start })
+                    });
+                }
+            ></div>
+        }
+    };
+
+    let rotate_handle = move || {
+        let (min, max) = selected_bounds.get()?;
+        let camera = camera.get();
+        let top = camera.to_screen(&Position {
+            x: (min.x + max.x) / 2.0,
+            y: min.y,
+        });
+        Some(format!(
+            "left: {}px; top: {}px",
+            top.x,
+            top.y - ROTATE_HANDLE_OFFSET
+        ))
+    };
+
+    view! {
+        <Show when=move || selected_bounds.with(|b| b.is_some())>
+            <div
+                class="selection-box"
+                style=move || {
+                    selected_bounds
+                        .get()
+                        .map(|(min, max)| screen_rect(&camera.get(), min, max))
+                        .unwrap_or_default()
+                }
+            ></div>
+            {(0..4).map(corner_handle).collect_view()}
+            <div
+                class="selection-handle rotate"
+                style=move || rotate_handle().unwrap_or_default()
+                on:pointerdown=move |e| {
+                    let Some((min, max)) = selected_bounds.get_untracked() else {
+                        return;
+                    };
+                    e.stop_propagation();
+                    let pivot = Position {
+                        x: (min.x + max.x) / 2.0,
+                        y: (min.y + max.y) / 2.0,
+                    };
+                    let start = pointer(&e);
+                    selection.update(|s| {
+                        s.drag = Some(Drag::Rotate { pivot, start, current: start })
+                    });
+                }
+            ></div>
+        </Show>
+        {move || {
+            selection
+                .with(|s| match s.drag {
+                    Some(Drag::Marquee { start, current }) => Some((start, current)),
+                    _ => None,
+                })
+                .map(|(start, current)| {
+                    let min = Position {
+                        x: start.x.min(current.x),
+                        y: start.y.min(current.y),
+                    };
+                    let max = Position {
+                        x: start.x.max(current.x),
+                        y: start.y.max(current.y),
+                    };
+                    view! {
+                        <div class="selection-marquee" style=screen_rect(&camera.get(), min, max)></div>
+                    }
+                })
+        }}
+    }
+}
@@ -1,8 +1,10 @@
 #version 300 es
 precision mediump float;
 
-layout (location = 0) out vec4 color;
+in vec4 vertex_color;
+
+layout (location = 0) out vec4 fragment_color;
 
 void main() {
-  color = vec4(0.0, 0.0, 0.0, 1.0);
-}
\ No newline at end of file
+  fragment_color = vertex_color;
+}
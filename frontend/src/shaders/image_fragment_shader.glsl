@@ -0,0 +1,12 @@
+#version 300 es
+precision mediump float;
+
+in vec2 texture_position;
+
+uniform sampler2D image;
+
+layout (location = 0) out vec4 color;
+
+void main() {
+  color = texture(image, texture_position);
+}
@@ -0,0 +1,17 @@
+#version 300 es
+precision mediump float;
+
+in vec2 position;
+in vec2 uv;
+
+uniform vec2 resolution;
+uniform vec2 offset;
+uniform float zoom;
+
+out vec2 texture_position;
+
+void main() {
+  vec2 screen_position = (position - offset) * zoom / resolution * 2.0 - 1.0;
+  gl_Position = vec4(screen_position.x, -screen_position.y, 0.0, 1.0);
+  texture_position = uv;
+}
@@ -2,10 +2,16 @@
 precision mediump float;
 
 in vec2 position;
+in vec4 color;
 
 uniform vec2 resolution;
+uniform vec2 offset;
+uniform float zoom;
+
+out vec4 vertex_color;
 
 void main() {
-  vec2 screen_position = position / vec2(500,300);
-  gl_Position = vec4(screen_position, 0.0, 1.0);
-}
\ No newline at end of file
+  vec2 screen_position = (position - offset) * zoom / resolution * 2.0 - 1.0;
+  gl_Position = vec4(screen_position.x, -screen_position.y, 0.0, 1.0);
+  vertex_color = color;
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use common::{
+    entities::{Element, ElementId},
+    spatial::{Rect, SpatialIndex},
+};
+use leptos::*;
+
+/// Spatial index of elements, kept up to date with a signal of elements by indexing only those
+/// that changed since the last update
+#[derive(Default)]
+struct Indexed {
+    index: SpatialIndex<ElementId>,
+    /// Elements as they were when indexed
+    elements: HashMap<ElementId, Element>,
+}
+
+impl Indexed {
+    fn sync(&mut self, elements: &HashMap<ElementId, Element>) {
+        for (id, element) in elements {
+            if self.elements.get(id) == Some(element) {
+                continue;
+            }
+            match element.bounds() {
+                Some(bounds) => self.index.insert(*id, bounds),
+                None => {
+                    self.index.remove(id);
+                }
+            }
+            self.elements.insert(*id, element.clone());
+        }
+        if self.elements.len() != elements.len() {
+            let index = &mut self.index;
+            self.elements.retain(|id, _| {
+                let keep = elements.contains_key(id);
+                if !keep {
+                    index.remove(id);
+                }
+                keep
+            });
+        }
+    }
+}
+
+/// Shared spatial index of a signal of elements, for culling and hit-testing
+#[derive(Clone, Copy)]
+pub struct ElementIndex {
+    indexed: StoredValue<Indexed>,
+    /// Changes whenever the index is brought up to date, reading it first makes sure the index
+    /// matches the elements
+    revision: Memo<u64>,
+}
+
+impl ElementIndex {
+    pub fn new(elements: Signal<HashMap<ElementId, Element>>) -> Self {
+        let indexed = store_value(Indexed::default());
+        let revision = create_memo(move |revision: Option<&u64>| {
+            elements.with(|elements| indexed.update_value(|indexed| indexed.sync(elements)));
+            revision.map_or(0, |r| r + 1)
+        });
+        ElementIndex { indexed, revision }
+    }
+
+    /// Elements whose bounds intersect `rect`, in no particular order
+    pub fn query(&self, rect: &Rect) -> Vec<ElementId> {
+        self.revision.track();
+        self.query_untracked(rect)
+    }
+
+    pub fn query_untracked(&self, rect: &Rect) -> Vec<ElementId> {
+        self.revision.get_untracked();
+        self.indexed.with_value(|indexed| indexed.index.query(rect))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::entities::{Color, Position, Stroke};
+
+    use super::*;
+
+    fn stroke(x: f32) -> Element {
+        Element::Stroke(Stroke {
+            points: vec![Position { x, y: 0.0 }, Position { x: x + 1.0, y: 0.0 }],
+            width: 1.0,
+            color: Color::BLACK,
+            pressure: vec![],
+            style: Default::default(),
+        })
+    }
+
+    #[test]
+    fn sync_follows_changes() {
+        let mut indexed = Indexed::default();
+        let mut elements = HashMap::from([(1, stroke(0.0)), (2, stroke(100.0))]);
+        indexed.sync(&elements);
+        let at =
+            |indexed: &Indexed, x: f32| indexed.index.query_point(Position { x: x + 0.5, y: 0.0 });
+        assert_eq!(at(&indexed, 0.0), vec![1]);
+
+        elements.insert(1, stroke(50.0));
+        elements.remove(&2);
+        indexed.sync(&elements);
+        assert!(at(&indexed, 0.0).is_empty());
+        assert!(at(&indexed, 100.0).is_empty());
+        assert_eq!(at(&indexed, 50.0), vec![1]);
+        assert_eq!(indexed.index.len(), 1);
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+
+use common::{
+    entities::{Element, ElementId, Sticky},
+    text_ot::{self, TextOp},
+    websocket::ToServer,
+};
+use leptos::*;
+
+use crate::{camera::Camera, pending, Client};
+
+/// Local edits of a sticky note not yet applied by the server
+#[derive(Default)]
+struct PendingEdits {
+    /// Edit sent to the server and waiting for acknowledgement
+    outstanding: Option<Vec<TextOp>>,
+    /// Edits made while another one was outstanding, sent together after the acknowledgement
+    buffer: Vec<TextOp>,
+}
+
+/// Client side of the sticky note synchronisation, at most one edit per note is in flight
+#[derive(Default)]
+pub struct StickySync {
+    pending: HashMap<ElementId, PendingEdits>,
+}
+
+impl StickySync {
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn remove(&mut self, id: ElementId) {
+        self.pending.remove(&id);
+    }
+
+    fn local_edit(&mut self, id: ElementId, revision: u64, ops: Vec<TextOp>, client: &Client) {
+        let pending = self.pending.entry(id).or_default();
+        if pending.outstanding.is_some() {
+            pending.buffer.extend(ops);
+        } else {
+            client.send(ToServer::EditSticky {
+                id,
+                revision,
+                ops: ops.clone(),
+            });
+            pending.outstanding = Some(ops);
+        }
+    }
+
+    pub fn ack(&mut self, id: ElementId, revision: u64, client: &Client) {
+        let Some(pending) = self.pending.get_mut(&id) else {
+            return;
+        };
+        pending.outstanding = None;
+        if !pending.buffer.is_empty() {
+            let ops = std::mem::take(&mut pending.buffer);
+            client.send(ToServer::EditSticky {
+                id,
+                revision,
+                ops: ops.clone(),
+            });
+            pending.outstanding = Some(ops);
+        }
+    }
+
+    /// Rebases local pending edits on top of a remote edit, returning the remote edit
+    /// transformed so that it applies to the local text
+    pub fn remote_edit(&mut self, id: ElementId, ops: Vec<TextOp>) -> Vec<TextOp> {
+        let Some(pending) = self.pending.get_mut(&id) else {
+            return ops;
+        };
+        let mut ops = ops;
+        if let Some(outstanding) = &pending.outstanding {
+            let (outstanding, rebased) = text_ot::transform(outstanding, &ops, false);
+            pending.outstanding = Some(outstanding);
+            ops = rebased;
+        }
+        let (buffer, rebased) = text_ot::transform(&pending.buffer, &ops, false);
+        pending.buffer = buffer;
+        rebased
+    }
+}
+
+/// Carets of other users, by client id
+pub type Carets = HashMap<u64, (ElementId, usize)>;
+
+/// Moves carets in note `id` to follow a change of its text
+pub fn map_carets(carets: &mut Carets, id: ElementId, old: &str, new: &str) {
+    for (note, position) in carets.values_mut() {
+        if *note == id {
+            *position = text_ot::map_position(old, new, *position);
+        }
+    }
+}
+
+fn char_to_utf16(text: &str, position: usize) -> u32 {
+    text.chars()
+        .take(position)
+        .map(|c| c.len_utf16() as u32)
+        .sum()
+}
+
+fn utf16_to_char(text: &str, position: u32) -> usize {
+    let mut units = 0;
+    text.chars()
+        .take_while(|c| {
+            units += c.len_utf16() as u32;
+            units <= position
+        })
+        .count()
+}
+
+fn caret_color(client: u64) -> String {
+    format!("hsl({}, 70%, 45%)", client % 360)
+}
+
+#[component]
+pub fn StickyNotes(
+    #[prop(into)] elements: Signal<HashMap<ElementId, Element>>,
+    #[prop(into)] ranks: Signal<HashMap<ElementId, usize>>,
+    set_elements: WriteSignal<HashMap<ElementId, Element>>,
+    carets: RwSignal<Carets>,
+    sync: StoredValue<StickySync>,
+    camera: RwSignal<Camera>,
+    client: Client,
+    #[prop(into)] on_select: Callback<(ElementId, ev::PointerEvent)>,
+) -> impl IntoView {
+    let client = store_value(client);
+    let notes = move || {
+        elements.with(|elements| {
+            elements
+                .iter()
+                .filter(|(_, element)| matches!(element, Element::Sticky(_)))
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>()
+        })
+    };
+    view! {
+        <For
+            each=notes
+            key=|id| *id
+            children=move |id| {
+                let sticky = create_memo(move |_| {
+                    elements
+                        .with(|elements| match elements.get(&id) {
+                            Some(Element::Sticky(sticky)) => Some(sticky.clone()),
+                            _ => None,
+                        })
+                });
+                let exists = create_memo(move |_| sticky.with(|s| s.is_some()));
+                let rank = Signal::derive(move || ranks.with(|r| r.get(&id).copied().unwrap_or(0)));
+                view! {
+                    {move || {
+                        exists
+                            .get()
+                            .then(|| {
+                                view! {
+                                    <StickyNote
+                                        id=id
+                                        sticky=sticky
+                                        rank=rank
+                                        set_elements=set_elements
+                                        carets=carets
+                                        sync=sync
+                                        camera=camera
+                                        client=client
+                                        on_select=on_select
+                                    />
+                                }
+                            })
+                    }}
+                }
+            }
+        />
+    }
+}
+
+#[component]
+fn StickyNote(
+    id: ElementId,
+    sticky: Memo<Option<Sticky>>,
+    rank: Signal<usize>,
+    set_elements: WriteSignal<HashMap<ElementId, Element>>,
+    carets: RwSignal<Carets>,
+    sync: StoredValue<StickySync>,
+    camera: RwSignal<Camera>,
+    client: StoredValue<Client>,
+    on_select: Callback<(ElementId, ev::PointerEvent)>,
+) -> impl IntoView {
+    let textarea = create_node_ref::<html::Textarea>();
+    let text = create_memo(move |_| sticky.get().map(|s| s.text).unwrap_or_default());
+    let last_caret = store_value(None::<usize>);
+
+    // Remote edits replace the textarea value, keep the local caret where it was in the text
+    create_effect(move |_| {
+        let text = text.get();
+        let Some(textarea) = textarea.get() else {
+            return;
+        };
+        let old = textarea.value();
+        if old == text {
+            return;
+        }
+        let selection = textarea.selection_start().ok().flatten();
+        textarea.set_value(&text);
+        if let Some(selection) = selection {
+            let caret = text_ot::map_position(&old, &text, utf16_to_char(&old, selection));
+            let caret = char_to_utf16(&text, caret);
+            let _ = textarea.set_selection_range(caret, caret);
+        }
+    });
+
+    let send_caret = move |position: Option<usize>| {
+        if last_caret.get_value() != position {
+            last_caret.set_value(position);
+            client.with_value(|client| client.send(ToServer::StickyCaret { id, position }));
+        }
+    };
+
+    let update_caret = move || {
+        let Some(textarea) = textarea.get_untracked() else {
+            return;
+        };
+        if let Ok(Some(selection)) = textarea.selection_end() {
+            send_caret(Some(utf16_to_char(&textarea.value(), selection)));
+        }
+    };
+
+    let on_input = move |_| {
+        let Some(textarea) = textarea.get_untracked() else {
+            return;
+        };
+        let new = textarea.value();
+        let Some(Sticky {
+            text: old,
+            revision,
+            ..
+        }) = sticky.get_untracked()
+        else {
+            return;
+        };
+        let ops = text_ot::diff(&old, &new);
+        if ops.is_empty() {
+            return;
+        }
+        carets.update(|carets| map_carets(carets, id, &old, &new));
+        set_elements.update(|elements| {
+            if let Some(Element::Sticky(sticky)) = elements.get_mut(&id) {
+                sticky.text = new;
+            }
+        });
+        sync.update_value(|sync| {
+            client.with_value(|client| sync.local_edit(id, revision, ops, client))
+        });
+        update_caret();
+    };
+
+    let mirror = move || {
+        let text = text.get();
+        let mut note_carets = carets.with(|carets| {
+            carets
+                .iter()
+                .filter(|(_, (note, _))| *note == id)
+                .map(|(client, (_, position))| (*position, *client))
+                .collect::<Vec<_>>()
+        });
+        note_carets.sort();
+        let chars = text.chars().collect::<Vec<_>>();
+        let mut start = 0;
+        let mut parts = vec![];
+        for (position, client) in note_carets {
+            let position = position.min(chars.len()).max(start);
+            parts.push(
+                chars[start..position]
+                    .iter()
+                    .collect::<String>()
+                    .into_view(),
+            );
+            parts.push(
+                view! { <span class="caret" style:border-color=caret_color(client)></span> }
+                    .into_view(),
+            );
+            start = position;
+        }
+        parts.push(chars[start..].iter().collect::<String>().into_view());
+        parts
+    };
+
+    let style = move || {
+        let Some(sticky) = sticky.get() else {
+            return String::new();
+        };
+        format!(
+            "{}; width: {}px; height: {}px; background: {}; z-index: {}",
+            camera
+                .get()
+                .css_transform(&sticky.position, sticky.rotation),
+            sticky.width,
+            sticky.height,
+            sticky.color.to_css(),
+            rank.get()
+        )
+    };
+
+    view! {
+        <div class="sticky-note" style=style>
+            <div
+                class="sticky-grip"
+                on:pointerdown=move |e| {
+                    e.stop_propagation();
+                    on_select.call((id, e));
+                }
+            ></div>
+            <div class="sticky-mirror">{mirror}</div>
+            <textarea
+                _ref=textarea
+                class="sticky-text"
+                readonly=move || {
+                    pending::is_provisional(id) || !client.with_value(Client::connected)
+                }
+                prop:value=move || text.get_untracked()
+                on:input=on_input
+                on:keyup=move |_| update_caret()
+                on:click=move |_| update_caret()
+                on:focus=move |_| update_caret()
+                on:blur=move |_| send_caret(None)
+            ></textarea>
+            <button
+                class="sticky-delete"
+                on:click=move |_| {
+                    client.with_value(|client| client.send(ToServer::DeleteElement { id }))
+                }
+            >
+                "×"
+            </button>
+        </div>
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use common::{
+    entities::{Element, ElementId, LayerId, Text},
+    websocket::ToServer,
+};
+use leptos::*;
+
+use crate::{camera::Camera, pending, toolbar::Tool, Client};
+
+/// Text box currently open in the editor, `id` is `None` for texts not yet sent to the server
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextDraft {
+    pub id: Option<ElementId>,
+    pub text: Text,
+}
+
+fn text_style(text: &Text, camera: &Camera) -> String {
+    format!(
+        "{}; font-size: {}px; color: {}",
+        camera.css_transform(&text.position, text.rotation),
+        text.font_size,
+        text.color.to_css()
+    )
+}
+
+#[component]
+pub fn TextLayer(
+    #[prop(into)] elements: Signal<HashMap<ElementId, Element>>,
+    #[prop(into)] ranks: Signal<HashMap<ElementId, usize>>,
+    camera: RwSignal<Camera>,
+    tool: RwSignal<Tool>,
+    editing: RwSignal<Option<TextDraft>>,
+    #[prop(into)] on_select: Callback<(ElementId, ev::PointerEvent)>,
+) -> impl IntoView {
+    let texts = move || {
+        elements.with(|elements| {
+            elements
+                .iter()
+                .filter(|(_, element)| matches!(element, Element::Text(_)))
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>()
+        })
+    };
+    view! {
+        <For
+            each=texts
+            key=|id| *id
+            children=move |id| {
+                let text = create_memo(move |_| {
+                    elements
+                        .with(|elements| match elements.get(&id) {
+                            Some(Element::Text(text)) => Some(text.clone()),
+                            _ => None,
+                        })
+                });
+                let edit = move || {
+                    if pending::is_provisional(id) {
+                        return;
+                    }
+                    if let Some(text) = text.get_untracked() {
+                        editing.set(Some(TextDraft { id: Some(id), text }));
+                    }
+                };
+                view! {
+                    <div
+                        class="text-element"
+                        class:editable=move || tool.get() == Tool::Text
+                        style=move || {
+                            let Some(text) = text.get() else {
+                                return String::new();
+                            };
+                            // A single style attribute, setting it replaces individual properties
+                            let edited = editing.with(|e| e.as_ref().and_then(|e| e.id) == Some(id));
+                            format!(
+                                "{}; z-index: {}; visibility: {}",
+                                text_style(&text, &camera.get()),
+                                ranks.with(|r| r.get(&id).copied().unwrap_or(0)),
+                                if edited { "hidden" } else { "visible" }
+                            )
+                        }
+                        on:pointerdown=move |e| {
+                            if tool.get_untracked() == Tool::Select {
+                                e.stop_propagation();
+                                on_select.call((id, e));
+                            }
+                        }
+                        on:click=move |e| {
+                            if tool.get_untracked() == Tool::Text {
+                                e.stop_propagation();
+                                edit();
+                            }
+                        }
+                        on:dblclick=move |e| {
+                            e.stop_propagation();
+                            edit();
+                        }
+                    >
+                        {move || text.get().map(|t| t.content).unwrap_or_default()}
+                    </div>
+                }
+            }
+        />
+    }
+}
+
+fn content_untracked(editing: RwSignal<Option<TextDraft>>) -> String {
+    editing.with_untracked(|e| {
+        e.as_ref()
+            .map(|d| d.text.content.clone())
+            .unwrap_or_default()
+    })
+}
+
+fn commit(draft: TextDraft, layer: Option<LayerId>, client: &Client) {
+    let empty = draft.text.content.trim().is_empty();
+    match draft.id {
+        None if empty => (),
+        None => client.send(ToServer::CreateElement {
+            element: Element::Text(draft.text),
+            layer,
+        }),
+        Some(id) if empty => client.send(ToServer::DeleteElement { id }),
+        Some(id) => client.send(ToServer::UpdateElement {
+            id,
+            element: Element::Text(draft.text),
+        }),
+    }
+}
+
+#[component]
+pub fn TextEditor(
+    editing: RwSignal<Option<TextDraft>>,
+    camera: RwSignal<Camera>,
+    /// Layer new text boxes are added to
+    #[prop(into)]
+    layer: Signal<Option<LayerId>>,
+    client: Client,
+) -> impl IntoView {
+    let textarea = create_node_ref::<html::Textarea>();
+
+    create_effect(move |_| {
+        if let Some(textarea) = textarea.get() {
+            let _ = textarea.focus();
+        }
+    });
+
+    let client = store_value(client);
+    let finish = move || {
+        if let Some(draft) = editing.get_untracked() {
+            editing.set(None);
+            client.with_value(|client| commit(draft, layer.get_untracked(), client));
+        }
+    };
+
+    // Only recreate the textarea when a different text box is opened, not on every keystroke
+    let session =
+        create_memo(move |_| editing.with(|e| e.as_ref().map(|d| (d.id, d.text.position))));
+    let content = move || {
+        editing.with(|e| {
+            e.as_ref()
+                .map(|d| d.text.content.clone())
+                .unwrap_or_default()
+        })
+    };
+
+    move || {
+        session.get().map(|_| {
+            let initial = content_untracked(editing);
+            let rows = move || content().split('\n').count();
+            let cols = move || {
+                content()
+                    .split('\n')
+                    .map(|l| l.chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    + 1
+            };
+            view! {
+                <textarea
+                    _ref=textarea
+                    class="text-editor"
+                    rows=rows
+                    cols=cols
+                    style=move || {
+                        editing
+                            .with(|e| e.as_ref().map(|d| text_style(&d.text, &camera.get())))
+                            .unwrap_or_default()
+                    }
+                    prop:value=initial
+                    on:input=move |e| {
+                        let content = event_target_value(&e);
+                        editing.update(|d| {
+                            if let Some(d) = d {
+                                d.text.content = content;
+                            }
+                        });
+                    }
+                    on:keydown=move |e| {
+                        match e.key().as_str() {
+                            "Escape" => editing.set(None),
+                            "Enter" if !e.shift_key() => {
+                                e.prevent_default();
+                                finish();
+                            }
+                            _ => (),
+                        }
+                    }
+                    on:blur=move |_| finish()
+                ></textarea>
+            }
+        })
+    }
+}
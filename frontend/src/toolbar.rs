@@ -0,0 +1,201 @@
+use common::entities::{Cap, Color, Join, StrokeStyle};
+use leptos::*;
+
+/// Miter limit of SVG, sharper corners are bevelled
+const MITER_LIMIT: f32 = 4.0;
+
+const JOINS: [(&str, Join); 3] = [
+    ("Round", Join::Round),
+    ("Miter", Join::Miter { limit: MITER_LIMIT }),
+    ("Bevel", Join::Bevel),
+];
+
+const DASHES: [(&str, &[f32]); 3] = [
+    ("Solid", &[]),
+    ("Dashed", &StrokeStyle::DASHED),
+    ("Dotted", &StrokeStyle::DOTTED),
+];
+
+const CAPS: [(&str, Cap); 3] = [
+    ("Round", Cap::Round),
+    ("Butt", Cap::Butt),
+    ("Square", Cap::Square),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    Select,
+    Pen,
+    Text,
+    Sticky,
+}
+
+impl Tool {
+    const ALL: [Tool; 4] = [Tool::Select, Tool::Pen, Tool::Text, Tool::Sticky];
+
+    pub fn class(&self) -> &'static str {
+        match self {
+            Tool::Select => "tool-select",
+            Tool::Pen => "tool-pen",
+            Tool::Text => "tool-text",
+            Tool::Sticky => "tool-sticky",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Tool::Select => "Select",
+            Tool::Pen => "Pen",
+            Tool::Text => "Text",
+            Tool::Sticky => "Sticky note",
+        }
+    }
+}
+
+#[component]
+pub fn Toolbar(
+    tool: RwSignal<Tool>,
+    font_size: RwSignal<f32>,
+    pen_width: RwSignal<f32>,
+    stroke_style: RwSignal<StrokeStyle>,
+    /// Colour of new strokes and texts, picking one also recolours the selected texts
+    color: RwSignal<Color>,
+) -> impl IntoView {
+    view! {
+        <div class="toolbar no-select">
+            {Tool::ALL
+                .into_iter()
+                .map(|t| {
+                    view! {
+                        <button
+                            class:selected=move || tool.get() == t
+                            on:click=move |_| tool.set(t)
+                        >
+                            {t.label()}
+                        </button>
+                    }
+                })
+                .collect_view()}
+            <label>
+                "Pen width"
+                <input
+                    type="range"
+                    min="1"
+                    max="50"
+                    prop:value=move || pen_width.get()
+                    on:input=move |e| {
+                        if let Ok(width) = event_target_value(&e).parse::<f32>() {
+                            pen_width.set(width);
+                        }
+                    }
+                />
+            </label>
+            <label>
+                "Colour"
+                <input
+                    type="color"
+                    prop:value=move || color.with(Color::to_hex)
+                    on:input=move |e| {
+                        if let Some(picked) = Color::from_hex(&event_target_value(&e)) {
+                            color.update(|c| *c = Color { a: c.a, ..picked });
+                        }
+                    }
+                />
+            </label>
+            <label>
+                "Opacity"
+                <input
+                    type="range"
+                    min="0"
+                    max="255"
+                    prop:value=move || color.with(|c| c.a)
+                    on:input=move |e| {
+                        if let Ok(a) = event_target_value(&e).parse::<u8>() {
+                            color.update(|c| c.a = a);
+                        }
+                    }
+                />
+            </label>
+            <label>
+                "Line"
+                <select on:change=move |e| {
+                    if let Some((_, dash)) = DASHES.get(event_target_value(&e).parse::<usize>().unwrap_or(0)) {
+                        stroke_style.update(|s| {
+                            s.dash = dash.to_vec();
+                            // Dots are zero length dashes, which only have caps to show
+                            if s.dash == StrokeStyle::DOTTED && s.cap == Cap::Butt {
+                                s.cap = Cap::Round;
+                            }
+                        });
+                    }
+                }>
+                    {DASHES
+                        .iter()
+                        .enumerate()
+                        .map(|(i, (label, dash))| {
+                            view! {
+                                <option value=i selected=move || stroke_style.with(|s| s.dash == *dash)>
+                                    {*label}
+                                </option>
+                            }
+                        })
+                        .collect_view()}
+                </select>
+            </label>
+            <label>
+                "Joins"
+                <select on:change=move |e| {
+                    if let Some((_, join)) = JOINS.get(event_target_value(&e).parse::<usize>().unwrap_or(0)) {
+                        stroke_style.update(|s| s.join = *join);
+                    }
+                }>
+                    {JOINS
+                        .iter()
+                        .enumerate()
+                        .map(|(i, (label, join))| {
+                            view! {
+                                <option value=i selected=move || stroke_style.with(|s| s.join == *join)>
+                                    {*label}
+                                </option>
+                            }
+                        })
+                        .collect_view()}
+                </select>
+            </label>
+            <label>
+                "Caps"
+                <select on:change=move |e| {
+                    if let Some((_, cap)) = CAPS.get(event_target_value(&e).parse::<usize>().unwrap_or(0)) {
+                        stroke_style.update(|s| s.cap = *cap);
+                    }
+                }>
+                    {CAPS
+                        .iter()
+                        .enumerate()
+                        .map(|(i, (label, cap))| {
+                            view! {
+                                <option value=i selected=move || stroke_style.with(|s| s.cap == *cap)>
+                                    {*label}
+                                </option>
+                            }
+                        })
+                        .collect_view()}
+                </select>
+            </label>
+            <label>
+                "Font size"
+                <input
+                    type="number"
+                    min="4"
+                    max="200"
+                    prop:value=move || font_size.get()
+                    on:change=move |e| {
+                        if let Ok(size) = event_target_value(&e).parse::<f32>() {
+                            font_size.set(size.clamp(4.0, 200.0));
+                        }
+                    }
+                />
+            </label>
+        </div>
+    }
+}